@@ -3,7 +3,7 @@ use std::env;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use winres::VersionInfo;
 
 #[derive(Deserialize)]
@@ -20,18 +20,35 @@ struct CargoPackage {
 
 fn main() {
     let cargo = read_cargo();
-    let icon = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+    embed_git_commit();
+    let icon_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
         .parent()
         .unwrap()
-        .join("assets/icon/terminal.ico");
+        .join("assets/icon");
+    let icon = icon_dir.join("terminal.ico");
+    check_icon_sizes(&icon);
     let manifest_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("manifest.xml");
     let mut f = File::create(manifest_path.clone()).unwrap();
     f.write_all(get_manifest(&cargo).as_bytes()).unwrap();
     let now = chrono::Local::now();
     let version = parse_version(&cargo.package.version);
-    winres::WindowsResource::new()
-        .set_manifest_file(manifest_path.to_str().unwrap())
-        .set_icon_with_id(icon.to_str().unwrap(), "app")
+    let mut res = winres::WindowsResource::new();
+    res.set_icon_with_id(icon.to_str().unwrap(), "app");
+    // named icons are indexed alphabetically, see DISTRO_ICONS in icon.rs
+    for distro in ["debian", "fedora", "ubuntu"] {
+        let path = icon_dir.join(format!("{}.ico", distro));
+        check_icon_sizes(&path);
+        res.set_icon_with_id(path.to_str().unwrap(), &format!("distro_{}", distro));
+    }
+    let messages_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("messages.bin");
+    File::create(&messages_path)
+        .and_then(|mut f| f.write_all(&message_table(EVENT_MESSAGE_IDS)))
+        .unwrap();
+    res.append_rc_content(&format!(
+        "1 MESSAGETABLE \"{}\"",
+        messages_path.to_str().unwrap().replace('\\', "\\\\")
+    ));
+    res.set_manifest_file(manifest_path.to_str().unwrap())
         .set("ProductName", "WSL Script")
         .set("FileDescription", &cargo.package.description)
         .set("FileVersion", &cargo.package.version)
@@ -47,6 +64,94 @@ fn main() {
         .unwrap();
 }
 
+/// Expose the commit the binary was built from as `WSLSCRIPT_GIT_COMMIT`.
+///
+/// Builds outside a git checkout report `unknown`.
+fn embed_git_commit() {
+    let git_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+        .parent()
+        .unwrap()
+        .join(".git");
+    // HEAD changes on checkout, index on commit
+    for name in ["HEAD", "index"] {
+        let path = git_dir.join(name);
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    let commit = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=WSLSCRIPT_GIT_COMMIT={}", commit);
+}
+
+/// Event IDs of the Event Log messages, see `eventlog.rs`.
+const EVENT_MESSAGE_IDS: std::ops::RangeInclusive<u32> = 1..=3;
+
+/// Build a message table whose messages consist of the first insertion
+/// string only, so that Event Viewer displays the reported text as is.
+///
+/// See: https://learn.microsoft.com/en-us/windows/win32/api/winnt/ns-winnt-message_resource_data
+fn message_table(ids: std::ops::RangeInclusive<u32>) -> Vec<u8> {
+    // MESSAGE_RESOURCE_ENTRY with flag 1 for UTF-16 text, padded to 4 bytes
+    let mut entry = Vec::new();
+    let text = "%1\r\n\0".encode_utf16().collect::<Vec<_>>();
+    let len = (4 + text.len() * 2 + 3) & !3;
+    entry.extend_from_slice(&(len as u16).to_le_bytes());
+    entry.extend_from_slice(&1_u16.to_le_bytes());
+    text.iter()
+        .for_each(|c| entry.extend_from_slice(&c.to_le_bytes()));
+    entry.resize(len, 0);
+    // MESSAGE_RESOURCE_DATA with a single MESSAGE_RESOURCE_BLOCK
+    let mut data = Vec::new();
+    data.extend_from_slice(&1_u32.to_le_bytes());
+    data.extend_from_slice(&ids.start().to_le_bytes());
+    data.extend_from_slice(&ids.end().to_le_bytes());
+    data.extend_from_slice(&16_u32.to_le_bytes());
+    for _ in ids {
+        data.extend_from_slice(&entry);
+    }
+    data
+}
+
+/// Icon sizes in pixels required for 100% to 200% display scaling.
+const ICON_SIZES: &[u32] = &[16, 20, 24, 32, 40, 48, 64, 256];
+
+/// Check that an icon file contains an image of each required size.
+///
+/// Windows scales the nearest size otherwise, which looks blurry.
+fn check_icon_sizes(path: &Path) {
+    println!("cargo:rerun-if-changed={}", path.display());
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .unwrap();
+    // ICONDIR header followed by 16 byte ICONDIRENTRY for each image
+    let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+    let sizes = (0..count)
+        .map(|i| match data[6 + i * 16] {
+            // width of 0 means 256 pixels
+            0 => 256,
+            w => w as u32,
+        })
+        .collect::<Vec<_>>();
+    for size in ICON_SIZES {
+        assert!(
+            sizes.contains(size),
+            "{} has no {}x{} image",
+            path.display(),
+            size,
+            size
+        );
+    }
+}
+
 /// Parse version string to resource version.
 ///
 /// See: https://docs.microsoft.com/en-us/windows/win32/menurc/versioninfo-resource
@@ -101,6 +206,12 @@ fn get_manifest(cargo: &Cargo) -> String {
                 language="*" />
         </dependentAssembly>
     </dependency>
+    <application xmlns="urn:schemas-microsoft-com:asm.v3">
+        <windowsSettings>
+            <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">true/pm</dpiAware>
+            <dpiAwareness xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">PerMonitorV2, PerMonitor</dpiAwareness>
+        </windowsSettings>
+    </application>
 </assembly>"#,
         name = format!("github.sop.{}", cargo.package.name),
         description = cargo.package.description,
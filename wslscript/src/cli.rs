@@ -0,0 +1,764 @@
+//! Command line interface for managing filetype registrations without the GUI.
+//!
+//! ```text
+//! wslscript register --ext sh [--distro Ubuntu] [--hold error|never|always|timeout]
+//!                    [--interactive] [--icon "path,index"] [--association openwith|default|full]
+//!                    [--type-name "description"] [--out-of-process] [--quiet]
+//! wslscript list [--json]
+//! wslscript convert [--distro Ubuntu] [--json] path...
+//! wslscript copy-path [--ext sh | --distro Ubuntu] [--quiet] path...
+//! wslscript completions bash|powershell
+//! wslscript run [--wait] script [args...]
+//! wslscript install-wsl-command [--distro Ubuntu] [--quiet]
+//! wslscript mount-root [--distro Ubuntu] [root | --reset]
+//! wslscript --version [--json]
+//! ```
+//!
+//! `list`, `convert`, `completions` and `--version` write their output to the
+//! standard output, `list`, `convert` and `--version` with `--json` in
+//! a machine-readable format.
+//!
+//! `copy-path` places converted paths on the clipboard, one per line.
+//! It backs the _Copy WSL path_ verb of Explorer, which starts an instance
+//! per selected file. Instances started together hand their paths over to
+//! the first one, so that the whole selection is copied at once.
+//!
+//! `run` runs a script like opening it from Explorer. When invoked from
+//! a WSL shell via interop, Linux paths are passed to the script as is.
+//! With `--wait` it returns after the console is closed, printing the exit
+//! code and run time of the script, and exits with the script's exit code.
+//!
+//! `mount-root` prints the root under which Windows drives are mounted in
+//! a distribution, or sets it for distributions whose `wsl.conf` changes it
+//! and can't be probed.
+//!
+//! `install-wsl-command` installs a `wslscript` command into WSL for opening
+//! paths in Explorer, running PowerShell commands and running scripts.
+//!
+//! Exit code is 0 on success, 1 if the operation failed
+//! and 2 if the arguments were invalid, except for `run --wait`.
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use wslscript_common::error::*;
+use wslscript_common::icon::ShellIcon;
+use wslscript_common::{registry, throttle, ver, wcstring, win32, wsl};
+
+/// Exit code when the operation failed.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code when command line arguments were invalid.
+const EXIT_USAGE: i32 = 2;
+
+/// Time in milliseconds that `copy-path` waits for paths from other
+/// instances after the last one received.
+const COPY_PATH_COLLECT_MS: u32 = 500;
+
+/// Commands and their arguments offered by shell completions.
+const COMPLETIONS: &[(&str, &[&str])] = &[
+    (
+        "register",
+        &[
+            "--ext",
+            "--distro",
+            "--hold",
+            "--interactive",
+            "--icon",
+            "--association",
+            "--type-name",
+            "--out-of-process",
+            "--quiet",
+        ],
+    ),
+    ("list", &["--json", "--quiet"]),
+    ("convert", &["--distro", "--json", "--quiet"]),
+    ("copy-path", &["--ext", "--distro", "--quiet"]),
+    ("completions", &["bash", "powershell"]),
+    ("run", &["--wait"]),
+    ("install-wsl-command", &["--distro", "--quiet"]),
+    ("mount-root", &["--distro", "--reset"]),
+    ("--version", &["--json"]),
+];
+
+/// Values of the `--hold` argument offered by shell completions.
+const HOLD_COMPLETIONS: &[&str] = &["error", "never", "always", "timeout"];
+
+/// Values of the `--association` argument offered by shell completions.
+const ASSOCIATION_COMPLETIONS: &[&str] = &["openwith", "default", "full"];
+
+/// Run a command given in command line arguments.
+///
+/// Returns the process exit code, or None if arguments don't specify a command.
+pub fn run(args: &[OsString]) -> Option<i32> {
+    let (cmd, args) = args.split_first()?;
+    let quiet = args.iter().any(|arg| arg == "--quiet");
+    // whether the result is written to the console instead of a dialog
+    let (result, to_console) = match cmd.to_str() {
+        Some("register") => (register(args), false),
+        Some("list") => (list(args), true),
+        Some("convert") => (convert(args), true),
+        Some(registry::COPY_PATH_COMMAND) => (copy_path(args), false),
+        Some("completions") => (completions(args), true),
+        Some("run") => (run_script(args), true),
+        Some("install-wsl-command") => (install_wsl_command(args), false),
+        Some("mount-root") => (mount_root(args), true),
+        Some("--version" | "version") => (version(args), true),
+        _ => return None,
+    };
+    if to_console {
+        attach_console();
+    }
+    let code = match result {
+        Ok(output) if to_console => {
+            if !output.is_empty() {
+                let mut stdout = std::io::stdout();
+                let _ = writeln!(stdout, "{}", output).and_then(|_| stdout.flush());
+            }
+            0
+        }
+        Ok(msg) => {
+            if !quiet && !msg.is_empty() {
+                message(&msg);
+            }
+            0
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            if to_console {
+                eprintln!("{}", e);
+            } else if !quiet {
+                win32::error_message(&e.to_wide());
+            }
+            match e {
+                Error::InvalidArgumentError(_) => EXIT_USAGE,
+                Error::ScriptExitError { code, .. } => code,
+                _ => EXIT_FAILURE,
+            }
+        }
+    };
+    Some(code)
+}
+
+/// Register an extension with options given in arguments.
+fn register(args: &[OsString]) -> Result<String, Error> {
+    let mut ext: Option<String> = None;
+    let mut distro: Option<String> = None;
+    let mut hold_mode = registry::HoldMode::default();
+    let mut interactive = false;
+    let mut out_of_process = false;
+    let mut icon: Option<String> = None;
+    let mut association = registry::Association::default();
+    let mut type_name: Option<String> = None;
+    let mut iter = args.iter().map(|arg| arg.to_string_lossy().into_owned());
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| Error::InvalidArgumentError(format!("{} requires a value", arg)))
+        };
+        match arg.as_str() {
+            "--ext" => ext = Some(value()?.trim_start_matches('.').to_owned()),
+            "--distro" => distro = Some(value()?),
+            "--hold" => {
+                let s = value()?;
+                hold_mode = s.to_lowercase().parse().map_err(|_| {
+                    Error::InvalidArgumentError(format!("Invalid hold mode: {}", s))
+                })?;
+            }
+            "--icon" => icon = Some(value()?),
+            "--association" => {
+                let s = value()?;
+                association = s.to_lowercase().parse().map_err(|_| {
+                    Error::InvalidArgumentError(format!("Invalid association: {}", s))
+                })?;
+            }
+            "--type-name" => type_name = Some(value()?),
+            "--interactive" => interactive = true,
+            "--out-of-process" => out_of_process = true,
+            "--quiet" => {}
+            _ => return Err(Error::InvalidArgumentError(arg)),
+        }
+    }
+    let ext = ext
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| Error::InvalidArgumentError("--ext is required".to_owned()))?;
+    let distro = distro.map(|name| find_distro(&name)).transpose()?;
+    let icon = match icon {
+        Some(s) => ShellIcon::from_str(&s)?,
+        None => {
+            let distros = registry::query_distros().unwrap_or_default();
+            ShellIcon::load_default_for(distros.name(distro.as_ref()))?
+        }
+    };
+    let config = registry::ExtConfig {
+        extension: ext.clone(),
+        icon: Some(icon),
+        hold_mode,
+        interactive,
+        distro,
+        type_name: type_name.filter(|s| !s.trim().is_empty()),
+        association,
+        ..Default::default()
+    };
+    if out_of_process {
+        registry::set_out_of_process(true)?;
+    }
+    registry::register_extension(&config)?;
+    Ok(format!("Registered .{} extension.", ext))
+}
+
+/// List registered extensions.
+///
+/// With `--json`, full configurations are written as a configuration document.
+fn list(args: &[OsString]) -> Result<String, Error> {
+    let mut json = false;
+    for arg in args {
+        match arg.to_string_lossy().as_ref() {
+            "--json" => json = true,
+            "--quiet" => {}
+            _ => {
+                return Err(Error::InvalidArgumentError(
+                    arg.to_string_lossy().into_owned(),
+                ))
+            }
+        }
+    }
+    let exts = registry::query_registered_extensions()?;
+    if !json {
+        return Ok(exts.join("\n"));
+    }
+    let configs = exts
+        .iter()
+        .map(|ext| registry::get_extension_config(ext))
+        .collect::<Result<Vec<_>, _>>()?;
+    serde_json::to_string_pretty(&registry::ConfigDocument::new(configs))
+        .map_err(|e| Error::GenericError(e.to_string()))
+}
+
+/// Convert Windows paths to WSL paths.
+///
+/// With `--json`, an array of objects with `windows` and `wsl` paths is written.
+fn convert(args: &[OsString]) -> Result<String, Error> {
+    let mut json = false;
+    let mut distro: Option<String> = None;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.to_string_lossy().as_ref() {
+            "--json" => json = true,
+            "--quiet" => {}
+            "--distro" => {
+                let name = iter.next().ok_or_else(|| {
+                    Error::InvalidArgumentError("--distro requires a value".to_owned())
+                })?;
+                distro = Some(name.to_string_lossy().into_owned());
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        return Err(Error::InvalidArgumentError(
+            "No paths to convert".to_owned(),
+        ));
+    }
+    let mut opts_args: Vec<OsString> = Vec::new();
+    if let Some(name) = distro {
+        // validate the name before invoking WSL
+        find_distro(&name)?;
+        opts_args.extend([OsString::from("-d"), OsString::from(name)]);
+    }
+    let opts = wsl::WSLOptions::from_args(opts_args);
+    // relative paths are relative to the working directory
+    let cwd = std::env::current_dir()?;
+    let paths = paths.into_iter().map(|p| cwd.join(p)).collect::<Vec<_>>();
+    let wsl_paths = wsl::paths_to_wsl(&paths, &opts, None)?;
+    if !json {
+        let lines = wsl_paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        return Ok(lines.join("\n"));
+    }
+    let pairs = paths
+        .iter()
+        .zip(&wsl_paths)
+        .map(|(win, wsl)| {
+            serde_json::json!({
+                "windows": win.to_string_lossy(),
+                "wsl": wsl.to_string_lossy(),
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string_pretty(&pairs).map_err(|e| Error::GenericError(e.to_string()))
+}
+
+/// Copy WSL paths of files to the clipboard.
+///
+/// With `--ext`, paths are converted in the distribution of the filetype.
+fn copy_path(args: &[OsString]) -> Result<String, Error> {
+    let mut ext: Option<String> = None;
+    let mut distro: Option<String> = None;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .map(|s| s.to_string_lossy().into_owned())
+                .ok_or_else(|| {
+                    Error::InvalidArgumentError(format!(
+                        "{} requires a value",
+                        arg.to_string_lossy()
+                    ))
+                })
+        };
+        match arg.to_string_lossy().as_ref() {
+            "--ext" => ext = Some(value()?),
+            "--distro" => distro = Some(value()?),
+            "--quiet" => {}
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        return Err(Error::InvalidArgumentError("No paths to copy".to_owned()));
+    }
+    let cwd = std::env::current_dir()?;
+    let paths = paths.into_iter().map(|p| cwd.join(p)).collect::<Vec<_>>();
+    let key = ext.as_deref().or(distro.as_deref()).unwrap_or("default");
+    let Some(paths) = collect_paths(key, paths) else {
+        return Ok(String::new());
+    };
+    let opts = match (ext, distro) {
+        (Some(ext), _) => wsl::WSLOptions::from_ext(&ext).unwrap_or_default(),
+        (None, Some(name)) => {
+            find_distro(&name)?;
+            wsl::WSLOptions::from_args(vec![OsString::from("-d"), OsString::from(name)])
+        }
+        (None, None) => wsl::WSLOptions::default(),
+    };
+    let wsl_paths = wsl::paths_to_wsl(&paths, &opts, None)?;
+    let text = wsl_paths
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("\n");
+    crate::gui::copy_to_clipboard(windows::Win32::Foundation::HWND::default(), &text)?;
+    Ok(String::new())
+}
+
+/// Collect the paths of `copy-path` instances started together.
+///
+/// Explorer starts an instance per selected file. The first instance creates
+/// a mailslot named after the session and `key`, and receives paths from
+/// the others until none arrive for [`COPY_PATH_COLLECT_MS`]. Returns all
+/// paths to copy, or `None` if they were handed over to another instance.
+fn collect_paths(key: &str, mut paths: Vec<PathBuf>) -> Option<Vec<PathBuf>> {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{CloseHandle, GENERIC_WRITE};
+    use windows::Win32::Storage::FileSystem;
+    use windows::Win32::System::{Mailslots, RemoteDesktop, Threading};
+    let mut session = 0;
+    let _ = unsafe {
+        RemoteDesktop::ProcessIdToSessionId(Threading::GetCurrentProcessId(), &mut session)
+    };
+    let key = key
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .to_lowercase();
+    let name = wcstring(format!(
+        r"\\.\mailslot\wslscript\copy-path\{}\{}",
+        session, key
+    ));
+    // each message is a single path of at most 32767 UTF-16 units
+    if let Ok(slot) =
+        unsafe { Mailslots::CreateMailslotW(PCWSTR(name.as_ptr()), 0, COPY_PATH_COLLECT_MS, None) }
+    {
+        let mut buf = vec![0u8; 0x10000];
+        let mut read = 0u32;
+        // read fails once no message arrives within the timeout
+        while unsafe { FileSystem::ReadFile(slot, Some(&mut buf), Some(&mut read), None) }.is_ok() {
+            let wide = buf[..read as usize]
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect::<Vec<_>>();
+            paths.push(PathBuf::from(OsString::from_wide(&wide)));
+        }
+        let _ = unsafe { CloseHandle(slot) };
+        return Some(paths);
+    }
+    // another instance is collecting
+    let Ok(file) = (unsafe {
+        FileSystem::CreateFileW(
+            PCWSTR(name.as_ptr()),
+            GENERIC_WRITE.0,
+            FileSystem::FILE_SHARE_READ,
+            None,
+            FileSystem::OPEN_EXISTING,
+            FileSystem::FILE_FLAGS_AND_ATTRIBUTES::default(),
+            None,
+        )
+    }) else {
+        return Some(paths);
+    };
+    let mut sent = 0;
+    for path in &paths {
+        let bytes = path
+            .as_os_str()
+            .encode_wide()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let mut written = 0u32;
+        if unsafe { FileSystem::WriteFile(file, Some(&bytes), Some(&mut written), None) }.is_err() {
+            break;
+        }
+        sent += 1;
+    }
+    let _ = unsafe { CloseHandle(file) };
+    // copy the paths that couldn't be handed over
+    if sent < paths.len() {
+        log::debug!("Handed over {} of {} paths", sent, paths.len());
+        return Some(paths.split_off(sent));
+    }
+    None
+}
+
+/// Run a script with arguments.
+///
+/// When invoked from WSL, absolute Linux paths are passed unchanged, while
+/// Windows paths and relative paths are converted. Otherwise all arguments
+/// are Windows paths. Script runs in the distribution it was invoked from.
+///
+/// With `--wait`, the exit code and run time of the script are reported,
+/// and a failed script is reported as an error.
+fn run_script(args: &[OsString]) -> Result<String, Error> {
+    let wait = args.first().is_some_and(|arg| arg == "--wait");
+    let args = &args[wait as usize..];
+    let (script, args) = args
+        .split_first()
+        .ok_or_else(|| Error::InvalidArgumentError("Script is required".to_owned()))?;
+    let cwd = std::env::current_dir()?;
+    let distro = wsl::interop_distro();
+    // Windows path of each argument and whether it needs conversion
+    let paths = std::iter::once(script)
+        .chain(args)
+        .map(|arg| match (&distro, wsl::interop_arg_context(arg)) {
+            (Some(distro), wsl::ArgContext::Wsl) => {
+                (wsl::distro_unc_path(distro, arg), Some(PathBuf::from(arg)))
+            }
+            _ => (cwd.join(arg), None),
+        })
+        .collect::<Vec<_>>();
+    let win_script = paths[0].0.clone();
+    let mut opts = wsl::WSLOptions::from_path(&win_script).unwrap_or_default();
+    if let Some(distro) = distro {
+        log::debug!("Invoked from {} via interop", distro.to_string_lossy());
+        opts.set_distribution(distro);
+    }
+    // convert only the paths in Windows context
+    let to_convert = paths
+        .iter()
+        .filter(|(_, wsl_path)| wsl_path.is_none())
+        .map(|(win_path, _)| win_path.clone())
+        .collect::<Vec<_>>();
+    let mut converted = if to_convert.is_empty() {
+        Vec::new()
+    } else {
+        wsl::paths_to_wsl(&to_convert, &opts, None)?
+    }
+    .into_iter();
+    let wsl_paths = paths
+        .into_iter()
+        .map(|(_, wsl_path)| wsl_path.or_else(|| converted.next()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::InvalidPathError)?;
+    wsl::check_self_invocation(&win_script)?;
+    let slot = throttle::begin_run(&opts)?;
+    let status = wsl::run_wsl(
+        &win_script,
+        &wsl_paths[0],
+        &wsl_paths[1..],
+        &opts,
+        wait || slot.is_some(),
+    )?;
+    // icon set on the console is destroyed when this process exits
+    wsl::wait_console_icons();
+    let script = win_script.file_name().unwrap_or_default().to_string_lossy();
+    match status.filter(|_| wait) {
+        Some(status) if status.exit_code != 0 => Err(Error::ScriptExitError {
+            script: script.into_owned(),
+            code: status.exit_code,
+            secs: status.duration.as_secs(),
+        }),
+        Some(status) => Ok(format!(
+            "{} exited with code 0 after {}s.",
+            script,
+            status.duration.as_secs()
+        )),
+        None => Ok(String::new()),
+    }
+}
+
+/// Print or set the mount root of Windows drives in a distribution.
+fn mount_root(args: &[OsString]) -> Result<String, Error> {
+    let mut distro: Option<String> = None;
+    let mut root: Option<String> = None;
+    let mut reset = false;
+    let mut iter = args.iter().map(|arg| arg.to_string_lossy().into_owned());
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--distro" => {
+                distro = Some(iter.next().ok_or_else(|| {
+                    Error::InvalidArgumentError("--distro requires a value".to_owned())
+                })?)
+            }
+            "--reset" => reset = true,
+            "--quiet" => {}
+            _ if root.is_none() && wsl::is_valid_mount_root(&arg) => {
+                root = Some(wsl::normalize_mount_root(&arg))
+            }
+            _ => return Err(Error::InvalidArgumentError(arg)),
+        }
+    }
+    if let Some(name) = &distro {
+        find_distro(name)?;
+    }
+    let distro = distro.map(OsString::from);
+    let distro = distro.as_deref();
+    if reset || root.is_some() {
+        registry::set_distro_mount_root(distro, root.as_deref())?;
+    }
+    Ok(wsl::mount_root(distro))
+}
+
+/// Install the `wslscript` command into a distribution.
+fn install_wsl_command(args: &[OsString]) -> Result<String, Error> {
+    let mut distro: Option<String> = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.to_string_lossy().as_ref() {
+            "--quiet" => {}
+            "--distro" => {
+                let name = iter.next().ok_or_else(|| {
+                    Error::InvalidArgumentError("--distro requires a value".to_owned())
+                })?;
+                distro = Some(name.to_string_lossy().into_owned());
+            }
+            _ => {
+                return Err(Error::InvalidArgumentError(
+                    arg.to_string_lossy().into_owned(),
+                ))
+            }
+        }
+    }
+    if let Some(name) = &distro {
+        // validate the name before invoking WSL
+        find_distro(name)?;
+    }
+    let path = wsl::install_companion(distro.map(OsString::from))?;
+    Ok(format!(
+        "Installed the wslscript command to ~/{}.\n\n\
+         Make sure ~/.local/bin is in PATH.",
+        path
+    ))
+}
+
+/// Report versions of WSL Script, the registered drop handler and WSL.
+///
+/// With `--json`, an object with `version`, `commit`, `handler` and `wsl`
+/// is written.
+fn version(args: &[OsString]) -> Result<String, Error> {
+    let mut json = false;
+    for arg in args {
+        match arg.to_string_lossy().as_ref() {
+            "--json" => json = true,
+            "--quiet" => {}
+            _ => {
+                return Err(Error::InvalidArgumentError(
+                    arg.to_string_lossy().into_owned(),
+                ))
+            }
+        }
+    }
+    let version = env!("CARGO_PKG_VERSION");
+    let commit = env!("WSLSCRIPT_GIT_COMMIT");
+    let handler = registry::get_server_dll_path().ok();
+    let handler_version = handler.as_deref().and_then(ver::product_version);
+    let wsl_version = wsl::wsl_version();
+    if json {
+        let value = serde_json::json!({
+            "version": version,
+            "commit": commit,
+            "handler": handler.as_ref().map(|path| serde_json::json!({
+                "path": path.to_string_lossy(),
+                "version": handler_version,
+            })),
+            "wsl": wsl_version,
+        });
+        return serde_json::to_string_pretty(&value)
+            .map_err(|e| Error::GenericError(e.to_string()));
+    }
+    let mut lines = vec![format!("wslscript {} ({})", version, commit)];
+    lines.push(match &handler {
+        Some(path) => format!(
+            "Drop handler: {} ({})",
+            handler_version.as_deref().unwrap_or("unknown"),
+            path.display()
+        ),
+        None => "Drop handler: not registered".to_owned(),
+    });
+    match wsl_version {
+        Some(s) => lines.push(format!("\n{}", s.replace("\r\n", "\n"))),
+        None => lines.push("WSL: wsl.exe --version failed".to_owned()),
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Generate shell completion script.
+fn completions(args: &[OsString]) -> Result<String, Error> {
+    match args.first().and_then(|s| s.to_str()) {
+        Some("bash") => Ok(bash_completions()),
+        Some("powershell") => Ok(powershell_completions()),
+        Some(shell) => Err(Error::InvalidArgumentError(format!(
+            "Unsupported shell: {}",
+            shell
+        ))),
+        None => Err(Error::InvalidArgumentError(
+            "Shell is required: bash or powershell".to_owned(),
+        )),
+    }
+}
+
+/// Generate completion script for bash, eg. for use within WSL.
+fn bash_completions() -> String {
+    let commands = COMPLETIONS
+        .iter()
+        .map(|(cmd, _)| *cmd)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cases = COMPLETIONS
+        .iter()
+        .map(|(cmd, args)| {
+            // commands taking paths in addition to options
+            let files = if matches!(*cmd, "convert" | "copy-path" | "run") {
+                "-f "
+            } else {
+                ""
+            };
+            format!(
+                "        {}) COMPREPLY=($(compgen {}-W \"{}\" -- \"$cur\")) ;;\n",
+                cmd,
+                files,
+                args.join(" ")
+            )
+        })
+        .collect::<String>();
+    format!(
+        r#"_wslscript() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    if [ "$COMP_CWORD" -eq 1 ]; then
+        COMPREPLY=($(compgen -W "{commands}" -- "$cur"))
+        return
+    fi
+    case "$prev" in
+        --hold) COMPREPLY=($(compgen -W "{holds}" -- "$cur")); return ;;
+        --association) COMPREPLY=($(compgen -W "{associations}" -- "$cur")); return ;;
+        --ext|--distro|--icon|--type-name) return ;;
+    esac
+    case "${{COMP_WORDS[1]}}" in
+{cases}    esac
+}}
+complete -F _wslscript wslscript.exe wslscript"#,
+        commands = commands,
+        holds = HOLD_COMPLETIONS.join(" "),
+        associations = ASSOCIATION_COMPLETIONS.join(" "),
+        cases = cases,
+    )
+}
+
+/// Generate completion script for PowerShell.
+fn powershell_completions() -> String {
+    let list = |items: &[&str]| {
+        items
+            .iter()
+            .map(|s| format!("'{}'", s))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let commands = COMPLETIONS.iter().map(|(cmd, _)| *cmd).collect::<Vec<_>>();
+    let cases = COMPLETIONS
+        .iter()
+        .map(|(cmd, args)| format!("            '{}' {{ {} }}\n", cmd, list(args)))
+        .collect::<String>();
+    format!(
+        r#"Register-ArgumentCompleter -Native -CommandName wslscript, wslscript.exe -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    $count = $words.Count
+    if ($wordToComplete) {{ $count-- }}
+    $candidates = if ($count -le 1) {{
+        {commands}
+    }} elseif ($words[$count - 1] -eq '--hold') {{
+        {holds}
+    }} elseif ($words[$count - 1] -eq '--association') {{
+        {associations}
+    }} else {{
+        switch ($words[1]) {{
+{cases}        }}
+    }}
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}"#,
+        commands = list(&commands),
+        holds = list(HOLD_COMPLETIONS),
+        associations = list(ASSOCIATION_COMPLETIONS),
+        cases = cases,
+    )
+}
+
+/// Find distribution by name or GUID.
+fn find_distro(name: &str) -> Result<registry::DistroGUID, Error> {
+    let distros = registry::query_distros()?;
+    distros
+        .list
+        .iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name))
+        .map(|(guid, _)| guid.clone())
+        .or_else(|| {
+            registry::DistroGUID::from_str(name)
+                .ok()
+                .filter(|guid| distros.list.contains_key(guid))
+        })
+        .ok_or_else(|| Error::InvalidArgumentError(format!("Unknown distribution: {}", name)))
+}
+
+/// Attach to the console of the parent process, unless the standard output
+/// is already redirected.
+///
+/// Program runs in the Windows subsystem, so it has no console of its own.
+fn attach_console() {
+    use windows::Win32::System::Console;
+    let has_stdout = unsafe { Console::GetStdHandle(Console::STD_OUTPUT_HANDLE) }
+        .is_ok_and(|h| !h.is_invalid() && h.0 != 0);
+    if !has_stdout {
+        let _ = unsafe { Console::AttachConsole(Console::ATTACH_PARENT_PROCESS) };
+    }
+}
+
+/// Display an informational message.
+fn message(msg: &str) {
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::*;
+    let s = wcstring(msg);
+    unsafe {
+        MessageBoxW(
+            HWND::default(),
+            PCWSTR(s.as_ptr()),
+            w!("WSL Script"),
+            MB_OK | MB_ICONINFORMATION,
+        )
+    };
+}
@@ -0,0 +1,571 @@
+//! Output window for test runs.
+//!
+//! Script is run in a pseudo console (ConPTY) and its output is displayed
+//! in a read-only edit control, so no separate console window is needed.
+
+use crate::gui;
+use once_cell::sync::Lazy;
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::os::windows::io::FromRawHandle;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use widestring::*;
+use windows::core::{w, PCWSTR, PWSTR};
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi;
+use windows::Win32::System::Console::{ClosePseudoConsole, CreatePseudoConsole, COORD, HPCON};
+use windows::Win32::System::LibraryLoader;
+use windows::Win32::System::Pipes::CreatePipe;
+use windows::Win32::System::Threading;
+use windows::Win32::UI::Controls;
+use windows::Win32::UI::HiDpi;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use wslscript_common::error::*;
+use wslscript_common::font::Font;
+use wslscript_common::wcstring;
+use wslscript_common::win32::{self, OwnedHwnd};
+use wslscript_common::window::{self, WindowProc};
+
+/// Console window class name.
+static WND_CLASS: Lazy<WideCString> = Lazy::new(|| wcstring("WSLScriptConsole"));
+
+/// Window message signalling that output is available.
+const WM_PTY_OUTPUT: u32 = WM_USER + 1;
+/// Window message signalling that the process has exited.
+///
+/// WPARAM is the exit code.
+const WM_PTY_EXITED: u32 = WM_USER + 2;
+/// Window message signalling that all output has been read.
+const WM_PTY_CLOSED: u32 = WM_USER + 3;
+
+/// Size of the pseudo console in character cells.
+const PTY_SIZE: COORD = COORD { X: 120, Y: 30 };
+
+/// Child window identifiers.
+#[derive(PartialEq)]
+#[repr(u16)]
+enum Control {
+    Output = 100,
+}
+
+/// Initial window size.
+const WINDOW_SIZE: (i32, i32) = (720, 420);
+
+/// Height of the output font at 96 DPI.
+const FONT_SIZE: i32 = 16;
+
+/// Window displaying output of a script run in a pseudo console.
+#[derive(Default)]
+pub(crate) struct ConsoleWindow {
+    /// Window handle, destroyed before other fields are dropped.
+    hwnd: OwnedHwnd,
+    /// Font for the output.
+    font: Font,
+    /// Pseudo console session of the running script.
+    session: Option<PtySession>,
+}
+
+impl ConsoleWindow {
+    /// Create window owned by given window.
+    pub fn new(owner: HWND, title: &str) -> Result<Pin<Box<Self>>, Error> {
+        if !Self::is_window_class_registered() {
+            Self::register_window_class()?;
+        }
+        let wnd = Pin::new(Box::new(Self::default()));
+        let instance = unsafe { LibraryLoader::GetModuleHandleW(None) }?;
+        let title = wcstring(title);
+        let dpi = unsafe { HiDpi::GetDpiForWindow(owner) };
+        let (width, height) = (
+            gui::scale_for_dpi(WINDOW_SIZE.0, dpi),
+            gui::scale_for_dpi(WINDOW_SIZE.1, dpi),
+        );
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), PCWSTR(WND_CLASS.as_ptr()), PCWSTR(title.as_ptr()),
+            WS_OVERLAPPEDWINDOW | WS_VISIBLE,
+            CW_USEDEFAULT, CW_USEDEFAULT, width, height,
+            owner, None, instance, Some(&*wnd as *const Self as _)) };
+        if hwnd.0 == 0 {
+            return Err(window::take_error().unwrap_or_else(win32::last_error));
+        }
+        Ok(wnd)
+    }
+
+    /// Run command line in a pseudo console and display its output.
+    pub fn start(&mut self, cmdline: &WideCStr) -> Result<(), Error> {
+        self.session = Some(PtySession::start(self.hwnd.get(), cmdline)?);
+        Ok(())
+    }
+
+    /// Create child control windows.
+    fn create_window_controls(&mut self) -> Result<(), Error> {
+        let instance = HINSTANCE(unsafe { GetWindowLongPtrW(self.hwnd.get(), GWL_HINSTANCE) });
+        let dpi = unsafe { HiDpi::GetDpiForWindow(self.hwnd.get()) };
+        self.font = Font::new_monospace(gui::scale_for_dpi(FONT_SIZE, dpi))?;
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_MULTILINE | ES_READONLY | ES_AUTOVSCROLL) as u32)
+                | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::Output as isize), instance, None,
+        ) };
+        unsafe {
+            SendMessageW(
+                hwnd,
+                WM_SETFONT,
+                WPARAM(self.font.handle().0 as _),
+                LPARAM(TRUE.0 as _),
+            );
+            // no limit for the text length
+            SendMessageW(hwnd, Controls::EM_SETLIMITTEXT, WPARAM(0), LPARAM(0));
+        }
+        Ok(())
+    }
+
+    /// Append text to the end of the output.
+    fn append_output(&self, text: &str) {
+        let hwnd = self.get_control_handle(Control::Output);
+        let len = unsafe { GetWindowTextLengthW(hwnd) };
+        let s = wcstring(text);
+        unsafe {
+            SendMessageW(
+                hwnd,
+                Controls::EM_SETSEL,
+                WPARAM(len as _),
+                LPARAM(len as _),
+            );
+            SendMessageW(
+                hwnd,
+                Controls::EM_REPLACESEL,
+                WPARAM(FALSE.0 as _),
+                LPARAM(s.as_ptr() as _),
+            );
+        }
+    }
+
+    /// Display output received from the pseudo console.
+    fn on_output(&self) {
+        if let Some(session) = &self.session {
+            let text: String = session.output.try_iter().collect();
+            if !text.is_empty() {
+                self.append_output(&text);
+            }
+        }
+    }
+
+    /// Get window handle of given control.
+    fn get_control_handle(&self, control: Control) -> HWND {
+        unsafe { GetDlgItem(self.hwnd.get(), control as i32) }
+    }
+
+    /// Check whether window class is registered.
+    fn is_window_class_registered() -> bool {
+        unsafe {
+            let instance = LibraryLoader::GetModuleHandleW(None).unwrap_or_default();
+            let mut wc: WNDCLASSEXW = mem::zeroed();
+            GetClassInfoExW(instance, PCWSTR(WND_CLASS.as_ptr()), &mut wc).is_ok()
+        }
+    }
+
+    /// Register window class.
+    fn register_window_class() -> Result<(), Error> {
+        let instance = unsafe { LibraryLoader::GetModuleHandleW(None) }?;
+        let wc = WNDCLASSEXW {
+            cbSize: mem::size_of::<WNDCLASSEXW>() as u32,
+            style: CS_HREDRAW | CS_VREDRAW,
+            hbrBackground: Gdi::HBRUSH((Gdi::COLOR_WINDOW.0 + 1) as _),
+            lpfnWndProc: Some(window::window_proc_wrapper::<ConsoleWindow>),
+            hInstance: instance.into(),
+            lpszClassName: PCWSTR(WND_CLASS.as_ptr()),
+            hIcon: unsafe { LoadIconW(instance, w!("app")) }.unwrap_or_default(),
+            hCursor: unsafe { LoadCursorW(None, IDC_ARROW) }.unwrap_or_default(),
+            ..unsafe { mem::zeroed() }
+        };
+        if 0 == unsafe { RegisterClassExW(&wc) } {
+            Err(win32::last_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl WindowProc for ConsoleWindow {
+    fn window_proc(
+        &mut self,
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> Option<LRESULT> {
+        match msg {
+            WM_NCCREATE => {
+                self.hwnd = unsafe { OwnedHwnd::from_raw(hwnd) };
+                // WM_NCCREATE must be passed to DefWindowProc
+                None
+            }
+            WM_CREATE => match self.create_window_controls() {
+                Err(e) => {
+                    log::error!("Failed to create window controls: {}", e);
+                    Some(LRESULT(-1))
+                }
+                Ok(()) => Some(LRESULT(0)),
+            },
+            WM_SIZE => {
+                let hwnd = self.get_control_handle(Control::Output);
+                let (width, height) = (
+                    (lparam.0 & 0xffff) as i32,
+                    ((lparam.0 >> 16) & 0xffff) as i32,
+                );
+                let _ = unsafe { MoveWindow(hwnd, 0, 0, width, height, TRUE) };
+                Some(LRESULT(0))
+            }
+            WM_DPICHANGED => {
+                let dpi = u32::from((wparam.0 >> 16) as u16);
+                match Font::new_monospace(gui::scale_for_dpi(FONT_SIZE, dpi)) {
+                    Ok(font) => {
+                        self.font = font;
+                        let output = self.get_control_handle(Control::Output);
+                        let font = WPARAM(self.font.handle().0 as _);
+                        unsafe { SendMessageW(output, WM_SETFONT, font, LPARAM(TRUE.0 as _)) };
+                    }
+                    Err(e) => log::error!("Failed to create font: {}", e),
+                }
+                let rect = unsafe { &*(lparam.0 as *const RECT) };
+                let _ = unsafe {
+                    SetWindowPos(
+                        hwnd,
+                        None,
+                        rect.left,
+                        rect.top,
+                        rect.right - rect.left,
+                        rect.bottom - rect.top,
+                        SWP_NOZORDER | SWP_NOACTIVATE,
+                    )
+                };
+                Some(LRESULT(0))
+            }
+            // read-only edit control is drawn as static
+            WM_CTLCOLORSTATIC => Some(LRESULT(
+                unsafe {
+                    Gdi::GetStockObject(Gdi::GET_STOCK_OBJECT_FLAGS(Gdi::COLOR_WINDOW.0 + 1))
+                }
+                .0,
+            )),
+            WM_PTY_OUTPUT => {
+                self.on_output();
+                Some(LRESULT(0))
+            }
+            WM_PTY_EXITED => {
+                if let Some(session) = &mut self.session {
+                    session.exit_code = Some(wparam.0 as u32);
+                    // output pipe is closed once the pseudo console is closed
+                    session.close_console();
+                }
+                Some(LRESULT(0))
+            }
+            WM_PTY_CLOSED => {
+                self.on_output();
+                if let Some(code) = self.session.take().and_then(|s| s.exit_code) {
+                    self.append_output(&format!("\r\n[Process exited - exit code {}]", code));
+                }
+                Some(LRESULT(0))
+            }
+            WM_DESTROY => {
+                // terminates the process if it's still running
+                self.session.take();
+                Some(LRESULT(0))
+            }
+            WM_NCDESTROY => {
+                // window is gone, nothing left to destroy on drop
+                self.hwnd.release();
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Process running in a pseudo console.
+struct PtySession {
+    /// Pseudo console, closed when the process exits.
+    console: Option<HPCON>,
+    /// Process handle.
+    process: HANDLE,
+    /// Write end of the console input.
+    input: HANDLE,
+    /// Receiver for the console output.
+    output: mpsc::Receiver<String>,
+    /// Exit code of the process once it has exited.
+    exit_code: Option<u32>,
+    /// Threads reading the output and waiting for the process.
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl PtySession {
+    /// Start command line in a pseudo console.
+    ///
+    /// Output and process exit are signalled to `hwnd` with `WM_PTY_*` messages.
+    fn start(hwnd: HWND, cmdline: &WideCStr) -> Result<Self, Error> {
+        let (mut input_read, mut input_write) = (HANDLE::default(), HANDLE::default());
+        let (mut output_read, mut output_write) = (HANDLE::default(), HANDLE::default());
+        unsafe {
+            CreatePipe(&mut input_read, &mut input_write, None, 0)?;
+            CreatePipe(&mut output_read, &mut output_write, None, 0)?;
+        }
+        let console = unsafe { CreatePseudoConsole(PTY_SIZE, input_read, output_write, 0) };
+        // pseudo console has duplicated the handles
+        unsafe {
+            let _ = CloseHandle(input_read);
+            let _ = CloseHandle(output_write);
+        }
+        // output pipe is owned by the reader from here on
+        let output_file = unsafe { File::from_raw_handle(output_read.0 as _) };
+        let console = match console {
+            Ok(console) => console,
+            Err(e) => {
+                let _ = unsafe { CloseHandle(input_write) };
+                return Err(e.into());
+            }
+        };
+        let (tx, rx) = mpsc::channel();
+        let mut session = Self {
+            console: Some(console),
+            process: HANDLE::default(),
+            input: input_write,
+            output: rx,
+            exit_code: None,
+            threads: Vec::new(),
+        };
+        session.process = spawn_attached(console, cmdline)?;
+        let hwnd_raw = hwnd.0;
+        session.threads.push(thread::spawn(move || {
+            read_output(output_file, tx, HWND(hwnd_raw));
+        }));
+        let process_raw = session.process.0;
+        session.threads.push(thread::spawn(move || {
+            let process = HANDLE(process_raw);
+            let mut code = 0_u32;
+            unsafe {
+                Threading::WaitForSingleObject(process, Threading::INFINITE);
+                let _ = Threading::GetExitCodeProcess(process, &mut code);
+                let _ = PostMessageW(HWND(hwnd_raw), WM_PTY_EXITED, WPARAM(code as _), LPARAM(0));
+            }
+        }));
+        Ok(session)
+    }
+
+    /// Close pseudo console, which ends the output.
+    fn close_console(&mut self) {
+        if let Some(console) = self.console.take() {
+            unsafe { ClosePseudoConsole(console) };
+        }
+    }
+}
+
+impl Drop for PtySession {
+    fn drop(&mut self) {
+        if !self.process.is_invalid()
+            && unsafe { Threading::WaitForSingleObject(self.process, 0) } == WAIT_TIMEOUT
+        {
+            log::debug!("Terminating test run");
+            let _ = unsafe { Threading::TerminateProcess(self.process, 1) };
+        }
+        self.close_console();
+        for t in self.threads.drain(..) {
+            let _ = t.join();
+        }
+        unsafe {
+            let _ = CloseHandle(self.input);
+            if !self.process.is_invalid() {
+                let _ = CloseHandle(self.process);
+            }
+        }
+    }
+}
+
+/// Create process attached to a pseudo console.
+fn spawn_attached(console: HPCON, cmdline: &WideCStr) -> Result<HANDLE, Error> {
+    use Threading::*;
+    let mut size: usize = 0;
+    // query the size of the attribute list, fails by design
+    let _ = unsafe {
+        InitializeProcThreadAttributeList(LPPROC_THREAD_ATTRIBUTE_LIST::default(), 1, 0, &mut size)
+    };
+    let mut buf = vec![0_u8; size];
+    let attrs = LPPROC_THREAD_ATTRIBUTE_LIST(buf.as_mut_ptr() as _);
+    unsafe { InitializeProcThreadAttributeList(attrs, 1, 0, &mut size) }?;
+    let result = unsafe {
+        UpdateProcThreadAttribute(
+            attrs,
+            0,
+            PROC_THREAD_ATTRIBUTE_PSEUDOCONSOLE as _,
+            Some(console.0 as _),
+            mem::size_of::<HPCON>(),
+            None,
+            None,
+        )
+    }
+    .and_then(|_| {
+        let si = STARTUPINFOEXW {
+            StartupInfo: STARTUPINFOW {
+                cb: mem::size_of::<STARTUPINFOEXW>() as u32,
+                ..Default::default()
+            },
+            lpAttributeList: attrs,
+        };
+        let mut cmdline = cmdline.to_ucstring().into_vec_with_nul();
+        let mut pi = PROCESS_INFORMATION::default();
+        unsafe {
+            CreateProcessW(
+                PCWSTR::null(),
+                PWSTR(cmdline.as_mut_ptr()),
+                None,
+                None,
+                false,
+                EXTENDED_STARTUPINFO_PRESENT,
+                None,
+                PCWSTR::null(),
+                &si.StartupInfo,
+                &mut pi,
+            )
+        }
+        .map(|_| {
+            let _ = unsafe { CloseHandle(pi.hThread) };
+            pi.hProcess
+        })
+    });
+    unsafe { DeleteProcThreadAttributeList(attrs) };
+    Ok(result?)
+}
+
+/// Read pseudo console output until the console is closed.
+fn read_output(mut pipe: File, tx: mpsc::Sender<String>, hwnd: HWND) {
+    let mut filter = VtFilter::default();
+    let mut buf = [0_u8; 4096];
+    while let Ok(len) = pipe.read(&mut buf) {
+        if len == 0 {
+            break;
+        }
+        let text = filter.push(&buf[..len]);
+        if text.is_empty() {
+            continue;
+        }
+        if tx.send(text).is_err() {
+            break;
+        }
+        let _ = unsafe { PostMessageW(hwnd, WM_PTY_OUTPUT, WPARAM(0), LPARAM(0)) };
+    }
+    let _ = unsafe { PostMessageW(hwnd, WM_PTY_CLOSED, WPARAM(0), LPARAM(0)) };
+}
+
+/// State of the escape sequence parser.
+#[derive(Clone, Copy, PartialEq, Default)]
+enum VtState {
+    #[default]
+    Text,
+    /// After ESC.
+    Escape,
+    /// Character set designation after `ESC (` or `ESC )`.
+    Charset,
+    /// Control sequence after `ESC [`.
+    Csi,
+    /// Operating system command after `ESC ]`.
+    Osc,
+    /// ESC within operating system command.
+    OscEscape,
+}
+
+/// Upper bound for spaces emitted by a single cursor forward sequence.
+const MAX_CURSOR_FORWARD: usize = 512;
+
+/// Filter that converts pseudo console output to plain text.
+///
+/// Escape sequences are dropped, except cursor forward which the pseudo
+/// console uses in place of runs of spaces.
+#[derive(Default)]
+struct VtFilter {
+    state: VtState,
+    /// Parameters of the current control sequence.
+    params: String,
+    /// Bytes of an incomplete UTF-8 sequence.
+    pending: Vec<u8>,
+}
+
+impl VtFilter {
+    /// Push output bytes and get the text to display.
+    fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        // leave incomplete UTF-8 sequence at the end for the next push
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        let bytes: Vec<u8> = self.pending.drain(..valid).collect();
+        let mut out = String::new();
+        for c in String::from_utf8_lossy(&bytes).chars() {
+            self.feed(c, &mut out);
+        }
+        out
+    }
+
+    fn feed(&mut self, c: char, out: &mut String) {
+        use VtState::*;
+        self.state = match self.state {
+            Text => match c {
+                '\x1b' => Escape,
+                // edit control requires CRLF line breaks
+                '\n' => {
+                    out.push_str("\r\n");
+                    Text
+                }
+                '\t' => {
+                    out.push(c);
+                    Text
+                }
+                c if c.is_control() => Text,
+                c => {
+                    out.push(c);
+                    Text
+                }
+            },
+            Escape => match c {
+                '[' => {
+                    self.params.clear();
+                    Csi
+                }
+                ']' => Osc,
+                '(' | ')' => Charset,
+                _ => Text,
+            },
+            Charset => Text,
+            Csi => match c {
+                '\x40'..='\x7e' => {
+                    if c == 'C' {
+                        let n = self
+                            .params
+                            .parse::<usize>()
+                            .unwrap_or(1)
+                            .min(MAX_CURSOR_FORWARD);
+                        out.extend(std::iter::repeat_n(' ', n));
+                    }
+                    Text
+                }
+                _ => {
+                    self.params.push(c);
+                    Csi
+                }
+            },
+            Osc => match c {
+                '\x07' => Text,
+                '\x1b' => OscEscape,
+                _ => Osc,
+            },
+            OscEscape => Text,
+        };
+    }
+}
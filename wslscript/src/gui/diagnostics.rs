@@ -0,0 +1,201 @@
+//! Diagnostics report for issue reports.
+//!
+//! Report is formatted in Markdown, so that it can be pasted
+//! into a GitHub issue as is.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use windows::Win32::Foundation::*;
+use windows::Win32::System::DataExchange;
+use windows::Win32::System::Memory;
+use windows::Win32::System::Ole::CF_UNICODETEXT;
+use wslscript_common::error::*;
+use wslscript_common::probe::DistroFacts;
+use wslscript_common::{audit, registry, ver, wsl};
+
+/// Number of lines included from the end of each log file.
+const LOG_LINES: usize = 100;
+
+/// Gather diagnostics report.
+pub(crate) fn report() -> String {
+    let mut s = String::from("## WSL Script diagnostics\n\n");
+    let exe = std::env::current_exe().ok();
+    let version = exe.as_deref().and_then(ver::product_version);
+    let _ = writeln!(s, "- Version: {}", version.as_deref().unwrap_or("unknown"));
+    if let Some(exe) = &exe {
+        let _ = writeln!(s, "- Executable: `{}`", exe.display());
+    }
+    let _ = writeln!(
+        s,
+        "- Verify signatures: {}",
+        yes_no(registry::verify_signatures())
+    );
+    let _ = writeln!(
+        s,
+        "- Drop handler CLSID: `{}`",
+        registry::drop_handler_clsid()
+    );
+    s.push_str("\n### WSL\n\n");
+    match wsl::wsl_version() {
+        Some(version) => {
+            let _ = writeln!(s, "```\n{}\n```\n", version.replace("\r\n", "\n"));
+        }
+        None => s.push_str("`wsl.exe --version` failed.\n\n"),
+    }
+    write_distros(&mut s);
+    s.push_str("\n### Registered extensions\n\n");
+    write_extensions(&mut s);
+    s.push_str("\n### Logs\n");
+    let mut logs: Vec<PathBuf> = Vec::new();
+    // debug builds log next to the binaries
+    if let Some(exe) = &exe {
+        if let (Some(dir), Some(stem)) = (exe.parent(), exe.file_stem()) {
+            logs.push(dir.join(format!("{}.log", stem.to_string_lossy())));
+            logs.push(dir.join("wslscript_handler.log"));
+        }
+    }
+    logs.extend(audit::log_path());
+    for path in logs {
+        if let Some(lines) = tail(&path, LOG_LINES) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let _ = writeln!(s, "\n`{}`\n\n```\n{}\n```", name, lines.join("\n"));
+        }
+    }
+    s
+}
+
+/// Write list of WSL distributions and their probed facts.
+fn write_distros(s: &mut String) {
+    let distros = match registry::query_distros() {
+        Ok(distros) => distros,
+        Err(e) => {
+            let _ = writeln!(s, "Failed to query distributions: {}", e);
+            return;
+        }
+    };
+    s.push_str(
+        "| Distribution | Default | Bash | systemd | User | Mount root | Interop | Windows PATH |\n",
+    );
+    s.push_str("| --- | --- | --- | --- | --- | --- | --- | --- |\n");
+    for (guid, name) in distros.sorted_pairs() {
+        let facts = DistroFacts::load(Some(std::ffi::OsStr::new(name)));
+        let default = distros.default.as_ref() == Some(guid);
+        let _ = writeln!(
+            s,
+            "| {} | {} | {} | {} | {} | {} | {} | {} |",
+            name,
+            yes_no(default),
+            facts.as_ref().map_or("?", |f| yes_no(f.has_bash)),
+            facts.as_ref().map_or("?", |f| yes_no(f.systemd)),
+            facts
+                .as_ref()
+                .and_then(|f| f.default_user.as_deref())
+                .unwrap_or("?"),
+            mount_root(name, facts.as_ref()),
+            facts.as_ref().map_or("?", |f| yes_no(f.interop)),
+            facts
+                .as_ref()
+                .map_or("?", |f| yes_no(f.append_windows_path)),
+        );
+    }
+}
+
+/// Format the mount root of a distribution, noting whether it was set
+/// in the settings.
+///
+/// Distributions aren't probed for the report, so the root is unknown
+/// for those never probed.
+fn mount_root(name: &str, facts: Option<&DistroFacts>) -> String {
+    match registry::distro_mount_root(Some(std::ffi::OsStr::new(name))) {
+        Some(root) if !wsl::is_valid_mount_root(&root) => format!("`{}` (set, unsafe)", root),
+        Some(root) => format!("`{}` (set)", root),
+        None => facts
+            .and_then(|f| f.mount_root.as_deref())
+            .map_or_else(|| "?".to_string(), |root| format!("`{}`", root)),
+    }
+}
+
+/// Write registered extensions with their commands.
+fn write_extensions(s: &mut String) {
+    let exts = match registry::query_registered_extensions() {
+        Ok(exts) => exts,
+        Err(e) => {
+            let _ = writeln!(s, "Failed to query extensions: {}", e);
+            return;
+        }
+    };
+    if exts.is_empty() {
+        s.push_str("None.\n");
+    }
+    for ext in exts {
+        let _ = writeln!(s, "- `.{}`", ext);
+        match registry::get_open_command(&ext) {
+            Ok(cmd) => {
+                let _ = writeln!(s, "  - Open: `{}`", cmd);
+            }
+            Err(e) => {
+                let _ = writeln!(s, "  - Open: {}", e);
+            }
+        }
+        let Some(opts) = wsl::WSLOptions::from_ext(&ext) else {
+            continue;
+        };
+        let script = super::sample_script_path(&ext);
+        match wsl::preview_command_line(&script, &opts) {
+            Ok(cmd) => {
+                let _ = writeln!(s, "  - Run: `{}`", cmd.to_string_lossy());
+            }
+            Err(e) => {
+                let _ = writeln!(s, "  - Run: {}", e);
+            }
+        }
+    }
+}
+
+/// Format flag for the report.
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Read last `count` lines of a text file.
+fn tail(path: &Path, count: usize) -> Option<Vec<String>> {
+    let text = std::fs::read(path).ok()?;
+    let text = String::from_utf8_lossy(&text);
+    let lines = text.lines().collect::<Vec<_>>();
+    let start = lines.len().saturating_sub(count);
+    Some(lines[start..].iter().map(|l| l.to_string()).collect())
+}
+
+/// Copy text to clipboard.
+pub(crate) fn copy_to_clipboard(hwnd: HWND, text: &str) -> Result<(), Error> {
+    let wide = text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let size = wide.len() * std::mem::size_of::<u16>();
+    unsafe { DataExchange::OpenClipboard(hwnd) }
+        .map_err(|e| Error::WinAPIError(format!("OpenClipboard: {}", e)))?;
+    let result = (|| unsafe {
+        DataExchange::EmptyClipboard()
+            .map_err(|e| Error::WinAPIError(format!("EmptyClipboard: {}", e)))?;
+        let mem = Memory::GlobalAlloc(Memory::GMEM_MOVEABLE, size)
+            .map_err(|e| Error::WinAPIError(format!("GlobalAlloc: {}", e)))?;
+        let ptr = Memory::GlobalLock(mem) as *mut u16;
+        if ptr.is_null() {
+            let _ = GlobalFree(mem);
+            return Err(Error::WinAPIError("GlobalLock failed.".to_string()));
+        }
+        std::ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len());
+        let _ = Memory::GlobalUnlock(mem);
+        // clipboard owns the memory on success
+        if let Err(e) = DataExchange::SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(mem.0 as _))
+        {
+            let _ = GlobalFree(mem);
+            return Err(Error::WinAPIError(format!("SetClipboardData: {}", e)));
+        }
+        Ok(())
+    })();
+    let _ = unsafe { DataExchange::CloseClipboard() };
+    result
+}
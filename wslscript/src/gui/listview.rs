@@ -1,75 +1,165 @@
 use crate::gui;
 use std::mem;
-use std::ptr;
 use wchar::*;
 use widestring::*;
-use winapi::shared::ntdef;
-use winapi::shared::windef;
-use winapi::um::commctrl;
-use winapi::um::libloaderapi;
-use winapi::um::winuser;
+use windows::core::PWSTR;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::LibraryLoader;
+use windows::Win32::UI::Controls;
+use windows::Win32::UI::HiDpi;
+use windows::Win32::UI::WindowsAndMessaging as wm;
+use wslscript_common::icon::ShellIcon;
 use wslscript_common::registry;
 use wslscript_common::wcstring;
 use wslscript_common::win32;
 
-pub(crate) struct ExtensionsListView {
-    hwnd: windef::HWND,
+/// Widths of the columns at 96 DPI.
+const COLUMN_WIDTHS: [i32; 2] = [80, 130];
+
+/// Grouping of the listview items.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub(crate) enum Grouping {
+    #[default]
+    None,
+    /// Group by WSL distribution.
+    Distro,
+    /// Group by user-defined category.
+    Category,
 }
 
-impl Default for ExtensionsListView {
-    fn default() -> Self {
-        Self {
-            hwnd: ptr::null_mut(),
-        }
-    }
+#[derive(Default)]
+pub(crate) struct ExtensionsListView {
+    hwnd: HWND,
+    /// Small icons of the items.
+    ///
+    /// Owned by the listview and destroyed along with it.
+    images: Controls::HIMAGELIST,
 }
 
 impl ExtensionsListView {
     pub fn create(main: &gui::MainWindow) -> Self {
-        use commctrl::*;
-        use winuser::*;
+        use wm::*;
+        use Controls::*;
+        let instance = unsafe { LibraryLoader::GetModuleHandleW(None) }.unwrap_or_default();
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            LVS_EX_FULLROWSELECT | LVS_EX_GRIDLINES,
-            wcstring(WC_LISTVIEW).as_ptr(), ptr::null_mut(),
-            WS_CHILD | WS_VISIBLE | WS_BORDER | LVS_REPORT | LVS_SINGLESEL | LVS_SHOWSELALWAYS,
-            0, 0, 0, 0, main.hwnd,
-            gui::Control::ListViewExtensions as u16 as _,
-            libloaderapi::GetModuleHandleW(ptr::null_mut()), ptr::null_mut(),
+            WINDOW_EX_STYLE(LVS_EX_FULLROWSELECT | LVS_EX_GRIDLINES),
+            WC_LISTVIEW, None,
+            WS_CHILD | WS_VISIBLE | WS_BORDER
+                | WINDOW_STYLE(LVS_REPORT | LVS_SHOWSELALWAYS),
+            0, 0, 0, 0, main.hwnd.get(),
+            HMENU(gui::Control::ListViewExtensions as isize),
+            instance, None,
         ) };
-        let lv = Self { hwnd };
+        let dpi = unsafe { HiDpi::GetDpiForWindow(hwnd) };
+        let images = unsafe {
+            ImageList_Create(
+                HiDpi::GetSystemMetricsForDpi(SM_CXSMICON, dpi),
+                HiDpi::GetSystemMetricsForDpi(SM_CYSMICON, dpi),
+                ILC_COLOR32 | ILC_MASK,
+                8,
+                8,
+            )
+        };
+        unsafe {
+            SendMessageW(
+                hwnd,
+                LVM_SETIMAGELIST,
+                WPARAM(LVSIL_SMALL as _),
+                LPARAM(images.0),
+            )
+        };
+        let lv = Self { hwnd, images };
         gui::set_window_font(hwnd, &main.caption_font);
         unsafe {
             SendMessageW(
                 hwnd,
                 LVM_SETEXTENDEDLISTVIEWSTYLE,
-                LVS_EX_FULLROWSELECT as _,
-                LVS_EX_FULLROWSELECT as _,
+                WPARAM((LVS_EX_FULLROWSELECT | LVS_EX_INFOTIP) as _),
+                LPARAM((LVS_EX_FULLROWSELECT | LVS_EX_INFOTIP) as _),
             )
         };
         // insert columns
-        let mut col = LV_COLUMNW {
+        let mut col = LVCOLUMNW {
             mask: LVCF_FMT | LVCF_WIDTH | LVCF_TEXT,
             fmt: LVCFMT_LEFT,
-            cx: 80,
-            pszText: wchz!("Filetype").as_ptr() as _,
+            cx: COLUMN_WIDTHS[0],
+            pszText: PWSTR(wchz!("Filetype").as_ptr() as _),
             ..unsafe { mem::zeroed() }
         };
-        unsafe { SendMessageW(hwnd, LVM_INSERTCOLUMNW, 0, &col as *const _ as _) };
-        col.pszText = wchz!("Distribution").as_ptr() as _;
-        col.cx = 130;
-        unsafe { SendMessageW(hwnd, LVM_INSERTCOLUMNW, 1, &col as *const _ as _) };
-        // insert items
+        unsafe {
+            SendMessageW(
+                hwnd,
+                LVM_INSERTCOLUMNW,
+                WPARAM(0),
+                LPARAM(&col as *const _ as _),
+            )
+        };
+        col.pszText = PWSTR(wchz!("Distribution").as_ptr() as _);
+        col.cx = COLUMN_WIDTHS[1];
+        unsafe {
+            SendMessageW(
+                hwnd,
+                LVM_INSERTCOLUMNW,
+                WPARAM(1),
+                LPARAM(&col as *const _ as _),
+            )
+        };
+        lv.set_dpi(dpi);
+        lv.populate(main, "", None);
+        lv
+    }
+
+    /// Scale column widths to given DPI.
+    pub fn set_dpi(&self, dpi: u32) {
+        for (col, width) in COLUMN_WIDTHS.iter().enumerate() {
+            unsafe {
+                wm::SendMessageW(
+                    self.hwnd,
+                    Controls::LVM_SETCOLUMNWIDTH,
+                    WPARAM(col),
+                    LPARAM(gui::scale_for_dpi(*width, dpi) as _),
+                )
+            };
+        }
+    }
+
+    /// Fill listview with registered extensions.
+    ///
+    /// Only extensions whose name or distribution label contains `filter`
+    /// are listed. Matching is case-insensitive.
+    /// Extension `keep` is listed regardless of the filter.
+    pub fn populate(&self, main: &gui::MainWindow, filter: &str, keep: Option<&str>) {
+        unsafe {
+            wm::SendMessageW(
+                self.hwnd,
+                Controls::LVM_DELETEALLITEMS,
+                WPARAM(0),
+                LPARAM(0),
+            )
+        };
+        let _ = unsafe { Controls::ImageList_Remove(self.images, -1) };
+        let filter = filter.trim().to_lowercase();
         match registry::query_registered_extensions().map(|exts| {
             exts.iter()
                 .filter_map(|ext| registry::get_extension_config(ext).ok())
                 .collect::<Vec<_>>()
         }) {
             Ok(configs) => {
-                for (i, cfg) in configs.iter().enumerate() {
-                    if let Some(item) = lv.insert_item(i, &wcstring(&cfg.extension)) {
-                        let name = main.get_distro_label(cfg.distro.as_ref());
-                        lv.set_subitem_text(item, 1, &wcstring(name));
+                let mut i = 0;
+                for cfg in configs.iter() {
+                    let name = main.get_distro_label(cfg.distro.as_ref());
+                    if !filter.is_empty()
+                        && keep != Some(cfg.extension.as_str())
+                        && !cfg.extension.to_lowercase().contains(&filter)
+                        && !name.to_lowercase().contains(&filter)
+                    {
+                        continue;
+                    }
+                    let label = wcstring(&cfg.extension);
+                    if let Some(item) = self.insert_item(i, &label, cfg.icon.as_ref()) {
+                        self.set_subitem_text(item, 1, &wcstring(name));
+                        i += 1;
                     }
                 }
             }
@@ -78,7 +168,6 @@ impl ExtensionsListView {
                 win32::error_message(&s);
             }
         }
-        lv
     }
 
     /// Insert item to listview.
@@ -87,30 +176,69 @@ impl ExtensionsListView {
     ///
     /// * `idx` - Index at which the the new item is inserted
     /// * `label` - Item label
-    pub fn insert_item(&self, idx: usize, label: &WideCStr) -> Option<usize> {
-        let lvi = commctrl::LV_ITEMW {
-            mask: commctrl::LVIF_TEXT,
+    /// * `icon` - Item icon
+    pub fn insert_item(
+        &self,
+        idx: usize,
+        label: &WideCStr,
+        icon: Option<&ShellIcon>,
+    ) -> Option<usize> {
+        let lvi = Controls::LVITEMW {
+            mask: Controls::LVIF_TEXT | Controls::LVIF_IMAGE,
             iItem: idx as _,
-            pszText: label.as_ptr() as _,
+            pszText: PWSTR(label.as_ptr() as _),
+            iImage: self.add_image(icon),
             ..unsafe { mem::zeroed() }
         };
         let rv = unsafe {
-            winuser::SendMessageW(
+            wm::SendMessageW(
                 self.hwnd,
-                commctrl::LVM_INSERTITEMW,
-                0,
-                &lvi as *const _ as _,
+                Controls::LVM_INSERTITEMW,
+                WPARAM(0),
+                LPARAM(&lvi as *const _ as _),
             )
-        };
+        }
+        .0;
         match rv {
             -1 => None,
             _ => Some(rv as usize),
         }
     }
 
+    /// Set icon of an item.
+    pub fn set_item_icon(&self, idx: usize, icon: Option<&ShellIcon>) {
+        let lvi = Controls::LVITEMW {
+            mask: Controls::LVIF_IMAGE,
+            iItem: idx as _,
+            iImage: self.add_image(icon),
+            ..unsafe { mem::zeroed() }
+        };
+        unsafe {
+            wm::SendMessageW(
+                self.hwnd,
+                Controls::LVM_SETITEMW,
+                WPARAM(0),
+                LPARAM(&lvi as *const _ as _),
+            )
+        };
+    }
+
+    /// Add small icon to the image list.
+    ///
+    /// Returns the image index, or `I_IMAGENONE` if there's no icon.
+    fn add_image(&self, icon: Option<&ShellIcon>) -> i32 {
+        // image list copies the icon
+        match icon.map(|icon| unsafe {
+            Controls::ImageList_ReplaceIcon(self.images, -1, icon.small_handle())
+        }) {
+            Some(i) if i >= 0 => i,
+            _ => Controls::I_IMAGENONE,
+        }
+    }
+
     /// Delete item from listview.
     pub fn delete_item(&self, idx: usize) {
-        unsafe { winuser::SendMessageW(self.hwnd, commctrl::LVM_DELETEITEM, idx, 0) };
+        unsafe { wm::SendMessageW(self.hwnd, Controls::LVM_DELETEITEM, WPARAM(idx), LPARAM(0)) };
     }
 
     /// Set text to subitem.
@@ -119,15 +247,20 @@ impl ExtensionsListView {
     /// * `sub_idx` - Subitem index
     /// * `label` - Text to insert
     pub fn set_subitem_text(&self, idx: usize, sub_idx: usize, label: &WideCStr) {
-        let lvi = commctrl::LV_ITEMW {
-            mask: commctrl::LVIF_TEXT,
+        let lvi = Controls::LVITEMW {
+            mask: Controls::LVIF_TEXT,
             iItem: idx as _,
             iSubItem: sub_idx as _,
-            pszText: label.as_ptr() as _,
+            pszText: PWSTR(label.as_ptr() as _),
             ..unsafe { mem::zeroed() }
         };
         unsafe {
-            winuser::SendMessageW(self.hwnd, commctrl::LVM_SETITEMW, 0, &lvi as *const _ as _)
+            wm::SendMessageW(
+                self.hwnd,
+                Controls::LVM_SETITEMW,
+                WPARAM(0),
+                LPARAM(&lvi as *const _ as _),
+            )
         };
     }
 
@@ -136,41 +269,124 @@ impl ExtensionsListView {
     /// Returns listview index or None if extension wasn't found.
     pub fn find_ext(&self, ext: &str) -> Option<usize> {
         let s = wcstring(ext);
-        let lvf = commctrl::LVFINDINFOW {
-            flags: commctrl::LVFI_STRING,
-            psz: s.as_ptr(),
+        let lvf = Controls::LVFINDINFOW {
+            flags: Controls::LVFI_STRING,
+            psz: windows::core::PCWSTR(s.as_ptr()),
             ..unsafe { mem::zeroed() }
         };
         let idx = unsafe {
-            winuser::SendMessageW(
+            wm::SendMessageW(
                 self.hwnd,
-                commctrl::LVM_FINDITEMW,
-                -1_isize as usize,
-                &lvf as *const _ as _,
+                Controls::LVM_FINDITEMW,
+                WPARAM(-1_isize as usize),
+                LPARAM(&lvf as *const _ as _),
             )
-        };
+        }
+        .0;
         match idx {
             -1 => None,
             _ => Some(idx as usize),
         }
     }
 
+    /// Get number of items in the listview.
+    pub fn item_count(&self) -> usize {
+        unsafe { wm::SendMessageW(self.hwnd, Controls::LVM_GETITEMCOUNT, WPARAM(0), LPARAM(0)) }.0
+            as usize
+    }
+
+    /// Get indices of the selected items.
+    pub fn selected_items(&self) -> Vec<usize> {
+        let mut items = Vec::new();
+        let mut idx: isize = -1;
+        loop {
+            idx = unsafe {
+                wm::SendMessageW(
+                    self.hwnd,
+                    Controls::LVM_GETNEXTITEM,
+                    WPARAM(idx as usize),
+                    LPARAM(Controls::LVNI_SELECTED as _),
+                )
+            }
+            .0;
+            if idx < 0 {
+                break;
+            }
+            items.push(idx as usize);
+        }
+        items
+    }
+
+    /// Arrange items into collapsible groups.
+    ///
+    /// `groups` is a list of group headers and the indices of the items in
+    /// each group. Group view is disabled if the list is empty.
+    pub fn set_groups(&self, groups: &[(String, Vec<usize>)]) {
+        use Controls::*;
+        unsafe { wm::SendMessageW(self.hwnd, LVM_REMOVEALLGROUPS, WPARAM(0), LPARAM(0)) };
+        for (id, (header, items)) in groups.iter().enumerate() {
+            let header = wcstring(header);
+            let group = LVGROUP {
+                cbSize: mem::size_of::<LVGROUP>() as _,
+                mask: LVGF_HEADER | LVGF_GROUPID | LVGF_STATE,
+                pszHeader: PWSTR(header.as_ptr() as _),
+                iGroupId: id as _,
+                stateMask: LVGS_COLLAPSIBLE,
+                state: LVGS_COLLAPSIBLE,
+                ..unsafe { mem::zeroed() }
+            };
+            unsafe {
+                wm::SendMessageW(
+                    self.hwnd,
+                    LVM_INSERTGROUP,
+                    WPARAM(-1_isize as usize),
+                    LPARAM(&group as *const _ as _),
+                )
+            };
+            for idx in items {
+                let lvi = LVITEMW {
+                    mask: LVIF_GROUPID,
+                    iItem: *idx as _,
+                    iGroupId: id as _,
+                    ..unsafe { mem::zeroed() }
+                };
+                unsafe {
+                    wm::SendMessageW(
+                        self.hwnd,
+                        LVM_SETITEMW,
+                        WPARAM(0),
+                        LPARAM(&lvi as *const _ as _),
+                    )
+                };
+            }
+        }
+        let enable = !groups.is_empty();
+        unsafe {
+            wm::SendMessageW(
+                self.hwnd,
+                LVM_ENABLEGROUPVIEW,
+                WPARAM(enable as _),
+                LPARAM(0),
+            )
+        };
+    }
+
     /// Get listview text by index.
     pub fn get_item_text(&self, idx: usize) -> Option<String> {
-        let mut buf: Vec<ntdef::WCHAR> = Vec::with_capacity(32);
-        let lvi = commctrl::LV_ITEMW {
-            pszText: buf.as_mut_ptr(),
+        let mut buf: Vec<u16> = Vec::with_capacity(32);
+        let lvi = Controls::LVITEMW {
+            pszText: PWSTR(buf.as_mut_ptr()),
             cchTextMax: buf.capacity() as _,
             ..unsafe { mem::zeroed() }
         };
         unsafe {
-            let len = winuser::SendMessageW(
+            let len = wm::SendMessageW(
                 self.hwnd,
-                commctrl::LVM_GETITEMTEXTW,
-                idx,
-                &lvi as *const _ as _,
+                Controls::LVM_GETITEMTEXTW,
+                WPARAM(idx),
+                LPARAM(&lvi as *const _ as _),
             );
-            buf.set_len(len as usize);
+            buf.set_len(len.0 as usize);
         };
         WideCString::from_vec(buf).ok().map(|u| u.to_string_lossy())
     }
@@ -2,90 +2,59 @@ use num_enum::{IntoPrimitive, TryFromPrimitive};
 use once_cell::sync::Lazy;
 use std::mem;
 use std::pin::Pin;
-use std::ptr;
 use std::str::FromStr;
 use wchar::*;
 use widestring::*;
-use winapi::shared::basetsd;
-use winapi::shared::minwindef as win;
-use winapi::shared::ntdef;
-use winapi::shared::windef;
-use winapi::um::commctrl;
-use winapi::um::errhandlingapi;
-use winapi::um::libloaderapi;
-use winapi::um::wingdi;
-use winapi::um::winuser::*;
+use windows::core::{w, PCWSTR, PWSTR};
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi;
+use windows::Win32::System::LibraryLoader;
+use windows::Win32::System::SystemServices::*;
+use windows::Win32::UI::Controls;
+use windows::Win32::UI::HiDpi;
+use windows::Win32::UI::Input::KeyboardAndMouse::{EnableWindow, VIRTUAL_KEY, VK_RETURN};
+use windows::Win32::UI::Shell;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use wslscript_common::elevate;
 use wslscript_common::error::*;
 use wslscript_common::font::Font;
 use wslscript_common::icon::ShellIcon;
 use wslscript_common::registry;
-use wslscript_common::win32;
-use wslscript_common::{wcstr, wcstring};
+use wslscript_common::win32::{self, OwnedHmenu, OwnedHwnd};
+use wslscript_common::window::{self, MessageLoop, WindowProc};
+use wslscript_common::{wcstr, wcstring, wsl};
 
+mod console;
+mod diagnostics;
 mod listview;
 
+pub(crate) use diagnostics::copy_to_clipboard;
+
 /// Default extension to register.
 static DEFAULT_EXTENSION: Lazy<WideCString> = Lazy::new(|| wcstring("sh"));
 
 /// Start WSL Script GUI app.
 pub fn start_gui() -> Result<(), Error> {
+    // extensions registered by earlier versions may not be normalized
+    if let Err(e) = registry::migrate_extension_names() {
+        log::error!("Failed to migrate extension names: {}", e);
+    }
     let wnd = MainWindow::new(wcstr(wchz!("WSL Script")))?;
     wnd.run()
 }
 
-pub trait WindowProc {
-    /// Window procedure callback.
-    ///
-    /// If None is returned, underlying wrapper calls `DefWindowProcW`.
-    fn window_proc(
-        &mut self,
-        hwnd: windef::HWND,
-        msg: win::UINT,
-        wparam: win::WPARAM,
-        lparam: win::LPARAM,
-    ) -> Option<win::LRESULT>;
-}
-
-/// Window procedure wrapper that stores struct pointer to window attributes.
-///
-/// Proxies messages to `window_proc()` with *self*.
-extern "system" fn window_proc_wrapper<T: WindowProc>(
-    hwnd: windef::HWND,
-    msg: win::UINT,
-    wparam: win::WPARAM,
-    lparam: win::LPARAM,
-) -> win::LRESULT {
-    // get pointer to T from userdata
-    let mut ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut T;
-    // not yet set, initialize from CREATESTRUCT
-    if ptr.is_null() && msg == WM_NCCREATE {
-        let cs = unsafe { &*(lparam as LPCREATESTRUCTW) };
-        ptr = cs.lpCreateParams as *mut T;
-        unsafe { errhandlingapi::SetLastError(0) };
-        if 0 == unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, ptr as *const _ as _) }
-            && unsafe { errhandlingapi::GetLastError() } != 0
-        {
-            return win::FALSE as _;
-        }
-    }
-    // call wrapped window proc
-    if !ptr.is_null() {
-        let this = unsafe { &mut *ptr };
-        if let Some(result) = this.window_proc(hwnd, msg, wparam, lparam) {
-            return result;
-        }
-    }
-    unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
-}
-
 /// Main window.
 pub(crate) struct MainWindow {
     /// Main window handle.
-    hwnd: windef::HWND,
+    ///
+    /// Declared first so that the window is destroyed before other fields are dropped.
+    hwnd: OwnedHwnd,
     /// Font for captions.
     caption_font: Font,
     /// Font for filetype extension.
     ext_font: Font,
+    /// DPI of the display the window is on.
+    dpi: u32,
     /// Currently selected extension index in the listview.
     current_ext_idx: Option<usize>,
     /// Configuration of the currently selected extension.
@@ -96,19 +65,48 @@ pub(crate) struct MainWindow {
     lv_extensions: listview::ExtensionsListView,
     /// Message to display on GUI.
     message: Option<String>,
+    /// Output window of the latest test run.
+    console: Option<Pin<Box<console::ConsoleWindow>>>,
+    /// Script of the latest test run.
+    test_script: Option<std::path::PathBuf>,
+    /// Grouping of the extensions listview.
+    grouping: listview::Grouping,
+    /// Whether the current configuration has unsaved changes.
+    dirty: bool,
+    /// Extension whose handler signatures were last verified, and the result.
+    signature_check: std::cell::RefCell<Option<(String, bool)>>,
+    /// Last non-blocking error shown in the banner area.
+    error: std::cell::RefCell<Option<ErrorBanner>>,
+}
+
+/// Error shown in the banner area.
+struct ErrorBanner {
+    /// One line summary of the error.
+    summary: String,
+    /// Detailed description of the error.
+    details: String,
+    /// Whether details are shown instead of the summary.
+    expanded: bool,
 }
 
 impl Default for MainWindow {
     fn default() -> Self {
         Self {
-            hwnd: ptr::null_mut(),
+            hwnd: OwnedHwnd::default(),
             caption_font: Default::default(),
             ext_font: Default::default(),
+            dpi: DEFAULT_DPI,
             current_ext_idx: None,
             current_ext_cfg: None,
             distros: registry::query_distros().unwrap_or_else(|_| registry::Distros::default()),
             lv_extensions: Default::default(),
             message: None,
+            console: None,
+            test_script: None,
+            grouping: listview::Grouping::default(),
+            dirty: false,
+            signature_check: Default::default(),
+            error: Default::default(),
         }
     }
 }
@@ -131,6 +129,8 @@ pub(crate) enum Control {
     StaticIcon,
     /// Label for icon.
     IconLabel,
+    /// Input for icon location as path and index.
+    IconPathEdit,
     /// Combo box for hold mode.
     HoldModeCombo,
     /// Label for hold mode.
@@ -145,6 +145,134 @@ pub(crate) enum Control {
     DistroLabel,
     /// Save button.
     BtnSave,
+    /// Combo box for CRLF mode.
+    CrlfModeCombo,
+    /// Label for CRLF mode.
+    CrlfModeLabel,
+    /// Checkbox for raw arguments.
+    RawArgsCheckbox,
+    /// Label for raw arguments checkbox.
+    RawArgsLabel,
+    /// Checkbox for opening the console at drop point.
+    ConsoleAtDropCheckbox,
+    /// Label for console at drop point checkbox.
+    ConsoleAtDropLabel,
+    /// Label for console title.
+    ConsoleTitleLabel,
+    /// Input for console title.
+    ConsoleTitleEdit,
+    /// Checkbox for UTF-8 console.
+    Utf8ConsoleCheckbox,
+    /// Label for UTF-8 console checkbox.
+    Utf8ConsoleLabel,
+    /// Label for console size.
+    ConsoleSizeLabel,
+    /// Input for console size.
+    ConsoleSizeEdit,
+    /// Label for console colors.
+    ConsoleColorsLabel,
+    /// Input for console colors.
+    ConsoleColorsEdit,
+    /// Checkbox for reusing the console window.
+    ReuseConsoleCheckbox,
+    /// Label for reuse console checkbox.
+    ReuseConsoleLabel,
+    /// Button to run a script with output in the GUI.
+    BtnTestRun,
+    /// Label for category.
+    CategoryLabel,
+    /// Input for category.
+    CategoryEdit,
+    /// Input for filtering extensions listview.
+    FilterEdit,
+    /// Label for exit message.
+    HoldMessageLabel,
+    /// Input for exit message.
+    HoldMessageEdit,
+    /// Label for key to wait for on exit.
+    HoldKeyLabel,
+    /// Combo box for key to wait for on exit.
+    HoldKeyCombo,
+    /// Input for timeout when waiting for a key on exit.
+    HoldTimeoutEdit,
+    /// Label for multiple files policy.
+    DropPolicyLabel,
+    /// Combo box for multiple files policy.
+    DropPolicyCombo,
+    /// Input for concurrent runs limit or batch size.
+    DropCountEdit,
+    /// Label for order of dropped files.
+    ArgOrderLabel,
+    /// Combo box for order of dropped files.
+    ArgOrderCombo,
+    /// Checkbox for aborting when dropped files are missing.
+    AbortOnMissingCheckbox,
+    /// Label for abort on missing files checkbox.
+    AbortOnMissingLabel,
+    /// Label for the number of files requiring confirmation.
+    MaxArgsLabel,
+    /// Input for the number of files requiring confirmation.
+    MaxArgsEdit,
+    /// Label for minimum interval between runs.
+    MinIntervalLabel,
+    /// Input for minimum interval between runs.
+    MinIntervalEdit,
+    /// Label for maximum number of running scripts.
+    MaxInstancesLabel,
+    /// Input for maximum number of running scripts.
+    MaxInstancesEdit,
+    /// Checkbox for showing progress windows from the drop handler.
+    ShowProgressCheckbox,
+    /// Label for show progress checkbox.
+    ShowProgressLabel,
+    /// Checkbox for running the script as a single instance.
+    SingleInstanceCheckbox,
+    /// Label for single instance checkbox.
+    SingleInstanceLabel,
+    /// Checkbox for using the Linux side helper script.
+    UseHelperCheckbox,
+    /// Label for use helper checkbox.
+    UseHelperLabel,
+    /// Label for Event Log mode.
+    EventLogLabel,
+    /// Combo box for Event Log mode.
+    EventLogCombo,
+    /// Label for output log age limit.
+    LogMaxAgeLabel,
+    /// Input for days after which output logs are deleted.
+    LogMaxAgeEdit,
+    /// Label for output log count limit.
+    LogMaxFilesLabel,
+    /// Input for number of output logs kept.
+    LogMaxFilesEdit,
+    /// Label for chmod of dropped files.
+    ArgChmodLabel,
+    /// Input for mode applied to dropped files.
+    ArgChmodEdit,
+    /// Label for chown of dropped files.
+    ArgChownLabel,
+    /// Input for owner applied to dropped files.
+    ArgChownEdit,
+    /// Label for environment variables input.
+    WslEnvLabel,
+    /// Input for environment variables shared via WSLENV.
+    WslEnvEdit,
+    /// Label for association strength.
+    AssociationLabel,
+    /// Combo box for association strength.
+    AssociationCombo,
+    /// Label for type description.
+    TypeNameLabel,
+    /// Input for type description shown in Explorer.
+    TypeNameEdit,
+    /// Label for command preview.
+    CommandPreviewLabel,
+    /// Read-only preview of the command written on save.
+    CommandPreviewEdit,
+    /// Banner for the last non-blocking error.
+    StaticErrorBanner,
+    /// Button to expand error details.
+    BtnErrorDetails,
 }
 
 /// Menu item ID's.
@@ -155,8 +283,41 @@ enum MenuItem {
     Unregister = 100,
     /// Edit extension.
     EditExtension,
+    /// Don't group extensions.
+    NoGrouping,
+    /// Group extensions by distribution.
+    GroupByDistro,
+    /// Group extensions by category.
+    GroupByCategory,
+    /// Unregister selected extensions.
+    UnregisterSelected,
+    /// Set hold mode of selected extensions to close on success.
+    SetHoldModeError,
+    /// Set hold mode of selected extensions to always close.
+    SetHoldModeNever,
+    /// Set hold mode of selected extensions to keep open.
+    SetHoldModeAlways,
+}
+
+/// Keyboard accelerator command ID's.
+#[derive(IntoPrimitive, TryFromPrimitive, PartialEq)]
+#[repr(u16)]
+enum Accelerator {
+    /// Run the latest test run again with the unsaved settings.
+    RerunTest = 100,
 }
 
+/// Menu item ID of the first distribution in "Set distribution" submenu.
+///
+/// Default distribution is at the base, followed by distributions
+/// in the order of `Distros::sorted_pairs`.
+const DISTRO_MENU_BASE: u32 = 1000;
+
+/// System menu item ID of the first distribution in "Terminate distribution" submenu.
+///
+/// Distributions are in the order of `Distros::sorted_pairs`.
+const TERMINATE_MENU_BASE: u32 = 2000;
+
 /// System menu item ID's.
 #[derive(IntoPrimitive, TryFromPrimitive, PartialEq)]
 #[repr(u32)]
@@ -165,132 +326,183 @@ enum SystemMenu {
     About = 100,
     /// Visit website.
     Homepage,
+    /// View log of filetype changes.
+    ChangeLog,
+    /// Toggle signature verification of handler binaries.
+    VerifySignatures,
+    /// Toggle running the drop handler out of process.
+    OutOfProcess,
+    /// Toggle the "Copy WSL path" verb for all files.
+    CopyPathAllFiles,
+    /// Toggle saving copies of chosen icons.
+    ManagedIcons,
+    /// Create a new script from the template.
+    NewScript,
+    /// Copy diagnostics report to clipboard.
+    CopyDiagnostics,
+    /// Shut down WSL.
+    ShutdownWsl,
+    /// Restart distribution of the extension being edited,
+    /// or the default distribution.
+    RestartDistro,
 }
 
 /// Minimum and initial main window size.
-const MIN_WINDOW_SIZE: (i32, i32) = (300, 315);
+const MIN_WINDOW_SIZE: (i32, i32) = (300, 1090);
+
+/// Default timeout in seconds when waiting for a key on exit.
+const DEFAULT_HOLD_TIMEOUT: u32 = 10;
+
+/// Verb of the elevated action that registers the Event Log source.
+pub(crate) const EVENT_SOURCE_VERB: &str = "register-event-source";
+
+/// Default number of files per run when running in batches.
+const DEFAULT_BATCH_SIZE: u32 = 10;
 
 impl MainWindow {
     /// Create application window.
     fn new(title: &WideCStr) -> Result<Pin<Box<Self>>, Error> {
         let wnd = Pin::new(Box::new(Self::default()));
-        let instance = unsafe { libloaderapi::GetModuleHandleW(ptr::null_mut()) };
+        let instance: HINSTANCE = unsafe { LibraryLoader::GetModuleHandleW(None) }?.into();
         let class_name = wchz!("WSLScript");
         // register window class
         let wc = WNDCLASSEXW {
             cbSize: mem::size_of::<WNDCLASSEXW>() as _,
             style: CS_OWNDC | CS_HREDRAW | CS_VREDRAW,
-            hbrBackground: (COLOR_WINDOW + 1) as _,
-            lpfnWndProc: Some(window_proc_wrapper::<MainWindow>),
+            hbrBackground: Gdi::HBRUSH((Gdi::COLOR_WINDOW.0 + 1) as _),
+            lpfnWndProc: Some(window::window_proc_wrapper::<MainWindow>),
             hInstance: instance,
-            lpszClassName: class_name.as_ptr(),
-            hIcon: unsafe { LoadIconW(instance, wchz!("app").as_ptr()) },
-            hCursor: unsafe { LoadCursorW(ptr::null_mut(), IDC_ARROW) },
+            lpszClassName: PCWSTR(class_name.as_ptr()),
+            hIcon: unsafe { LoadIconW(instance, w!("app")) }.unwrap_or_default(),
+            hCursor: unsafe { LoadCursorW(None, IDC_ARROW) }.unwrap_or_default(),
             ..unsafe { mem::zeroed() }
         };
         if 0 == unsafe { RegisterClassExW(&wc) } {
             return Err(win32::last_error());
         }
-        // create window
+        // create window, sized for the primary display until WM_DPICHANGED
+        let dpi = unsafe { HiDpi::GetDpiForSystem() };
+        let (width, height) = (
+            scale_for_dpi(MIN_WINDOW_SIZE.0, dpi),
+            scale_for_dpi(MIN_WINDOW_SIZE.1, dpi),
+        );
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, class_name.as_ptr(), title.as_ptr(),
+            WINDOW_EX_STYLE::default(), PCWSTR(class_name.as_ptr()), PCWSTR(title.as_ptr()),
             WS_OVERLAPPEDWINDOW & !WS_MAXIMIZEBOX | WS_VISIBLE,
-            CW_USEDEFAULT, CW_USEDEFAULT, MIN_WINDOW_SIZE.0, MIN_WINDOW_SIZE.1,
-            ptr::null_mut(), ptr::null_mut(), instance, &*wnd as *const Self as _) };
-        if hwnd.is_null() {
-            return Err(win32::last_error());
+            CW_USEDEFAULT, CW_USEDEFAULT, width, height,
+            None, None, instance, Some(&*wnd as *const Self as _)) };
+        if hwnd.0 == 0 {
+            return Err(window::take_error().unwrap_or_else(win32::last_error));
         }
         Ok(wnd)
     }
 
     /// Run message loop.
     fn run(&self) -> Result<(), Error> {
-        loop {
-            let mut msg: MSG = unsafe { mem::zeroed() };
-            match unsafe { GetMessageW(&mut msg, ptr::null_mut(), 0, 0) } {
-                1..=std::i32::MAX => {
-                    unsafe { TranslateMessage(&msg) };
-                    unsafe { DispatchMessageW(&msg) };
-                }
-                std::i32::MIN..=-1 => return Err(win32::last_error()),
-                0 => return Ok(()),
-            }
-        }
+        let accels = [ACCEL {
+            fVirt: FCONTROL | FVIRTKEY,
+            key: b'R' as u16,
+            cmd: Accelerator::RerunTest.into(),
+        }];
+        let accel = unsafe { CreateAcceleratorTableW(&accels) }?;
+        let result = MessageLoop::new()
+            .with_accelerators(self.hwnd.get(), accel)
+            .run();
+        let _ = unsafe { DestroyAcceleratorTable(accel) };
+        result
     }
 
     /// Create window controls.
     fn create_window_controls(&mut self) -> Result<(), Error> {
-        let instance = unsafe { GetWindowLongW(self.hwnd, GWL_HINSTANCE) as win::HINSTANCE };
-        self.caption_font = Font::new_default_caption()?;
-        self.ext_font = Font::new_caption(24)?;
+        let instance = HINSTANCE(unsafe { GetWindowLongPtrW(self.hwnd.get(), GWL_HINSTANCE) });
+        self.dpi = unsafe { HiDpi::GetDpiForWindow(self.hwnd.get()) };
+        self.create_fonts()?;
         // init common controls
-        let icex = commctrl::INITCOMMONCONTROLSEX {
-            dwSize: mem::size_of::<commctrl::INITCOMMONCONTROLSEX>() as _,
-            dwICC: commctrl::ICC_LISTVIEW_CLASSES,
+        let icex = Controls::INITCOMMONCONTROLSEX {
+            dwSize: mem::size_of::<Controls::INITCOMMONCONTROLSEX>() as _,
+            dwICC: Controls::ICC_LISTVIEW_CLASSES,
         };
-        unsafe { commctrl::InitCommonControlsEx(&icex) };
+        unsafe { Controls::InitCommonControlsEx(&icex) };
 
         // static message area
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), ptr::null_mut(),
-            SS_CENTER | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::StaticMsg as u16 as _, instance, ptr::null_mut(),
+            WINDOW_EX_STYLE::default(), w!("STATIC"), PCWSTR::null(),
+            WINDOW_STYLE(SS_CENTER.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::StaticMsg as isize), instance, None,
         ) };
         set_window_font(hwnd, &self.caption_font);
 
         // register button
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("BUTTON").as_ptr(), wchz!("Register").as_ptr(),
-            WS_TABSTOP | WS_VISIBLE | WS_CHILD | BS_DEFPUSHBUTTON,
-            0, 0, 0, 0, self.hwnd,
-            Control::BtnRegister as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Register"),
+            WINDOW_STYLE(BS_DEFPUSHBUTTON as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::BtnRegister as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
 
         // register label
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), wchz!("Extension:").as_ptr(),
-            SS_CENTERIMAGE | SS_RIGHT | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::RegisterLabel as u16 as _, instance, ptr::null_mut(),
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Extension:"),
+            WINDOW_STYLE(SS_CENTERIMAGE.0 | SS_RIGHT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::RegisterLabel as isize), instance, None,
         ) };
         set_window_font(hwnd, &self.caption_font);
 
         // extension input
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("EDIT").as_ptr(), ptr::null_mut(),
-            ES_LEFT | ES_LOWERCASE | WS_CHILD | WS_VISIBLE | WS_BORDER,
-            0, 0, 0, 0, self.hwnd,
-            Control::EditExtension as u16 as _, instance, ptr::null_mut(),
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_LOWERCASE) as u32) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::EditExtension as isize), instance, None,
         ) };
         set_window_font(hwnd, &self.caption_font);
         let self_ptr = self as *const _;
         // use custom window proc
-        unsafe { commctrl::SetWindowSubclass(hwnd, Some(extension_input_proc), 0, self_ptr as _) };
+        unsafe { Shell::SetWindowSubclass(hwnd, Some(extension_input_proc), 0, self_ptr as _) };
         // if no extensions are registered, set default value to input box
         if registry::query_registered_extensions()
             .unwrap_or_default()
             .is_empty()
         {
-            unsafe { SetWindowTextW(hwnd, DEFAULT_EXTENSION.as_ptr()) };
+            let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(DEFAULT_EXTENSION.as_ptr())) };
         }
 
+        // filter input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::FilterEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        unsafe {
+            SendMessageW(
+                hwnd,
+                Controls::EM_SETCUEBANNER,
+                WPARAM(0),
+                LPARAM(w!("Filter by filetype or distribution").as_ptr() as _),
+            )
+        };
+
         // extensions listview
         self.lv_extensions = listview::ExtensionsListView::create(self);
 
         // extension icon
         #[rustfmt::skip]
         unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), ptr::null_mut(),
-            SS_ICON | SS_CENTERIMAGE | SS_NOTIFY | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::StaticIcon as u16 as _, instance, ptr::null_mut(),
+            WINDOW_EX_STYLE::default(), w!("STATIC"), PCWSTR::null(),
+            WINDOW_STYLE(SS_ICON.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::StaticIcon as isize), instance, None,
         ) };
 
         // icon tooltip
@@ -302,39 +514,85 @@ impl MainWindow {
         // icon label
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), wchz!("Icon").as_ptr(),
-            SS_CENTER | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::IconLabel as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Icon"),
+            WINDOW_STYLE(SS_CENTER.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::IconLabel as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
 
+        // icon location input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::IconPathEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        // path and a comma followed by at most 10 digits
+        unsafe {
+            SendMessageW(
+                hwnd,
+                Controls::EM_SETLIMITTEXT,
+                WPARAM(MAX_PATH as usize + 11),
+                LPARAM(0),
+            )
+        };
+
+        // tooltip for icon location
+        self.create_control_tooltip(
+            Control::IconPathEdit,
+            wcstr(wchz!(
+                "Icon file, or a .dll or .exe file followed by a comma and \
+                the icon index, eg. C:\\Windows\\System32\\shell32.dll,3. \
+                Leave empty to use the icon of the distribution."
+            )),
+        );
+
         // hold mode combo box
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("COMBOBOX").as_ptr(), ptr::null_mut(),
-            CBS_DROPDOWNLIST | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::HoldModeCombo as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::HoldModeCombo as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
         let insert_item = |mode: registry::HoldMode, label: &[wchar_t]| {
-            let idx =
-                unsafe { SendMessageW(hwnd, CB_INSERTSTRING, -1_isize as _, label.as_ptr() as _) };
+            let idx = unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(label.as_ptr() as _),
+                )
+            };
             let s = mode.as_wcstr();
-            unsafe { SendMessageW(hwnd, CB_SETITEMDATA, idx as _, s.as_ptr() as _) };
+            unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_SETITEMDATA,
+                    WPARAM(idx.0 as _),
+                    LPARAM(s.as_ptr() as _),
+                )
+            };
         };
         insert_item(registry::HoldMode::Error, wchz!("Close on success"));
         insert_item(registry::HoldMode::Never, wchz!("Always close"));
         insert_item(registry::HoldMode::Always, wchz!("Keep open"));
+        insert_item(
+            registry::HoldMode::Timeout(registry::HoldMode::DEFAULT_TIMEOUT),
+            wchz!("Close after timeout"),
+        );
 
         // hold mode label
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), wchz!("Exit behaviour").as_ptr(),
-            SS_CENTER | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::HoldModeLabel as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Exit behaviour"),
+            WINDOW_STYLE(SS_CENTER.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::HoldModeLabel as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
 
@@ -347,19 +605,19 @@ impl MainWindow {
         // interactive shell checkbox
         #[rustfmt::skip]
         unsafe { CreateWindowExW(
-            0, wchz!("BUTTON").as_ptr(), ptr::null_mut(),
-            WS_TABSTOP | WS_VISIBLE | WS_CHILD | BS_AUTOCHECKBOX,
-            0, 0, 0, 0, self.hwnd,
-            Control::InteractiveCheckbox as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::InteractiveCheckbox as isize), instance, None
         ) };
 
         // interactive shell label
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), wchz!("Interactive").as_ptr(),
-            SS_LEFT | SS_CENTERIMAGE | SS_NOTIFY | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::InteractiveLabel as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Interactive"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::InteractiveLabel as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
 
@@ -375,25 +633,30 @@ impl MainWindow {
         // distro combo box
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("COMBOBOX").as_ptr(), ptr::null_mut(),
-            CBS_DROPDOWNLIST | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::DistroCombo as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::DistroCombo as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
         let insert_item = |guid: Option<&registry::DistroGUID>, name: &str| {
             unsafe {
                 let s = WideCString::from_str_unchecked(name);
-                let idx = SendMessageW(hwnd, CB_INSERTSTRING, -1_isize as _, s.as_ptr() as _);
+                let idx = SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(s.as_ptr() as _),
+                );
                 if let Some(guid) = guid {
                     SendMessageW(
                         hwnd,
                         CB_SETITEMDATA,
-                        idx as _,
-                        guid.as_wcstr().as_ptr() as _,
+                        WPARAM(idx.0 as _),
+                        LPARAM(guid.as_wcstr().as_ptr() as _),
                     );
                 } else {
-                    SendMessageW(hwnd, CB_SETITEMDATA, idx as _, 0);
+                    SendMessageW(hwnd, CB_SETITEMDATA, WPARAM(idx.0 as _), LPARAM(0));
                 }
             };
         };
@@ -405,10 +668,10 @@ impl MainWindow {
         // distro label
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), wchz!("Distribution").as_ptr(),
-            SS_CENTER | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::DistroLabel as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Distribution"),
+            WINDOW_STYLE(SS_CENTER.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::DistroLabel as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
 
@@ -418,299 +681,2279 @@ impl MainWindow {
             wcstr(wchz!("WSL distribution on which to run the script.")),
         );
 
-        // save button
+        // CRLF mode combo box
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::CrlfModeCombo as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        let insert_item = |mode: registry::CrlfMode, label: &[wchar_t]| {
+            let idx = unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(label.as_ptr() as _),
+                )
+            };
+            let s = mode.as_wcstr();
+            unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_SETITEMDATA,
+                    WPARAM(idx.0 as _),
+                    LPARAM(s.as_ptr() as _),
+                )
+            };
+        };
+        insert_item(registry::CrlfMode::Warn, wchz!("Warn"));
+        insert_item(registry::CrlfMode::Bash, wchz!("Run with bash"));
+        insert_item(registry::CrlfMode::Fix, wchz!("Strip CR"));
+
+        // CRLF mode label
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("BUTTON").as_ptr(), wchz!("Save").as_ptr(),
-            WS_TABSTOP | WS_VISIBLE | WS_CHILD | BS_DEFPUSHBUTTON,
-            0, 0, 0, 0, self.hwnd,
-            Control::BtnSave as u16 as _, instance, ptr::null_mut()
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("CRLF line endings"),
+            WINDOW_STYLE(SS_CENTER.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::CrlfModeLabel as isize), instance, None
         ) };
         set_window_font(hwnd, &self.caption_font);
 
-        self.update_control_states();
-        Ok(())
-    }
+        // CRLF mode tooltip
+        self.create_control_tooltip(
+            Control::CrlfModeCombo,
+            wcstr(wchz!(
+                "What to do when the script has Windows (CRLF) line endings. \
+                Strip CR removes carriage returns as the script is read, \
+                leaving the file unchanged."
+            )),
+        );
 
-    /// Create a tooltip and assign it to given control.
-    fn create_control_tooltip(&self, control: Control, text: &WideCStr) {
-        use commctrl::*;
-        let instance = unsafe { GetWindowLongW(self.hwnd, GWL_HINSTANCE) as win::HINSTANCE };
+        // raw arguments checkbox
         #[rustfmt::skip]
-        let hwnd_tt = unsafe { CreateWindowExW(
-            0, wchz!("tooltips_class32").as_ptr(), ptr::null_mut(),
-            WS_POPUP | TTS_ALWAYSTIP | TTS_BALLOON,
-            CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT, self.hwnd,
-            ptr::null_mut(), instance, ptr::null_mut()
-        ) };
-        let ti = TOOLINFOW {
-            cbSize: mem::size_of::<TOOLINFOW>() as _,
-            hwnd: self.hwnd,
-            uFlags: TTF_IDISHWND | TTF_SUBCLASS,
-            uId: self.get_control_handle(control) as _,
-            lpszText: text.as_ptr() as _,
-            ..unsafe { mem::zeroed() }
-        };
-        unsafe { SendMessageW(hwnd_tt, TTM_ADDTOOLW, 0, &ti as *const _ as _) };
-        unsafe { SendMessageW(hwnd_tt, TTM_ACTIVATE, win::TRUE as _, 0) };
-    }
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::RawArgsCheckbox as isize), instance, None
+        ) };
 
-    /// Update control states.
-    fn update_control_states(&self) {
-        // set message
-        let hwnd = self.get_control_handle(Control::StaticMsg);
-        if let Some(mut ext) = self.get_current_extension() {
-            // if extension is registered for WSL, but handler is in another directory
-            if !registry::is_registered_for_current_executable(&ext).unwrap_or(true) {
-                let exe = std::env::current_exe()
-                    .ok()
-                    .and_then(|p| p.file_name().map(|s| s.to_os_string()))
-                    .and_then(|s| s.into_string().ok())
-                    .unwrap_or_default();
-                let s = wcstring(format!(
-                    ".{} handler found in another directory!\n\
-                     Did you move {}?",
-                    ext, exe
-                ));
-                unsafe { SetWindowTextW(hwnd, s.as_ptr()) };
-                set_window_font(hwnd, &self.caption_font);
-            } else if let Some(msg) = &self.message {
-                unsafe { SetWindowTextW(hwnd, wcstring(msg).as_ptr()) };
-                set_window_font(hwnd, &self.caption_font);
-            } else {
-                ext.insert(0, '.');
-                unsafe { SetWindowTextW(hwnd, wcstring(ext).as_ptr()) };
-                set_window_font(hwnd, &self.ext_font);
-            }
-        } else {
-            let s = wchz!(
-                "Enter the extension and click \
-                 Register to associate a filetype with WSL."
-            );
-            unsafe { SetWindowTextW(hwnd, s.as_ptr()) };
-            set_window_font(hwnd, &self.caption_font);
-        };
-        let visible = self.current_ext_cfg.is_some();
-        // hold mode label
-        self.set_control_visibility(Control::HoldModeLabel, visible);
-        // hold mode combo
-        self.set_control_visibility(Control::HoldModeCombo, visible);
-        if let Some(mode) = self.current_ext_cfg.as_ref().map(|cfg| cfg.hold_mode) {
-            self.set_selected_hold_mode(mode);
-        }
-        // interactive shell label
-        self.set_control_visibility(Control::InteractiveLabel, visible);
-        // interactive shell checkbox
-        self.set_control_visibility(Control::InteractiveCheckbox, visible);
-        // set button state
-        if let Some(state) = self.current_ext_cfg.as_ref().map(|cfg| cfg.interactive) {
-            self.set_interactive_state(state);
-        }
-        // distro label
-        self.set_control_visibility(Control::DistroLabel, visible);
-        // distro combo
-        self.set_control_visibility(Control::DistroCombo, visible);
-        self.set_selected_distro(
-            self.current_ext_cfg
-                .as_ref()
-                .and_then(|cfg| cfg.distro.as_ref()),
+        // raw arguments label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Raw arguments"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::RawArgsLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for raw arguments
+        self.create_control_tooltip(
+            Control::RawArgsCheckbox,
+            wcstr(wchz!(
+                "WARNING: Pass arguments unquoted, separated by spaces. \
+                Paths containing spaces or shell metacharacters are split \
+                and interpreted by bash! Only for scripts that do their own \
+                word splitting. Paths containing % or \" are refused."
+            )),
         );
-        // set icon
-        self.set_control_visibility(Control::StaticIcon, visible);
-        let hwnd = self.get_control_handle(Control::StaticIcon);
-        if let Some(icon) = self
-            .current_ext_cfg
-            .as_ref()
-            .and_then(|cfg| cfg.icon.as_ref())
-        {
-            unsafe { SendMessageW(hwnd, STM_SETICON, icon.handle() as _, 0) };
-        } else {
-            // NOTE: DestroyIcon not needed for shared icons
-            let hicon = unsafe { LoadIconW(ptr::null_mut(), IDI_WARNING) };
-            unsafe { SendMessageW(hwnd, STM_SETICON, hicon as _, 0) };
-        }
-        // icon label
-        self.set_control_visibility(Control::IconLabel, visible);
-        // save button
-        self.set_control_visibility(Control::BtnSave, visible);
-    }
 
-    /// Set control visibility.
-    fn set_control_visibility(&self, control: Control, visible: bool) {
-        let visibility = if visible { SW_SHOW } else { SW_HIDE };
-        unsafe {
-            ShowWindow(self.get_control_handle(control), visibility);
-        }
-    }
+        // console at drop point checkbox
+        #[rustfmt::skip]
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleAtDropCheckbox as isize), instance, None
+        ) };
 
-    /// Add items to system menu.
-    fn extend_system_menu(&self) -> Result<(), Error> {
-        let menu = unsafe { GetSystemMenu(self.hwnd, win::FALSE) };
-        unsafe {
-            AppendMenuW(menu, MF_SEPARATOR, 0, ptr::null());
-            AppendMenuW(
-                menu,
-                MF_ENABLED | MF_STRING,
-                SystemMenu::About as _,
-                wchz!("About WSL Script").as_ptr(),
-            );
-            AppendMenuW(
-                menu,
-                MF_ENABLED | MF_STRING,
-                SystemMenu::Homepage as _,
-                wchz!("Visit website").as_ptr(),
-            );
-        }
-        Ok(())
-    }
+        // console at drop point label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Open at drop point"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleAtDropLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
 
-    /// Handle WM_SYSCOMMAND message when custom menu item was selected.
-    fn on_system_menu_command(&self, id: SystemMenu) -> win::LRESULT {
-        match id {
-            SystemMenu::About => {
-                let mut text = format!("WSL Script");
-                if let Ok(p) = std::env::current_exe() {
-                    if let Some(version) = wslscript_common::ver::product_version(&p) {
-                        text.push_str(&format!("\nVersion {}", version));
-                    }
-                };
-                unsafe {
-                    MessageBoxW(
-                        self.hwnd,
-                        wcstring(text).as_ptr(),
-                        wchz!("About WSL Script").as_ptr(),
-                        MB_OK | MB_ICONINFORMATION,
-                    );
-                }
-                0
-            }
-            SystemMenu::Homepage => {
-                unsafe {
-                    winapi::um::shellapi::ShellExecuteW(
-                        ptr::null_mut(),
-                        wchz!("open").as_ptr(),
-                        wchz!("https://sop.github.io/wslscript/").as_ptr(),
-                        ptr::null(),
-                        ptr::null(),
-                        SW_SHOWNORMAL,
-                    );
-                }
-                0
-            }
-        }
-    }
+        // tooltip for console at drop point
+        self.create_control_tooltip(
+            Control::ConsoleAtDropCheckbox,
+            wcstr(wchz!(
+                "Open the console window where the files were dropped, \
+                on the same monitor."
+            )),
+        );
 
-    /// Handle WM_SIZE message.
-    ///
-    /// * `width` - Window width
-    /// * `height` - Window height
-    fn on_resize(&self, width: i32, _height: i32) {
-        self.move_control(Control::StaticMsg, 10, 10, width - 20, 40);
-        self.move_control(Control::RegisterLabel, 10, 50, 60, 25);
-        self.move_control(Control::EditExtension, 80, 50, width - 90 - 100, 25);
-        self.move_control(Control::BtnRegister, width - 100, 50, 90, 25);
-        self.move_control(Control::ListViewExtensions, 10, 85, width - 20, 75);
-        self.move_control(Control::HoldModeLabel, 10, 170, 130, 20);
-        self.move_control(Control::HoldModeCombo, 10, 190, 130, 100);
-        self.move_control(Control::InteractiveLabel, 170, 190, 130, 20);
-        self.move_control(Control::InteractiveCheckbox, 150, 190, 20, 20);
-        self.move_control(Control::DistroLabel, 10, 220, 130, 20);
-        self.move_control(Control::DistroCombo, 10, 240, 130, 100);
-        self.move_control(Control::IconLabel, 150, 220, 32, 16);
-        self.move_control(Control::StaticIcon, 150, 236, 32, 32);
-        self.move_control(Control::BtnSave, width - 90, 240, 80, 25);
-    }
+        // reuse console checkbox
+        #[rustfmt::skip]
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ReuseConsoleCheckbox as isize), instance, None
+        ) };
 
-    /// Move window control.
-    fn move_control(&self, control: Control, x: i32, y: i32, width: i32, height: i32) {
-        let hwnd = self.get_control_handle(control);
-        unsafe { MoveWindow(hwnd, x, y, width, height, win::TRUE) };
-    }
+        // reuse console label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Reuse console"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ReuseConsoleLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
 
-    /// Handle WM_COMMAND message from a control.
-    ///
-    /// * `hwnd` - Handle of the sending control
-    /// * `control_id` - ID of the sending control
-    /// * `code` - Notification code
-    fn on_control(
-        &mut self,
-        _hwnd: windef::HWND,
-        control_id: Control,
-        code: win::WORD,
-    ) -> Result<win::LRESULT, Error> {
-        #[allow(clippy::single_match)]
-        match control_id {
-            Control::BtnRegister => match code {
-                BN_CLICKED => return self.on_register_button_clicked(),
-                _ => {}
+        // tooltip for reuse console
+        self.create_control_tooltip(
+            Control::ReuseConsoleCheckbox,
+            wcstr(wchz!(
+                "Keep the console open after the script exits and run \
+                subsequent scripts of this type in the same console."
+            )),
+        );
+
+        // use helper checkbox
+        #[rustfmt::skip]
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::UseHelperCheckbox as isize), instance, None
+        ) };
+
+        // use helper label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Use helper"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::UseHelperLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for use helper
+        self.create_control_tooltip(
+            Control::UseHelperCheckbox,
+            wcstr(wchz!(
+                "Install a helper script to ~/.local/share/wslscript in WSL \
+                and use it for path conversion, environment setup and run \
+                status reporting. Variables in ~/.config/wslscript/env are \
+                exported to the script."
+            )),
+        );
+
+        // console title label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Console title"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleTitleLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // console title input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleTitleEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for console title
+        self.create_control_tooltip(
+            Control::ConsoleTitleEdit,
+            wcstr(wchz!(
+                "Title of the console window. Leave empty to use the script name."
+            )),
+        );
+
+        // utf-8 console checkbox
+        #[rustfmt::skip]
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::Utf8ConsoleCheckbox as isize), instance, None
+        ) };
+
+        // utf-8 console label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("UTF-8 console"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::Utf8ConsoleLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for utf-8 console
+        self.create_control_tooltip(
+            Control::Utf8ConsoleCheckbox,
+            wcstr(wchz!(
+                "Set the console code page to UTF-8 and use a TrueType font, \
+                so that Unicode output is displayed correctly."
+            )),
+        );
+
+        // console size label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Console size"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleSizeLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // console size input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleSizeEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for console size
+        self.create_control_tooltip(
+            Control::ConsoleSizeEdit,
+            wcstr(wchz!(
+                "Console buffer size as columns x rows, eg. 120x30. \
+                Leave empty to use the system default."
+            )),
+        );
+
+        // console colors label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Colors"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleColorsLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // console colors input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_UPPERCASE) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ConsoleColorsEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        unsafe { SendMessageW(hwnd, Controls::EM_SETLIMITTEXT, WPARAM(2), LPARAM(0)) };
+
+        // tooltip for console colors
+        self.create_control_tooltip(
+            Control::ConsoleColorsEdit,
+            wcstr(wchz!(
+                "Console colors as two hex digits, background and text, \
+                as with the color command, eg. 1F for white on blue. \
+                Leave empty to use the system default."
+            )),
+        );
+
+        // category label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Category"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::CategoryLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // category input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::CategoryEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for category
+        self.create_control_tooltip(
+            Control::CategoryEdit,
+            wcstr(wchz!(
+                "Category for grouping filetypes in the list. \
+                Right-click the list to change grouping."
+            )),
+        );
+
+        // exit message label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Exit message"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::HoldMessageLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // exit message input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::HoldMessageEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        let cue = wcstring(wsl::DEFAULT_HOLD_MESSAGE);
+        unsafe {
+            SendMessageW(
+                hwnd,
+                Controls::EM_SETCUEBANNER,
+                WPARAM(0),
+                LPARAM(cue.as_ptr() as _),
+            )
+        };
+
+        // tooltip for exit message
+        self.create_control_tooltip(
+            Control::HoldMessageEdit,
+            wcstr(wchz!(
+                "Message displayed when the console is kept open after the script exits. \
+                {code} is replaced with the exit code."
+            )),
+        );
+
+        // wait key label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Wait for"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::HoldKeyLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // wait key combo box
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::HoldKeyCombo as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        // see get_selected_hold_key for the order of items
+        for label in [w!("Any key"), w!("Enter"), w!("Timeout")] {
+            unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(label.as_ptr() as _),
+                )
+            };
+        }
+
+        // tooltip for wait key
+        self.create_control_tooltip(
+            Control::HoldKeyCombo,
+            wcstr(wchz!(
+                "Key to close the console when it's kept open. \
+                With timeout, any key closes the console before the given seconds have passed."
+            )),
+        );
+
+        // timeout input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_NUMBER) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::HoldTimeoutEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for timeout
+        self.create_control_tooltip(
+            Control::HoldTimeoutEdit,
+            wcstr(wchz!("Seconds to wait before closing the console.")),
+        );
+
+        // multiple files label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Multiple files"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::DropPolicyLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // multiple files combo box
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::DropPolicyCombo as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        // see get_selected_drop_policy for the order of items
+        for label in [
+            w!("All in one run"),
+            w!("One run per file"),
+            w!("Runs in batches"),
+        ] {
+            unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(label.as_ptr() as _),
+                )
+            };
+        }
+
+        // tooltip for multiple files
+        self.create_control_tooltip(
+            Control::DropPolicyCombo,
+            wcstr(wchz!(
+                "How the script is run when multiple files are dropped on it."
+            )),
+        );
+
+        // concurrent runs limit or batch size input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_NUMBER) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::DropCountEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for concurrent runs limit or batch size
+        self.create_control_tooltip(
+            Control::DropCountEdit,
+            wcstr(wchz!(
+                "Maximum number of consoles open at a time when running per file, \
+                empty for no limit. Number of files per run when running in batches."
+            )),
+        );
+
+        // confirmation limit label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Confirm over"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::MaxArgsLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // confirmation limit input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_NUMBER) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::MaxArgsEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for confirmation limit
+        self.create_control_tooltip(
+            Control::MaxArgsEdit,
+            wcstr(wchz!(
+                "Ask for confirmation when more than this many files are dropped, \
+                empty to never ask."
+            )),
+        );
+
+        // show progress checkbox
+        #[rustfmt::skip]
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ShowProgressCheckbox as isize), instance, None
+        ) };
+
+        // show progress label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Show progress"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ShowProgressLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for show progress
+        self.create_control_tooltip(
+            Control::ShowProgressCheckbox,
+            wcstr(wchz!(
+                "Show a progress window when converting many dropped files \
+                or waiting for WSL to start."
+            )),
+        );
+
+        // order of dropped files label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Order of files"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ArgOrderLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // order of dropped files combo box
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ArgOrderCombo as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        let insert_item = |order: registry::ArgOrder, label: &[wchar_t]| {
+            let idx = unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(label.as_ptr() as _),
+                )
+            };
+            let s = order.as_wcstr();
+            unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_SETITEMDATA,
+                    WPARAM(idx.0 as _),
+                    LPARAM(s.as_ptr() as _),
+                )
+            };
+        };
+        insert_item(registry::ArgOrder::None, wchz!("As dropped"));
+        insert_item(registry::ArgOrder::Name, wchz!("By name"));
+        insert_item(registry::ArgOrder::Path, wchz!("By path"));
+        insert_item(registry::ArgOrder::Modified, wchz!("By modification time"));
+
+        // tooltip for order of dropped files
+        self.create_control_tooltip(
+            Control::ArgOrderCombo,
+            wcstr(wchz!(
+                "Order in which dropped files are passed to the script. \
+                Explorer doesn't guarantee any particular order."
+            )),
+        );
+
+        // abort on missing files checkbox
+        #[rustfmt::skip]
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::AbortOnMissingCheckbox as isize), instance, None
+        ) };
+
+        // abort on missing files label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Abort if files are missing"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::AbortOnMissingLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for abort on missing files
+        self.create_control_tooltip(
+            Control::AbortOnMissingCheckbox,
+            wcstr(wchz!(
+                "Don't run the script if some of the dropped files no longer exist. \
+                By default missing files are skipped."
+            )),
+        );
+
+        // minimum interval label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Min. interval (s)"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::MinIntervalLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // minimum interval input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_NUMBER) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::MinIntervalEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for minimum interval
+        self.create_control_tooltip(
+            Control::MinIntervalEdit,
+            wcstr(wchz!(
+                "Ask for confirmation when the script is run again within this many seconds, \
+                empty to never ask."
+            )),
+        );
+
+        // maximum instances label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Max. running"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::MaxInstancesLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // maximum instances input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_NUMBER) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::MaxInstancesEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for maximum instances
+        self.create_control_tooltip(
+            Control::MaxInstancesEdit,
+            wcstr(wchz!(
+                "Ask for confirmation when this many scripts of the filetype are already \
+                running, empty for no limit."
+            )),
+        );
+
+        // single instance checkbox
+        #[rustfmt::skip]
+        unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), PCWSTR::null(),
+            WINDOW_STYLE(BS_AUTOCHECKBOX as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::SingleInstanceCheckbox as isize), instance, None
+        ) };
+
+        // single instance label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Single instance"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0 | SS_NOTIFY.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::SingleInstanceLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for single instance
+        self.create_control_tooltip(
+            Control::SingleInstanceCheckbox,
+            wcstr(wchz!(
+                "If the script is already running, bring its console to the front \
+                instead of running it again."
+            )),
+        );
+
+        // Event Log mode label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Event Log"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::EventLogLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // Event Log mode combo box
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::EventLogCombo as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        let insert_item = |mode: registry::EventLog, label: &[wchar_t]| {
+            let idx = unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(label.as_ptr() as _),
+                )
+            };
+            let s = mode.as_wcstr();
+            unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_SETITEMDATA,
+                    WPARAM(idx.0 as _),
+                    LPARAM(s.as_ptr() as _),
+                )
+            };
+        };
+        insert_item(registry::EventLog::Off, wchz!("Off"));
+        insert_item(registry::EventLog::Events, wchz!("Start and exit"));
+        insert_item(registry::EventLog::Output, wchz!("With output"));
+
+        // tooltip for Event Log mode
+        self.create_control_tooltip(
+            Control::EventLogCombo,
+            wcstr(wchz!(
+                "Run the script without a console and log its start and exit \
+                to the Windows Event Log, optionally with the end of its output."
+            )),
+        );
+
+        // output log age label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Keep logs (days)"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::LogMaxAgeLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // output log age input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_NUMBER) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::LogMaxAgeEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for output log age
+        self.create_control_tooltip(
+            Control::LogMaxAgeEdit,
+            wcstr(wchz!(
+                "Delete output logs older than this many days, empty to keep them."
+            )),
+        );
+
+        // output log count label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Keep logs (files)"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::LogMaxFilesLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // output log count input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_NUMBER) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::LogMaxFilesEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for output log count
+        self.create_control_tooltip(
+            Control::LogMaxFilesEdit,
+            wcstr(wchz!(
+                "Keep only this many of the most recent output logs, empty for no limit."
+            )),
+        );
+
+        // chmod of dropped files label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Chmod dropped files"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ArgChmodLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // chmod of dropped files input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ArgChmodEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for chmod of dropped files
+        self.create_control_tooltip(
+            Control::ArgChmodEdit,
+            wcstr(wchz!(
+                "Mode set with chmod on dropped files before the script runs, \
+                eg. 644 or u+rx. Leave empty to keep the files as they are."
+            )),
+        );
+
+        // chown of dropped files label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Chown dropped files"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ArgChownLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // chown of dropped files input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ArgChownEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for chown of dropped files
+        self.create_control_tooltip(
+            Control::ArgChownEdit,
+            wcstr(wchz!(
+                "Owner set with chown on dropped files before the script runs, \
+                eg. user or user:group. Leave empty to keep the files as they are."
+            )),
+        );
+
+        // environment variables label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Environment variables"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::WslEnvLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // environment variables input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE(ES_LEFT as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::WslEnvEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for environment variables
+        self.create_control_tooltip(
+            Control::WslEnvEdit,
+            wcstr(wchz!(
+                "Windows environment variables passed to the script via WSLENV, \
+                separated by colons. Append /p to translate a path, eg. USERPROFILE/p. \
+                Prefix with ! to keep the value out of logs, eg. !API_TOKEN."
+            )),
+        );
+
+        // association label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Association"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::AssociationLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // association combo box
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("COMBOBOX"), PCWSTR::null(),
+            WINDOW_STYLE(CBS_DROPDOWNLIST as u32) | WS_VSCROLL | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::AssociationCombo as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+        let insert_item = |association: registry::Association, label: &[wchar_t]| {
+            let idx = unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_INSERTSTRING,
+                    WPARAM(-1_isize as _),
+                    LPARAM(label.as_ptr() as _),
+                )
+            };
+            let s = association.as_wcstr();
+            unsafe {
+                SendMessageW(
+                    hwnd,
+                    CB_SETITEMDATA,
+                    WPARAM(idx.0 as _),
+                    LPARAM(s.as_ptr() as _),
+                )
+            };
+        };
+        insert_item(registry::Association::OpenWith, wchz!("Open with only"));
+        insert_item(registry::Association::Default, wchz!("Default"));
+        insert_item(registry::Association::Full, wchz!("Default with drop"));
+
+        // tooltip for association
+        self.create_control_tooltip(
+            Control::AssociationCombo,
+            wcstr(wchz!(
+                "Whether WSL Script becomes the default program of the filetype. \
+                With Open with only, the current default program is kept and \
+                files can still be dropped on scripts."
+            )),
+        );
+
+        // type description label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Type description"),
+            WINDOW_STYLE(SS_LEFT.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::TypeNameLabel as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // type description input
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32) | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::TypeNameEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for type description
+        self.create_control_tooltip(
+            Control::TypeNameEdit,
+            wcstr(wchz!(
+                "Description of the filetype shown in the Type column of Explorer. \
+                Leave empty to use the default."
+            )),
+        );
+
+        // command preview label
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Command:"),
+            WINDOW_STYLE(SS_LEFT.0 | SS_CENTERIMAGE.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::CommandPreviewLabel as isize), instance, None,
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // command preview
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR::null(),
+            WINDOW_STYLE((ES_MULTILINE | ES_READONLY | ES_AUTOVSCROLL) as u32)
+                | WS_VSCROLL | WS_TABSTOP | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::CommandPreviewEdit as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for command preview
+        self.create_control_tooltip(
+            Control::CommandPreviewEdit,
+            wcstr(wchz!(
+                "Command registered for the filetype on save, \
+                and the command run in WSL for an example script."
+            )),
+        );
+
+        // save button
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Save"),
+            WINDOW_STYLE(BS_DEFPUSHBUTTON as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::BtnSave as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // test run button
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Test run..."),
+            WINDOW_STYLE(BS_PUSHBUTTON as u32) | WS_TABSTOP | WS_VISIBLE | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::BtnTestRun as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for test run
+        self.create_control_tooltip(
+            Control::BtnTestRun,
+            wcstr(wchz!(
+                "Run a script with the saved settings and show its output \
+                in a window instead of a console. Press Ctrl+R to run it again \
+                with the unsaved settings."
+            )),
+        );
+
+        // error banner
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("STATIC"), PCWSTR::null(),
+            WINDOW_STYLE(SS_LEFT.0 | SS_NOTIFY.0) | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::StaticErrorBanner as isize), instance, None,
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        // tooltip for error banner
+        self.create_control_tooltip(
+            Control::StaticErrorBanner,
+            wcstr(wchz!("Click to dismiss.")),
+        );
+
+        // error details button
+        #[rustfmt::skip]
+        let hwnd = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Details"),
+            WINDOW_STYLE(BS_PUSHBUTTON as u32) | WS_TABSTOP | WS_CHILD,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::BtnErrorDetails as isize), instance, None
+        ) };
+        set_window_font(hwnd, &self.caption_font);
+
+        self.update_control_states();
+        Ok(())
+    }
+
+    /// Create a tooltip and assign it to given control.
+    fn create_control_tooltip(&self, control: Control, text: &WideCStr) {
+        use Controls::*;
+        let instance = HINSTANCE(unsafe { GetWindowLongPtrW(self.hwnd.get(), GWL_HINSTANCE) });
+        #[rustfmt::skip]
+        let hwnd_tt = unsafe { CreateWindowExW(
+            WINDOW_EX_STYLE::default(), TOOLTIPS_CLASSW, PCWSTR::null(),
+            WS_POPUP | WINDOW_STYLE(TTS_ALWAYSTIP | TTS_BALLOON),
+            CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT, self.hwnd.get(),
+            None, instance, None
+        ) };
+        let ti = TTTOOLINFOW {
+            cbSize: mem::size_of::<TTTOOLINFOW>() as _,
+            hwnd: self.hwnd.get(),
+            uFlags: TTF_IDISHWND | TTF_SUBCLASS,
+            uId: self.get_control_handle(control).0 as _,
+            lpszText: PWSTR(text.as_ptr() as _),
+            ..unsafe { mem::zeroed() }
+        };
+        unsafe {
+            SendMessageW(
+                hwnd_tt,
+                TTM_ADDTOOLW,
+                WPARAM(0),
+                LPARAM(&ti as *const _ as _),
+            )
+        };
+        unsafe { SendMessageW(hwnd_tt, TTM_ACTIVATE, WPARAM(TRUE.0 as _), LPARAM(0)) };
+    }
+
+    /// Whether handler binaries of an extension are signed by the same
+    /// signer as this executable.
+    ///
+    /// Always true if signature verification is disabled. Result is cached
+    /// for the last checked extension, since verification is slow.
+    fn handler_signatures_match(&self, ext: &str) -> bool {
+        if !registry::verify_signatures() {
+            return true;
+        }
+        if let Some((checked_ext, result)) = self.signature_check.borrow().as_ref() {
+            if checked_ext == ext {
+                return *result;
+            }
+        }
+        let result = std::env::current_exe()
+            .map(|exe| registry::handler_signatures_match(ext, &exe))
+            .unwrap_or(true);
+        self.signature_check
+            .replace(Some((ext.to_string(), result)));
+        result
+    }
+
+    /// Update control states.
+    fn update_control_states(&self) {
+        // set message
+        let hwnd = self.get_control_handle(Control::StaticMsg);
+        if let Some(mut ext) = self.get_current_extension() {
+            // if extension is registered for WSL, but handler is in another directory
+            if !registry::is_registered_for_current_executable(&ext).unwrap_or(true) {
+                let exe = std::env::current_exe()
+                    .ok()
+                    .and_then(|p| p.file_name().map(|s| s.to_os_string()))
+                    .and_then(|s| s.into_string().ok())
+                    .unwrap_or_default();
+                let s = wcstring(format!(
+                    ".{} handler found in another directory!\n\
+                     Did you move {}?",
+                    ext, exe
+                ));
+                let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(s.as_ptr())) };
+                set_window_font(hwnd, &self.caption_font);
+            } else if !self.handler_signatures_match(&ext) {
+                let s = wcstring(format!(
+                    ".{} handler is not signed by the same publisher!\n\
+                     It may have been replaced.",
+                    ext
+                ));
+                let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(s.as_ptr())) };
+                set_window_font(hwnd, &self.caption_font);
+            } else if let Some(msg) = &self.message {
+                let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(wcstring(msg).as_ptr())) };
+                set_window_font(hwnd, &self.caption_font);
+            } else {
+                ext.insert(0, '.');
+                let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(wcstring(ext).as_ptr())) };
+                set_window_font(hwnd, &self.ext_font);
+            }
+        } else {
+            let s = wchz!(
+                "Enter the extension and click \
+                 Register to associate a filetype with WSL."
+            );
+            let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(s.as_ptr())) };
+            set_window_font(hwnd, &self.caption_font);
+        };
+        let visible = self.current_ext_cfg.is_some();
+        // hold mode label
+        self.set_control_visibility(Control::HoldModeLabel, visible);
+        // hold mode combo
+        self.set_control_visibility(Control::HoldModeCombo, visible);
+        if let Some(mode) = self.current_ext_cfg.as_ref().map(|cfg| cfg.hold_mode) {
+            self.set_selected_hold_mode(mode);
+        }
+        // interactive shell label
+        self.set_control_visibility(Control::InteractiveLabel, visible);
+        // interactive shell checkbox
+        self.set_control_visibility(Control::InteractiveCheckbox, visible);
+        // set button state
+        if let Some(state) = self.current_ext_cfg.as_ref().map(|cfg| cfg.interactive) {
+            self.set_interactive_state(state);
+        }
+        // distro label
+        self.set_control_visibility(Control::DistroLabel, visible);
+        // distro combo
+        self.set_control_visibility(Control::DistroCombo, visible);
+        self.set_selected_distro(
+            self.current_ext_cfg
+                .as_ref()
+                .and_then(|cfg| cfg.distro.as_ref()),
+        );
+        // CRLF mode label
+        self.set_control_visibility(Control::CrlfModeLabel, visible);
+        // CRLF mode combo
+        self.set_control_visibility(Control::CrlfModeCombo, visible);
+        if let Some(mode) = self.current_ext_cfg.as_ref().map(|cfg| cfg.crlf_mode) {
+            self.set_selected_crlf_mode(mode);
+        }
+        // raw arguments label
+        self.set_control_visibility(Control::RawArgsLabel, visible);
+        // raw arguments checkbox
+        self.set_control_visibility(Control::RawArgsCheckbox, visible);
+        if let Some(state) = self.current_ext_cfg.as_ref().map(|cfg| cfg.raw_args) {
+            self.set_raw_args_state(state);
+        }
+        // console at drop point label
+        self.set_control_visibility(Control::ConsoleAtDropLabel, visible);
+        // console at drop point checkbox
+        self.set_control_visibility(Control::ConsoleAtDropCheckbox, visible);
+        if let Some(state) = self.current_ext_cfg.as_ref().map(|cfg| cfg.console_at_drop) {
+            self.set_console_at_drop_state(state);
+        }
+        // reuse console label
+        self.set_control_visibility(Control::ReuseConsoleLabel, visible);
+        // reuse console checkbox
+        self.set_control_visibility(Control::ReuseConsoleCheckbox, visible);
+        if let Some(state) = self.current_ext_cfg.as_ref().map(|cfg| cfg.reuse_console) {
+            self.set_reuse_console_state(state);
+        }
+        // console settings
+        for control in [
+            Control::ConsoleTitleLabel,
+            Control::ConsoleTitleEdit,
+            Control::Utf8ConsoleCheckbox,
+            Control::Utf8ConsoleLabel,
+            Control::ConsoleSizeLabel,
+            Control::ConsoleSizeEdit,
+            Control::ConsoleColorsLabel,
+            Control::ConsoleColorsEdit,
+            Control::CategoryLabel,
+            Control::CategoryEdit,
+            Control::HoldMessageLabel,
+            Control::HoldMessageEdit,
+            Control::HoldKeyLabel,
+            Control::HoldKeyCombo,
+            Control::HoldTimeoutEdit,
+            Control::DropPolicyLabel,
+            Control::DropPolicyCombo,
+            Control::DropCountEdit,
+            Control::ArgOrderLabel,
+            Control::ArgOrderCombo,
+            Control::AbortOnMissingCheckbox,
+            Control::AbortOnMissingLabel,
+            Control::MaxArgsLabel,
+            Control::MaxArgsEdit,
+            Control::ShowProgressCheckbox,
+            Control::ShowProgressLabel,
+            Control::MinIntervalLabel,
+            Control::MinIntervalEdit,
+            Control::MaxInstancesLabel,
+            Control::MaxInstancesEdit,
+            Control::SingleInstanceCheckbox,
+            Control::SingleInstanceLabel,
+            Control::UseHelperCheckbox,
+            Control::UseHelperLabel,
+            Control::EventLogLabel,
+            Control::EventLogCombo,
+            Control::LogMaxAgeLabel,
+            Control::LogMaxAgeEdit,
+            Control::LogMaxFilesLabel,
+            Control::LogMaxFilesEdit,
+            Control::ArgChmodLabel,
+            Control::ArgChmodEdit,
+            Control::ArgChownLabel,
+            Control::ArgChownEdit,
+            Control::WslEnvLabel,
+            Control::WslEnvEdit,
+            Control::AssociationLabel,
+            Control::AssociationCombo,
+            Control::TypeNameLabel,
+            Control::TypeNameEdit,
+            Control::CommandPreviewLabel,
+            Control::CommandPreviewEdit,
+        ] {
+            self.set_control_visibility(control, visible);
+        }
+        if let Some(cfg) = self.current_ext_cfg.as_ref() {
+            let title = cfg.console_title.clone().unwrap_or_default();
+            let size = cfg.console_size.map(|s| s.to_string()).unwrap_or_default();
+            let colors = cfg
+                .console_colors
+                .map(|c| c.to_string())
+                .unwrap_or_default();
+            self.set_control_text(Control::ConsoleTitleEdit, &title);
+            self.set_utf8_console_state(cfg.utf8_console);
+            self.set_control_text(Control::ConsoleSizeEdit, &size);
+            self.set_control_text(Control::ConsoleColorsEdit, &colors);
+            let category = cfg.category.clone().unwrap_or_default();
+            self.set_control_text(Control::CategoryEdit, &category);
+            let type_name = cfg.type_name.clone().unwrap_or_default();
+            self.set_control_text(Control::TypeNameEdit, &type_name);
+            let cue = wcstring(registry::default_type_name(&cfg.extension));
+            unsafe {
+                SendMessageW(
+                    self.get_control_handle(Control::TypeNameEdit),
+                    Controls::EM_SETCUEBANNER,
+                    WPARAM(0),
+                    LPARAM(cue.as_ptr() as _),
+                )
+            };
+            let message = cfg.hold_message.clone().unwrap_or_default();
+            self.set_control_text(Control::HoldMessageEdit, &message);
+            self.set_selected_hold_key(cfg.hold_key);
+            self.update_hold_inputs();
+            self.set_selected_drop_policy(cfg.drop_policy);
+            self.set_selected_arg_order(cfg.arg_order);
+            self.set_abort_on_missing_state(cfg.abort_on_missing);
+            self.set_use_helper_state(cfg.use_helper);
+            self.set_single_instance_state(cfg.single_instance);
+            self.set_show_progress_state(cfg.show_progress);
+            self.set_selected_event_log(cfg.event_log);
+            self.set_selected_association(cfg.association);
+            let max_args = match cfg.max_args {
+                0 => String::new(),
+                n => n.to_string(),
+            };
+            self.set_control_text(Control::MaxArgsEdit, &max_args);
+            let number = |n: u32| match n {
+                0 => String::new(),
+                n => n.to_string(),
+            };
+            self.set_control_text(Control::MinIntervalEdit, &number(cfg.min_interval));
+            self.set_control_text(Control::MaxInstancesEdit, &number(cfg.max_instances));
+            self.set_control_text(Control::LogMaxAgeEdit, &number(cfg.log_max_age));
+            self.set_control_text(Control::LogMaxFilesEdit, &number(cfg.log_max_files));
+            self.update_log_inputs();
+            let mode = cfg.arg_chmod.clone().unwrap_or_default();
+            self.set_control_text(Control::ArgChmodEdit, &mode);
+            let owner = cfg.arg_chown.clone().unwrap_or_default();
+            self.set_control_text(Control::ArgChownEdit, &owner);
+            self.set_control_text(Control::WslEnvEdit, &cfg.wslenv.to_string());
+        }
+        // set icon
+        self.set_control_visibility(Control::StaticIcon, visible);
+        let hwnd = self.get_control_handle(Control::StaticIcon);
+        if let Some(icon) = self
+            .current_ext_cfg
+            .as_ref()
+            .and_then(|cfg| cfg.icon.as_ref())
+        {
+            unsafe { SendMessageW(hwnd, STM_SETICON, WPARAM(icon.handle().0 as _), LPARAM(0)) };
+        } else {
+            // NOTE: DestroyIcon not needed for shared icons
+            let hicon = unsafe { LoadIconW(None, IDI_WARNING) }.unwrap_or_default();
+            unsafe { SendMessageW(hwnd, STM_SETICON, WPARAM(hicon.0 as _), LPARAM(0)) };
+        }
+        // icon label
+        self.set_control_visibility(Control::IconLabel, visible);
+        // icon location
+        self.set_control_visibility(Control::IconPathEdit, visible);
+        let location = self
+            .current_ext_cfg
+            .as_ref()
+            .and_then(|cfg| cfg.icon.as_ref())
+            .map(|icon| icon.shell_path().to_string_lossy())
+            .unwrap_or_default();
+        self.set_control_text(Control::IconPathEdit, &location);
+        // save button
+        self.set_control_visibility(Control::BtnSave, visible);
+        // test run button
+        self.set_control_visibility(Control::BtnTestRun, visible);
+    }
+
+    /// Show a non-blocking error in the banner area.
+    ///
+    /// Full details are written to the log.
+    fn show_error(&self, summary: impl Into<String>, e: &Error) {
+        let summary = summary.into();
+        log::error!("{}: {:?}", summary, e);
+        self.error.replace(Some(ErrorBanner {
+            summary: format!("{}: {}", summary, e),
+            details: format!("{}:\n{:?}", summary, e),
+            expanded: false,
+        }));
+        self.update_error_banner();
+    }
+
+    /// Update error banner text and visibility.
+    fn update_error_banner(&self) {
+        let error = self.error.borrow();
+        let visible = error.is_some();
+        if let Some(banner) = error.as_ref() {
+            let (text, label) = if banner.expanded {
+                (&banner.details, w!("Hide"))
+            } else {
+                (&banner.summary, w!("Details"))
+            };
+            let hwnd = self.get_control_handle(Control::StaticErrorBanner);
+            let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(wcstring(text).as_ptr())) };
+            let hwnd = self.get_control_handle(Control::BtnErrorDetails);
+            let _ = unsafe { SetWindowTextW(hwnd, label) };
+        }
+        self.set_control_visibility(Control::StaticErrorBanner, visible);
+        self.set_control_visibility(Control::BtnErrorDetails, visible);
+    }
+
+    /// Set control visibility.
+    fn set_control_visibility(&self, control: Control, visible: bool) {
+        let visibility = if visible { SW_SHOW } else { SW_HIDE };
+        unsafe {
+            ShowWindow(self.get_control_handle(control), visibility);
+        }
+    }
+
+    /// Add items to system menu.
+    fn extend_system_menu(&self) -> Result<(), Error> {
+        let menu = unsafe { GetSystemMenu(self.hwnd.get(), FALSE) };
+        unsafe {
+            AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null())?;
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING,
+                SystemMenu::About as _,
+                w!("About WSL Script"),
+            )?;
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING,
+                SystemMenu::Homepage as _,
+                w!("Visit website"),
+            )?;
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING,
+                SystemMenu::ChangeLog as _,
+                w!("View change log"),
+            )?;
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING,
+                SystemMenu::NewScript as _,
+                w!("New script\u{2026}"),
+            )?;
+            let checked = if registry::verify_signatures() {
+                MF_CHECKED
+            } else {
+                MF_UNCHECKED
+            };
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING | checked,
+                SystemMenu::VerifySignatures as _,
+                w!("Verify handler signatures"),
+            )?;
+            let checked = if registry::out_of_process() {
+                MF_CHECKED
+            } else {
+                MF_UNCHECKED
+            };
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING | checked,
+                SystemMenu::OutOfProcess as _,
+                w!("Run drop handler out of process"),
+            )?;
+            let checked = if registry::copy_path_for_all_files() {
+                MF_CHECKED
+            } else {
+                MF_UNCHECKED
+            };
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING | checked,
+                SystemMenu::CopyPathAllFiles as _,
+                w!("Copy WSL path for all files"),
+            )?;
+            let checked = if registry::managed_icons() {
+                MF_CHECKED
+            } else {
+                MF_UNCHECKED
+            };
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING | checked,
+                SystemMenu::ManagedIcons as _,
+                w!("Keep copies of icons"),
+            )?;
+            AppendMenuW(
+                menu,
+                MF_ENABLED | MF_STRING,
+                SystemMenu::CopyDiagnostics as _,
+                w!("Copy diagnostics"),
+            )?;
+            // WSL control submenu, destroyed along with the system menu
+            let submenu = CreatePopupMenu()?;
+            AppendMenuW(
+                submenu,
+                MF_ENABLED | MF_STRING,
+                SystemMenu::ShutdownWsl as _,
+                w!("Shut down WSL"),
+            )?;
+            AppendMenuW(
+                submenu,
+                MF_ENABLED | MF_STRING,
+                SystemMenu::RestartDistro as _,
+                w!("Restart selected distribution"),
+            )?;
+            let terminate = CreatePopupMenu()?;
+            for (i, (_, name)) in self.distros.sorted_pairs().iter().enumerate() {
+                let s = wcstring(name);
+                AppendMenuW(
+                    terminate,
+                    MF_ENABLED | MF_STRING,
+                    (TERMINATE_MENU_BASE + i as u32) as _,
+                    PCWSTR(s.as_ptr()),
+                )?;
+            }
+            AppendMenuW(
+                submenu,
+                MF_POPUP,
+                terminate.0 as _,
+                w!("Terminate distribution"),
+            )?;
+            AppendMenuW(menu, MF_POPUP, submenu.0 as _, w!("WSL control"))?;
+        }
+        Ok(())
+    }
+
+    /// Terminate a distribution selected from "Terminate distribution" submenu.
+    ///
+    /// `idx` is the index in `Distros::sorted_pairs`.
+    fn on_terminate_distro(&self, idx: usize) {
+        let Some(name) = self
+            .distros
+            .sorted_pairs()
+            .get(idx)
+            .map(|(_, name)| name.to_string())
+        else {
+            return;
+        };
+        if self.confirm_wsl_control(&format!(
+            "Terminate {}? Scripts running in it are stopped.",
+            name
+        )) {
+            if let Err(e) = wsl::terminate_distro(std::ffi::OsStr::new(&name)) {
+                self.show_error(format!("Failed to terminate {}", name), &e);
+            }
+        }
+    }
+
+    /// Ask user to confirm a WSL control action.
+    fn confirm_wsl_control(&self, text: &str) -> bool {
+        let result = unsafe {
+            MessageBoxW(
+                self.hwnd.get(),
+                PCWSTR(wcstring(text).as_ptr()),
+                w!("WSL control"),
+                MB_OKCANCEL | MB_ICONWARNING | MB_DEFBUTTON2,
+            )
+        };
+        result == IDOK
+    }
+
+    /// Handle WM_SYSCOMMAND message when custom menu item was selected.
+    fn on_system_menu_command(&mut self, id: SystemMenu) -> LRESULT {
+        match id {
+            SystemMenu::About => {
+                let mut text = format!("WSL Script");
+                if let Ok(p) = std::env::current_exe() {
+                    if let Some(version) = wslscript_common::ver::product_version(&p) {
+                        text.push_str(&format!("\nVersion {}", version));
+                    }
+                };
+                unsafe {
+                    MessageBoxW(
+                        self.hwnd.get(),
+                        PCWSTR(wcstring(text).as_ptr()),
+                        w!("About WSL Script"),
+                        MB_OK | MB_ICONINFORMATION,
+                    );
+                }
+                LRESULT(0)
+            }
+            SystemMenu::Homepage => {
+                unsafe {
+                    Shell::ShellExecuteW(
+                        None,
+                        w!("open"),
+                        w!("https://sop.github.io/wslscript/"),
+                        PCWSTR::null(),
+                        PCWSTR::null(),
+                        SW_SHOWNORMAL,
+                    );
+                }
+                LRESULT(0)
+            }
+            SystemMenu::ChangeLog => {
+                match wslscript_common::audit::log_path().filter(|p| p.exists()) {
+                    Some(path) => unsafe {
+                        Shell::ShellExecuteW(
+                            None,
+                            w!("open"),
+                            PCWSTR(WideCString::from_os_str_truncate(&path).as_ptr()),
+                            PCWSTR::null(),
+                            PCWSTR::null(),
+                            SW_SHOWNORMAL,
+                        );
+                    },
+                    None => unsafe {
+                        MessageBoxW(
+                            self.hwnd.get(),
+                            w!("No changes have been logged yet."),
+                            w!("Change log"),
+                            MB_OK | MB_ICONINFORMATION,
+                        );
+                    },
+                }
+                LRESULT(0)
+            }
+            SystemMenu::NewScript => {
+                if let Err(e) = self.new_script() {
+                    self.show_error("Failed to create script", &e);
+                }
+                LRESULT(0)
+            }
+            SystemMenu::ShutdownWsl => {
+                if self.confirm_wsl_control(
+                    "Shut down WSL? All running distributions and scripts are stopped.",
+                ) {
+                    if let Err(e) = wsl::shutdown_wsl() {
+                        self.show_error("Failed to shut down WSL", &e);
+                    }
+                }
+                LRESULT(0)
+            }
+            SystemMenu::RestartDistro => {
+                let guid = self
+                    .current_ext_cfg
+                    .as_ref()
+                    .and_then(|cfg| cfg.distro.clone())
+                    .or_else(|| self.distros.default.clone());
+                let Some(name) = guid.and_then(|guid| self.distros.list.get(&guid).cloned()) else {
+                    return LRESULT(0);
+                };
+                if self.confirm_wsl_control(&format!(
+                    "Restart {}? Scripts running in it are stopped.",
+                    name
+                )) {
+                    if let Err(e) = wsl::restart_distro(std::ffi::OsStr::new(&name)) {
+                        self.show_error(format!("Failed to restart {}", name), &e);
+                    }
+                }
+                LRESULT(0)
+            }
+            SystemMenu::CopyDiagnostics => {
+                let report = diagnostics::report();
+                match diagnostics::copy_to_clipboard(self.hwnd.get(), &report) {
+                    Ok(()) => unsafe {
+                        MessageBoxW(
+                            self.hwnd.get(),
+                            w!("Diagnostics were copied to the clipboard."),
+                            w!("Copy diagnostics"),
+                            MB_OK | MB_ICONINFORMATION,
+                        );
+                    },
+                    Err(e) => self.show_error("Failed to copy diagnostics", &e),
+                }
+                LRESULT(0)
+            }
+            SystemMenu::VerifySignatures => {
+                let enabled = !registry::verify_signatures();
+                if let Err(e) = registry::set_verify_signatures(enabled) {
+                    self.show_error("Failed to change signature verification", &e);
+                    return LRESULT(0);
+                }
+                let checked = if enabled { MF_CHECKED } else { MF_UNCHECKED };
+                let menu = unsafe { GetSystemMenu(self.hwnd.get(), FALSE) };
+                unsafe { CheckMenuItem(menu, SystemMenu::VerifySignatures as _, checked.0) };
+                self.signature_check.replace(None);
+                self.update_control_states();
+                LRESULT(0)
+            }
+            SystemMenu::OutOfProcess => {
+                let enabled = !registry::out_of_process();
+                let result = registry::set_out_of_process(enabled)
+                    .and_then(|_| registry::reregister_server());
+                if let Err(e) = result {
+                    // keep the setting in line with the registered server
+                    let _ = registry::set_out_of_process(!enabled);
+                    self.show_error("Failed to change drop handler mode", &e);
+                    return LRESULT(0);
+                }
+                let checked = if enabled { MF_CHECKED } else { MF_UNCHECKED };
+                let menu = unsafe { GetSystemMenu(self.hwnd.get(), FALSE) };
+                unsafe { CheckMenuItem(menu, SystemMenu::OutOfProcess as _, checked.0) };
+                LRESULT(0)
+            }
+            SystemMenu::CopyPathAllFiles => {
+                let enabled = !registry::copy_path_for_all_files();
+                if let Err(e) = registry::set_copy_path_for_all_files(enabled) {
+                    self.show_error("Failed to change Copy WSL path verb", &e);
+                    return LRESULT(0);
+                }
+                let checked = if enabled { MF_CHECKED } else { MF_UNCHECKED };
+                let menu = unsafe { GetSystemMenu(self.hwnd.get(), FALSE) };
+                unsafe { CheckMenuItem(menu, SystemMenu::CopyPathAllFiles as _, checked.0) };
+                LRESULT(0)
+            }
+            SystemMenu::ManagedIcons => {
+                let enabled = !registry::managed_icons();
+                if let Err(e) = registry::set_managed_icons(enabled) {
+                    self.show_error("Failed to change icon copies", &e);
+                    return LRESULT(0);
+                }
+                let checked = if enabled { MF_CHECKED } else { MF_UNCHECKED };
+                let menu = unsafe { GetSystemMenu(self.hwnd.get(), FALSE) };
+                unsafe { CheckMenuItem(menu, SystemMenu::ManagedIcons as _, checked.0) };
+                LRESULT(0)
+            }
+        }
+    }
+
+    /// Handle WM_SIZE message.
+    ///
+    /// * `width` - Window width
+    /// * `height` - Window height
+    fn on_resize(&self, width: i32, _height: i32) {
+        self.move_control(Control::StaticMsg, 10, 10, width - 20, 40);
+        self.move_control(Control::RegisterLabel, 10, 50, 60, 25);
+        self.move_control(Control::EditExtension, 80, 50, width - 90 - 100, 25);
+        self.move_control(Control::BtnRegister, width - 100, 50, 90, 25);
+        self.move_control(Control::FilterEdit, 10, 85, width - 20, 22);
+        self.move_control(Control::ListViewExtensions, 10, 115, width - 20, 75);
+        self.move_control(Control::HoldModeLabel, 10, 200, 130, 20);
+        self.move_control(Control::HoldModeCombo, 10, 220, 130, 100);
+        self.move_control(Control::InteractiveLabel, 170, 220, 130, 20);
+        self.move_control(Control::InteractiveCheckbox, 150, 220, 20, 20);
+        self.move_control(Control::DistroLabel, 10, 250, 130, 20);
+        self.move_control(Control::DistroCombo, 10, 270, 130, 100);
+        self.move_control(Control::IconLabel, 150, 250, 32, 16);
+        self.move_control(Control::StaticIcon, 150, 266, 32, 32);
+        self.move_control(Control::IconPathEdit, 190, 246, width - 200, 22);
+        self.move_control(Control::UseHelperCheckbox, 200, 274, 20, 20);
+        self.move_control(Control::UseHelperLabel, 220, 274, width - 230, 20);
+        self.move_control(Control::CrlfModeLabel, 10, 300, 130, 20);
+        self.move_control(Control::CrlfModeCombo, 10, 320, 130, 100);
+        self.move_control(Control::RawArgsLabel, 170, 320, 130, 20);
+        self.move_control(Control::RawArgsCheckbox, 150, 320, 20, 20);
+        self.move_control(Control::ConsoleAtDropLabel, 30, 350, 110, 20);
+        self.move_control(Control::ReuseConsoleLabel, 170, 350, 130, 20);
+        self.move_control(Control::ReuseConsoleCheckbox, 150, 350, 20, 20);
+        self.move_control(Control::ConsoleAtDropCheckbox, 10, 350, 20, 20);
+        self.move_control(Control::ConsoleTitleLabel, 10, 380, 130, 20);
+        self.move_control(Control::ConsoleTitleEdit, 10, 400, width - 160, 22);
+        self.move_control(Control::Utf8ConsoleCheckbox, width - 140, 401, 20, 20);
+        self.move_control(Control::Utf8ConsoleLabel, width - 120, 401, 110, 20);
+        self.move_control(Control::ConsoleSizeLabel, 10, 430, 130, 20);
+        self.move_control(Control::ConsoleSizeEdit, 10, 450, 130, 22);
+        self.move_control(Control::ConsoleColorsLabel, 150, 430, 130, 20);
+        self.move_control(Control::ConsoleColorsEdit, 150, 450, 40, 22);
+        self.move_control(Control::CategoryLabel, 200, 430, width - 210, 20);
+        self.move_control(Control::CategoryEdit, 200, 450, width - 210, 22);
+        self.move_control(Control::HoldMessageLabel, 10, 480, width - 160, 20);
+        self.move_control(Control::HoldMessageEdit, 10, 500, width - 160, 22);
+        self.move_control(Control::HoldKeyLabel, width - 140, 480, 130, 20);
+        self.move_control(Control::HoldKeyCombo, width - 140, 500, 80, 100);
+        self.move_control(Control::HoldTimeoutEdit, width - 55, 500, 45, 22);
+        self.move_control(Control::DropPolicyLabel, 10, 530, 130, 20);
+        self.move_control(Control::DropPolicyCombo, 10, 550, 130, 100);
+        self.move_control(Control::DropCountEdit, 150, 550, 40, 22);
+        self.move_control(Control::MaxArgsLabel, 200, 530, width - 210, 20);
+        self.move_control(Control::MaxArgsEdit, 200, 550, 60, 22);
+        self.move_control(Control::ShowProgressCheckbox, 270, 550, 20, 20);
+        self.move_control(Control::ShowProgressLabel, 290, 550, width - 300, 20);
+        self.move_control(Control::ArgOrderLabel, 10, 580, 130, 20);
+        self.move_control(Control::ArgOrderCombo, 10, 600, 130, 100);
+        self.move_control(Control::AbortOnMissingCheckbox, 150, 600, 20, 20);
+        self.move_control(Control::AbortOnMissingLabel, 170, 600, width - 180, 20);
+        self.move_control(Control::MinIntervalLabel, 10, 630, 130, 20);
+        self.move_control(Control::MinIntervalEdit, 10, 650, 60, 22);
+        self.move_control(Control::MaxInstancesLabel, 150, 630, 70, 20);
+        self.move_control(Control::MaxInstancesEdit, 150, 650, 60, 22);
+        self.move_control(Control::SingleInstanceCheckbox, 230, 650, 20, 20);
+        self.move_control(Control::SingleInstanceLabel, 250, 650, width - 260, 20);
+        self.move_control(Control::EventLogLabel, 10, 680, 130, 20);
+        self.move_control(Control::EventLogCombo, 10, 700, 130, 100);
+        self.move_control(Control::WslEnvLabel, 150, 680, width - 160, 20);
+        self.move_control(Control::WslEnvEdit, 150, 700, width - 160, 22);
+        self.move_control(Control::LogMaxAgeLabel, 10, 730, 130, 20);
+        self.move_control(Control::LogMaxAgeEdit, 10, 750, 60, 22);
+        self.move_control(Control::LogMaxFilesLabel, 150, 730, width - 160, 20);
+        self.move_control(Control::LogMaxFilesEdit, 150, 750, 60, 22);
+        self.move_control(Control::ArgChmodLabel, 10, 780, 130, 20);
+        self.move_control(Control::ArgChmodEdit, 10, 800, 130, 22);
+        self.move_control(Control::ArgChownLabel, 150, 780, width - 160, 20);
+        self.move_control(Control::ArgChownEdit, 150, 800, 130, 22);
+        self.move_control(Control::AssociationLabel, 10, 830, 130, 20);
+        self.move_control(Control::AssociationCombo, 10, 850, 130, 100);
+        self.move_control(Control::TypeNameLabel, 150, 830, width - 160, 20);
+        self.move_control(Control::TypeNameEdit, 150, 850, width - 160, 22);
+        self.move_control(Control::CommandPreviewLabel, 10, 880, width - 20, 20);
+        self.move_control(Control::CommandPreviewEdit, 10, 900, width - 20, 60);
+        self.move_control(Control::BtnSave, width - 90, 970, 80, 25);
+        self.move_control(Control::BtnTestRun, width - 180, 970, 80, 25);
+        self.move_control(Control::StaticErrorBanner, 10, 1005, width - 110, 40);
+        self.move_control(Control::BtnErrorDetails, width - 90, 1005, 80, 25);
+    }
+
+    /// Move window control.
+    ///
+    /// Position and size are given at 96 DPI and scaled to the window's DPI.
+    fn move_control(&self, control: Control, x: i32, y: i32, width: i32, height: i32) {
+        let hwnd = self.get_control_handle(control);
+        let [x, y, width, height] = [x, y, width, height].map(|v| scale_for_dpi(v, self.dpi));
+        let _ = unsafe { MoveWindow(hwnd, x, y, width, height, TRUE) };
+    }
+
+    /// Create fonts for the window's DPI.
+    fn create_fonts(&mut self) -> Result<(), Error> {
+        self.caption_font = Font::new_caption_for_dpi(0, self.dpi)?;
+        self.ext_font = Font::new_caption_for_dpi(24, self.dpi)?;
+        Ok(())
+    }
+
+    /// Handle WM_DPICHANGED message.
+    ///
+    /// Fonts are recreated for the new DPI and the window is moved to
+    /// the suggested position, which lays out the controls again.
+    fn on_dpi_changed(&mut self, dpi: u32, suggested: &RECT) -> Result<(), Error> {
+        self.dpi = dpi;
+        self.create_fonts()?;
+        unsafe extern "system" fn set_font(hwnd: HWND, font: LPARAM) -> BOOL {
+            SendMessageW(hwnd, WM_SETFONT, WPARAM(font.0 as _), LPARAM(TRUE.0 as _));
+            TRUE
+        }
+        let font = LPARAM(self.caption_font.handle().0 as _);
+        let _ = unsafe { EnumChildWindows(self.hwnd.get(), Some(set_font), font) };
+        self.lv_extensions.set_dpi(dpi);
+        // message area may use the extension font
+        self.update_control_states();
+        let _ = unsafe {
+            SetWindowPos(
+                self.hwnd.get(),
+                None,
+                suggested.left,
+                suggested.top,
+                suggested.right - suggested.left,
+                suggested.bottom - suggested.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            )
+        };
+        Ok(())
+    }
+
+    /// Handle WM_COMMAND message from a control.
+    ///
+    /// * `hwnd` - Handle of the sending control
+    /// * `control_id` - ID of the sending control
+    /// * `code` - Notification code
+    fn on_control(&mut self, hwnd: HWND, control_id: Control, code: u16) -> Result<LRESULT, Error> {
+        #[allow(clippy::single_match)]
+        match control_id {
+            Control::BtnRegister => match code as u32 {
+                BN_CLICKED => return self.on_register_button_clicked(),
+                _ => {}
+            },
+            Control::FilterEdit => match code as u32 {
+                EN_CHANGE => self.on_filter_changed(),
+                _ => {}
+            },
+            Control::HoldModeCombo => match code as u32 {
+                CBN_SELCHANGE => {
+                    self.set_dirty(true);
+                    // carry over the seconds from the timeout input
+                    let secs = self.get_hold_timeout();
+                    let mode = match self.get_selected_hold_mode() {
+                        Some(registry::HoldMode::Timeout(secs_default)) => {
+                            Some(registry::HoldMode::Timeout(secs.unwrap_or(secs_default)))
+                        }
+                        mode => mode,
+                    };
+                    if let Some(mode) = mode {
+                        if let Some(cfg) = &mut self.current_ext_cfg {
+                            cfg.hold_mode = mode;
+                        }
+                    }
+                    self.update_hold_inputs();
+                }
+                _ => {}
+            },
+            Control::EventLogCombo => match code as u32 {
+                CBN_SELCHANGE => {
+                    self.set_dirty(true);
+                    if let Some(mode) = self.get_selected_event_log() {
+                        if let Some(cfg) = &mut self.current_ext_cfg {
+                            cfg.event_log = mode;
+                        }
+                        self.update_log_inputs();
+                        if mode != registry::EventLog::Off {
+                            self.ensure_event_source();
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Control::AssociationCombo => match code as u32 {
+                CBN_SELCHANGE => {
+                    self.set_dirty(true);
+                    if let Some(association) = self.get_selected_association() {
+                        if let Some(cfg) = &mut self.current_ext_cfg {
+                            cfg.association = association;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Control::CrlfModeCombo => match code as u32 {
+                CBN_SELCHANGE => {
+                    self.set_dirty(true);
+                    if let Some(mode) = self.get_selected_crlf_mode() {
+                        if let Some(cfg) = &mut self.current_ext_cfg {
+                            cfg.crlf_mode = mode;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Control::InteractiveCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_interactive_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.interactive = state;
+                    }
+                }
+                _ => {}
+            },
+            Control::InteractiveLabel => match code as u32 {
+                // when interactive shell label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_interactive_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.interactive = state;
+                    }
+                    self.set_interactive_state(state);
+                }
+                _ => {}
+            },
+            Control::RawArgsCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_raw_args_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.raw_args = state;
+                    }
+                }
+                _ => {}
             },
-            Control::HoldModeCombo => match code {
-                CBN_SELCHANGE => {
-                    if let Some(mode) = self.get_selected_hold_mode() {
-                        if let Some(cfg) = &mut self.current_ext_cfg {
-                            cfg.hold_mode = mode;
-                        }
+            Control::RawArgsLabel => match code as u32 {
+                // when raw arguments label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_raw_args_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.raw_args = state;
                     }
+                    self.set_raw_args_state(state);
                 }
                 _ => {}
             },
-            Control::InteractiveCheckbox => match code {
+            Control::ConsoleAtDropCheckbox => match code as u32 {
                 BN_CLICKED => {
-                    let state = self.get_interactive_state();
+                    self.set_dirty(true);
+                    let state = self.get_console_at_drop_state();
                     if let Some(cfg) = &mut self.current_ext_cfg {
-                        cfg.interactive = state;
+                        cfg.console_at_drop = state;
                     }
                 }
                 _ => {}
             },
-            Control::InteractiveLabel => match code {
-                // when interactive shell label is clicked
+            Control::ConsoleAtDropLabel => match code as u32 {
+                // when console at drop point label is clicked
                 STN_CLICKED => {
-                    let state = !self.get_interactive_state();
+                    self.set_dirty(true);
+                    let state = !self.get_console_at_drop_state();
                     if let Some(cfg) = &mut self.current_ext_cfg {
-                        cfg.interactive = state;
+                        cfg.console_at_drop = state;
                     }
-                    self.set_interactive_state(state);
+                    self.set_console_at_drop_state(state);
+                }
+                _ => {}
+            },
+            Control::ReuseConsoleCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_reuse_console_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.reuse_console = state;
+                    }
+                }
+                _ => {}
+            },
+            Control::ReuseConsoleLabel => match code as u32 {
+                // when reuse console label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_reuse_console_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.reuse_console = state;
+                    }
+                    self.set_reuse_console_state(state);
+                }
+                _ => {}
+            },
+            Control::AbortOnMissingCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_abort_on_missing_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.abort_on_missing = state;
+                    }
+                }
+                _ => {}
+            },
+            Control::AbortOnMissingLabel => match code as u32 {
+                // when abort on missing files label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_abort_on_missing_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.abort_on_missing = state;
+                    }
+                    self.set_abort_on_missing_state(state);
+                }
+                _ => {}
+            },
+            Control::UseHelperCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_use_helper_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.use_helper = state;
+                    }
+                }
+                _ => {}
+            },
+            Control::Utf8ConsoleCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_utf8_console_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.utf8_console = state;
+                    }
+                }
+                _ => {}
+            },
+            Control::Utf8ConsoleLabel => match code as u32 {
+                // when utf-8 console label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_utf8_console_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.utf8_console = state;
+                    }
+                    self.set_utf8_console_state(state);
+                }
+                _ => {}
+            },
+            Control::ShowProgressCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_show_progress_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.show_progress = state;
+                    }
+                }
+                _ => {}
+            },
+            Control::ShowProgressLabel => match code as u32 {
+                // when show progress label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_show_progress_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.show_progress = state;
+                    }
+                    self.set_show_progress_state(state);
+                }
+                _ => {}
+            },
+            Control::SingleInstanceCheckbox => match code as u32 {
+                BN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = self.get_single_instance_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.single_instance = state;
+                    }
+                }
+                _ => {}
+            },
+            Control::SingleInstanceLabel => match code as u32 {
+                // when single instance label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_single_instance_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.single_instance = state;
+                    }
+                    self.set_single_instance_state(state);
+                }
+                _ => {}
+            },
+            Control::UseHelperLabel => match code as u32 {
+                // when use helper label is clicked
+                STN_CLICKED => {
+                    self.set_dirty(true);
+                    let state = !self.get_use_helper_state();
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.use_helper = state;
+                    }
+                    self.set_use_helper_state(state);
                 }
                 _ => {}
             },
-            Control::DistroCombo => match code {
+            Control::DistroCombo => match code as u32 {
                 CBN_SELCHANGE => {
+                    self.set_dirty(true);
                     let distro = self.get_selected_distro();
+                    let name = self.distros.name(distro.as_ref()).map(str::to_owned);
                     if let Some(cfg) = &mut self.current_ext_cfg {
                         cfg.distro = distro;
+                        // follow the distribution unless a custom icon was chosen
+                        if cfg.icon.as_ref().is_none_or(ShellIcon::is_default) {
+                            if let Ok(icon) = ShellIcon::load_default_for(name.as_deref()) {
+                                cfg.icon = Some(icon);
+                            }
+                        }
                     }
+                    self.update_control_states();
                 }
                 _ => {}
             },
-            Control::StaticIcon => match code {
+            Control::StaticIcon => match code as u32 {
                 STN_DBLCLK => {
                     if let Some(icon) = self.pick_icon_dlg() {
                         if let Some(cfg) = &mut self.current_ext_cfg {
                             cfg.icon = Some(icon);
                         }
+                        self.set_dirty(true);
                         self.update_control_states();
                     }
                 }
                 _ => {}
             },
-            Control::BtnSave => match code {
+            Control::IconPathEdit => match code as u32 {
+                EN_CHANGE
+                    if unsafe {
+                        SendMessageW(hwnd, Controls::EM_GETMODIFY, WPARAM(0), LPARAM(0))
+                    }
+                    .0 != 0 =>
+                {
+                    self.set_dirty(true);
+                    self.preview_icon_location();
+                }
+                _ => {}
+            },
+            Control::HoldKeyCombo => match code as u32 {
+                CBN_SELCHANGE => {
+                    self.set_dirty(true);
+                    let key = match self.get_selected_hold_key() {
+                        // keep the previous timeout if there was one
+                        registry::HoldKey::Timeout(_) => match self.current_ext_cfg.as_ref() {
+                            Some(cfg) if matches!(cfg.hold_key, registry::HoldKey::Timeout(_)) => {
+                                cfg.hold_key
+                            }
+                            _ => registry::HoldKey::Timeout(DEFAULT_HOLD_TIMEOUT),
+                        },
+                        key => key,
+                    };
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.hold_key = key;
+                    }
+                    self.set_selected_hold_key(key);
+                    self.update_hold_inputs();
+                }
+                _ => {}
+            },
+            Control::ArgOrderCombo => match code as u32 {
+                CBN_SELCHANGE => {
+                    self.set_dirty(true);
+                    if let Some(order) = self.get_selected_arg_order() {
+                        if let Some(cfg) = &mut self.current_ext_cfg {
+                            cfg.arg_order = order;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Control::DropPolicyCombo => match code as u32 {
+                CBN_SELCHANGE => {
+                    self.set_dirty(true);
+                    let policy = match (
+                        self.get_selected_drop_policy(),
+                        self.current_ext_cfg.as_ref().map(|cfg| cfg.drop_policy),
+                    ) {
+                        // keep the previous count if the policy didn't change
+                        (
+                            registry::DropPolicy::PerFile(_),
+                            Some(p @ registry::DropPolicy::PerFile(_)),
+                        )
+                        | (
+                            registry::DropPolicy::Batch(_),
+                            Some(p @ registry::DropPolicy::Batch(_)),
+                        ) => p,
+                        (policy, _) => policy,
+                    };
+                    if let Some(cfg) = &mut self.current_ext_cfg {
+                        cfg.drop_policy = policy;
+                    }
+                    self.set_selected_drop_policy(policy);
+                }
+                _ => {}
+            },
+            Control::ConsoleTitleEdit
+            | Control::ConsoleSizeEdit
+            | Control::ConsoleColorsEdit
+            | Control::CategoryEdit
+            | Control::TypeNameEdit
+            | Control::HoldMessageEdit
+            | Control::HoldTimeoutEdit
+            | Control::DropCountEdit
+            | Control::MaxArgsEdit
+            | Control::MinIntervalEdit
+            | Control::MaxInstancesEdit
+            | Control::LogMaxAgeEdit
+            | Control::LogMaxFilesEdit
+            | Control::ArgChmodEdit
+            | Control::ArgChownEdit
+            | Control::WslEnvEdit => match code as u32 {
+                // modify flag is cleared when the text is set programmatically
+                EN_CHANGE
+                    if unsafe {
+                        SendMessageW(hwnd, Controls::EM_GETMODIFY, WPARAM(0), LPARAM(0))
+                    }
+                    .0 != 0 =>
+                {
+                    self.set_dirty(true);
+                }
+                _ => {}
+            },
+            Control::BtnSave => match code as u32 {
                 BN_CLICKED => return self.on_save_button_clicked(),
                 _ => {}
             },
+            Control::BtnTestRun => match code as u32 {
+                BN_CLICKED => return self.on_test_run_button_clicked(),
+                _ => {}
+            },
+            Control::StaticErrorBanner => match code as u32 {
+                // dismiss error when banner is clicked
+                STN_CLICKED => {
+                    self.error.replace(None);
+                    self.update_error_banner();
+                }
+                _ => {}
+            },
+            Control::BtnErrorDetails => match code as u32 {
+                BN_CLICKED => {
+                    if let Some(banner) = self.error.borrow_mut().as_mut() {
+                        banner.expanded = !banner.expanded;
+                    }
+                    self.update_error_banner();
+                }
+                _ => {}
+            },
             _ => {}
         }
-        Ok(0)
+        // setting preview text notifies of a change as well
+        if control_id != Control::CommandPreviewEdit {
+            self.update_command_preview();
+        }
+        Ok(LRESULT(0))
     }
 
     /// Handle register button click.
-    fn on_register_button_clicked(&mut self) -> Result<win::LRESULT, Error> {
-        let ext = self
-            .get_extension_input_text()
-            .trim_matches('.')
-            .to_string();
-        if ext.is_empty() {
-            return Ok(0);
+    fn on_register_button_clicked(&mut self) -> Result<LRESULT, Error> {
+        let ext = registry::normalize_extension(self.get_extension_input_text().trim_matches('.'));
+        if ext.is_empty() || !self.confirm_discard_changes() {
+            return Ok(LRESULT(0));
+        }
+        // compound extensions, eg. tar.gz, may not have empty parts
+        if ext.split('.').any(str::is_empty) {
+            self.message = Some(format!("Invalid extension .{}", ext));
+            self.update_control_states();
+            return Ok(LRESULT(0));
         }
         if registry::is_registered_for_other(&ext)? {
             let s = wcstring(format!(
@@ -720,75 +2963,555 @@ impl MainWindow {
             ));
             let result = unsafe {
                 MessageBoxW(
-                    self.hwnd,
-                    s.as_ptr(),
-                    wchz!("Confirm extension registration.").as_ptr(),
+                    self.hwnd.get(),
+                    PCWSTR(s.as_ptr()),
+                    w!("Confirm extension registration."),
                     MB_YESNO | MB_ICONQUESTION | MB_DEFBUTTON2,
                 )
             };
             if result == IDNO {
-                return Ok(0);
+                return Ok(LRESULT(0));
             }
         }
-        let icon = ShellIcon::load_default()?;
+        let icon = ShellIcon::load_default_for(self.distros.name(None))?;
         let config = registry::ExtConfig {
             extension: ext.clone(),
             icon: Some(icon),
-            hold_mode: registry::HoldMode::Error,
-            interactive: false,
-            distro: None,
+            ..Default::default()
         };
         registry::register_extension(&config)?;
         // clear extension input
         self.set_extension_input_text(wcstr(wchz!("")));
         let idx = self.lv_extensions.find_ext(&ext).or_else(|| {
             // insert to listview
-            if let Some(item) = self.lv_extensions.insert_item(0, &wcstring(&ext)) {
+            let label = wcstring(&ext);
+            if let Some(item) = self
+                .lv_extensions
+                .insert_item(0, &label, config.icon.as_ref())
+            {
                 let name = self.get_distro_label(None);
                 self.lv_extensions
                     .set_subitem_text(item, 1, &wcstring(name));
                 return Some(item);
             }
-            None
-        });
-        self.set_current_extension(idx);
-        self.message = Some(format!("Registered .{} extension.", &ext));
-        self.update_control_states();
-        Ok(0)
+            None
+        });
+        self.set_current_extension(idx);
+        self.update_groups();
+        self.message = Some(format!("Registered .{} extension.", &ext));
+        // Explorer picks the handler by the last extension only
+        if let Some((_, last)) = ext.rsplit_once('.') {
+            if !registry::is_extension_registered_for_wsl(last).unwrap_or(false) {
+                self.message = Some(format!(
+                    "Registered .{}, register .{} too to run these files.",
+                    &ext, last
+                ));
+            }
+        }
+        self.update_control_states();
+        Ok(LRESULT(0))
+    }
+
+    /// Handle save button click.
+    fn on_save_button_clicked(&mut self) -> Result<LRESULT, Error> {
+        if !self.read_text_settings() {
+            return Ok(LRESULT(0));
+        }
+        if let Some(config) = self.current_ext_cfg.as_ref() {
+            registry::register_extension(config)?;
+            self.message = Some(format!("Saved .{} extension.", config.extension));
+            self.update_control_states();
+            if let Some(item) = self.current_ext_idx {
+                let name = self.get_distro_label(config.distro.as_ref());
+                self.lv_extensions
+                    .set_subitem_text(item, 1, &wcstring(name));
+                self.lv_extensions.set_item_icon(item, config.icon.as_ref());
+            }
+            self.update_groups();
+            self.set_dirty(false);
+        }
+        Ok(LRESULT(0))
+    }
+
+    /// Handle test run button click.
+    ///
+    /// Runs a script chosen by the user with the saved settings of the
+    /// current extension, and displays its output in a console window.
+    fn on_test_run_button_clicked(&mut self) -> Result<LRESULT, Error> {
+        let ext = match self.get_current_extension() {
+            Some(ext) => ext,
+            None => return Ok(LRESULT(0)),
+        };
+        let path = match self.select_script_file(&ext) {
+            Some(path) => path,
+            None => return Ok(LRESULT(0)),
+        };
+        let opts = wsl::WSLOptions::from_ext(&ext).unwrap_or_default();
+        self.start_test_run(path, &opts)
+    }
+
+    /// Run the script of the latest test run again with the current,
+    /// possibly unsaved, settings of the extension.
+    ///
+    /// User is asked for a script if there hasn't been a test run yet.
+    fn on_rerun_test(&mut self) -> Result<LRESULT, Error> {
+        if !self.read_text_settings() {
+            return Ok(LRESULT(0));
+        }
+        let config = match self.current_ext_cfg.as_ref() {
+            Some(config) => config,
+            None => return Ok(LRESULT(0)),
+        };
+        let opts = wsl::WSLOptions::from(config);
+        let path = match self
+            .test_script
+            .clone()
+            .filter(|path| path.is_file())
+            .or_else(|| self.select_script_file(&config.extension))
+        {
+            Some(path) => path,
+            None => return Ok(LRESULT(0)),
+        };
+        self.start_test_run(path, &opts)
+    }
+
+    /// Run a script and display its output in a console window.
+    fn start_test_run(
+        &mut self,
+        path: std::path::PathBuf,
+        opts: &wsl::WSLOptions,
+    ) -> Result<LRESULT, Error> {
+        let wsl_path = wsl::paths_to_wsl(std::slice::from_ref(&path), opts, None)?
+            .pop()
+            .ok_or(Error::InvalidPathError)?;
+        let cmdline = wsl::test_run_command_line(&path, &wsl_path, opts)?;
+        let name = path
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // previous output window is closed
+        self.console = None;
+        let mut wnd =
+            console::ConsoleWindow::new(self.hwnd.get(), &format!("{} - WSL Script", name))?;
+        wnd.start(&cmdline)?;
+        self.console = Some(wnd);
+        self.test_script = Some(path);
+        Ok(LRESULT(0))
+    }
+
+    /// Prompt user to select a script file with given extension.
+    fn select_script_file(&self, ext: &str) -> Option<std::path::PathBuf> {
+        use windows::Win32::UI::Controls::Dialogs::*;
+        // filter is a list of nul terminated description and pattern pairs
+        let filter: Vec<u16> = format!(".{ext} scripts\0*.{ext}\0All files\0*.*\0\0")
+            .encode_utf16()
+            .collect();
+        let mut buf = [0_u16; 1024];
+        let mut ofn = OPENFILENAMEW {
+            lStructSize: mem::size_of::<OPENFILENAMEW>() as u32,
+            hwndOwner: self.hwnd.get(),
+            lpstrFilter: PCWSTR(filter.as_ptr()),
+            lpstrFile: PWSTR(buf.as_mut_ptr()),
+            nMaxFile: buf.len() as u32,
+            lpstrTitle: w!("Select script to run"),
+            Flags: OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST | OFN_HIDEREADONLY,
+            ..Default::default()
+        };
+        if !unsafe { GetOpenFileNameW(&mut ofn) }.as_bool() {
+            return None;
+        }
+        let path = unsafe { WideCStr::from_ptr_str(buf.as_ptr()) };
+        Some(std::path::PathBuf::from(path.to_os_string()))
+    }
+
+    /// Create a script from the template in a location chosen by the user.
+    ///
+    /// `.sh` extension is registered first if it isn't already,
+    /// so that the script can be run right away.
+    fn new_script(&mut self) -> Result<(), Error> {
+        let Some(path) = self.select_new_script_file() else {
+            return Ok(());
+        };
+        if !registry::is_extension_registered_for_wsl("sh")? {
+            self.set_extension_input_text(&DEFAULT_EXTENSION);
+            self.on_register_button_clicked()?;
+        }
+        let opts = registry::get_extension_config("sh")
+            .map(|cfg| wsl::WSLOptions::from(&cfg))
+            .unwrap_or_default();
+        wsl::create_script(&path, &opts)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.message = Some(format!("Created {}.", name));
+        self.update_control_states();
+        // show the script in Explorer
+        let params = wcstring(format!("/select,\"{}\"", path.display()));
+        unsafe {
+            Shell::ShellExecuteW(
+                None,
+                w!("open"),
+                w!("explorer.exe"),
+                PCWSTR(params.as_ptr()),
+                PCWSTR::null(),
+                SW_SHOWNORMAL,
+            );
+        }
+        Ok(())
+    }
+
+    /// Prompt user to choose where to save a new script.
+    fn select_new_script_file(&self) -> Option<std::path::PathBuf> {
+        use windows::Win32::UI::Controls::Dialogs::*;
+        // filter is a list of nul terminated description and pattern pairs
+        let filter: Vec<u16> = ".sh scripts\0*.sh\0\0".encode_utf16().collect();
+        let mut buf = [0_u16; 1024];
+        for (dst, src) in buf.iter_mut().zip("script.sh".encode_utf16()) {
+            *dst = src;
+        }
+        let mut ofn = OPENFILENAMEW {
+            lStructSize: mem::size_of::<OPENFILENAMEW>() as u32,
+            hwndOwner: self.hwnd.get(),
+            lpstrFilter: PCWSTR(filter.as_ptr()),
+            lpstrFile: PWSTR(buf.as_mut_ptr()),
+            nMaxFile: buf.len() as u32,
+            lpstrTitle: w!("New script"),
+            lpstrDefExt: w!("sh"),
+            Flags: OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST | OFN_HIDEREADONLY,
+            ..Default::default()
+        };
+        if !unsafe { GetSaveFileNameW(&mut ofn) }.as_bool() {
+            return None;
+        }
+        let path = unsafe { WideCStr::from_ptr_str(buf.as_ptr()) };
+        Some(std::path::PathBuf::from(path.to_os_string()))
+    }
+
+    /// Read settings from text input controls to the current configuration.
+    ///
+    /// Returns false if the settings are invalid, in which case the user
+    /// has been notified.
+    fn read_text_settings(&mut self) -> bool {
+        let title = self.get_control_text(Control::ConsoleTitleEdit);
+        let category = self.get_control_text(Control::CategoryEdit);
+        let type_name = self.get_control_text(Control::TypeNameEdit);
+        let size = self.get_control_text(Control::ConsoleSizeEdit);
+        let colors = self.get_control_text(Control::ConsoleColorsEdit);
+        let size = match size.trim() {
+            "" => None,
+            s => match s.parse::<registry::ConsoleSize>() {
+                Ok(size) => Some(size),
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid console size. Use columns x rows, eg. 120x30."
+                    )));
+                    return false;
+                }
+            },
+        };
+        let hold_mode = self.get_selected_hold_mode();
+        let hold_key = self.get_selected_hold_key();
+        let needs_timeout = match hold_mode {
+            Some(registry::HoldMode::Timeout(_)) => true,
+            Some(registry::HoldMode::Never) => false,
+            _ => matches!(hold_key, registry::HoldKey::Timeout(_)),
+        };
+        let secs = match self.get_hold_timeout() {
+            Some(secs) => Some(secs),
+            None if needs_timeout => {
+                win32::error_message(wcstr(wchz!(
+                    "Invalid timeout. Enter the number of seconds to wait."
+                )));
+                return false;
+            }
+            None => None,
+        };
+        let message = self.get_control_text(Control::HoldMessageEdit);
+        if !wsl::is_cmd_safe(std::ffi::OsStr::new(&message)) {
+            win32::error_message(wcstr(wchz!(
+                "Invalid exit message. Double quotes and percent signs are not allowed."
+            )));
+            return false;
+        }
+        let count = self.get_control_text(Control::DropCountEdit);
+        let drop_policy = match (self.get_selected_drop_policy(), count.trim()) {
+            (registry::DropPolicy::PerFile(_), "") => registry::DropPolicy::PerFile(0),
+            (registry::DropPolicy::PerFile(_), s) => match s.parse::<u32>() {
+                Ok(limit) => registry::DropPolicy::PerFile(limit),
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid limit. Enter the maximum number of consoles open at a time."
+                    )));
+                    return false;
+                }
+            },
+            (registry::DropPolicy::Batch(_), s) => match s.parse::<u32>() {
+                Ok(size) if size > 0 => registry::DropPolicy::Batch(size),
+                _ => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid batch size. Enter the number of files per run."
+                    )));
+                    return false;
+                }
+            },
+            (policy, _) => policy,
+        };
+        // empty input disables the confirmation
+        let max_args = self.get_control_text(Control::MaxArgsEdit);
+        let max_args = match max_args.trim() {
+            "" => 0,
+            s => match s.parse::<u32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid number of files. Enter the number of files above which to confirm."
+                    )));
+                    return false;
+                }
+            },
+        };
+        // empty inputs disable the run limits
+        let min_interval = match self.get_control_text(Control::MinIntervalEdit).trim() {
+            "" => 0,
+            s => match s.parse::<u32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid interval. Enter the minimum number of seconds between runs."
+                    )));
+                    return false;
+                }
+            },
+        };
+        let max_instances = match self.get_control_text(Control::MaxInstancesEdit).trim() {
+            "" => 0,
+            s => match s.parse::<u32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid number of scripts. Enter how many may run at a time."
+                    )));
+                    return false;
+                }
+            },
+        };
+        // empty inputs disable the log retention limits
+        let log_max_age = match self.get_control_text(Control::LogMaxAgeEdit).trim() {
+            "" => 0,
+            s => match s.parse::<u32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid number of days. Enter how long to keep output logs."
+                    )));
+                    return false;
+                }
+            },
+        };
+        let log_max_files = match self.get_control_text(Control::LogMaxFilesEdit).trim() {
+            "" => 0,
+            s => match s.parse::<u32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid number of logs. Enter how many output logs to keep."
+                    )));
+                    return false;
+                }
+            },
+        };
+        let arg_chmod = Some(
+            self.get_control_text(Control::ArgChmodEdit)
+                .trim()
+                .to_string(),
+        )
+        .filter(|s| !s.is_empty());
+        if arg_chmod
+            .as_ref()
+            .is_some_and(|s| !wsl::is_valid_chmod_mode(s))
+        {
+            win32::error_message(wcstr(wchz!(
+                "Invalid mode. Enter an octal mode, eg. 644, or a symbolic mode, eg. u+rx."
+            )));
+            return false;
+        }
+        let arg_chown = Some(
+            self.get_control_text(Control::ArgChownEdit)
+                .trim()
+                .to_string(),
+        )
+        .filter(|s| !s.is_empty());
+        if arg_chown
+            .as_ref()
+            .is_some_and(|s| !wsl::is_valid_chown_owner(s))
+        {
+            win32::error_message(wcstr(wchz!(
+                "Invalid owner. Enter a user name, optionally followed by a colon and a group."
+            )));
+            return false;
+        }
+        let icon = match self.get_control_text(Control::IconPathEdit).trim() {
+            "" => ShellIcon::load_default_for(
+                self.distros.name(
+                    self.current_ext_cfg
+                        .as_ref()
+                        .and_then(|cfg| cfg.distro.as_ref()),
+                ),
+            )
+            .ok(),
+            s => match parse_icon_location(s) {
+                Ok(icon) => Some(icon),
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid icon. Use an icon file, or a .dll or .exe file \
+                        followed by a comma and the icon index, eg. shell32.dll,3."
+                    )));
+                    return false;
+                }
+            },
+        };
+        let wslenv = self.get_control_text(Control::WslEnvEdit);
+        let wslenv = match wslenv.parse::<registry::WslEnv>() {
+            Ok(wslenv) => wslenv,
+            Err(_) => {
+                win32::error_message(wcstr(wchz!(
+                    "Invalid environment variables. Use names separated by colons, \
+                    optionally followed by WSLENV flags, eg. USERPROFILE/p:LANG."
+                )));
+                return false;
+            }
+        };
+        let colors = match colors.trim() {
+            "" => None,
+            s => match s.parse::<registry::ConsoleColors>() {
+                Ok(colors) => Some(colors),
+                Err(_) => {
+                    win32::error_message(wcstr(wchz!(
+                        "Invalid console colors. Use two different hex digits, eg. 1F."
+                    )));
+                    return false;
+                }
+            },
+        };
+        if let Some(cfg) = &mut self.current_ext_cfg {
+            cfg.console_title = Some(title.trim().to_string()).filter(|s| !s.is_empty());
+            cfg.console_size = size;
+            cfg.console_colors = colors;
+            cfg.category = Some(category.trim().to_string()).filter(|s| !s.is_empty());
+            cfg.type_name = Some(type_name.trim().to_string()).filter(|s| !s.is_empty());
+            match (cfg.hold_mode, secs) {
+                (registry::HoldMode::Timeout(_), Some(secs)) => {
+                    cfg.hold_mode = registry::HoldMode::Timeout(secs);
+                }
+                (_, Some(secs)) if matches!(hold_key, registry::HoldKey::Timeout(_)) => {
+                    cfg.hold_key = registry::HoldKey::Timeout(secs);
+                }
+                _ => {}
+            }
+            cfg.hold_message = Some(message).filter(|s| !s.trim().is_empty());
+            cfg.drop_policy = drop_policy;
+            cfg.max_args = max_args;
+            cfg.min_interval = min_interval;
+            cfg.max_instances = max_instances;
+            cfg.log_max_age = log_max_age;
+            cfg.log_max_files = log_max_files;
+            cfg.arg_chmod = arg_chmod;
+            cfg.arg_chown = arg_chown;
+            cfg.wslenv = wslenv;
+            if icon.is_some() {
+                cfg.icon = icon;
+            }
+        }
+        true
+    }
+
+    /// Show the icon at the location being typed.
+    ///
+    /// Warning icon is shown until the location is valid.
+    fn preview_icon_location(&mut self) {
+        let hwnd = self.get_control_handle(Control::StaticIcon);
+        match parse_icon_location(&self.get_control_text(Control::IconPathEdit)) {
+            Ok(icon) => {
+                unsafe { SendMessageW(hwnd, STM_SETICON, WPARAM(icon.handle().0 as _), LPARAM(0)) };
+                if let Some(cfg) = &mut self.current_ext_cfg {
+                    cfg.icon = Some(icon);
+                }
+            }
+            Err(_) => {
+                // NOTE: DestroyIcon not needed for shared icons
+                let hicon = unsafe { LoadIconW(None, IDI_WARNING) }.unwrap_or_default();
+                unsafe { SendMessageW(hwnd, STM_SETICON, WPARAM(hicon.0 as _), LPARAM(0)) };
+            }
+        }
+    }
+
+    /// Re-populate extensions listview according to the filter input.
+    fn on_filter_changed(&mut self) {
+        let current = self.get_current_extension();
+        let filter = self.get_control_text(Control::FilterEdit);
+        // extension being edited is always listed
+        self.lv_extensions
+            .populate(self, &filter, current.as_deref());
+        self.current_ext_idx = current.and_then(|ext| self.lv_extensions.find_ext(&ext));
+        self.update_groups();
+    }
+
+    /// Arrange extensions listview into groups according to current grouping.
+    fn update_groups(&self) {
+        let key = |cfg: &registry::ExtConfig| match self.grouping {
+            listview::Grouping::None => None,
+            listview::Grouping::Distro => Some(self.get_distro_label(cfg.distro.as_ref())),
+            listview::Grouping::Category => Some(
+                cfg.category
+                    .clone()
+                    .unwrap_or_else(|| "Uncategorized".to_string()),
+            ),
+        };
+        let mut groups: std::collections::BTreeMap<String, Vec<usize>> = Default::default();
+        for idx in 0..self.lv_extensions.item_count() {
+            if let Some(header) = self
+                .lv_extensions
+                .get_item_text(idx)
+                .and_then(|ext| registry::get_extension_config(&ext).ok())
+                .and_then(|cfg| key(&cfg))
+            {
+                groups.entry(header).or_default().push(idx);
+            }
+        }
+        let groups: Vec<(String, Vec<usize>)> = groups.into_iter().collect();
+        self.lv_extensions.set_groups(&groups);
     }
 
-    /// Handle save button click.
-    fn on_save_button_clicked(&mut self) -> Result<win::LRESULT, Error> {
-        if let Some(config) = self.current_ext_cfg.as_ref() {
-            registry::register_extension(config)?;
-            self.message = Some(format!("Saved .{} extension.", config.extension));
-            self.update_control_states();
-            if let Some(item) = self.current_ext_idx {
-                let name = self.get_distro_label(config.distro.as_ref());
-                self.lv_extensions
-                    .set_subitem_text(item, 1, &wcstring(name));
+    /// Handle keyboard accelerator command.
+    fn on_accelerator(&mut self, id: Accelerator) -> LRESULT {
+        match id {
+            Accelerator::RerunTest => {
+                if let Err(e) = self.on_rerun_test() {
+                    self.show_error("Test run failed", &e);
+                }
             }
         }
-        Ok(0)
+        LRESULT(0)
     }
 
     /// Handle message from a menu.
     ///
     /// * `hmenu` - Handle to the menu
     /// * `item_id` - ID of the clicked menu item
-    fn on_menucommand(&mut self, hmenu: windef::HMENU, item_id: MenuItem) -> win::LRESULT {
+    fn on_menucommand(&mut self, hmenu: HMENU, item_id: MenuItem) -> LRESULT {
         match item_id {
             MenuItem::Unregister => {
                 let idx = Self::get_menu_data::<usize>(hmenu);
                 if let Some(ext) = self.lv_extensions.get_item_text(idx) {
-                    if let Err(e) = registry::unregister_extension(&ext) {
-                        let s = wcstring(format!("Failed to unregister extension: {}", e));
-                        win32::error_message(&s);
-                        return 0;
+                    let Some(restore) = self.confirm_restore(std::slice::from_ref(&ext)) else {
+                        return LRESULT(0);
+                    };
+                    if let Err(e) = registry::unregister_extensions(&[&ext], restore) {
+                        self.show_error("Failed to unregister extension", &e);
+                        return LRESULT(0);
                     }
                 }
                 self.lv_extensions.delete_item(idx);
                 self.set_current_extension(None);
+                self.update_groups();
                 self.update_control_states();
                 // if there's no more registered extensions, and if extension
                 // input was empty, reset to default extension
@@ -801,25 +3524,191 @@ impl MainWindow {
                 }
             }
             MenuItem::EditExtension => {
+                if !self.confirm_discard_changes() {
+                    return LRESULT(0);
+                }
                 let idx = Self::get_menu_data::<usize>(hmenu);
                 self.set_current_extension(Some(idx));
                 self.update_control_states();
             }
+            MenuItem::UnregisterSelected => {
+                let exts: Vec<String> = self
+                    .lv_extensions
+                    .selected_items()
+                    .into_iter()
+                    .filter_map(|idx| self.lv_extensions.get_item_text(idx))
+                    .collect();
+                let Some(restore) = self.confirm_restore(&exts) else {
+                    return LRESULT(0);
+                };
+                if let Err(e) = registry::unregister_extensions(&exts, restore) {
+                    self.show_error("Failed to unregister extensions", &e);
+                    return LRESULT(0);
+                }
+                self.set_current_extension(None);
+                let filter = self.get_control_text(Control::FilterEdit);
+                self.lv_extensions.populate(self, &filter, None);
+                self.message = Some(format!(
+                    "Unregistered {} extensions.",
+                    win32::format_number(exts.len() as u64)
+                ));
+                self.update_groups();
+                self.update_control_states();
+            }
+            MenuItem::SetHoldModeError
+            | MenuItem::SetHoldModeNever
+            | MenuItem::SetHoldModeAlways => {
+                let mode = match item_id {
+                    MenuItem::SetHoldModeNever => registry::HoldMode::Never,
+                    MenuItem::SetHoldModeAlways => registry::HoldMode::Always,
+                    _ => registry::HoldMode::Error,
+                };
+                self.update_selected(|cfg| cfg.hold_mode = mode);
+            }
+            MenuItem::NoGrouping | MenuItem::GroupByDistro | MenuItem::GroupByCategory => {
+                self.grouping = match item_id {
+                    MenuItem::GroupByDistro => listview::Grouping::Distro,
+                    MenuItem::GroupByCategory => listview::Grouping::Category,
+                    _ => listview::Grouping::None,
+                };
+                self.update_groups();
+            }
+        }
+        LRESULT(0)
+    }
+
+    /// Ask whether to restore the handlers that were replaced by WSL Script.
+    ///
+    /// Returns whether to restore, or None if the user cancelled.
+    fn confirm_restore(&self, exts: &[String]) -> Option<bool> {
+        let previous: Vec<String> = exts
+            .iter()
+            .filter_map(|ext| {
+                let handler = registry::get_previous_handler(ext)?;
+                let prog_id = handler.user_choice.or(handler.prog_id)?;
+                Some(format!(".{} \u{2192} {}", ext, prog_id))
+            })
+            .collect();
+        if previous.is_empty() {
+            return Some(false);
+        }
+        let s = wcstring(format!(
+            "Files were previously opened with another application:\n\n{}\n\n\
+             Restore the previous association?",
+            previous.join("\n")
+        ));
+        let result = unsafe {
+            MessageBoxW(
+                self.hwnd.get(),
+                PCWSTR(s.as_ptr()),
+                w!("Restore association"),
+                MB_YESNOCANCEL | MB_ICONQUESTION,
+            )
+        };
+        match result {
+            IDYES => Some(true),
+            IDNO => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Append actions for multiple selected extensions to a context menu.
+    fn append_bulk_menu_items(&self, hmenu: HMENU, count: usize) {
+        let label = wcstring(format!(
+            "Unregister {} filetypes",
+            win32::format_number(count as u64)
+        ));
+        let _ = unsafe {
+            AppendMenuW(
+                hmenu,
+                MF_STRING,
+                MenuItem::UnregisterSelected as usize,
+                PCWSTR(label.as_ptr()),
+            )
+        };
+        // distribution submenu
+        if let Ok(submenu) = unsafe { CreatePopupMenu() } {
+            let append = |id: u32, name: &str| {
+                let s = wcstring(name);
+                let _ = unsafe { AppendMenuW(submenu, MF_STRING, id as usize, PCWSTR(s.as_ptr())) };
+            };
+            append(DISTRO_MENU_BASE, &self.get_distro_label(None));
+            for (i, (_, name)) in self.distros.sorted_pairs().iter().enumerate() {
+                append(DISTRO_MENU_BASE + 1 + i as u32, name);
+            }
+            // submenu is destroyed along with the parent menu
+            let _ =
+                unsafe { AppendMenuW(hmenu, MF_POPUP, submenu.0 as usize, w!("Set distribution")) };
+        }
+        // hold mode submenu
+        if let Ok(submenu) = unsafe { CreatePopupMenu() } {
+            for (item, label) in [
+                (MenuItem::SetHoldModeError, w!("Close on success")),
+                (MenuItem::SetHoldModeNever, w!("Always close")),
+                (MenuItem::SetHoldModeAlways, w!("Keep open")),
+            ] {
+                let _ = unsafe { AppendMenuW(submenu, MF_STRING, item as usize, label) };
+            }
+            let _ =
+                unsafe { AppendMenuW(hmenu, MF_POPUP, submenu.0 as usize, w!("Set hold mode")) };
+        }
+    }
+
+    /// Apply a change to all selected extensions and save them at once.
+    fn update_selected<F>(&mut self, f: F)
+    where
+        F: Fn(&mut registry::ExtConfig),
+    {
+        let selected = self.lv_extensions.selected_items();
+        let mut configs = Vec::with_capacity(selected.len());
+        for idx in selected.iter() {
+            let Some(ext) = self.lv_extensions.get_item_text(*idx) else {
+                continue;
+            };
+            match registry::get_extension_config(&ext) {
+                Ok(mut cfg) => {
+                    f(&mut cfg);
+                    configs.push((*idx, cfg));
+                }
+                Err(e) => {
+                    self.show_error(format!("Failed to read .{} extension", ext), &e);
+                    return;
+                }
+            }
+        }
+        let (items, configs): (Vec<usize>, Vec<registry::ExtConfig>) = configs.into_iter().unzip();
+        if let Err(e) = registry::register_extensions(&configs) {
+            self.show_error("Failed to save extensions", &e);
+            return;
+        }
+        for (idx, cfg) in items.iter().zip(configs.iter()) {
+            let name = self.get_distro_label(cfg.distro.as_ref());
+            self.lv_extensions
+                .set_subitem_text(*idx, 1, &wcstring(name));
         }
-        0
+        // reload the extension being edited
+        if let Some(idx) = self.current_ext_idx.filter(|idx| items.contains(idx)) {
+            self.set_current_extension(Some(idx));
+        }
+        self.message = Some(format!(
+            "Saved {} extensions.",
+            win32::format_number(configs.len() as u64)
+        ));
+        self.update_groups();
+        self.update_control_states();
     }
 
     /// Get application-defined value associated with a menu.
-    fn get_menu_data<T>(hmenu: windef::HMENU) -> T
+    fn get_menu_data<T>(hmenu: HMENU) -> T
     where
-        T: From<winapi::shared::basetsd::ULONG_PTR>,
+        T: From<usize>,
     {
         let mut mi = MENUINFO {
             cbSize: mem::size_of::<MENUINFO>() as u32,
             fMask: MIM_MENUDATA,
             ..unsafe { mem::zeroed() }
         };
-        unsafe { GetMenuInfo(hmenu, &mut mi) };
+        let _ = unsafe { GetMenuInfo(hmenu, &mut mi) };
         T::from(mi.dwMenuData)
     }
 
@@ -831,60 +3720,144 @@ impl MainWindow {
     /// * `lparam` - Notification specific parameter
     fn on_notify(
         &mut self,
-        hwnd: windef::HWND,
+        hwnd: HWND,
         control_id: Control,
         code: u32,
         lparam: *const isize,
-    ) -> win::LRESULT {
-        use commctrl::*;
+    ) -> LRESULT {
+        use Controls::*;
         #[allow(clippy::single_match)]
         match control_id {
             Control::ListViewExtensions => match code {
                 // when listview item is activated (eg. double clicked)
                 LVN_ITEMACTIVATE => {
-                    let nmia = unsafe { &*(lparam as LPNMITEMACTIVATE) };
-                    if nmia.iItem < 0 {
-                        return 0;
+                    let nmia = unsafe { &*(lparam as *const NMITEMACTIVATE) };
+                    if nmia.iItem < 0 || !self.confirm_discard_changes() {
+                        return LRESULT(0);
                     }
                     self.set_current_extension(Some(nmia.iItem as usize));
                     self.update_control_states();
                 }
+                // when mouse hovers over listview item
+                LVN_GETINFOTIPW => {
+                    let tip = unsafe { &mut *(lparam as *mut NMLVGETINFOTIPW) };
+                    if tip.iItem < 0 || tip.cchTextMax <= 0 {
+                        return LRESULT(0);
+                    }
+                    let Some(text) = self
+                        .lv_extensions
+                        .get_item_text(tip.iItem as usize)
+                        .and_then(|ext| self.extension_info_tip(&ext))
+                    else {
+                        return LRESULT(0);
+                    };
+                    let s = WideCString::from_str_truncate(text);
+                    let len = s.len().min(tip.cchTextMax as usize - 1);
+                    unsafe {
+                        std::ptr::copy_nonoverlapping(s.as_ptr(), tip.pszText.0, len);
+                        *tip.pszText.0.add(len) = 0;
+                    }
+                }
                 // when listview item is right-clicked
                 NM_RCLICK => {
-                    let nmia = unsafe { &*(lparam as LPNMITEMACTIVATE) };
-                    if nmia.iItem < 0 {
-                        return 0;
-                    }
-                    let hmenu = unsafe { CreatePopupMenu() };
+                    let nmia = unsafe { &*(lparam as *const NMITEMACTIVATE) };
+                    let hmenu = match unsafe { CreatePopupMenu() } {
+                        Ok(h) => unsafe { OwnedHmenu::from_raw(h) },
+                        Err(_) => return LRESULT(0),
+                    };
                     let mi = MENUINFO {
                         cbSize: mem::size_of::<MENUINFO>() as _,
-                        fMask: MIM_MENUDATA | MIM_STYLE,
-                        dwStyle: MNS_NOTIFYBYPOS,
+                        fMask: MIM_MENUDATA,
                         dwMenuData: nmia.iItem as usize,
                         ..unsafe { mem::zeroed() }
                     };
-                    unsafe { SetMenuInfo(hmenu, &mi) };
+                    let _ = unsafe { SetMenuInfo(hmenu.get(), &mi) };
                     let mut mii = MENUITEMINFOW {
                         cbSize: mem::size_of::<MENUITEMINFOW>() as _,
                         fMask: MIIM_TYPE | MIIM_ID,
                         fType: MFT_STRING,
                         ..unsafe { mem::zeroed() }
                     };
-                    mii.wID = MenuItem::EditExtension as _;
-                    mii.dwTypeData = wchz!("Edit").as_ptr() as _;
-                    unsafe { InsertMenuItemW(hmenu, 0, win::TRUE, &mii) };
-                    mii.wID = MenuItem::Unregister as _;
-                    mii.dwTypeData = wchz!("Unregister").as_ptr() as _;
-                    unsafe { InsertMenuItemW(hmenu, 1, win::TRUE, &mii) };
-                    let mut pos: windef::POINT = nmia.ptAction;
-                    unsafe { ClientToScreen(hwnd, &mut pos) };
-                    unsafe { TrackPopupMenuEx(hmenu, 0, pos.x, pos.y, self.hwnd, ptr::null_mut()) };
+                    let selected = self.lv_extensions.selected_items();
+                    // bulk actions when clicked on one of multiple selected items
+                    if selected.len() > 1 && selected.contains(&(nmia.iItem as usize)) {
+                        self.append_bulk_menu_items(hmenu.get(), selected.len());
+                        let _ = unsafe { AppendMenuW(hmenu.get(), MF_SEPARATOR, 0, None) };
+                    }
+                    // item actions only when clicked on an item
+                    else if nmia.iItem >= 0 {
+                        mii.wID = MenuItem::EditExtension as _;
+                        mii.dwTypeData = PWSTR(wchz!("Edit").as_ptr() as _);
+                        let _ = unsafe { InsertMenuItemW(hmenu.get(), 0, TRUE, &mii) };
+                        mii.wID = MenuItem::Unregister as _;
+                        mii.dwTypeData = PWSTR(wchz!("Unregister").as_ptr() as _);
+                        let _ = unsafe { InsertMenuItemW(hmenu.get(), 1, TRUE, &mii) };
+                        let _ = unsafe { AppendMenuW(hmenu.get(), MF_SEPARATOR, 0, None) };
+                    }
+                    // grouping options
+                    for (item, label, grouping) in [
+                        (
+                            MenuItem::NoGrouping,
+                            w!("No grouping"),
+                            listview::Grouping::None,
+                        ),
+                        (
+                            MenuItem::GroupByDistro,
+                            w!("Group by distribution"),
+                            listview::Grouping::Distro,
+                        ),
+                        (
+                            MenuItem::GroupByCategory,
+                            w!("Group by category"),
+                            listview::Grouping::Category,
+                        ),
+                    ] {
+                        let checked = if self.grouping == grouping {
+                            MF_CHECKED
+                        } else {
+                            MF_UNCHECKED
+                        };
+                        let _ = unsafe {
+                            AppendMenuW(hmenu.get(), MF_STRING | checked, item as usize, label)
+                        };
+                    }
+                    let mut pos: POINT = nmia.ptAction;
+                    unsafe { Gdi::ClientToScreen(hwnd, &mut pos) };
+                    // return the selected item instead of posting a message,
+                    // so that the menu can be destroyed once it's handled
+                    let cmd = unsafe {
+                        TrackPopupMenuEx(
+                            hmenu.get(),
+                            TPM_RETURNCMD.0,
+                            pos.x,
+                            pos.y,
+                            self.hwnd.get(),
+                            None,
+                        )
+                    };
+                    let cmd = cmd.0 as u32;
+                    if let Ok(id) = MenuItem::try_from(cmd) {
+                        return self.on_menucommand(hmenu.get(), id);
+                    }
+                    if cmd >= DISTRO_MENU_BASE {
+                        let distro = match cmd - DISTRO_MENU_BASE {
+                            0 => Some(None),
+                            n => self
+                                .distros
+                                .sorted_pairs()
+                                .get(n as usize - 1)
+                                .map(|(guid, _)| Some((*guid).clone())),
+                        };
+                        if let Some(distro) = distro {
+                            self.update_selected(|cfg| cfg.distro = distro.clone());
+                        }
+                    }
                 }
                 _ => {}
             },
             _ => {}
         }
-        0
+        LRESULT(0)
     }
 
     /// Get currently selected extension.
@@ -894,30 +3867,41 @@ impl MainWindow {
     }
 
     /// Get window handle to control.
-    fn get_control_handle(&self, control: Control) -> windef::HWND {
-        unsafe { GetDlgItem(self.hwnd, control as _) }
+    fn get_control_handle(&self, control: Control) -> HWND {
+        unsafe { GetDlgItem(self.hwnd.get(), control as _) }
     }
 
     /// Get text from extension text input.
     fn get_extension_input_text(&self) -> String {
-        let mut buf: Vec<ntdef::WCHAR> = Vec::with_capacity(32);
-        unsafe {
-            // NOTE: if text is longer than buffer, it's truncated
-            let len = GetDlgItemTextW(
-                self.hwnd,
-                Control::EditExtension as _,
-                buf.as_mut_ptr(),
-                buf.capacity() as _,
-            );
-            buf.set_len(len as usize);
-        }
-        WideCString::from_vec(buf).unwrap().to_string_lossy()
+        let mut buf = [0_u16; 32];
+        // NOTE: if text is longer than buffer, it's truncated
+        let len =
+            unsafe { GetDlgItemTextW(self.hwnd.get(), Control::EditExtension as _, &mut buf) };
+        WideCString::from_vec_truncate(&buf[..len as usize]).to_string_lossy()
+    }
+
+    /// Get text from a control.
+    fn get_control_text(&self, control: Control) -> String {
+        let mut buf = [0_u16; 512];
+        // NOTE: if text is longer than buffer, it's truncated
+        let len = unsafe { GetDlgItemTextW(self.hwnd.get(), control as _, &mut buf) };
+        WideCString::from_vec_truncate(&buf[..len as usize]).to_string_lossy()
+    }
+
+    /// Set text to a control.
+    fn set_control_text(&self, control: Control, text: &str) {
+        let s = wcstring(text);
+        let _ = unsafe { SetDlgItemTextW(self.hwnd.get(), control as _, PCWSTR(s.as_ptr())) };
     }
 
     /// Set text to extension input control.
     fn set_extension_input_text(&self, text: &WideCStr) {
         unsafe {
-            SetDlgItemTextW(self.hwnd, Control::EditExtension as _, text.as_ptr());
+            let _ = SetDlgItemTextW(
+                self.hwnd.get(),
+                Control::EditExtension as _,
+                PCWSTR(text.as_ptr()),
+            );
         }
     }
 
@@ -928,14 +3912,85 @@ impl MainWindow {
             .get_current_extension()
             .and_then(|ext| registry::get_extension_config(&ext).ok());
         self.message = None;
+        self.set_dirty(false);
+        self.update_command_preview();
+    }
+
+    /// Set whether the current configuration has unsaved changes.
+    fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+        let _ = unsafe { EnableWindow(self.get_control_handle(Control::BtnSave), dirty) };
+    }
+
+    /// Update command preview of the configuration being edited.
+    ///
+    /// Unsaved exit message is included, other text inputs are
+    /// applied only on save.
+    fn update_command_preview(&self) {
+        let Some(mut cfg) = self.current_ext_cfg.clone() else {
+            self.set_control_text(Control::CommandPreviewEdit, "");
+            return;
+        };
+        if self.dirty {
+            let message = self.get_control_text(Control::HoldMessageEdit);
+            cfg.hold_message = Some(message).filter(|s| !s.is_empty());
+        }
+        let open = match registry::get_command(&cfg) {
+            Ok(cmd) => cmd.to_string_lossy(),
+            Err(e) => format!("Unavailable ({})", e),
+        };
+        let script = sample_script_path(&cfg.extension);
+        let run = match wsl::preview_command_line(&script, &wsl::WSLOptions::from(&cfg)) {
+            Ok(cmd) => cmd.to_string_lossy(),
+            Err(e) => format!("Unavailable ({})", e),
+        };
+        let text = format!("Open: {}\r\nRun: {}", open, run);
+        self.set_control_text(Control::CommandPreviewEdit, &text);
+    }
+
+    /// Ask user what to do with unsaved changes before they're discarded.
+    ///
+    /// Returns true if it's ok to proceed, ie. there were no changes, or
+    /// changes were either saved or discarded.
+    fn confirm_discard_changes(&mut self) -> bool {
+        let ext = match self.current_ext_cfg.as_ref() {
+            Some(cfg) if self.dirty => cfg.extension.clone(),
+            _ => return true,
+        };
+        let s = wcstring(format!(
+            "You have unsaved changes to .{} extension.\n\
+             Save changes?",
+            ext
+        ));
+        let result = unsafe {
+            MessageBoxW(
+                self.hwnd.get(),
+                PCWSTR(s.as_ptr()),
+                w!("Unsaved changes"),
+                MB_YESNOCANCEL | MB_ICONWARNING,
+            )
+        };
+        match result {
+            IDYES => {
+                if let Err(e) = self.on_save_button_clicked() {
+                    let s = wcstring(format!("Failed to save extension: {}", e));
+                    win32::error_message(&s);
+                    return false;
+                }
+                // settings were invalid
+                !self.dirty
+            }
+            IDNO => true,
+            _ => false,
+        }
     }
 
     /// Launch icon picker dialog.
     ///
     /// Returns ShellIcon or None if no icon was selected.
     fn pick_icon_dlg(&self) -> Option<ShellIcon> {
-        let mut buf = [0_u16; win::MAX_PATH];
-        let mut idx: std::os::raw::c_int = 0;
+        let mut buf = [0_u16; MAX_PATH as usize];
+        let mut idx: i32 = 0;
         if let Some(si) = self
             .current_ext_cfg
             .as_ref()
@@ -951,7 +4006,7 @@ impl MainWindow {
                 idx = si.index() as i32;
             }
         }
-        let result = unsafe { PickIconDlg(self.hwnd, buf.as_mut_ptr(), buf.len() as _, &mut idx) };
+        let result = unsafe { Shell::PickIconDlg(self.hwnd.get(), &mut buf, Some(&mut idx)) };
         if result == 0 {
             return None;
         }
@@ -962,8 +4017,7 @@ impl MainWindow {
                     match ShellIcon::load(p, idx as u32) {
                         Ok(icon) => Some(icon),
                         Err(e) => {
-                            let s = wcstring(format!("Failed load icon: {}", e));
-                            win32::error_message(&s);
+                            self.show_error("Failed to load icon", &e);
                             None
                         }
                     }
@@ -978,22 +4032,22 @@ impl MainWindow {
     /// Get currently select hold mode.
     fn get_selected_hold_mode(&self) -> Option<registry::HoldMode> {
         let hwnd = self.get_control_handle(Control::HoldModeCombo);
-        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, 0, 0) };
-        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, idx as _, 0) };
-        let cs = unsafe { WideCStr::from_ptr_str(data as *const ntdef::WCHAR) };
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+        let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
         registry::HoldMode::from_wcstr(cs)
     }
 
     /// Set hold mode to control.
     fn set_selected_hold_mode(&self, mode: registry::HoldMode) -> Option<usize> {
         let hwnd = self.get_control_handle(Control::HoldModeCombo);
-        let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, 0, 0) as usize };
+        let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0 as usize };
         for idx in 0..count {
-            let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, idx as _, 0) };
-            let cs = unsafe { WideCStr::from_ptr_str(data as *const ntdef::WCHAR) };
+            let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+            let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
             if let Some(m) = registry::HoldMode::from_wcstr(cs) {
-                if m == mode {
-                    unsafe { SendMessageW(hwnd, CB_SETCURSEL, idx as _, 0) };
+                if m.same_mode(mode) {
+                    unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(idx as _), LPARAM(0)) };
                     return Some(idx);
                 }
             }
@@ -1001,15 +4055,400 @@ impl MainWindow {
         None
     }
 
+    /// Get currently selected key to wait for on exit.
+    ///
+    /// Timeout is returned as a placeholder, since it's read from the
+    /// timeout input.
+    fn get_selected_hold_key(&self) -> registry::HoldKey {
+        let hwnd = self.get_control_handle(Control::HoldKeyCombo);
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        match idx {
+            1 => registry::HoldKey::Enter,
+            2 => registry::HoldKey::Timeout(DEFAULT_HOLD_TIMEOUT),
+            _ => registry::HoldKey::Any,
+        }
+    }
+
+    /// Set key to wait for on exit to controls.
+    fn set_selected_hold_key(&self, key: registry::HoldKey) {
+        let hwnd = self.get_control_handle(Control::HoldKeyCombo);
+        let idx = match key {
+            registry::HoldKey::Any => 0,
+            registry::HoldKey::Enter => 1,
+            registry::HoldKey::Timeout(_) => 2,
+        };
+        unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(idx), LPARAM(0)) };
+    }
+
+    /// Update wait key and timeout inputs according to current hold settings.
+    ///
+    /// Timeout input holds the seconds of either the timeout hold mode,
+    /// or the timeout wait key.
+    fn update_hold_inputs(&self) {
+        let cfg = self.current_ext_cfg.as_ref();
+        let waits = cfg.is_some_and(|cfg| {
+            matches!(
+                cfg.hold_mode,
+                registry::HoldMode::Always | registry::HoldMode::Error
+            )
+        });
+        let secs = cfg.and_then(|cfg| match (cfg.hold_mode, cfg.hold_key) {
+            (registry::HoldMode::Timeout(secs), _) => Some(secs),
+            (_, registry::HoldKey::Timeout(secs)) if waits => Some(secs),
+            _ => None,
+        });
+        let timeout = secs.map(|secs| secs.to_string()).unwrap_or_default();
+        self.set_control_text(Control::HoldTimeoutEdit, &timeout);
+        let hwnd = self.get_control_handle(Control::HoldTimeoutEdit);
+        let _ = unsafe { EnableWindow(hwnd, secs.is_some()) };
+        let hwnd = self.get_control_handle(Control::HoldKeyCombo);
+        let _ = unsafe { EnableWindow(hwnd, waits) };
+    }
+
+    /// Enable output log retention inputs if output is logged.
+    fn update_log_inputs(&self) {
+        let enabled = self
+            .current_ext_cfg
+            .as_ref()
+            .is_some_and(|cfg| cfg.event_log == registry::EventLog::Output);
+        for control in [Control::LogMaxAgeEdit, Control::LogMaxFilesEdit] {
+            let hwnd = self.get_control_handle(control);
+            let _ = unsafe { EnableWindow(hwnd, enabled) };
+        }
+    }
+
+    /// Get timeout seconds from the timeout input, if valid.
+    fn get_hold_timeout(&self) -> Option<u32> {
+        self.get_control_text(Control::HoldTimeoutEdit)
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|secs| *secs > 0)
+    }
+
+    /// Get currently selected multiple files policy.
+    ///
+    /// Limit and batch size are returned as placeholders, since they're read
+    /// from the count input.
+    fn get_selected_drop_policy(&self) -> registry::DropPolicy {
+        let hwnd = self.get_control_handle(Control::DropPolicyCombo);
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        match idx {
+            1 => registry::DropPolicy::PerFile(0),
+            2 => registry::DropPolicy::Batch(DEFAULT_BATCH_SIZE),
+            _ => registry::DropPolicy::Single,
+        }
+    }
+
+    /// Set multiple files policy to controls.
+    fn set_selected_drop_policy(&self, policy: registry::DropPolicy) {
+        let hwnd = self.get_control_handle(Control::DropPolicyCombo);
+        let idx = match policy {
+            registry::DropPolicy::Single => 0,
+            registry::DropPolicy::PerFile(_) => 1,
+            registry::DropPolicy::Batch(_) => 2,
+        };
+        unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(idx), LPARAM(0)) };
+        let count = match policy {
+            registry::DropPolicy::PerFile(limit) if limit > 0 => limit.to_string(),
+            registry::DropPolicy::Batch(size) => size.to_string(),
+            _ => String::new(),
+        };
+        self.set_control_text(Control::DropCountEdit, &count);
+        let hwnd = self.get_control_handle(Control::DropCountEdit);
+        let _ = unsafe { EnableWindow(hwnd, policy != registry::DropPolicy::Single) };
+    }
+
+    /// Get currently selected order of dropped files.
+    fn get_selected_arg_order(&self) -> Option<registry::ArgOrder> {
+        let hwnd = self.get_control_handle(Control::ArgOrderCombo);
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+        let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+        registry::ArgOrder::from_wcstr(cs)
+    }
+
+    /// Set order of dropped files to control.
+    fn set_selected_arg_order(&self, order: registry::ArgOrder) -> Option<usize> {
+        let hwnd = self.get_control_handle(Control::ArgOrderCombo);
+        let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0 as usize };
+        for idx in 0..count {
+            let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+            let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+            if registry::ArgOrder::from_wcstr(cs) == Some(order) {
+                unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(idx as _), LPARAM(0)) };
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    /// Get currently selected Event Log mode.
+    fn get_selected_event_log(&self) -> Option<registry::EventLog> {
+        let hwnd = self.get_control_handle(Control::EventLogCombo);
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+        let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+        registry::EventLog::from_wcstr(cs)
+    }
+
+    /// Set Event Log mode to control.
+    fn set_selected_event_log(&self, mode: registry::EventLog) -> Option<usize> {
+        let hwnd = self.get_control_handle(Control::EventLogCombo);
+        let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0 as usize };
+        for idx in 0..count {
+            let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+            let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+            if registry::EventLog::from_wcstr(cs) == Some(mode) {
+                unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(idx as _), LPARAM(0)) };
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    /// Get currently selected association strength.
+    fn get_selected_association(&self) -> Option<registry::Association> {
+        let hwnd = self.get_control_handle(Control::AssociationCombo);
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+        let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+        registry::Association::from_wcstr(cs)
+    }
+
+    /// Set association strength to control.
+    fn set_selected_association(&self, association: registry::Association) -> Option<usize> {
+        let hwnd = self.get_control_handle(Control::AssociationCombo);
+        let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0 as usize };
+        for idx in 0..count {
+            let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+            let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+            if registry::Association::from_wcstr(cs) == Some(association) {
+                unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(idx as _), LPARAM(0)) };
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    /// Offer to register the Event Log source if it isn't registered.
+    ///
+    /// Without the source, Event Viewer shows the logged text with a notice
+    /// that the event description can't be found.
+    fn ensure_event_source(&self) {
+        if registry::is_event_source_registered() {
+            return;
+        }
+        let result = unsafe {
+            MessageBoxW(
+                self.hwnd.get(),
+                w!("Register WSL Script as an Event Log source?\n\n\
+                    This requires administrator rights and is done only once."),
+                w!("WSL Script"),
+                MB_YESNO | MB_ICONQUESTION,
+            )
+        };
+        if result != IDYES {
+            return;
+        }
+        match elevate::run_elevated(EVENT_SOURCE_VERB, &[]) {
+            Ok(()) | Err(Error::Cancel) => {}
+            Err(e) => self.show_error("Failed to register Event Log source", &e),
+        }
+    }
+
+    /// Get currently selected CRLF mode.
+    fn get_selected_crlf_mode(&self) -> Option<registry::CrlfMode> {
+        let hwnd = self.get_control_handle(Control::CrlfModeCombo);
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+        let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+        registry::CrlfMode::from_wcstr(cs)
+    }
+
+    /// Set CRLF mode to control.
+    fn set_selected_crlf_mode(&self, mode: registry::CrlfMode) -> Option<usize> {
+        let hwnd = self.get_control_handle(Control::CrlfModeCombo);
+        let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0 as usize };
+        for idx in 0..count {
+            let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+            let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
+            if registry::CrlfMode::from_wcstr(cs) == Some(mode) {
+                unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(idx as _), LPARAM(0)) };
+                return Some(idx);
+            }
+        }
+        None
+    }
+
     /// Get the interactive shell checkbox state.
     fn get_interactive_state(&self) -> bool {
-        let result = unsafe { IsDlgButtonChecked(self.hwnd, Control::InteractiveCheckbox as _) };
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::InteractiveCheckbox as _)
+        };
         result == 1
     }
 
     /// Set the interactive shell checkbox state.
     fn set_interactive_state(&self, state: bool) {
-        unsafe { CheckDlgButton(self.hwnd, Control::InteractiveCheckbox as _, state as _) };
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::InteractiveCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the raw arguments checkbox state.
+    fn get_raw_args_state(&self) -> bool {
+        let result =
+            unsafe { Controls::IsDlgButtonChecked(self.hwnd.get(), Control::RawArgsCheckbox as _) };
+        result == 1
+    }
+
+    /// Set the raw arguments checkbox state.
+    fn set_raw_args_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::RawArgsCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the console at drop point checkbox state.
+    fn get_console_at_drop_state(&self) -> bool {
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::ConsoleAtDropCheckbox as _)
+        };
+        result == 1
+    }
+
+    /// Set the console at drop point checkbox state.
+    fn set_console_at_drop_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::ConsoleAtDropCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the reuse console checkbox state.
+    fn get_reuse_console_state(&self) -> bool {
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::ReuseConsoleCheckbox as _)
+        };
+        result == 1
+    }
+
+    /// Set the reuse console checkbox state.
+    fn set_reuse_console_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::ReuseConsoleCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the abort on missing files checkbox state.
+    fn get_abort_on_missing_state(&self) -> bool {
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::AbortOnMissingCheckbox as _)
+        };
+        result == 1
+    }
+
+    /// Set the abort on missing files checkbox state.
+    fn set_abort_on_missing_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::AbortOnMissingCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the use helper checkbox state.
+    fn get_use_helper_state(&self) -> bool {
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::UseHelperCheckbox as _)
+        };
+        result == 1
+    }
+
+    /// Set the use helper checkbox state.
+    fn set_use_helper_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::UseHelperCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the UTF-8 console checkbox state.
+    fn get_utf8_console_state(&self) -> bool {
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::Utf8ConsoleCheckbox as _)
+        };
+        result == 1
+    }
+
+    /// Set the UTF-8 console checkbox state.
+    fn set_utf8_console_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::Utf8ConsoleCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the show progress checkbox state.
+    fn get_show_progress_state(&self) -> bool {
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::ShowProgressCheckbox as _)
+        };
+        result == 1
+    }
+
+    /// Set the show progress checkbox state.
+    fn set_show_progress_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::ShowProgressCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
+    }
+
+    /// Get the single instance checkbox state.
+    fn get_single_instance_state(&self) -> bool {
+        let result = unsafe {
+            Controls::IsDlgButtonChecked(self.hwnd.get(), Control::SingleInstanceCheckbox as _)
+        };
+        result == 1
+    }
+
+    /// Set the single instance checkbox state.
+    fn set_single_instance_state(&self, state: bool) {
+        let _ = unsafe {
+            Controls::CheckDlgButton(
+                self.hwnd.get(),
+                Control::SingleInstanceCheckbox as _,
+                Controls::DLG_BUTTON_CHECK_STATE(state as _),
+            )
+        };
     }
 
     /// Set selected distro in combo box.
@@ -1017,29 +4456,30 @@ impl MainWindow {
         let hwnd = self.get_control_handle(Control::DistroCombo);
         let mut sel: usize = 0;
         if let Some(guid) = distro {
-            let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, 0, 0) as usize };
+            let count = unsafe { SendMessageW(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0 as usize };
             for idx in 1..count {
-                let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, idx as _, 0) };
-                let guid_str = unsafe { WideCStr::from_ptr_str(data as *const ntdef::WCHAR) };
+                let data =
+                    unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+                let guid_str = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
                 if guid_str == guid.as_wcstr() {
                     sel = idx;
                     break;
                 }
             }
         }
-        unsafe { SendMessageW(hwnd, CB_SETCURSEL, sel as _, 0) };
+        unsafe { SendMessageW(hwnd, CB_SETCURSEL, WPARAM(sel as _), LPARAM(0)) };
         Some(sel)
     }
 
     /// Get currently selected GUID in distro combo box.
     fn get_selected_distro(&self) -> Option<registry::DistroGUID> {
         let hwnd = self.get_control_handle(Control::DistroCombo);
-        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, 0, 0) };
-        if idx == 0 || idx == CB_ERR {
+        let idx = unsafe { SendMessageW(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        if idx == 0 || idx == CB_ERR as isize {
             return None;
         }
-        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, idx as _, 0) };
-        let cs = unsafe { WideCStr::from_ptr_str(data as *const ntdef::WCHAR) };
+        let data = unsafe { SendMessageW(hwnd, CB_GETITEMDATA, WPARAM(idx as _), LPARAM(0)) };
+        let cs = unsafe { WideCStr::from_ptr_str(data.0 as *const u16) };
         let s = cs.to_string_lossy();
         registry::DistroGUID::from_str(&s).ok()
     }
@@ -1050,100 +4490,208 @@ impl MainWindow {
             .or_else(|| Some(String::from("Default")))
             .unwrap_or_default()
     }
+
+    /// Get summary of extension's configuration for a listview tooltip.
+    fn extension_info_tip(&self, ext: &str) -> Option<String> {
+        let cfg = registry::get_extension_config(ext).ok()?;
+        let opts = wsl::WSLOptions::from(&cfg);
+        let hold = match cfg.hold_mode {
+            registry::HoldMode::Never => String::from("Always close"),
+            registry::HoldMode::Always => String::from("Keep open"),
+            registry::HoldMode::Error => String::from("Close on success"),
+            registry::HoldMode::Timeout(secs) => {
+                format!("Close after {} seconds", win32::format_number(secs as u64))
+            }
+        };
+        let icon = cfg
+            .icon
+            .as_ref()
+            .map(|icon| icon.shell_path().to_string_lossy())
+            .unwrap_or_else(|| String::from("Default"));
+        let script = sample_script_path(ext);
+        let command = match wsl::preview_command_line(&script, &opts) {
+            Ok(cmdline) => cmdline.to_string_lossy(),
+            Err(e) => format!("Unavailable ({})", e),
+        };
+        Some(format!(
+            ".{}\nDistribution: {}\nHold mode: {}\nIcon: {}\nCommand: {}",
+            ext,
+            self.get_distro_label(cfg.distro.as_ref()),
+            hold,
+            icon,
+            command
+        ))
+    }
+}
+
+/// Get WSL path of an example script to compose preview commands for.
+fn sample_script_path(ext: &str) -> std::path::PathBuf {
+    std::path::PathBuf::from(format!("/path/to/script.{}", ext))
+}
+
+/// Default DPI that the layout is given in.
+pub(crate) const DEFAULT_DPI: u32 = 96;
+
+/// Scale a length at 96 DPI to given DPI.
+pub(crate) fn scale_for_dpi(value: i32, dpi: u32) -> i32 {
+    (value as i64 * dpi as i64 / DEFAULT_DPI as i64) as i32
+}
+
+/// Load icon from a location given as `path,index`, eg. `shell32.dll,3`.
+///
+/// Index defaults to 0, and a comma not followed by a number is taken
+/// as a part of the path. Quotes around the path and environment
+/// variables in it are allowed.
+fn parse_icon_location(s: &str) -> Result<ShellIcon, Error> {
+    let s = s.trim();
+    let (path, index) = match s.rsplit_once(',') {
+        Some((path, index)) => match index.trim().parse::<u32>() {
+            Ok(index) => (path, index),
+            Err(_) => (s, 0),
+        },
+        None => (s, 0),
+    };
+    let path = path.trim().trim_matches('"');
+    if path.is_empty() {
+        return Err(Error::WinAPIError(String::from("No icon file given.")));
+    }
+    ShellIcon::load(win32::WinPathBuf::from(path).expand()?, index)
 }
 
 /// Set font to given window.
-fn set_window_font(hwnd: windef::HWND, font: &Font) {
-    unsafe { SendMessageW(hwnd, WM_SETFONT, font.handle as _, win::TRUE as _) };
+fn set_window_font(hwnd: HWND, font: &Font) {
+    unsafe {
+        SendMessageW(
+            hwnd,
+            WM_SETFONT,
+            WPARAM(font.handle().0 as _),
+            LPARAM(TRUE.0 as _),
+        )
+    };
 }
 
 impl WindowProc for MainWindow {
     fn window_proc(
         &mut self,
-        hwnd: windef::HWND,
-        msg: win::UINT,
-        wparam: win::WPARAM,
-        lparam: win::LPARAM,
-    ) -> Option<win::LRESULT> {
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> Option<LRESULT> {
         match msg {
             WM_NCCREATE => {
                 // store main window handle
-                self.hwnd = hwnd;
+                self.hwnd = unsafe { OwnedHwnd::from_raw(hwnd) };
                 // WM_NCCREATE must be passed to DefWindowProc
                 None
             }
             WM_CREATE => {
-                if self.create_window_controls().is_err() {
-                    return Some(-1);
+                if let Err(e) = self.create_window_controls() {
+                    window::quit_with_error(e);
+                    return Some(LRESULT(-1));
                 }
                 if self.extend_system_menu().is_err() {
                     log::error!("Failed to extend system menu.");
                 }
-                Some(0)
+                Some(LRESULT(0))
             }
             WM_SIZE => {
-                self.on_resize(
-                    i32::from(win::LOWORD(lparam as _)),
-                    i32::from(win::HIWORD(lparam as _)),
-                );
-                Some(0)
+                // layout is given at 96 DPI
+                let [width, height] = [loword(lparam.0 as _), hiword(lparam.0 as _)]
+                    .map(|v| i32::from(v) * DEFAULT_DPI as i32 / self.dpi as i32);
+                self.on_resize(width, height);
+                Some(LRESULT(0))
             }
             WM_GETMINMAXINFO => {
-                let mmi = unsafe { &mut *(lparam as LPMINMAXINFO) };
-                mmi.ptMinTrackSize.x = MIN_WINDOW_SIZE.0;
-                mmi.ptMinTrackSize.y = MIN_WINDOW_SIZE.1;
-                Some(0)
+                let mmi = unsafe { &mut *(lparam.0 as *mut MINMAXINFO) };
+                mmi.ptMinTrackSize.x = scale_for_dpi(MIN_WINDOW_SIZE.0, self.dpi);
+                mmi.ptMinTrackSize.y = scale_for_dpi(MIN_WINDOW_SIZE.1, self.dpi);
+                Some(LRESULT(0))
+            }
+            WM_DPICHANGED => {
+                let suggested = unsafe { &*(lparam.0 as *const RECT) };
+                if let Err(e) = self.on_dpi_changed(u32::from(hiword(wparam.0 as _)), suggested) {
+                    log::error!("Failed to handle DPI change: {}", e);
+                }
+                Some(LRESULT(0))
+            }
+            WM_CTLCOLORSTATIC
+                if HWND(lparam.0) == self.get_control_handle(Control::StaticErrorBanner) =>
+            {
+                // draw error banner with tooltip colors
+                let hdc = Gdi::HDC(wparam.0 as _);
+                unsafe {
+                    Gdi::SetTextColor(hdc, COLORREF(Gdi::GetSysColor(Gdi::COLOR_INFOTEXT)));
+                    Gdi::SetBkColor(hdc, COLORREF(Gdi::GetSysColor(Gdi::COLOR_INFOBK)));
+                    Some(LRESULT(Gdi::GetSysColorBrush(Gdi::COLOR_INFOBK).0))
+                }
             }
-            WM_CTLCOLORSTATIC => Some(unsafe { wingdi::GetStockObject(COLOR_WINDOW + 1_i32) } as _),
+            WM_CTLCOLORSTATIC => Some(LRESULT(
+                unsafe {
+                    Gdi::GetStockObject(Gdi::GET_STOCK_OBJECT_FLAGS(Gdi::COLOR_WINDOW.0 + 1))
+                }
+                .0,
+            )),
             WM_COMMAND => {
                 // if lParam is non-zero, message is from a control
-                if lparam != 0 {
-                    if let Ok(id) = Control::try_from(win::LOWORD(wparam as _)) {
-                        match self.on_control(lparam as _, id, win::HIWORD(wparam as _)) {
+                if lparam.0 != 0 {
+                    if let Ok(id) = Control::try_from(loword(wparam.0 as _)) {
+                        match self.on_control(HWND(lparam.0), id, hiword(wparam.0 as _)) {
                             Err(e) => {
-                                win32::error_message(&e.to_wide());
-                                return Some(0);
+                                self.show_error("Operation failed", &e);
+                                return Some(LRESULT(0));
                             }
                             Ok(l) => return Some(l),
                         }
                     }
                 }
                 // if lParam is zero and HIWORD of wParam is zero, message is from a menu
-                else if win::HIWORD(wparam as u32) == 0 {
-                    if let Ok(id) = MenuItem::try_from(wparam as u32) {
-                        return Some(self.on_menucommand(ptr::null_mut(), id));
+                else if hiword(wparam.0 as u32) == 0 {
+                    if let Ok(id) = MenuItem::try_from(wparam.0 as u32) {
+                        return Some(self.on_menucommand(HMENU::default(), id));
                     }
                 }
-                None
-            }
-            WM_MENUCOMMAND => {
-                let hmenu = lparam as windef::HMENU;
-                let item_id = unsafe { GetMenuItemID(hmenu, wparam as _) };
-                if let Ok(id) = MenuItem::try_from(item_id) {
-                    return Some(self.on_menucommand(hmenu, id));
+                // if HIWORD of wParam is one, message is from an accelerator
+                else if hiword(wparam.0 as u32) == 1 {
+                    if let Ok(id) = Accelerator::try_from(loword(wparam.0 as u32)) {
+                        return Some(self.on_accelerator(id));
+                    }
                 }
                 None
             }
             WM_SYSCOMMAND => {
-                if let Ok(id) = SystemMenu::try_from(wparam as u32) {
+                if let Ok(id) = SystemMenu::try_from(wparam.0 as u32) {
                     return Some(self.on_system_menu_command(id));
                 }
+                let id = wparam.0 as u32;
+                if (TERMINATE_MENU_BASE..TERMINATE_MENU_BASE + 100).contains(&id) {
+                    self.on_terminate_distro((id - TERMINATE_MENU_BASE) as usize);
+                    return Some(LRESULT(0));
+                }
                 None
             }
             WM_NOTIFY => {
-                let hdr = unsafe { &*(lparam as LPNMHDR) };
+                let hdr = unsafe { &*(lparam.0 as *const Controls::NMHDR) };
                 if let Ok(id) = Control::try_from(hdr.idFrom as u16) {
-                    return Some(self.on_notify(hdr.hwndFrom, id, hdr.code, lparam as *const _));
+                    return Some(self.on_notify(hdr.hwndFrom, id, hdr.code, lparam.0 as *const _));
                 }
                 None
             }
             WM_CLOSE => {
-                unsafe { DestroyWindow(hwnd) };
-                Some(0)
+                if !self.confirm_discard_changes() {
+                    return Some(LRESULT(0));
+                }
+                let _ = unsafe { DestroyWindow(hwnd) };
+                Some(LRESULT(0))
             }
             WM_DESTROY => {
                 unsafe { PostQuitMessage(0) };
-                Some(0)
+                Some(LRESULT(0))
+            }
+            WM_NCDESTROY => {
+                // window is gone, nothing left to destroy on drop
+                self.hwnd.release();
+                None
             }
             _ => None,
         }
@@ -1152,39 +4700,37 @@ impl WindowProc for MainWindow {
 
 /// Subclass callback for the extension input control.
 extern "system" fn extension_input_proc(
-    hwnd: windef::HWND,
-    msg: win::UINT,
-    wparam: win::WPARAM,
-    lparam: win::LPARAM,
-    _subclass_id: basetsd::UINT_PTR,
-    data: basetsd::DWORD_PTR,
-) -> win::LRESULT {
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _subclass_id: usize,
+    data: usize,
+) -> LRESULT {
     let wnd = unsafe { &mut *(data as *mut MainWindow) };
     #[allow(clippy::single_match)]
     match msg {
         // TODO: filter dots etc.
-        WM_KEYDOWN => match wparam as i32 {
+        WM_KEYDOWN => match VIRTUAL_KEY(wparam.0 as _) {
             VK_RETURN => {
                 if let Err(e) = wnd.on_register_button_clicked() {
-                    win32::error_message(&e.to_wide());
+                    wnd.show_error("Failed to register extension", &e);
                 }
-                return 0;
+                return LRESULT(0);
             }
             _ => {}
         },
-        WM_CHAR => match wparam as i32 {
+        WM_CHAR => match VIRTUAL_KEY(wparam.0 as _) {
             VK_RETURN => {
-                return 0;
+                return LRESULT(0);
             }
             _ => {
-                if let Some(ch) = std::char::from_u32(wparam as _) {
+                if let Some(ch) = std::char::from_u32(wparam.0 as _) {
                     match ch {
                         // illegal filename characters
-                        '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => return 0,
+                        '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => return LRESULT(0),
                         // space
-                        ' ' => return 0,
-                        // no periods in extension
-                        '.' => return 0,
+                        ' ' => return LRESULT(0),
                         _ => {}
                     }
                 }
@@ -1192,15 +4738,15 @@ extern "system" fn extension_input_proc(
         },
         _ => {}
     }
-    unsafe { commctrl::DefSubclassProc(hwnd, msg, wparam, lparam) }
+    unsafe { Shell::DefSubclassProc(hwnd, msg, wparam, lparam) }
+}
+
+/// Get the low-order word of a value.
+fn loword(l: u32) -> u16 {
+    (l & 0xffff) as u16
 }
 
-extern "system" {
-    /// https://docs.microsoft.com/en-us/windows/win32/api/shlobj_core/nf-shlobj_core-pickicondlg
-    pub fn PickIconDlg(
-        hwnd: windef::HWND,
-        pszIconPath: ntdef::PWSTR,
-        cchIconPath: win::UINT,
-        piIconIndex: *mut std::os::raw::c_int,
-    ) -> std::os::raw::c_int;
+/// Get the high-order word of a value.
+fn hiword(l: u32) -> u16 {
+    ((l >> 16) & 0xffff) as u16
 }
@@ -4,20 +4,37 @@ use std::env;
 use std::ffi::OsString;
 use std::path::PathBuf;
 use wchar::*;
+use wslscript_common::elevate;
 use wslscript_common::error::*;
-use wslscript_common::wsl;
+use wslscript_common::{throttle, wsl};
 
+mod cli;
 mod gui;
+mod server;
+mod setup;
 
 fn main() {
     if let Err(e) = run_app() {
         log::error!("{}", e);
+        if matches!(
+            e.inner(),
+            Error::WSLUpdateRequired | Error::WSLRestartRequired
+        ) {
+            setup::offer_wsl_update(windows::Win32::Foundation::HWND::default(), e.inner());
+            return;
+        }
+        if let Some(stderr) = e.stderr() {
+            wslscript_common::win32::error_dialog("Error", &e.to_string(), stderr);
+            return;
+        }
         unsafe {
-            use winapi::um::winuser::*;
+            use windows::core::PCWSTR;
+            use windows::Win32::Foundation::HWND;
+            use windows::Win32::UI::WindowsAndMessaging::*;
             MessageBoxW(
-                std::ptr::null_mut(),
-                e.to_wide().as_ptr(),
-                wchz!("Error").as_ptr(),
+                HWND::default(),
+                PCWSTR(e.to_wide().as_ptr()),
+                PCWSTR(wchz!("Error").as_ptr()),
                 MB_OK | MB_ICONERROR | MB_SERVICE_NOTIFICATION,
             );
         }
@@ -41,6 +58,25 @@ fn run_app() -> Result<(), Error> {
     env::args_os()
         .enumerate()
         .for_each(|(n, arg)| log::debug!("Arg {}: {}", n, arg.to_string_lossy()));
+    // clean up temporary files left behind by earlier runs
+    wsl::sweep_temp_files();
+    if let Err(e) = wslscript_common::registry::upgrade_settings() {
+        log::error!("Failed to upgrade settings: {}", e);
+    }
+    // action requested from an elevated instance
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    // started by COM to host the drop handler out of process
+    if server::is_embedding(&args) {
+        return server::run();
+    }
+    if let Some(req) = elevate::ElevatedRequest::from_args(&args) {
+        let result = run_elevated_action(&req.verb, &req.payload);
+        std::process::exit(req.complete(result));
+    }
+    // command line management, eg. for deployment tooling
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
     // if program was started with the first and only argument being a .sh file
     // or one of the registered extensions.
     // this handles a script file being dragged and dropped to wslscript.exe.
@@ -52,7 +88,7 @@ fn run_app() -> Result<(), Error> {
             let path = PathBuf::from(&arg);
             let ext = path.extension().unwrap_or_default().to_string_lossy();
             // check whether extension is registered
-            let opts = match wsl::WSLOptions::from_ext(&ext) {
+            let opts = match wsl::WSLOptions::from_path(&path) {
                 Some(opts) => Some(opts),
                 // if extension is ".sh", use default options
                 None if ext == "sh" => Some(wsl::WSLOptions::default()),
@@ -63,34 +99,77 @@ fn run_app() -> Result<(), Error> {
             }
         }
     }
-    // seek for -E flag and collect all arguments after that
-    let wsl_args: Vec<OsString> = env::args_os()
-        .skip_while(|arg| arg != "-E")
-        .skip(1)
-        .collect();
-    if !wsl_args.is_empty() {
-        // collect arguments preceding -E
-        let opts: Vec<OsString> = env::args_os().take_while(|arg| arg != "-E").collect();
-        return execute_wsl(wsl_args, wsl::WSLOptions::from_args(opts));
+    // script and its arguments follow --exec, or -E in older registrations
+    if let Some(exec) = wsl::ExecArgs::parse(&args) {
+        let opts = exec.options;
+        let mut wsl_args = exec.args;
+        wsl_args.insert(0, exec.script);
+        let shell = opts
+            .iter()
+            .any(|arg| arg == wslscript_common::registry::SHELL_FLAG);
+        // shell invokes the handler of the last extension only,
+        // so prefer options of a longer compound extension, eg. tar.gz
+        // "Open with" passes no options at all, so the filetype's are used
+        let compound = (opts.is_empty() || opts.iter().any(|arg| arg == "--ext"))
+            .then(|| wsl::WSLOptions::from_path(&PathBuf::from(&wsl_args[0])))
+            .flatten();
+        let opts = compound.unwrap_or_else(|| wsl::WSLOptions::from_args(opts));
+        if shell {
+            if !setup::ensure_wsl(windows::Win32::Foundation::HWND::default()) {
+                return Ok(());
+            }
+            return wsl::open_shell(&PathBuf::from(&wsl_args[0]), &opts);
+        }
+        return execute_wsl(wsl_args, opts);
     }
+    // registration works without WSL, so only offer to set it up
+    setup::ensure_wsl(windows::Win32::Foundation::HWND::default());
     // start Windows GUI
     gui::start_gui()
 }
 
+/// Run action that requires administrator rights.
+///
+/// Invoked in an instance started by `elevate::run_elevated`.
+fn run_elevated_action(verb: &str, _payload: &[OsString]) -> Result<(), Error> {
+    if !elevate::is_elevated() {
+        return Err(Error::ElevationError("Process is not elevated.".to_owned()));
+    }
+    match verb {
+        setup::INSTALL_VERB => setup::install_wsl(),
+        gui::EVENT_SOURCE_VERB => wslscript_common::registry::register_event_source(),
+        _ => Err(Error::GenericError(format!("Unknown action: {}", verb))),
+    }
+}
+
 fn execute_wsl(args: Vec<OsString>, opts: wsl::WSLOptions) -> Result<(), Error> {
-    // convert args to paths, canonicalize when possible
+    if !setup::ensure_wsl(windows::Win32::Foundation::HWND::default()) {
+        return Ok(());
+    }
+    // convert args to paths, canonicalize when possible,
+    // otherwise at least expand short names
     let paths: Vec<PathBuf> = args
         .iter()
         .map(PathBuf::from)
-        .map(|p| p.canonicalize().unwrap_or(p))
+        .map(|p| {
+            p.canonicalize()
+                .unwrap_or_else(|_| wslscript_common::win32::long_path(&p))
+        })
         .collect();
     // ensure not trying to invoke self
-    if let Some(exe_os) = env::current_exe().ok().and_then(|p| p.canonicalize().ok()) {
-        if paths[0] == exe_os {
-            return Err(Error::InvalidPathError);
-        }
-    }
+    wsl::check_self_invocation(&paths[0])?;
+    // counted instance is kept until the script exits
+    let slot = throttle::begin_run(&opts)?;
     // convert paths to WSL equivalents
     let wsl_paths = wsl::paths_to_wsl(&paths, &opts, None)?;
-    wsl::run_wsl(&wsl_paths[0], &wsl_paths[1..], &opts)
+    wsl::run_wsl(
+        &paths[0],
+        &wsl_paths[0],
+        &wsl_paths[1..],
+        &opts,
+        slot.is_some(),
+    )?;
+    // icon set on the console is destroyed when this process exits
+    wsl::wait_console_icons();
+    Ok(())
 }
@@ -0,0 +1,121 @@
+//! Out-of-process COM server for the drop handler.
+//!
+//! When the drop handler is registered under `LocalServer32`, COM starts
+//! `wslscript.exe -Embedding` instead of loading the handler library into
+//! Explorer. The library is then loaded into this process and its class
+//! factory registered with COM, so that a crash in the handler can't take
+//! down Explorer.
+
+use std::ffi::c_void;
+use std::time::{Duration, Instant};
+use windows::core::{s, Interface, GUID, HRESULT, PCWSTR};
+use windows::Win32::Foundation::{HMODULE, S_OK};
+use windows::Win32::System::Com;
+use windows::Win32::System::LibraryLoader;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use wslscript_common::error::*;
+use wslscript_common::wcstring;
+
+/// Interval of checks whether the handler is still in use.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time the handler must remain unused before the server exits.
+///
+/// Gives COM time to create the first object after the server starts.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Signature of `DllGetClassObject`.
+type DllGetClassObjectFn =
+    unsafe extern "system" fn(*const GUID, *const GUID, *mut *mut c_void) -> HRESULT;
+
+/// Signature of `DllCanUnloadNow`.
+type DllCanUnloadNowFn = unsafe extern "system" fn() -> HRESULT;
+
+/// Check whether the process was started by COM as a local server.
+pub(crate) fn is_embedding(args: &[std::ffi::OsString]) -> bool {
+    args.iter().any(|arg| {
+        arg.to_str()
+            .and_then(|s| s.strip_prefix(['-', '/']))
+            .is_some_and(|s| s.eq_ignore_ascii_case("embedding"))
+    })
+}
+
+/// Serve the drop handler until it's no longer in use.
+pub(crate) fn run() -> Result<(), Error> {
+    log::debug!("Starting drop handler server");
+    unsafe { Com::CoInitializeEx(None, Com::COINIT_APARTMENTTHREADED) }
+        .ok()
+        .map_err(|e| Error::WinAPIError(format!("CoInitializeEx: {}", e)))?;
+    let result = serve();
+    unsafe { Com::CoUninitialize() };
+    log::debug!("Drop handler server exited");
+    result
+}
+
+/// Register the handler's class factory and run a message loop.
+fn serve() -> Result<(), Error> {
+    let lib = load_handler()?;
+    let get_class_object: DllGetClassObjectFn = unsafe {
+        std::mem::transmute(
+            LibraryLoader::GetProcAddress(lib, s!("DllGetClassObject"))
+                .ok_or_else(|| Error::LibraryError("DllGetClassObject not found".to_owned()))?,
+        )
+    };
+    let can_unload_now: DllCanUnloadNowFn = unsafe {
+        std::mem::transmute(
+            LibraryLoader::GetProcAddress(lib, s!("DllCanUnloadNow"))
+                .ok_or_else(|| Error::LibraryError("DllCanUnloadNow not found".to_owned()))?,
+        )
+    };
+    let guid = wslscript_common::drop_handler_clsid().0;
+    let clsid = GUID::from_values(guid.Data1, guid.Data2, guid.Data3, guid.Data4);
+    let mut factory: *mut c_void = std::ptr::null_mut();
+    unsafe { get_class_object(&clsid, &Com::IClassFactory::IID, &mut factory) }
+        .ok()
+        .map_err(|e| Error::WinAPIError(format!("DllGetClassObject: {}", e)))?;
+    let factory = unsafe { Com::IClassFactory::from_raw(factory) };
+    let cookie = unsafe {
+        Com::CoRegisterClassObject(
+            &clsid,
+            &factory,
+            Com::CLSCTX_LOCAL_SERVER,
+            Com::REGCLS_MULTIPLEUSE,
+        )
+    }
+    .map_err(|e| Error::WinAPIError(format!("CoRegisterClassObject: {}", e)))?;
+    // handler objects and threads keep the library in use
+    let mut idle_since = Instant::now();
+    loop {
+        unsafe {
+            MsgWaitForMultipleObjects(
+                None,
+                false,
+                IDLE_CHECK_INTERVAL.as_millis() as u32,
+                QS_ALLINPUT,
+            )
+        };
+        let mut msg = MSG::default();
+        while unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE) }.as_bool() {
+            unsafe {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+        if unsafe { can_unload_now() } != S_OK {
+            idle_since = Instant::now();
+        } else if idle_since.elapsed() >= IDLE_TIMEOUT {
+            break;
+        }
+    }
+    let _ = unsafe { Com::CoRevokeClassObject(cookie) };
+    drop(factory);
+    Ok(())
+}
+
+/// Load the handler library from the directory of the executable.
+fn load_handler() -> Result<HMODULE, Error> {
+    let path = std::env::current_exe()?.with_file_name("wslscript_handler.dll");
+    let path = wcstring(path.to_string_lossy());
+    unsafe { LibraryLoader::LoadLibraryW(PCWSTR(path.as_ptr())) }
+        .map_err(|e| Error::LibraryError(format!("wslscript_handler.dll: {}", e)))
+}
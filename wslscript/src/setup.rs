@@ -0,0 +1,150 @@
+//! Guided setup when WSL is not installed.
+
+use std::process;
+use wchar::*;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::UI::Shell;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use wslscript_common::elevate;
+use wslscript_common::error::*;
+use wslscript_common::{wcstring, wsl};
+
+/// Verb of the elevated action that installs WSL.
+pub(crate) const INSTALL_VERB: &str = "wsl-install";
+
+/// Microsoft Store page of Windows Subsystem for Linux.
+const STORE_WSL_URL: &str = "ms-windows-store://pdp/?ProductId=9P9TQF7MRM4R";
+
+/// Microsoft Store page of Ubuntu, the default distribution.
+const STORE_DISTRO_URL: &str = "ms-windows-store://pdp/?ProductId=9PDXGNCFSCZV";
+
+/// Check that WSL is ready to run scripts, and offer to set it up if not.
+///
+/// Returns false if WSL isn't ready.
+pub(crate) fn ensure_wsl(hwnd: HWND) -> bool {
+    let e = match wsl::check_wsl_installed() {
+        Ok(()) => return true,
+        Err(e) => e,
+    };
+    log::debug!("WSL is not ready: {}", e);
+    let (problem, store_url) = match e {
+        Error::WSLFeatureDisabled => ("Windows Subsystem for Linux is not enabled.", STORE_WSL_URL),
+        Error::WSLNoDistribution => (
+            "No Linux distribution is installed in WSL.",
+            STORE_DISTRO_URL,
+        ),
+        _ => (
+            "Windows Subsystem for Linux is not installed (wsl.exe not found).",
+            STORE_WSL_URL,
+        ),
+    };
+    let text = wcstring(format!(
+        "{}\n\nWSL Script needs WSL to run scripts.\n\n\
+         Yes: install WSL now (requires administrator rights)\n\
+         No: open Microsoft Store\n\
+         Cancel: do nothing",
+        problem
+    ));
+    let result = unsafe {
+        MessageBoxW(
+            hwnd,
+            PCWSTR(text.as_ptr()),
+            w!("WSL Setup"),
+            MB_YESNOCANCEL | MB_ICONWARNING,
+        )
+    };
+    match result {
+        IDYES => match elevate::run_elevated(INSTALL_VERB, &[]) {
+            Ok(()) | Err(Error::Cancel) => {}
+            Err(e) => wslscript_common::win32::error_message(&wcstring(format!(
+                "Failed to install WSL: {}",
+                e
+            ))),
+        },
+        IDNO => unsafe {
+            Shell::ShellExecuteW(
+                None,
+                w!("open"),
+                PCWSTR(wcstring(store_url).as_ptr()),
+                PCWSTR::null(),
+                PCWSTR::null(),
+                SW_SHOWNORMAL,
+            );
+        },
+        _ => {}
+    }
+    false
+}
+
+/// Offer to update WSL after a command failed because WSL needs an update.
+///
+/// If Windows must be restarted instead, the user is only told so.
+pub(crate) fn offer_wsl_update(hwnd: HWND, e: &Error) {
+    if !matches!(e, Error::WSLUpdateRequired) {
+        let text = wcstring(format!("{}\n\nRestart Windows and try again.", e));
+        unsafe {
+            MessageBoxW(
+                hwnd,
+                PCWSTR(text.as_ptr()),
+                w!("WSL Setup"),
+                MB_OK | MB_ICONWARNING,
+            )
+        };
+        return;
+    }
+    let result = unsafe {
+        MessageBoxW(
+            hwnd,
+            w!("WSL requires an update before it can run scripts.\n\nUpdate WSL now?"),
+            w!("WSL Setup"),
+            MB_YESNO | MB_ICONWARNING,
+        )
+    };
+    if result != IDYES {
+        return;
+    }
+    if let Err(e) = wsl::update_wsl() {
+        wslscript_common::win32::error_message(&wcstring(format!("Failed to update WSL: {}", e)));
+    }
+}
+
+/// Install WSL with the default distribution.
+///
+/// Runs `wsl.exe --install` in a console window. Must be run elevated.
+pub(crate) fn install_wsl() -> Result<(), Error> {
+    let mut sysdir = std::env::var_os("SYSTEMROOT")
+        .map(std::path::PathBuf::from)
+        .ok_or(Error::WSLNotFound)?;
+    sysdir.push("System32");
+    let wsl_exe = sysdir.join("wsl.exe");
+    let status = if wsl_exe.is_file() {
+        process::Command::new(wsl_exe).arg("--install").status()
+    } else {
+        // Windows versions without wsl.exe must enable the optional feature
+        process::Command::new(sysdir.join("dism.exe"))
+            .args([
+                "/online",
+                "/enable-feature",
+                "/featurename:Microsoft-Windows-Subsystem-Linux",
+                "/all",
+                "/norestart",
+            ])
+            .status()
+    }?;
+    if !status.success() {
+        return Err(Error::GenericError(format!(
+            "Installer exited with code {}",
+            status.code().unwrap_or(-1)
+        )));
+    }
+    unsafe {
+        MessageBoxW(
+            HWND::default(),
+            PCWSTR(wchz!("WSL was installed. Restart Windows if asked to do so.").as_ptr()),
+            w!("WSL Setup"),
+            MB_OK | MB_ICONINFORMATION,
+        );
+    }
+    Ok(())
+}
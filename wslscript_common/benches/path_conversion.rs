@@ -0,0 +1,55 @@
+//! Benchmarks of Windows to WSL path conversion strategies.
+//!
+//! Requires WSL with a default distribution. Run with:
+//!
+//! ```text
+//! cargo bench -p wslscript_common --features bench
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::path::PathBuf;
+use wslscript_common::wsl::{self, bench, WSLOptions};
+
+/// Number of paths converted in each benchmark.
+const PATH_COUNTS: &[usize] = &[1, 100, 5_000];
+
+/// Generate paths under the temporary directory.
+///
+/// Paths needn't exist, `wslpath` converts any path on a mounted drive.
+fn sample_paths(count: usize) -> Vec<PathBuf> {
+    let dir = std::env::temp_dir();
+    (0..count)
+        .map(|i| {
+            dir.join(format!("wslscript bench {}", i / 100))
+                .join(format!("file {}.txt", i))
+        })
+        .collect()
+}
+
+fn path_conversion(c: &mut Criterion) {
+    let opts = WSLOptions::default();
+    let mut group = c.benchmark_group("path_conversion");
+    // a wslpath invocation per path takes seconds for large counts
+    group.sample_size(10);
+    for &count in PATH_COUNTS {
+        let paths = sample_paths(count);
+        wsl::check_drives(&paths).expect("drive of the temporary directory not found");
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("wslpath", count), &paths, |b, paths| {
+            b.iter(|| bench::convert_with_wslpath(paths, &opts).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("native", count), &paths, |b, paths| {
+            b.iter(|| bench::convert_native(paths, &opts).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("inline", count), &paths, |b, paths| {
+            b.iter(|| bench::convert_streaming(paths, &opts, false).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("helper", count), &paths, |b, paths| {
+            b.iter(|| bench::convert_streaming(paths, &opts, true).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, path_conversion);
+criterion_main!(benches);
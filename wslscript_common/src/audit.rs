@@ -0,0 +1,153 @@
+//! Log of changes made to the registered filetypes.
+//!
+//! Each registration, save and unregistration appends a line with a
+//! timestamp, the action, the extension and the changed values
+//! to a text file in the user's local application data folder.
+
+use crate::error::*;
+use crate::registry::{ExtConfig, HoldMode};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Get path to the change log file.
+pub fn log_path() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(|dir| {
+        let mut path = PathBuf::from(dir);
+        path.push("wslscript");
+        path.push("changes.log");
+        path
+    })
+}
+
+/// Record a change of an extension configuration.
+///
+/// `old` is the configuration prior to the change, or None if the extension
+/// wasn't registered. `new` is the configuration after the change, or None
+/// if the extension was unregistered.
+///
+/// Failure to write the log is only logged, so that it never prevents
+/// the change itself.
+pub(crate) fn record(ext: &str, old: Option<&ExtConfig>, new: Option<&ExtConfig>) {
+    let action = match (old, new) {
+        (None, Some(_)) => "register",
+        (Some(_), Some(_)) => "save",
+        (_, None) => "unregister",
+    };
+    let old = old.map(describe).unwrap_or_default();
+    let new = new.map(describe).unwrap_or_default();
+    let mut line = format!("{} {} .{}", timestamp(), action, ext);
+    let changes = changed_values(&old, &new);
+    if !changes.is_empty() {
+        line.push_str(": ");
+        line.push_str(&changes.join(", "));
+    }
+    if let Err(e) = append_line(&line) {
+        log::warn!("Failed to write change log: {}", e);
+    }
+}
+
+/// Describe changes between two lists of named values.
+fn changed_values(old: &[(&'static str, String)], new: &[(&'static str, String)]) -> Vec<String> {
+    let get = |values: &[(&'static str, String)], name: &str| {
+        values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    };
+    // either list is empty when the extension wasn't or is no longer registered
+    let names = if new.is_empty() { old } else { new };
+    names
+        .iter()
+        .filter_map(|(name, _)| {
+            let (o, n) = (get(old, name), get(new, name));
+            (o != n).then(|| format!("{} {:?} \u{2192} {:?}", name, o, n))
+        })
+        .collect()
+}
+
+/// Get configuration values as named strings.
+fn describe(cfg: &ExtConfig) -> Vec<(&'static str, String)> {
+    let distro = cfg.distro.as_ref().map(|guid| {
+        crate::registry::distro_guid_to_name(guid.clone()).unwrap_or_else(|| guid.to_string())
+    });
+    #[allow(unused_mut)]
+    let mut values = vec![
+        (
+            "HoldMode",
+            match cfg.hold_mode {
+                HoldMode::Timeout(secs) => format!("{} {}s", cfg.hold_mode.as_string(), secs),
+                mode => mode.as_string(),
+            },
+        ),
+        ("HoldKey", cfg.hold_key.to_string()),
+        ("HoldMessage", cfg.hold_message.clone().unwrap_or_default()),
+        ("Interactive", cfg.interactive.to_string()),
+        ("Distribution", distro.unwrap_or_default()),
+        ("CrlfMode", cfg.crlf_mode.as_string()),
+        ("RawArgs", cfg.raw_args.to_string()),
+        ("ConsoleAtDrop", cfg.console_at_drop.to_string()),
+        ("ReuseConsole", cfg.reuse_console.to_string()),
+        (
+            "ConsoleTitle",
+            cfg.console_title.clone().unwrap_or_default(),
+        ),
+        (
+            "ConsoleSize",
+            cfg.console_size.map(|s| s.to_string()).unwrap_or_default(),
+        ),
+        (
+            "ConsoleColors",
+            cfg.console_colors
+                .map(|c| c.to_string())
+                .unwrap_or_default(),
+        ),
+        ("Utf8Console", cfg.utf8_console.to_string()),
+        ("Category", cfg.category.clone().unwrap_or_default()),
+        ("TypeName", cfg.type_name.clone().unwrap_or_default()),
+        ("DropPolicy", cfg.drop_policy.to_string()),
+        ("ArgOrder", cfg.arg_order.as_string()),
+        ("ArgChmod", cfg.arg_chmod.clone().unwrap_or_default()),
+        ("ArgChown", cfg.arg_chown.clone().unwrap_or_default()),
+        ("AbortOnMissing", cfg.abort_on_missing.to_string()),
+        ("MaxArgs", cfg.max_args.to_string()),
+        ("UseHelper", cfg.use_helper.to_string()),
+        ("WslEnv", cfg.wslenv.to_string()),
+        ("MinInterval", cfg.min_interval.to_string()),
+        ("MaxInstances", cfg.max_instances.to_string()),
+        ("SingleInstance", cfg.single_instance.to_string()),
+        ("ShowProgress", cfg.show_progress.to_string()),
+        ("EventLog", cfg.event_log.as_string()),
+        ("LogMaxAge", cfg.log_max_age.to_string()),
+        ("LogMaxFiles", cfg.log_max_files.to_string()),
+        ("Association", cfg.association.as_string()),
+    ];
+    #[cfg(feature = "gui")]
+    values.push((
+        "Icon",
+        cfg.icon
+            .as_ref()
+            .map(|icon| icon.shell_path().to_string_lossy())
+            .unwrap_or_default(),
+    ));
+    values
+}
+
+/// Get current local time formatted as `YYYY-MM-DD hh:mm:ss`.
+fn timestamp() -> String {
+    use windows::Win32::System::SystemInformation::GetLocalTime;
+    let t = unsafe { GetLocalTime() };
+    crate::win32::format_date_time(&t)
+}
+
+/// Append a line to the change log.
+fn append_line(line: &str) -> Result<(), Error> {
+    let path = log_path().ok_or(Error::InvalidPathError)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! Re-launching the program elevated for operations that need
+//! administrator rights.
+//!
+//! Elevated instance is started with `--elevated <verb> <status file> [payload...]`.
+//! It reports the result with its exit code, and on failure writes the error
+//! message to the status file for the launching instance to display.
+
+use crate::error::*;
+use crate::wcstring;
+use crate::win32;
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{self, HANDLE};
+use windows::Win32::Security;
+use windows::Win32::System::Threading;
+use windows::Win32::UI::Shell;
+use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+/// Command line flag that starts an elevated action.
+pub const ELEVATED_FLAG: &str = "--elevated";
+
+/// Check whether the current process is elevated.
+pub fn is_elevated() -> bool {
+    let mut token = HANDLE::default();
+    if unsafe {
+        Threading::OpenProcessToken(
+            Threading::GetCurrentProcess(),
+            Security::TOKEN_QUERY,
+            &mut token,
+        )
+    }
+    .is_err()
+    {
+        return false;
+    }
+    let mut elevation = Security::TOKEN_ELEVATION::default();
+    let mut len = 0_u32;
+    let result = unsafe {
+        Security::GetTokenInformation(
+            token,
+            Security::TokenElevation,
+            Some(&mut elevation as *mut _ as _),
+            std::mem::size_of::<Security::TOKEN_ELEVATION>() as u32,
+            &mut len,
+        )
+    };
+    let _ = unsafe { Foundation::CloseHandle(token) };
+    result.is_ok() && elevation.TokenIsElevated != 0
+}
+
+/// Run an action in an elevated instance of the current executable.
+///
+/// User is prompted for consent. Blocks until the elevated instance exits.
+/// Returns `Error::Cancel` if the user declined.
+pub fn run_elevated(verb: &str, payload: &[OsString]) -> Result<(), Error> {
+    let exe = env::current_exe()?;
+    let status_file = status_file_path();
+    let mut params: Vec<u16> = Vec::new();
+    for arg in [
+        ELEVATED_FLAG.as_ref(),
+        verb.as_ref(),
+        status_file.as_os_str(),
+    ]
+    .into_iter()
+    .chain(payload.iter().map(|s| s.as_os_str()))
+    {
+        if !params.is_empty() {
+            params.push(' ' as u16);
+        }
+        win32::append_quoted_arg(&mut params, arg, false);
+    }
+    params.push(0);
+    let file = wcstring(exe.to_string_lossy());
+    let mut sei = Shell::SHELLEXECUTEINFOW {
+        cbSize: std::mem::size_of::<Shell::SHELLEXECUTEINFOW>() as u32,
+        fMask: Shell::SEE_MASK_NOCLOSEPROCESS | Shell::SEE_MASK_NOASYNC,
+        lpVerb: w!("runas"),
+        lpFile: PCWSTR(file.as_ptr()),
+        lpParameters: PCWSTR(params.as_ptr()),
+        nShow: SW_SHOWNORMAL.0,
+        ..Default::default()
+    };
+    log::debug!("Running elevated action {}", verb);
+    if let Err(e) = unsafe { Shell::ShellExecuteExW(&mut sei) } {
+        if e.code() == Foundation::ERROR_CANCELLED.to_hresult() {
+            return Err(Error::Cancel);
+        }
+        return Err(e.into());
+    }
+    let mut code = 0_u32;
+    unsafe {
+        Threading::WaitForSingleObject(sei.hProcess, Threading::INFINITE);
+        let _ = Threading::GetExitCodeProcess(sei.hProcess, &mut code);
+        let _ = Foundation::CloseHandle(sei.hProcess);
+    }
+    let message = std::fs::read_to_string(&status_file).unwrap_or_default();
+    let _ = std::fs::remove_file(&status_file);
+    if code == 0 {
+        Ok(())
+    } else if message.is_empty() {
+        Err(Error::ElevationError(format!("Exit code {}", code)))
+    } else {
+        Err(Error::ElevationError(message))
+    }
+}
+
+/// Get unique path for the status file of an elevated action.
+fn status_file_path() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    env::temp_dir().join(format!(
+        "wslscript-elevated-{}-{}.txt",
+        std::process::id(),
+        nanos
+    ))
+}
+
+/// Action requested from an elevated instance.
+pub struct ElevatedRequest {
+    /// Action verb.
+    pub verb: String,
+    /// Action specific arguments.
+    pub payload: Vec<OsString>,
+    /// File to write the error message to.
+    status_file: PathBuf,
+}
+
+impl ElevatedRequest {
+    /// Parse request from command line arguments, excluding the program name.
+    ///
+    /// Returns None if the arguments don't start with `ELEVATED_FLAG`.
+    pub fn from_args(args: &[OsString]) -> Option<Self> {
+        match args {
+            [flag, verb, status_file, payload @ ..] if flag == ELEVATED_FLAG => Some(Self {
+                verb: verb.to_string_lossy().into_owned(),
+                payload: payload.to_vec(),
+                status_file: PathBuf::from(status_file),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Report the result of the action to the launching instance.
+    ///
+    /// Returns the exit code for the elevated process.
+    pub fn complete(self, result: Result<(), Error>) -> i32 {
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                log::error!("Elevated action {} failed: {}", self.verb, e);
+                if std::fs::write(&self.status_file, e.to_string()).is_err() {
+                    log::error!("Failed to write status file");
+                }
+                1
+            }
+        }
+    }
+}
@@ -9,18 +9,72 @@ pub enum Error {
     #[error("Failed to convert Windows path to WSL path.")]
     WinToUnixPathError,
 
+    #[error("Failed to convert {0} to WSL path.")]
+    WinToUnixPathInputError(String),
+
     #[error("WSL not found or not installed.")]
     WSLNotFound,
 
-    #[error("Failed to start WSL process.")]
-    WSLProcessError,
+    #[error("Windows Subsystem for Linux is not enabled.")]
+    WSLFeatureDisabled,
+
+    #[error("No WSL distribution is installed.")]
+    WSLNoDistribution,
+
+    #[error("WSL requires an update. Run: wsl --update")]
+    WSLUpdateRequired,
+
+    #[error("WSL requires Windows to be restarted to finish an update.")]
+    WSLRestartRequired,
+
+    #[error("Failed to start WSL process: {source}")]
+    WSLProcessError {
+        /// Command line that failed to start.
+        command: String,
+        source: std::io::Error,
+    },
+
+    #[error("{error}")]
+    WithStderr {
+        error: Box<Error>,
+        /// Error output of `wsl.exe`, explaining the error.
+        stderr: String,
+    },
 
     #[error("Invalid path.")]
     InvalidPathError,
 
+    #[error(
+        "Removable drive {0}: couldn't be mounted in WSL. \
+         Reconnect the drive, or mount it in WSL with: sudo mount -t drvfs {0}: /mnt/<letter>"
+    )]
+    RemovableDriveError(char),
+
+    #[error("Drive {0}: is locked by BitLocker. Unlock the drive and try again.")]
+    DriveLockedError(char),
+
+    #[error("Drive {0}: is offline or not ready. Reconnect the drive and try again.")]
+    DriveNotReadyError(char),
+
     #[error("Command is too long.")]
     CommandTooLong,
 
+    #[error("Refusing to run: {0} contains characters that can't be passed safely to WSL.")]
+    UnsafeValue(&'static str),
+
+    #[error("Refusing to run {0}: it would start WSL Script again.")]
+    SelfInvocationError(String),
+
+    #[error("{script} exited with code {code} after {secs}s.")]
+    ScriptExitError {
+        /// Name of the script.
+        script: String,
+        /// Exit code of the script.
+        code: i32,
+        /// Run time in seconds.
+        secs: u64,
+    },
+
     #[error("String is not nul terminated.")]
     MissingNulError,
 
@@ -39,6 +93,12 @@ pub enum Error {
     #[error("WinAPI error: {0}")]
     WinAPIError(String),
 
+    #[error("Elevated operation failed: {0}")]
+    ElevationError(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgumentError(String),
+
     #[error("Drop handler error: {0}")]
     DropHandlerError(String),
 
@@ -53,6 +113,22 @@ impl Error {
     pub fn to_wide(&self) -> widestring::WideCString {
         wcstring(self.to_string())
     }
+
+    /// Error output of `wsl.exe` attached to the error, if any.
+    pub fn stderr(&self) -> Option<&str> {
+        match self {
+            Error::WithStderr { stderr, .. } => Some(stderr),
+            _ => None,
+        }
+    }
+
+    /// Error without the attached error output of `wsl.exe`.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::WithStderr { error, .. } => error.inner(),
+            e => e,
+        }
+    }
 }
 
 impl From<anyhow::Error> for Error {
@@ -73,3 +149,9 @@ impl From<widestring::error::MissingNulTerminator> for Error {
         Error::MissingNulError
     }
 }
+
+impl From<windows::core::Error> for Error {
+    fn from(e: windows::core::Error) -> Error {
+        Error::WinAPIError(e.message().to_string())
+    }
+}
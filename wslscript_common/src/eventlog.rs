@@ -0,0 +1,296 @@
+//! Logging of script runs to the Windows Event Log.
+//!
+//! Scripts of filetypes with event logging enabled are run without
+//! a console, and their start and exit are reported to the Application log
+//! under the `WSL Script` source. Source is registered with
+//! `registry::register_event_source`, which points it at the message table
+//! of `wslscript.exe`.
+//!
+//! When output is logged, the full output of each run is also written to
+//! a file in the user's local application data folder. The folder is
+//! excluded from Windows Search indexing, and old logs are deleted as set
+//! for the filetype.
+
+use crate::error::*;
+use crate::wcstring;
+use crate::wsl::RunStatus;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::PSID;
+use windows::Win32::Storage::FileSystem::{
+    GetFileAttributesW, SetFileAttributesW, FILE_ATTRIBUTE_NOT_CONTENT_INDEXED,
+    FILE_FLAGS_AND_ATTRIBUTES, INVALID_FILE_ATTRIBUTES,
+};
+use windows::Win32::System::EventLog::*;
+use windows::Win32::System::Threading;
+
+/// Event source name in the Application log.
+pub const EVENT_SOURCE: &str = "WSL Script";
+
+/// Event ID of a started script.
+///
+/// Event IDs must match the message table built by `build.rs`.
+const EVENT_STARTED: u32 = 1;
+
+/// Event ID of an exited script.
+const EVENT_EXITED: u32 = 2;
+
+/// Event ID of a script that failed to start.
+const EVENT_FAILED: u32 = 3;
+
+/// Maximum number of bytes of output included in the exit event.
+///
+/// Event text is limited to 31 839 characters, so only the end of a long
+/// output is logged.
+const MAX_OUTPUT_LEN: usize = 16 * 1024;
+
+/// Output logging of a script run.
+pub(crate) struct OutputLog<'a> {
+    /// Registered extension of the script, if any.
+    pub extension: Option<&'a str>,
+    /// Number of days after which logs are deleted, zero to keep them.
+    pub max_age: u32,
+    /// Number of most recent logs kept, zero for no limit.
+    pub max_files: u32,
+}
+
+/// Get path to the folder of output logs.
+pub fn output_log_root() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(|dir| {
+        let mut path = PathBuf::from(dir);
+        path.push("wslscript");
+        path.push("logs");
+        path
+    })
+}
+
+/// Run a command without a console and log its start and exit.
+///
+/// If `output` is set, the end of the command's output is included in
+/// the exit event, and the full output is written to a log file.
+/// Returns the exit code and run time after the command exits.
+pub(crate) fn run_logged(
+    mut cmd: process::Command,
+    script: &Path,
+    arg_count: usize,
+    output: Option<OutputLog>,
+) -> Result<RunStatus, Error> {
+    let log_file = output.as_ref().and_then(|output| {
+        create_output_log(output, script)
+            .map_err(|e| log::warn!("Failed to create output log: {}", e))
+            .ok()
+    });
+    let script = script.to_string_lossy();
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0)
+        .stdin(Stdio::null());
+    if output.is_some() {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    } else {
+        cmd.stdout(Stdio::null()).stderr(Stdio::null());
+    }
+    let started = Instant::now();
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(source) => {
+            report(
+                EVENTLOG_ERROR_TYPE,
+                EVENT_FAILED,
+                &format!("Failed to start {}: {}", script, source),
+            );
+            return Err(Error::WSLProcessError {
+                command: format!("{:?}", cmd),
+                source,
+            });
+        }
+    };
+    report(
+        EVENTLOG_INFORMATION_TYPE,
+        EVENT_STARTED,
+        &format!("Started {} with {} arguments.", script, arg_count),
+    );
+    // read both pipes concurrently, so that neither fills up
+    let log = log_file
+        .as_ref()
+        .and_then(|(_, file)| file.try_clone().ok());
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| std::thread::spawn(|| read_tail(pipe, log)));
+    let log = log_file
+        .as_ref()
+        .and_then(|(_, file)| file.try_clone().ok());
+    let stderr = child.stderr.take().map(|pipe| read_tail(pipe, log));
+    let status = child.wait()?;
+    let stdout = stdout.and_then(|t| t.join().ok());
+    let code = status.code().unwrap_or(-1);
+    let duration = started.elapsed();
+    let mut text = format!(
+        "{} exited with code {} after {}s.",
+        script,
+        code,
+        duration.as_secs()
+    );
+    for (name, output) in [("Output", stdout), ("Errors", stderr)] {
+        if let Some(output) = output.filter(|s| !s.trim().is_empty()) {
+            text.push_str(&format!("\r\n\r\n{}:\r\n{}", name, output));
+        }
+    }
+    if let Some((path, _)) = &log_file {
+        text.push_str(&format!("\r\n\r\nFull output: {}", path.to_string_lossy()));
+    }
+    let kind = if status.success() {
+        EVENTLOG_INFORMATION_TYPE
+    } else {
+        EVENTLOG_ERROR_TYPE
+    };
+    report(kind, EVENT_EXITED, &text);
+    Ok(RunStatus {
+        exit_code: code,
+        duration,
+    })
+}
+
+/// Create a log file for the output of a script run.
+///
+/// Logs of the extension exceeding the retention are deleted first.
+fn create_output_log(output: &OutputLog, script: &Path) -> Result<(PathBuf, File), Error> {
+    let root = output_log_root().ok_or(Error::InvalidPathError)?;
+    fs::create_dir_all(&root)?;
+    exclude_from_index(&root);
+    let dir = root.join(output.extension.unwrap_or("other"));
+    fs::create_dir_all(&dir)?;
+    exclude_from_index(&dir);
+    // make room for the new log
+    let keep = (output.max_files > 0).then(|| output.max_files as usize - 1);
+    prune_output_logs(&dir, output.max_age, keep);
+    let t = unsafe { windows::Win32::System::SystemInformation::GetLocalTime() };
+    let name = format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}.log",
+        script.file_stem().unwrap_or_default().to_string_lossy(),
+        t.wYear,
+        t.wMonth,
+        t.wDay,
+        t.wHour,
+        t.wMinute,
+        t.wSecond,
+        t.wMilliseconds
+    );
+    let path = dir.join(name);
+    let file = File::create(&path)?;
+    Ok((path, file))
+}
+
+/// Mark a folder not to be indexed by Windows Search.
+///
+/// Files created in the folder inherit the attribute.
+fn exclude_from_index(dir: &Path) {
+    let path = wcstring(dir.to_string_lossy());
+    let attrs = unsafe { GetFileAttributesW(PCWSTR(path.as_ptr())) };
+    if attrs == INVALID_FILE_ATTRIBUTES || attrs & FILE_ATTRIBUTE_NOT_CONTENT_INDEXED.0 != 0 {
+        return;
+    }
+    let attrs = FILE_FLAGS_AND_ATTRIBUTES(attrs | FILE_ATTRIBUTE_NOT_CONTENT_INDEXED.0);
+    if let Err(e) = unsafe { SetFileAttributesW(PCWSTR(path.as_ptr()), attrs) } {
+        log::warn!("Failed to exclude {} from indexing: {}", dir.display(), e);
+    }
+}
+
+/// Delete logs older than `max_age` days, zero to keep them regardless of
+/// age, and all but the `keep` most recent logs.
+fn prune_output_logs(dir: &Path, max_age: u32, keep: Option<usize>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut logs: Vec<(PathBuf, SystemTime)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "log"))
+        .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?.modified().ok()?)))
+        .collect();
+    // most recent first
+    logs.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    let max_age = Duration::from_secs(u64::from(max_age) * 24 * 60 * 60);
+    let now = SystemTime::now();
+    for (n, (path, modified)) in logs.iter().enumerate() {
+        let expired =
+            !max_age.is_zero() && now.duration_since(*modified).is_ok_and(|age| age > max_age);
+        if expired || keep.is_some_and(|keep| n >= keep) {
+            if let Err(e) = fs::remove_file(path) {
+                log::warn!("Failed to delete {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Read a stream to the end, keeping only its last `MAX_OUTPUT_LEN` bytes.
+///
+/// Everything read is also written to `log`, if given.
+fn read_tail(mut reader: impl Read, mut log: Option<File>) -> String {
+    let mut tail = Vec::new();
+    let mut buf = [0_u8; 4096];
+    let mut truncated = false;
+    while let Ok(n) = reader.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        if let Some(file) = &mut log {
+            if let Err(e) = file.write_all(&buf[..n]) {
+                log::warn!("Failed to write output log: {}", e);
+                log = None;
+            }
+        }
+        tail.extend_from_slice(&buf[..n]);
+        if tail.len() > 2 * MAX_OUTPUT_LEN {
+            tail.drain(..tail.len() - MAX_OUTPUT_LEN);
+            truncated = true;
+        }
+    }
+    if tail.len() > MAX_OUTPUT_LEN {
+        tail.drain(..tail.len() - MAX_OUTPUT_LEN);
+        truncated = true;
+    }
+    let s = String::from_utf8_lossy(&tail)
+        .replace("\r\n", "\n")
+        .replace('\n', "\r\n");
+    if truncated {
+        format!("...{}", s)
+    } else {
+        s
+    }
+}
+
+/// Report an event to the Application log.
+///
+/// Failure to report is only logged, so that it never prevents the run.
+fn report(kind: REPORT_EVENT_TYPE, id: u32, text: &str) {
+    let name = wcstring(EVENT_SOURCE);
+    let source = match unsafe { RegisterEventSourceW(PCWSTR::null(), PCWSTR(name.as_ptr())) } {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("Failed to register event source: {}", e);
+            return;
+        }
+    };
+    let text = wcstring(text);
+    let strings = [PCWSTR(text.as_ptr())];
+    let result = unsafe {
+        ReportEventW(
+            source,
+            kind,
+            0,
+            id,
+            PSID::default(),
+            0,
+            Some(&strings),
+            None,
+        )
+    };
+    if let Err(e) = result {
+        log::error!("Failed to report event: {}", e);
+    }
+    let _ = unsafe { DeregisterEventSource(source) };
+}
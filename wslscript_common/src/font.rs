@@ -1,23 +1,13 @@
 use crate::error::*;
-use crate::win32;
+use crate::win32::{self, OwnedHfont};
 use std::mem;
-use std::ptr;
-use winapi::shared::minwindef as win;
-use winapi::shared::windef;
-use winapi::um::wingdi;
-use winapi::um::winuser;
+use windows::Win32::Graphics::Gdi;
+use windows::Win32::UI::WindowsAndMessaging as wm;
 
 /// Logical font.
+#[derive(Default)]
 pub struct Font {
-    pub handle: windef::HFONT,
-}
-
-impl Default for Font {
-    fn default() -> Self {
-        Self {
-            handle: ptr::null_mut(),
-        }
-    }
+    handle: OwnedHfont,
 }
 
 impl Font {
@@ -29,39 +19,86 @@ impl Font {
     ///
     /// See: https://docs.microsoft.com/en-us/windows/win32/api/wingdi/ns-wingdi-logfonta
     pub fn new_caption(size: i32) -> Result<Self, Error> {
-        use winuser::*;
-        let mut metrics = NONCLIENTMETRICSW {
-            cbSize: mem::size_of::<NONCLIENTMETRICSW>() as _,
-            ..unsafe { mem::zeroed() }
+        let mut metrics = wm::NONCLIENTMETRICSW {
+            cbSize: mem::size_of::<wm::NONCLIENTMETRICSW>() as _,
+            ..Default::default()
         };
-        if win::FALSE
-            == unsafe {
-                SystemParametersInfoW(
-                    SPI_GETNONCLIENTMETRICS,
-                    metrics.cbSize,
-                    &mut metrics as *mut _ as *mut _,
-                    0,
-                )
-            }
-        {
-            return Err(win32::last_error());
+        unsafe {
+            wm::SystemParametersInfoW(
+                wm::SPI_GETNONCLIENTMETRICS,
+                metrics.cbSize,
+                Some(&mut metrics as *mut _ as *mut _),
+                wm::SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )
         }
-        let mut lf: wingdi::LOGFONTW = metrics.lfCaptionFont;
+        .map_err(|_| win32::last_error())?;
+        let mut lf: Gdi::LOGFONTW = metrics.lfCaptionFont;
         if size > 0 {
             lf.lfHeight = size;
         }
-        let font = unsafe { wingdi::CreateFontIndirectW(&lf) };
-        if font.is_null() {
+        let font = unsafe { Gdi::CreateFontIndirectW(&lf) };
+        if font.is_invalid() {
             return Err(win32::last_error());
         }
-        Ok(Self { handle: font })
+        Ok(Self {
+            handle: unsafe { OwnedHfont::from_raw(font) },
+        })
     }
-}
 
-impl Drop for Font {
-    fn drop(&mut self) {
-        if !self.handle.is_null() {
-            unsafe { wingdi::DeleteObject(self.handle as _) };
+    /// Get caption font with given size for a display of given DPI.
+    ///
+    /// `size` is the height at 96 DPI and is scaled to `dpi`, or the default
+    /// caption font size is used if it's zero.
+    pub fn new_caption_for_dpi(size: i32, dpi: u32) -> Result<Self, Error> {
+        use windows::Win32::UI::HiDpi;
+        let mut metrics = wm::NONCLIENTMETRICSW {
+            cbSize: mem::size_of::<wm::NONCLIENTMETRICSW>() as _,
+            ..Default::default()
+        };
+        unsafe {
+            HiDpi::SystemParametersInfoForDpi(
+                wm::SPI_GETNONCLIENTMETRICS.0,
+                metrics.cbSize,
+                Some(&mut metrics as *mut _ as *mut _),
+                0,
+                dpi,
+            )
+        }
+        .map_err(|_| win32::last_error())?;
+        let mut lf: Gdi::LOGFONTW = metrics.lfCaptionFont;
+        if size > 0 {
+            lf.lfHeight = size * dpi as i32 / 96;
         }
+        let font = unsafe { Gdi::CreateFontIndirectW(&lf) };
+        if font.is_invalid() {
+            return Err(win32::last_error());
+        }
+        Ok(Self {
+            handle: unsafe { OwnedHfont::from_raw(font) },
+        })
+    }
+
+    /// Get fixed-pitch font with given size, eg. for console output.
+    pub fn new_monospace(size: i32) -> Result<Self, Error> {
+        let mut lf = Gdi::LOGFONTW {
+            lfHeight: size,
+            lfWeight: Gdi::FW_NORMAL.0 as _,
+            lfPitchAndFamily: (Gdi::FIXED_PITCH.0 | Gdi::FF_MODERN.0) as _,
+            ..Default::default()
+        };
+        let face = crate::wcstring("Consolas");
+        let face = face.as_slice_with_nul();
+        lf.lfFaceName[..face.len()].copy_from_slice(face);
+        let font = unsafe { Gdi::CreateFontIndirectW(&lf) };
+        if font.is_invalid() {
+            return Err(win32::last_error());
+        }
+        Ok(Self {
+            handle: unsafe { OwnedHfont::from_raw(font) },
+        })
+    }
+
+    pub fn handle(&self) -> Gdi::HFONT {
+        self.handle.get()
     }
 }
@@ -1,21 +1,34 @@
 use crate::error::*;
 use crate::win32::*;
-use std::ptr::null_mut;
 use std::str::FromStr;
 use wchar::*;
 use widestring::*;
-use winapi::shared::windef;
-use winapi::um::libloaderapi;
-use winapi::um::shellapi;
-use winapi::um::winuser;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::S_OK;
+use windows::Win32::UI::Shell;
+use windows::Win32::UI::WindowsAndMessaging as wm;
+
+/// Size of large icons in pixels.
+const LARGE_ICON_SIZE: u32 = 32;
+
+/// Size of small icons in pixels.
+const SMALL_ICON_SIZE: u32 = 16;
+
+/// Icons of the executable for distributions, matched by distribution name.
+///
+/// `ExtractIcon` indexes named icon resources alphabetically, after
+/// the `app` icon at index 0.
+const DISTRO_ICONS: &[(&str, u32)] = &[("debian", 1), ("fedora", 2), ("ubuntu", 3)];
 
 /// The Old New Thing - How the shell converts an icon location into an icon
 /// https://devblogs.microsoft.com/oldnewthing/20100505-00/?p=14153
 
 #[derive(Clone)]
 pub struct ShellIcon {
-    /// Handle to loaded icon.
-    handle: windef::HICON,
+    /// Handle to loaded large icon.
+    handle: OwnedHicon,
+    /// Handle to loaded small icon.
+    small: OwnedHicon,
     /// Path to file containing icon.
     path: WinPathBuf,
     /// Icon index in a file.
@@ -25,23 +38,34 @@ pub struct ShellIcon {
 impl ShellIcon {
     pub fn load(path: WinPathBuf, index: u32) -> Result<Self, Error> {
         let s = path.to_wide();
-        let handle = unsafe {
-            shellapi::ExtractIconW(
-                libloaderapi::GetModuleHandleW(null_mut()),
-                s.as_ptr(),
-                index,
+        let mut large = wm::HICON::default();
+        let mut small = wm::HICON::default();
+        // both sizes are extracted from the same resource, so that each
+        // consumer gets an icon drawn for its size instead of a scaled one
+        let hr = unsafe {
+            Shell::SHDefExtractIconW(
+                PCWSTR(s.as_ptr()),
+                index as i32,
+                0,
+                Some(&mut large),
+                Some(&mut small),
+                LARGE_ICON_SIZE | (SMALL_ICON_SIZE << 16),
             )
         };
-        if handle.is_null() {
+        let handle = unsafe { OwnedHicon::from_raw(large) };
+        let small = unsafe { OwnedHicon::from_raw(small) };
+        if let Err(e) = hr.ok() {
+            return Err(Error::WinAPIError(format!("Failed to extract icon: {}", e)));
+        }
+        // S_FALSE if the file has no icon at the index
+        if hr != S_OK || handle.is_invalid() || small.is_invalid() {
             return Err(Error::WinAPIError(String::from(
                 "No icon found from the file.",
             )));
         }
-        if handle == 1 as _ {
-            return Err(Error::WinAPIError(String::from("File not found.")));
-        }
         Ok(Self {
             handle,
+            small,
             path,
             index,
         })
@@ -49,6 +73,36 @@ impl ShellIcon {
 
     /// Load default icon.
     pub fn load_default() -> Result<Self, Error> {
+        Self::load(Self::default_path()?, 0)
+    }
+
+    /// Load default icon for a distribution.
+    ///
+    /// Icon of a known distribution, such as Ubuntu, is chosen when
+    /// the name contains its name. Otherwise the default icon is loaded.
+    pub fn load_default_for(distro: Option<&str>) -> Result<Self, Error> {
+        let index = distro
+            .map(str::to_lowercase)
+            .and_then(|name| {
+                DISTRO_ICONS
+                    .iter()
+                    .find(|(id, _)| name.contains(id))
+                    .map(|&(_, index)| index)
+            })
+            .unwrap_or(0);
+        Self::load(Self::default_path()?, index)
+    }
+
+    /// Whether the icon is one of the default icons of the executable.
+    pub fn is_default(&self) -> bool {
+        Self::default_path().is_ok_and(|path| {
+            path.to_wide().to_string_lossy().to_lowercase()
+                == self.path.to_wide().to_string_lossy().to_lowercase()
+        })
+    }
+
+    /// Get path to the executable containing the default icons.
+    fn default_path() -> Result<WinPathBuf, Error> {
         use std::os::windows::ffi::OsStrExt;
         let s: Vec<WideChar> = std::env::current_exe()?
             .canonicalize()?
@@ -61,11 +115,16 @@ impl ShellIcon {
         } else {
             WideStr::from_slice(&s)
         };
-        Self::load(WinPathBuf::from(ws), 0)
+        Ok(WinPathBuf::from(ws))
+    }
+
+    pub fn handle(&self) -> wm::HICON {
+        self.handle.get()
     }
 
-    pub fn handle(&self) -> windef::HICON {
-        self.handle
+    /// Handle to the small icon, eg. for list items and window captions.
+    pub fn small_handle(&self) -> wm::HICON {
+        self.small.get()
     }
 
     pub fn path(&self) -> WinPathBuf {
@@ -81,12 +140,169 @@ impl ShellIcon {
         p.push(format!(",{}", self.index));
         unsafe { WideCString::from_os_str_unchecked(p) }
     }
+
+    /// Save a copy of the icon to the managed icons folder.
+    ///
+    /// Returns the icon loaded from the copy, so that the association keeps
+    /// working if the source file is moved. Default icons and icons already
+    /// in the folder are returned as is.
+    pub fn to_managed_copy(&self) -> Result<Self, Error> {
+        let dir = managed_icons_dir().ok_or(Error::LogicError("No local app data folder."))?;
+        let source = self.path.expand()?;
+        if self.is_default() || source.starts_with(&dir) {
+            return Ok(self.clone());
+        }
+        let ico = match source.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("ico") => std::fs::read(&*source)?,
+            _ => extract_icon_group(&source, self.index)?,
+        };
+        // name is unique for each source, so that saving again replaces the copy
+        let stem = source
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let key = source.to_string_lossy().to_lowercase();
+        let name = format!("{}-{}-{:08x}.ico", stem, self.index, fnv1a(key.as_bytes()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(name);
+        std::fs::write(&path, ico)?;
+        log::debug!(
+            "Saved icon {} to {}",
+            self.shell_path().display(),
+            path.display()
+        );
+        Self::load(WinPathBuf::new(path), 0)
+    }
 }
 
-impl Drop for ShellIcon {
-    fn drop(&mut self) {
-        unsafe { winuser::DestroyIcon(self.handle) };
+/// Get the folder where copies of chosen icons are saved,
+/// ie. `%LOCALAPPDATA%\wslscript\icons`.
+pub fn managed_icons_dir() -> Option<std::path::PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(|dir| {
+        let mut path = std::path::PathBuf::from(dir);
+        path.push("wslscript");
+        path.push("icons");
+        path
+    })
+}
+
+/// Extract an icon group from the resources of an executable or DLL
+/// as the contents of an .ico file.
+///
+/// `index` is the position of the group in the resources, as with
+/// `ExtractIcon`.
+fn extract_icon_group(path: &std::path::Path, index: u32) -> Result<Vec<u8>, Error> {
+    use windows::Win32::Foundation::{FreeLibrary, BOOL, HMODULE};
+    use windows::Win32::System::LibraryLoader::*;
+    /// `RT_GROUP_ICON`, ie. `RT_ICON + DIFFERENCE`.
+    const RT_GROUP_ICON: PCWSTR = PCWSTR(14 as _);
+    /// Size of `GRPICONDIR` header, and of the `ICONDIR` header of an .ico file.
+    const HEADER_SIZE: usize = 6;
+    /// Size of `GRPICONDIRENTRY`.
+    const GROUP_ENTRY_SIZE: usize = 14;
+    /// Size of `ICONDIRENTRY`.
+    const FILE_ENTRY_SIZE: usize = 16;
+
+    /// Names of the icon groups, as integer ID's or owned strings.
+    enum ResName {
+        Id(u16),
+        Name(WideCString),
     }
+    impl ResName {
+        fn as_pcwstr(&self) -> PCWSTR {
+            match self {
+                Self::Id(id) => PCWSTR(*id as usize as _),
+                Self::Name(name) => PCWSTR(name.as_ptr()),
+            }
+        }
+    }
+    unsafe extern "system" fn collect_name(
+        _module: HMODULE,
+        _ty: PCWSTR,
+        name: PCWSTR,
+        lparam: isize,
+    ) -> BOOL {
+        let names = &mut *(lparam as *mut Vec<ResName>);
+        // IS_INTRESOURCE
+        if (name.0 as usize) >> 16 == 0 {
+            names.push(ResName::Id(name.0 as usize as u16));
+        } else {
+            names.push(ResName::Name(WideCString::from_ptr_str(name.0)));
+        }
+        BOOL(1)
+    }
+    /// Get the data of a resource, valid until the module is freed.
+    unsafe fn resource<'a>(module: HMODULE, name: PCWSTR, ty: PCWSTR) -> Option<&'a [u8]> {
+        let res = FindResourceW(module, name, ty);
+        if res.is_invalid() {
+            return None;
+        }
+        let size = SizeofResource(module, res) as usize;
+        let ptr = LockResource(LoadResource(module, res).ok()?);
+        if ptr.is_null() {
+            return None;
+        }
+        Some(std::slice::from_raw_parts(ptr.cast::<u8>(), size))
+    }
+
+    let wide = WideCString::from_os_str_truncate(path);
+    let module = unsafe {
+        LoadLibraryExW(
+            PCWSTR(wide.as_ptr()),
+            None,
+            LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+        )
+    }?;
+    let result = (|| {
+        let mut names: Vec<ResName> = Vec::new();
+        let _ = unsafe {
+            EnumResourceNamesW(
+                module,
+                RT_GROUP_ICON,
+                Some(collect_name),
+                &mut names as *mut _ as isize,
+            )
+        };
+        let name = names
+            .get(index as usize)
+            .ok_or(Error::LogicError("No icon found at the index."))?;
+        let invalid = || Error::LogicError("Invalid icon resource.");
+        let group =
+            unsafe { resource(module, name.as_pcwstr(), RT_GROUP_ICON) }.ok_or_else(invalid)?;
+        let count = group
+            .get(4..HEADER_SIZE)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(invalid)?;
+        let mut header = group[..HEADER_SIZE].to_vec();
+        let mut images: Vec<u8> = Vec::new();
+        let mut offset = HEADER_SIZE + count * FILE_ENTRY_SIZE;
+        for i in 0..count {
+            let start = HEADER_SIZE + i * GROUP_ENTRY_SIZE;
+            let entry = group
+                .get(start..start + GROUP_ENTRY_SIZE)
+                .ok_or_else(invalid)?;
+            let id = u16::from_le_bytes([entry[12], entry[13]]);
+            let image = unsafe { resource(module, PCWSTR(id as usize as _), wm::RT_ICON) }
+                .ok_or_else(invalid)?;
+            // entry of the file has the offset of the image in place of its ID
+            header.extend_from_slice(&entry[..8]);
+            header.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(offset as u32).to_le_bytes());
+            images.extend_from_slice(image);
+            offset += image.len();
+        }
+        header.append(&mut images);
+        Ok(header)
+    })();
+    let _ = unsafe { FreeLibrary(module) };
+    result
+}
+
+/// 32-bit FNV-1a hash, stable across builds unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
 }
 
 impl FromStr for ShellIcon {
@@ -105,3 +321,17 @@ impl FromStr for ShellIcon {
         Self::load(WinPathBuf::from(path.as_str()), index)
     }
 }
+
+/// Serialized as the icon location, eg. `C:\path\to\icon.ico,0`.
+impl serde::Serialize for ShellIcon {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.shell_path().to_string_lossy())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ShellIcon {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -1,10 +1,28 @@
+//! Common libraries for WSL Script.
+//!
+//! Path conversion, command composition and reading the registered
+//! configuration are always available. Win32 GUI helpers and extension
+//! registration require the `gui` feature, which is enabled by default.
+
+pub mod audit;
+#[cfg(feature = "gui")]
+pub mod elevate;
 pub mod error;
+pub mod eventlog;
+#[cfg(feature = "gui")]
 pub mod font;
+#[cfg(feature = "gui")]
 pub mod icon;
+pub mod probe;
 pub mod registry;
+pub mod signature;
+#[cfg(feature = "gui")]
+pub mod throttle;
 pub mod ver;
 pub mod win32;
+#[cfg(feature = "gui")]
+pub mod window;
 pub mod wsl;
 
-pub use registry::DROP_HANDLER_CLSID;
+pub use registry::drop_handler_clsid;
 pub use win32::{wcstr, wcstring};
@@ -0,0 +1,212 @@
+//! Probing of WSL distribution capabilities.
+//!
+//! Facts about a distribution are probed with a single WSL invocation
+//! and stored in the registry, so that they're not probed again
+//! until `PROBE_TTL` has passed.
+
+use crate::registry;
+use crate::wsl;
+use std::ffi::OsStr;
+use std::os::windows::process::CommandExt;
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use windows::Win32::System::Threading;
+
+/// How long probed facts are considered valid.
+pub const PROBE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// POSIX shell script that prints the facts as `key=value` lines,
+/// followed by `WSL_CONF_MARKER` and the contents of `/etc/wsl.conf`.
+///
+/// Runs with `sh`, since bash might not be available.
+const PROBE_SCRIPT: &str = "command -v bash >/dev/null && echo bash=1; \
+    command -v tmux >/dev/null && echo tmux=1; \
+    [ -d /run/systemd/system ] && echo systemd=1; \
+    echo \"wslpath=$(wslinfo --wsl-version 2>/dev/null)\"; \
+    r=$(wslpath -u 'C:\\' 2>/dev/null); r=${r%/}; [ -n \"$r\" ] && echo \"mountroot=${r%/*}/\"; \
+    echo \"user=$(id -un)\"; \
+    echo '--- wsl.conf ---'; cat /etc/wsl.conf 2>/dev/null";
+
+/// Line separating the facts from `/etc/wsl.conf` in the probe output.
+///
+/// Configuration is parsed separately, since its `key=value` lines would
+/// otherwise be taken as facts.
+const WSL_CONF_MARKER: &str = "--- wsl.conf ---";
+
+/// Facts about a WSL distribution.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DistroFacts {
+    /// Whether bash is available.
+    pub has_bash: bool,
+    /// Whether tmux is available.
+    pub has_tmux: bool,
+    /// Version of WSL's Linux side tools, including `wslpath`.
+    /// Only known on WSL versions that ship `wslinfo`.
+    pub wslpath_version: Option<String>,
+    /// Whether the distribution runs systemd.
+    pub systemd: bool,
+    /// Default user of the distribution.
+    pub default_user: Option<String>,
+    /// Root under which Windows drives are mounted, eg. `/mnt`.
+    pub mount_root: Option<String>,
+    /// Whether Windows programs can be run, ie. interop isn't disabled
+    /// in `/etc/wsl.conf`.
+    pub interop: bool,
+    /// Whether Windows `PATH` is appended to `PATH` in WSL.
+    pub append_windows_path: bool,
+    /// When the facts were probed, in seconds since Unix epoch.
+    pub probed_at: u64,
+}
+
+impl Default for DistroFacts {
+    fn default() -> Self {
+        Self {
+            has_bash: false,
+            has_tmux: false,
+            wslpath_version: None,
+            systemd: false,
+            default_user: None,
+            mount_root: None,
+            interop: true,
+            append_windows_path: true,
+            probed_at: 0,
+        }
+    }
+}
+
+impl DistroFacts {
+    /// Whether the facts are older than `PROBE_TTL`.
+    pub fn is_expired(&self) -> bool {
+        now().saturating_sub(self.probed_at) > PROBE_TTL.as_secs()
+    }
+
+    /// Parse from the output of `PROBE_SCRIPT`.
+    fn parse(output: &str) -> Self {
+        let (output, conf) = match output.split_once(WSL_CONF_MARKER) {
+            Some((output, conf)) => (output, conf),
+            None => (output, ""),
+        };
+        let mut facts = Self {
+            interop: wsl::wsl_conf_flag(conf, "interop", "enabled").unwrap_or(true),
+            append_windows_path: wsl::wsl_conf_flag(conf, "interop", "appendWindowsPath")
+                .unwrap_or(true),
+            probed_at: now(),
+            ..Default::default()
+        };
+        for (key, value) in output.lines().filter_map(|line| line.split_once('=')) {
+            let value = value.trim();
+            match key {
+                "bash" => facts.has_bash = value == "1",
+                "tmux" => facts.has_tmux = value == "1",
+                "systemd" => facts.systemd = value == "1",
+                "wslpath" => {
+                    facts.wslpath_version = Some(value.to_string()).filter(|s| !s.is_empty())
+                }
+                "user" => facts.default_user = Some(value.to_string()).filter(|s| !s.is_empty()),
+                "mountroot" => facts.mount_root = Some(wsl::normalize_mount_root(value)),
+                _ => {}
+            }
+        }
+        facts
+    }
+
+    /// Load facts stored in the registry, regardless of their age.
+    pub fn load(distro: Option<&OsStr>) -> Option<Self> {
+        let key = registry::open_distro_settings(distro)?;
+        let flag = |name: &str| key.get_value::<u32, _>(name).is_ok_and(|v| v != 0);
+        let text = |name: &str| {
+            key.get_value::<String, _>(name)
+                .ok()
+                .filter(|s| !s.is_empty())
+        };
+        Some(Self {
+            has_bash: flag("HasBash"),
+            has_tmux: flag("HasTmux"),
+            wslpath_version: text("WslpathVersion"),
+            systemd: flag("Systemd"),
+            default_user: text("DefaultUser"),
+            mount_root: text("ProbedMountRoot"),
+            // facts stored before these were probed
+            interop: key.get_value::<u32, _>("Interop").map_or(true, |v| v != 0),
+            append_windows_path: key
+                .get_value::<u32, _>("AppendWindowsPath")
+                .map_or(true, |v| v != 0),
+            probed_at: key.get_value::<u64, _>("ProbedAt").ok()?,
+        })
+    }
+
+    /// Store facts in the registry.
+    fn store(&self, distro: Option<&OsStr>) -> std::io::Result<()> {
+        let key = registry::create_distro_settings(distro)
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        key.set_value("HasBash", &(self.has_bash as u32))?;
+        key.set_value("HasTmux", &(self.has_tmux as u32))?;
+        key.set_value("Systemd", &(self.systemd as u32))?;
+        key.set_value(
+            "WslpathVersion",
+            &self.wslpath_version.clone().unwrap_or_default(),
+        )?;
+        key.set_value(
+            "DefaultUser",
+            &self.default_user.clone().unwrap_or_default(),
+        )?;
+        key.set_value(
+            "ProbedMountRoot",
+            &self.mount_root.clone().unwrap_or_default(),
+        )?;
+        key.set_value("Interop", &(self.interop as u32))?;
+        key.set_value("AppendWindowsPath", &(self.append_windows_path as u32))?;
+        key.set_value("ProbedAt", &self.probed_at)
+    }
+}
+
+/// Get facts about a distribution, probing it if the stored facts are
+/// missing or expired.
+///
+/// `distro` is the distribution name, or `None` for the default distribution.
+/// Returns `None` if the distribution couldn't be probed.
+pub fn distro_facts(distro: Option<&OsStr>) -> Option<DistroFacts> {
+    if let Some(facts) = DistroFacts::load(distro).filter(|f| !f.is_expired()) {
+        return Some(facts);
+    }
+    let facts = probe(distro)?;
+    if let Err(e) = facts.store(distro) {
+        log::error!("Failed to store distribution facts: {}", e);
+    }
+    Some(facts)
+}
+
+/// Probe facts about a distribution.
+pub fn probe(distro: Option<&OsStr>) -> Option<DistroFacts> {
+    let mut cmd = process::Command::new(wsl::wsl_bin_path().ok()?);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    if let Some(distro) = distro {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    wsl::push_shell_command(&mut cmd, "sh", &[], OsStr::new(PROBE_SCRIPT));
+    let output = match cmd.output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            log::debug!(
+                "Failed to probe distribution: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return None;
+        }
+        Err(e) => {
+            log::debug!("Failed to probe distribution: {}", e);
+            return None;
+        }
+    };
+    let facts = DistroFacts::parse(&String::from_utf8_lossy(&output.stdout));
+    log::debug!("Probed distribution: {:?}", facts);
+    Some(facts)
+}
+
+/// Current time in seconds since Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
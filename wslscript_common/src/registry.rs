@@ -1,84 +1,485 @@
 use crate::error::*;
+#[cfg(feature = "gui")]
 use crate::icon::ShellIcon;
-use crate::win32::*;
 use guid_win::Guid;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::ffi::OsString;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::str::FromStr;
 use wchar::*;
 use widestring::*;
-use winapi::shared::minwindef;
-use winapi::shared::winerror;
-use winapi::um::winnt;
 use winreg::enums::*;
 use winreg::transaction::Transaction;
-use winreg::RegKey;
+use winreg::{RegKey, HKEY};
 
 const HANDLER_PREFIX: &str = "wslscript";
-const CLASSES_SUBKEY: &str = r"Software\Classes";
-const LXSS_SUBKEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Lxss";
+/// Registry key under HKCU for file associations.
+pub const CLASSES_SUBKEY: &str = r"Software\Classes";
+/// Registry key under HKCU for installed WSL distributions.
+pub const LXSS_SUBKEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Lxss";
+/// Registry key under HKCU for WSL Script's own settings.
+pub const SETTINGS_SUBKEY: &str = r"Software\wslscript";
+/// Explorer's per-user filetype settings under HKCU.
+const FILE_EXTS_SUBKEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Explorer\FileExts";
 
-/// Drop handler shell extension GUID: {81521ebe-a2d4-450b-9bf8-5c23ed8730d0}
-pub static DROP_HANDLER_CLSID: Lazy<Guid> =
+/// Name of the verb that opens a WSL shell in the folder of a file.
+#[cfg(feature = "gui")]
+const SHELL_VERB: &str = "wslshell";
+
+/// Command line flag to open a WSL shell instead of running the script.
+pub const SHELL_FLAG: &str = "--shell";
+
+/// Command line flag preceding the script and its arguments.
+///
+/// Followed by `--`, after which no argument is taken as an option.
+pub const EXEC_FLAG: &str = "--exec";
+
+/// Command line flag preceding the script and its arguments in commands
+/// registered by earlier versions.
+pub const LEGACY_EXEC_FLAG: &str = "-E";
+
+/// Name of the verb that copies the WSL path of a file.
+#[cfg(feature = "gui")]
+const COPY_PATH_VERB: &str = "wslpath";
+
+/// Command line command that copies WSL paths of files to the clipboard.
+pub const COPY_PATH_COMMAND: &str = "copy-path";
+
+/// Multi-select model of the copy path verb.
+///
+/// Explorer starts a `copy-path` instance per selected file, and the
+/// instances collect the paths into one, so the verb is offered for
+/// a selection of any size.
+const COPY_PATH_MULTI_SELECT: &str = "Player";
+
+/// Key of the verb that copies the WSL path of any file.
+const ALL_FILES_COPY_PATH_SUBKEY: &str = r"*\shell\wslpath";
+
+/// Drop handler shell extension GUID of earlier versions:
+/// {81521ebe-a2d4-450b-9bf8-5c23ed8730d0}
+///
+/// Used until a CLSID is generated for the installation.
+pub static LEGACY_DROP_HANDLER_CLSID: Lazy<Guid> =
     Lazy::new(|| Guid::from_str("81521ebe-a2d4-450b-9bf8-5c23ed8730d0").unwrap());
 
+/// Settings value of the drop handler CLSID generated for the installation.
+const DROP_HANDLER_CLSID_VALUE: &str = "DropHandlerCLSID";
+
+/// Get the drop handler shell extension CLSID of this installation.
+///
+/// CLSID is generated on first registration, so that separate installations
+/// don't register the same CLSID with different servers.
+/// Machine-wide installations use only the CLSID stored under HKLM, so that
+/// a per-user registration can't shadow it.
+pub fn drop_handler_clsid() -> Guid {
+    stored_drop_handler_clsid(server_scope()).unwrap_or_else(|| LEGACY_DROP_HANDLER_CLSID.clone())
+}
+
+/// Scope of a drop handler registration.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ServerScope {
+    /// Registered for the current user under HKCU.
+    User,
+    /// Registered for all users under HKLM.
+    Machine,
+}
+
+impl ServerScope {
+    /// Get the registry root of the scope.
+    fn root(self) -> HKEY {
+        match self {
+            Self::User => HKEY_CURRENT_USER,
+            Self::Machine => HKEY_LOCAL_MACHINE,
+        }
+    }
+}
+
+/// Get the drop handler CLSID stored in the settings of a scope.
+fn stored_drop_handler_clsid(scope: ServerScope) -> Option<Guid> {
+    RegKey::predef(scope.root())
+        .open_subkey(SETTINGS_SUBKEY)
+        .and_then(|key| key.get_value::<String, _>(DROP_HANDLER_CLSID_VALUE))
+        .ok()
+        .and_then(|s| Guid::from_str(&s).ok())
+}
+
+/// Get the scope of this installation's drop handler registration.
+///
+/// Installation is machine-wide if the server registered under HKLM is in
+/// the same directory as the running binary, and per-user otherwise.
+pub fn server_scope() -> ServerScope {
+    let dir = crate::win32::current_module_path().and_then(|p| p.parent().map(Path::to_owned));
+    let is_machine = stored_drop_handler_clsid(ServerScope::Machine)
+        .and_then(|clsid| registered_server_path(ServerScope::Machine, &clsid))
+        .is_some_and(|path| dir.is_some() && path.parent() == dir.as_deref());
+    if is_machine {
+        ServerScope::Machine
+    } else {
+        ServerScope::User
+    }
+}
+
+/// Get path to the binary registered as the server of a CLSID.
+fn registered_server_path(scope: ServerScope, clsid: &Guid) -> Option<PathBuf> {
+    let key = RegKey::predef(scope.root())
+        .open_subkey(format!(r"{}\CLSID\{}", CLASSES_SUBKEY, clsid))
+        .ok()?;
+    ["InProcServer32", "LocalServer32"]
+        .iter()
+        .find_map(|name| key.open_subkey(name).ok()?.get_value::<String, _>("").ok())
+        .map(|path| PathBuf::from(path.trim_matches('"')))
+}
+
+/// Generate a drop handler CLSID for the installation in a scope, unless
+/// one is already stored.
+///
+/// Extensions registered for the user are migrated from the legacy CLSID
+/// to the new one.
+pub fn ensure_drop_handler_clsid(scope: ServerScope) -> Result<Guid, Error> {
+    let (settings, _) = RegKey::predef(scope.root())
+        .create_subkey(SETTINGS_SUBKEY)
+        .map_err(Error::RegistryError)?;
+    if let Some(clsid) = settings
+        .get_value::<String, _>(DROP_HANDLER_CLSID_VALUE)
+        .ok()
+        .and_then(|s| Guid::from_str(&s).ok())
+    {
+        return Ok(clsid);
+    }
+    let guid = windows::core::GUID::new()?;
+    let clsid =
+        Guid::from_str(&format!("{:?}", guid)).map_err(|_| Error::LogicError("Invalid GUID."))?;
+    log::debug!("Generated drop handler CLSID {}", clsid);
+    // stored first, so that the server is registered with the new CLSID
+    settings
+        .set_value(DROP_HANDLER_CLSID_VALUE, &clsid.to_string())
+        .map_err(Error::RegistryError)?;
+    // extensions are registered per-user only
+    if scope == ServerScope::User {
+        if let Err(e) = migrate_drop_handler_clsid(&clsid) {
+            let _ = settings.delete_value(DROP_HANDLER_CLSID_VALUE);
+            return Err(e);
+        }
+    }
+    Ok(clsid)
+}
+
+/// Point drop handlers of registered extensions to a new CLSID and remove
+/// the legacy server registration.
+fn migrate_drop_handler_clsid(clsid: &Guid) -> Result<(), Error> {
+    let tx = Transaction::new().map_err(Error::RegistryError)?;
+    let base = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey_transacted_with_flags(CLASSES_SUBKEY, &tx, KEY_ALL_ACCESS)
+        .map_err(Error::RegistryError)?;
+    let value = clsid.to_string();
+    for ext in query_registered_extensions()? {
+        log::debug!("Migrating drop handler of .{}", ext);
+        let path = match get_extension_config(&ext).map(|config| config.association) {
+            Ok(Association::OpenWith) => system_drop_handler_subkey(&ext),
+            Ok(Association::Default) => continue,
+            _ => format!(r"{}\shellex\DropHandler", handler_name(&ext)),
+        };
+        set_value(&tx, &base, &path, "", &value)?;
+    }
+    delete_server_keys(&tx, &base, &LEGACY_DROP_HANDLER_CLSID)?;
+    tx.commit().map_err(Error::RegistryError)?;
+    notify_shell_change();
+    Ok(())
+}
+
+/// Normalize filename extension to the form used in the registry.
+///
+/// Leading dots are removed, and the extension is converted to Unicode NFC
+/// and lowercase, so that eg. `.SH` and `sh` refer to the same filetype.
+pub fn normalize_extension(ext: &str) -> String {
+    crate::win32::normalize_nfc(ext.trim_start_matches('.')).to_lowercase()
+}
+
+/// Get name of the handler ProgID for an extension.
+fn handler_name(ext: &str) -> String {
+    format!("{}.{}", HANDLER_PREFIX, normalize_extension(ext))
+}
+
 /// Configuration for registered file name extension.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ExtConfig {
     /// Filetype extension without leading dot.
     pub extension: String,
     /// Icon for the filetype.
+    #[cfg(feature = "gui")]
     pub icon: Option<ShellIcon>,
     /// Hold mode.
     pub hold_mode: HoldMode,
+    /// Key to wait for when the console is held open.
+    pub hold_key: HoldKey,
+    /// Message displayed when the console is held open.
+    /// `{code}` is replaced with the exit code.
+    pub hold_message: Option<String>,
     /// Whether to run bash as an interactive shell.
     pub interactive: bool,
     /// WSL distribution to run.
     pub distro: Option<DistroGUID>,
+    /// Handling of scripts with Windows line endings.
+    pub crlf_mode: CrlfMode,
+    /// Whether to pass arguments unquoted, separated by spaces.
+    pub raw_args: bool,
+    /// Whether to open the console on the monitor where files were dropped.
+    pub console_at_drop: bool,
+    /// Console window title. Defaults to the script name.
+    pub console_title: Option<String>,
+    /// Console screen buffer size.
+    pub console_size: Option<ConsoleSize>,
+    /// Console text and background colors.
+    pub console_colors: Option<ConsoleColors>,
+    /// Whether to set the console to UTF-8 with a TrueType font.
+    pub utf8_console: bool,
+    /// Whether to run subsequent drops in an already open console.
+    pub reuse_console: bool,
+    /// User-defined category for grouping extensions in the GUI.
+    pub category: Option<String>,
+    /// Description shown in the Type column of Explorer,
+    /// or None for the default.
+    pub type_name: Option<String>,
+    /// Handling of multiple dropped files.
+    pub drop_policy: DropPolicy,
+    /// Order of dropped files passed to the script.
+    pub arg_order: ArgOrder,
+    /// Mode applied with `chmod` to dropped files before the script runs.
+    pub arg_chmod: Option<String>,
+    /// Owner applied with `chown` to dropped files before the script runs.
+    pub arg_chown: Option<String>,
+    /// Whether to cancel the run if some of the dropped files are missing.
+    pub abort_on_missing: bool,
+    /// Number of dropped files above which the user is asked to confirm.
+    /// Zero disables the confirmation.
+    pub max_args: u32,
+    /// Whether to use the Linux side helper script for path conversion,
+    /// environment setup and run status reporting.
+    pub use_helper: bool,
+    /// Windows environment variables passed to the script via `WSLENV`.
+    pub wslenv: WslEnv,
+    /// Minimum number of seconds between runs before the user is asked
+    /// to confirm. Zero disables the check.
+    pub min_interval: u32,
+    /// Maximum number of scripts running at a time before the user is
+    /// asked to confirm. Zero for no limit.
+    pub max_instances: u32,
+    /// Whether to focus the console of a running script instead of
+    /// running the script again.
+    pub single_instance: bool,
+    /// Whether the drop handler shows progress while converting paths and
+    /// waiting for WSL to start.
+    pub show_progress: bool,
+    /// Whether the script is run without a console and logged to
+    /// the Windows Event Log instead.
+    pub event_log: EventLog,
+    /// Number of days after which output logs are deleted, zero to keep them.
+    pub log_max_age: u32,
+    /// Number of most recent output logs kept, zero for no limit.
+    pub log_max_files: u32,
+    /// Whether WSL Script is made the default program of the extension.
+    pub association: Association,
+}
+
+/// Default number of dropped files above which the user is asked to confirm.
+pub const DEFAULT_MAX_ARGS: u32 = 1000;
+
+/// Default number of days after which output logs are deleted.
+pub const DEFAULT_LOG_MAX_AGE: u32 = 30;
+
+/// Default number of most recent output logs kept.
+pub const DEFAULT_LOG_MAX_FILES: u32 = 20;
+
+impl Default for ExtConfig {
+    fn default() -> Self {
+        Self {
+            extension: String::new(),
+            #[cfg(feature = "gui")]
+            icon: None,
+            hold_mode: HoldMode::default(),
+            hold_key: HoldKey::default(),
+            hold_message: None,
+            interactive: false,
+            distro: None,
+            crlf_mode: CrlfMode::default(),
+            raw_args: false,
+            console_at_drop: false,
+            console_title: None,
+            console_size: None,
+            console_colors: None,
+            utf8_console: false,
+            reuse_console: false,
+            category: None,
+            type_name: None,
+            drop_policy: DropPolicy::default(),
+            arg_order: ArgOrder::default(),
+            arg_chmod: None,
+            arg_chown: None,
+            abort_on_missing: false,
+            max_args: DEFAULT_MAX_ARGS,
+            use_helper: false,
+            wslenv: WslEnv::default(),
+            min_interval: 0,
+            max_instances: 0,
+            single_instance: false,
+            show_progress: true,
+            event_log: EventLog::default(),
+            log_max_age: DEFAULT_LOG_MAX_AGE,
+            log_max_files: DEFAULT_LOG_MAX_FILES,
+            association: Association::default(),
+        }
+    }
+}
+
+/// Version of the serialized configuration schema.
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+/// Serialized set of filetype configurations.
+///
+/// Common format for anything that stores configurations outside
+/// the registry. Fields missing from a document take their default values.
+#[derive(Serialize, Deserialize)]
+pub struct ConfigDocument {
+    /// Schema version the document was written with.
+    pub version: u32,
+    /// Filetype configurations.
+    pub extensions: Vec<ExtConfig>,
+}
+
+impl ConfigDocument {
+    /// Create a document of the current schema version.
+    pub fn new(extensions: Vec<ExtConfig>) -> Self {
+        Self {
+            version: CONFIG_SCHEMA_VERSION,
+            extensions,
+        }
+    }
 }
 
 /// Terminal window hold mode after script exits.
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum HoldMode {
     /// Always close terminal window on exit.
     Never,
     /// Always wait for keypress on exit.
     Always,
     /// Wait for keypress when exit code != 0.
+    #[default]
     Error,
+    /// Close terminal window after given number of seconds,
+    /// or earlier on keypress.
+    Timeout(u32),
 }
 
 impl HoldMode {
+    /// Default number of seconds for timeout mode.
+    pub const DEFAULT_TIMEOUT: u32 = 10;
+
     const WCSTR_NEVER: &'static [WideChar] = wchz!("never");
     const WCSTR_ALWAYS: &'static [WideChar] = wchz!("always");
     const WCSTR_ERROR: &'static [WideChar] = wchz!("error");
+    const WCSTR_TIMEOUT: &'static [WideChar] = wchz!("timeout");
 
     /// Create from nul terminated wide string.
+    ///
+    /// Timeout mode is created with the default timeout.
     pub fn from_wcstr(s: &WideCStr) -> Option<Self> {
         match s.as_slice_with_nul() {
             Self::WCSTR_NEVER => Some(Self::Never),
             Self::WCSTR_ALWAYS => Some(Self::Always),
             Self::WCSTR_ERROR => Some(Self::Error),
+            Self::WCSTR_TIMEOUT => Some(Self::Timeout(Self::DEFAULT_TIMEOUT)),
             _ => None,
         }
     }
 
-    /// Create from &str.
-    pub fn from_str(s: &str) -> Option<Self> {
+    /// Get mode string as a nul terminated wide string.
+    pub fn as_wcstr(self) -> &'static WideCStr {
+        match self {
+            Self::Never => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_NEVER) },
+            Self::Always => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_ALWAYS) },
+            Self::Error => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_ERROR) },
+            Self::Timeout(_) => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_TIMEOUT) },
+        }
+    }
+
+    /// Whether modes are the same, regardless of the timeout.
+    pub fn same_mode(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    /// Get mode as a utf-8 string.
+    pub fn as_string(self) -> String {
+        self.as_wcstr().to_string_lossy()
+    }
+}
+
+impl FromStr for HoldMode {
+    type Err = ();
+    /// Parse from `never`, `always`, `error`, `timeout` or
+    /// `timeout:<seconds>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(secs) = s.strip_prefix("timeout:") {
+            return match secs.parse::<u32>() {
+                Ok(secs) if secs > 0 => Ok(Self::Timeout(secs)),
+                _ => Err(()),
+            };
+        }
         WideCString::from_str(s)
             .ok()
             .and_then(|s| Self::from_wcstr(&s))
+            .ok_or(())
+    }
+}
+
+impl std::fmt::Display for HoldMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout(secs) => write!(f, "timeout:{}", secs),
+            mode => f.write_str(&mode.as_string()),
+        }
+    }
+}
+
+/// Handling of scripts that have Windows (CRLF) line endings.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrlfMode {
+    /// Run the script as is, but print a warning to the console.
+    #[default]
+    Warn,
+    /// Run the script explicitly with bash, bypassing the shebang line.
+    Bash,
+    /// Strip carriage returns with sed and run the result with bash.
+    Fix,
+}
+
+impl CrlfMode {
+    const WCSTR_WARN: &'static [WideChar] = wchz!("warn");
+    const WCSTR_BASH: &'static [WideChar] = wchz!("bash");
+    const WCSTR_FIX: &'static [WideChar] = wchz!("fix");
+
+    /// Create from nul terminated wide string.
+    pub fn from_wcstr(s: &WideCStr) -> Option<Self> {
+        match s.as_slice_with_nul() {
+            Self::WCSTR_WARN => Some(Self::Warn),
+            Self::WCSTR_BASH => Some(Self::Bash),
+            Self::WCSTR_FIX => Some(Self::Fix),
+            _ => None,
+        }
     }
 
     /// Get mode string as a nul terminated wide string.
     pub fn as_wcstr(self) -> &'static WideCStr {
         match self {
-            Self::Never => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_NEVER) },
-            Self::Always => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_ALWAYS) },
-            Self::Error => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_ERROR) },
+            Self::Warn => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_WARN) },
+            Self::Bash => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_BASH) },
+            Self::Fix => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_FIX) },
         }
     }
 
@@ -88,14 +489,428 @@ impl HoldMode {
     }
 }
 
-impl Default for HoldMode {
-    fn default() -> Self {
-        Self::Error
+impl FromStr for CrlfMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WideCString::from_str(s)
+            .ok()
+            .and_then(|s| Self::from_wcstr(&s))
+            .ok_or(())
+    }
+}
+
+/// Order in which dropped files are passed to the script.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgOrder {
+    /// Order given by Explorer.
+    #[default]
+    None,
+    /// Sort by filename.
+    Name,
+    /// Sort by full path.
+    Path,
+    /// Sort by modification time, oldest first.
+    Modified,
+}
+
+impl ArgOrder {
+    const WCSTR_NONE: &'static [WideChar] = wchz!("none");
+    const WCSTR_NAME: &'static [WideChar] = wchz!("name");
+    const WCSTR_PATH: &'static [WideChar] = wchz!("path");
+    const WCSTR_MODIFIED: &'static [WideChar] = wchz!("modified");
+
+    /// Create from nul terminated wide string.
+    pub fn from_wcstr(s: &WideCStr) -> Option<Self> {
+        match s.as_slice_with_nul() {
+            Self::WCSTR_NONE => Some(Self::None),
+            Self::WCSTR_NAME => Some(Self::Name),
+            Self::WCSTR_PATH => Some(Self::Path),
+            Self::WCSTR_MODIFIED => Some(Self::Modified),
+            _ => None,
+        }
+    }
+
+    /// Get order as a nul terminated wide string.
+    pub fn as_wcstr(self) -> &'static WideCStr {
+        match self {
+            Self::None => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_NONE) },
+            Self::Name => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_NAME) },
+            Self::Path => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_PATH) },
+            Self::Modified => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_MODIFIED) },
+        }
+    }
+
+    /// Get order as a utf-8 string.
+    pub fn as_string(self) -> String {
+        self.as_wcstr().to_string_lossy()
+    }
+}
+
+impl FromStr for ArgOrder {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WideCString::from_str(s)
+            .ok()
+            .and_then(|s| Self::from_wcstr(&s))
+            .ok_or(())
+    }
+}
+
+/// Logging of script runs to the Windows Event Log.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventLog {
+    /// Run the script in a console window without logging.
+    #[default]
+    Off,
+    /// Run the script without a console and log when it starts and exits.
+    Events,
+    /// Like `Events`, and include the end of the script's output.
+    Output,
+}
+
+impl EventLog {
+    const WCSTR_OFF: &'static [WideChar] = wchz!("off");
+    const WCSTR_EVENTS: &'static [WideChar] = wchz!("events");
+    const WCSTR_OUTPUT: &'static [WideChar] = wchz!("output");
+
+    /// Create from nul terminated wide string.
+    pub fn from_wcstr(s: &WideCStr) -> Option<Self> {
+        match s.as_slice_with_nul() {
+            Self::WCSTR_OFF => Some(Self::Off),
+            Self::WCSTR_EVENTS => Some(Self::Events),
+            Self::WCSTR_OUTPUT => Some(Self::Output),
+            _ => None,
+        }
+    }
+
+    /// Get mode as a nul terminated wide string.
+    pub fn as_wcstr(self) -> &'static WideCStr {
+        match self {
+            Self::Off => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_OFF) },
+            Self::Events => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_EVENTS) },
+            Self::Output => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_OUTPUT) },
+        }
+    }
+
+    /// Get mode as a utf-8 string.
+    pub fn as_string(self) -> String {
+        self.as_wcstr().to_string_lossy()
+    }
+}
+
+impl FromStr for EventLog {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WideCString::from_str(s)
+            .ok()
+            .and_then(|s| Self::from_wcstr(&s))
+            .ok_or(())
+    }
+}
+
+/// How far an extension is associated with WSL Script.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Association {
+    /// Only listed in "Open with", so that the default program is kept.
+    /// Files can still be dropped on scripts, unless the default program
+    /// has a drop handler of its own.
+    OpenWith,
+    /// Default program, without the drop handler.
+    Default,
+    /// Default program with the drop handler.
+    #[default]
+    Full,
+}
+
+impl Association {
+    const WCSTR_OPEN_WITH: &'static [WideChar] = wchz!("openwith");
+    const WCSTR_DEFAULT: &'static [WideChar] = wchz!("default");
+    const WCSTR_FULL: &'static [WideChar] = wchz!("full");
+
+    /// Create from nul terminated wide string.
+    pub fn from_wcstr(s: &WideCStr) -> Option<Self> {
+        match s.as_slice_with_nul() {
+            Self::WCSTR_OPEN_WITH => Some(Self::OpenWith),
+            Self::WCSTR_DEFAULT => Some(Self::Default),
+            Self::WCSTR_FULL => Some(Self::Full),
+            _ => None,
+        }
+    }
+
+    /// Get association as a nul terminated wide string.
+    pub fn as_wcstr(self) -> &'static WideCStr {
+        match self {
+            Self::OpenWith => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_OPEN_WITH) },
+            Self::Default => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_DEFAULT) },
+            Self::Full => unsafe { WideCStr::from_slice_unchecked(Self::WCSTR_FULL) },
+        }
+    }
+
+    /// Get association as a utf-8 string.
+    pub fn as_string(self) -> String {
+        self.as_wcstr().to_string_lossy()
+    }
+}
+
+impl FromStr for Association {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WideCString::from_str(s)
+            .ok()
+            .and_then(|s| Self::from_wcstr(&s))
+            .ok_or(())
+    }
+}
+
+/// Key press awaited before closing a held console.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum HoldKey {
+    /// Any key.
+    #[default]
+    Any,
+    /// Enter key.
+    Enter,
+    /// Any key, or until given number of seconds have passed.
+    Timeout(u32),
+}
+
+impl FromStr for HoldKey {
+    type Err = ();
+    /// Parse from `any`, `enter` or number of seconds to wait.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "any" => Ok(Self::Any),
+            "enter" => Ok(Self::Enter),
+            s => match s.parse::<u32>() {
+                Ok(secs) if secs > 0 => Ok(Self::Timeout(secs)),
+                _ => Err(()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for HoldKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Any => f.write_str("any"),
+            Self::Enter => f.write_str("enter"),
+            Self::Timeout(secs) => write!(f, "{}", secs),
+        }
+    }
+}
+
+/// How the script is invoked when multiple files are dropped on it.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DropPolicy {
+    /// Run once with all files as arguments.
+    #[default]
+    Single,
+    /// Run once per file, with at most given number of runs at a time.
+    /// Zero means no limit.
+    PerFile(u32),
+    /// Run once per batch of at most given number of files.
+    Batch(u32),
+}
+
+impl FromStr for DropPolicy {
+    type Err = ();
+    /// Parse from `single`, `each`, `each:<limit>` or `batch:<size>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (policy, n) = match s.trim().split_once(':') {
+            Some((policy, n)) => (policy, Some(n.trim().parse::<u32>().map_err(|_| ())?)),
+            None => (s.trim(), None),
+        };
+        match (policy, n) {
+            ("single", None) => Ok(Self::Single),
+            ("each", n) => Ok(Self::PerFile(n.unwrap_or(0))),
+            ("batch", Some(n)) if n > 0 => Ok(Self::Batch(n)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for DropPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Single => f.write_str("single"),
+            Self::PerFile(0) => f.write_str("each"),
+            Self::PerFile(limit) => write!(f, "each:{}", limit),
+            Self::Batch(size) => write!(f, "batch:{}", size),
+        }
+    }
+}
+
+/// Console screen buffer size in character cells.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConsoleSize {
+    pub columns: u16,
+    pub rows: u16,
+}
+
+impl FromStr for ConsoleSize {
+    type Err = ();
+    /// Parse from `<columns>x<rows>`, eg. `120x30`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (columns, rows) = s.trim().split_once(['x', 'X']).ok_or(())?;
+        let columns = columns.trim().parse::<u16>().map_err(|_| ())?;
+        let rows = rows.trim().parse::<u16>().map_err(|_| ())?;
+        if columns == 0 || rows == 0 {
+            return Err(());
+        }
+        Ok(Self { columns, rows })
+    }
+}
+
+impl std::fmt::Display for ConsoleSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.columns, self.rows)
+    }
+}
+
+/// Console color attribute.
+///
+/// Background color in the high and text color in the low nibble,
+/// as with the `color` command of cmd.exe.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConsoleColors(pub u8);
+
+impl FromStr for ConsoleColors {
+    type Err = ();
+    /// Parse from two hexadecimal digits, eg. `1F`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() != 2 {
+            return Err(());
+        }
+        let attr = u8::from_str_radix(s, 16).map_err(|_| ())?;
+        // same text and background color would be unreadable
+        if attr >> 4 == attr & 0x0f {
+            return Err(());
+        }
+        Ok(Self(attr))
     }
 }
 
+impl std::fmt::Display for ConsoleColors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02X}", self.0)
+    }
+}
+
+/// Windows environment variables shared with WSL via `WSLENV`.
+///
+/// Each entry is a variable name, optionally followed by the translation
+/// flags of `WSLENV`, eg. `USERPROFILE/p`. Names prefixed with `!` are
+/// secret, and their values are never logged.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct WslEnv(Vec<String>);
+
+impl WslEnv {
+    /// Whether no variables are shared.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Names of the shared variables, and whether they're secret.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.0.iter().map(|entry| {
+            let name = entry.trim_start_matches('!');
+            let name = name.split('/').next().unwrap_or_default();
+            (name, entry.starts_with('!'))
+        })
+    }
+
+    /// Merge entries to an existing `WSLENV` value.
+    ///
+    /// Variables already present in `existing` are replaced, so that the
+    /// flags configured here take effect.
+    pub fn merge(&self, existing: &str) -> String {
+        let name = |entry: &str| {
+            let name = entry.split('/').next().unwrap_or_default();
+            name.to_ascii_uppercase()
+        };
+        let own = self
+            .0
+            .iter()
+            .map(|e| e.trim_start_matches('!'))
+            .collect::<Vec<_>>();
+        let mut entries = existing
+            .split(':')
+            .filter(|e| !e.is_empty())
+            .filter(|e| !own.iter().any(|o| name(o) == name(e)))
+            .collect::<Vec<_>>();
+        entries.extend(own);
+        entries.join(":")
+    }
+}
+
+impl FromStr for WslEnv {
+    type Err = ();
+    /// Parse from entries separated by colons or spaces, eg. `USERPROFILE/p:LANG`.
+    ///
+    /// Valid flags are `p`, `l`, `u` and `w`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries: Vec<String> = Vec::new();
+        for entry in s.split([':', ' ', '\t']).filter(|e| !e.is_empty()) {
+            let var = entry.strip_prefix('!').unwrap_or(entry);
+            let (name, flags) = var.split_once('/').unwrap_or((var, ""));
+            if name.is_empty() || name.contains('=') {
+                return Err(());
+            }
+            if !flags.chars().all(|c| "pluw/".contains(c)) {
+                return Err(());
+            }
+            entries.push(entry.to_string());
+        }
+        Ok(Self(entries))
+    }
+}
+
+impl std::fmt::Display for WslEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join(":"))
+    }
+}
+
+/// Implement conversions to serialize types by their string representation,
+/// which is validated when deserializing.
+macro_rules! serde_as_string {
+    ($($t:ty),*) => {$(
+        impl TryFrom<String> for $t {
+            type Error = String;
+            fn try_from(s: String) -> Result<Self, String> {
+                s.parse().map_err(|_| format!("invalid value: {}", s))
+            }
+        }
+        impl From<$t> for String {
+            fn from(value: $t) -> Self {
+                value.to_string()
+            }
+        }
+    )*};
+}
+
+serde_as_string!(
+    HoldMode,
+    HoldKey,
+    DropPolicy,
+    ConsoleSize,
+    ConsoleColors,
+    WslEnv,
+    DistroGUID
+);
+
 /// GUID of the WSL distribution.
-#[derive(Clone, Eq)]
+#[derive(Clone, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct DistroGUID {
     guid: Guid,
     /// Pinned wide c-string of the GUID for win32 usage. Enclosed in `{`...`}`.
@@ -125,8 +940,25 @@ impl std::fmt::Display for DistroGUID {
 
 impl FromStr for DistroGUID {
     type Err = ();
+    /// Parse from a GUID string.
+    ///
+    /// Hex digits may be in either case, with or without enclosing braces,
+    /// or in the URN form `urn:uuid:...`. Surrounding whitespace is ignored.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let guid = Guid::from_str(s).map_err(|_| ())?;
+        let s = s.trim();
+        let s = match s.get(..9) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("urn:uuid:") => &s[9..],
+            _ => s,
+        };
+        let s = s
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .unwrap_or(s);
+        // CLSIDFromString would also resolve ProgIDs, so check the format first
+        if !is_guid_format(s) {
+            return Err(());
+        }
+        let guid = Guid::from_str(&format!("{{{}}}", s)).map_err(|_| ())?;
         let s = guid.to_string().to_ascii_lowercase();
         let wcs = unsafe { WideCString::from_str_unchecked(s) };
         Ok(Self {
@@ -136,6 +968,12 @@ impl FromStr for DistroGUID {
     }
 }
 
+/// Check whether a string is a GUID of hex digits in `8-4-4-4-12` groups.
+fn is_guid_format(s: &str) -> bool {
+    s.split('-').map(str::len).eq([8, 4, 4, 4, 12])
+        && s.chars().all(|c| c == '-' || c.is_ascii_hexdigit())
+}
+
 impl std::cmp::PartialEq for DistroGUID {
     fn eq(&self, other: &Self) -> bool {
         self.guid.eq(&other.guid)
@@ -185,6 +1023,13 @@ impl Distros {
         });
         pairs
     }
+
+    /// Get name of a distribution, or of the default one if `guid` is None.
+    pub fn name(&self, guid: Option<&DistroGUID>) -> Option<&str> {
+        guid.or(self.default.as_ref())
+            .and_then(|guid| self.list.get(guid))
+            .map(String::as_str)
+    }
 }
 
 /// Registers WSL Script as a handler for given file extension.
@@ -193,9 +1038,34 @@ impl Distros {
 /// See https://docs.microsoft.com/en-us/windows/win32/shell/fa-progids
 /// See https://docs.microsoft.com/en-us/windows/win32/shell/fa-perceivedtypes
 ///
+#[cfg(feature = "gui")]
 pub fn register_extension(config: &ExtConfig) -> Result<(), Error> {
-    let ext = config.extension.as_str();
-    if ext.is_empty() {
+    register_extensions(std::slice::from_ref(config))
+}
+
+/// Register multiple extensions in a single transaction.
+///
+/// Either all or none of the extensions are registered.
+#[cfg(feature = "gui")]
+pub fn register_extensions(configs: &[ExtConfig]) -> Result<(), Error> {
+    let configs: Vec<ExtConfig> = configs
+        .iter()
+        .cloned()
+        .map(|mut config| {
+            config.extension = normalize_extension(&config.extension);
+            if managed_icons() {
+                config.icon = config.icon.map(|icon| {
+                    // a broken copy is no reason to fail the registration
+                    icon.to_managed_copy().unwrap_or_else(|e| {
+                        log::warn!("Failed to copy icon: {}", e);
+                        icon
+                    })
+                });
+            }
+            config
+        })
+        .collect();
+    if configs.iter().any(|config| config.extension.is_empty()) {
         return Err(Error::LogicError("No extension."));
     }
     register_server()?;
@@ -203,10 +1073,40 @@ pub fn register_extension(config: &ExtConfig) -> Result<(), Error> {
     let base = RegKey::predef(HKEY_CURRENT_USER)
         .open_subkey_transacted_with_flags(CLASSES_SUBKEY, &tx, KEY_ALL_ACCESS)
         .map_err(|e| Error::RegistryError(e))?;
+    let old: Vec<Option<ExtConfig>> = configs
+        .iter()
+        .map(|config| get_extension_config(&config.extension).ok())
+        .collect();
+    for config in configs.iter() {
+        write_extension(&tx, &base, config)?;
+    }
+    let mut exts = query_registered_extensions().unwrap_or_default();
+    for config in configs.iter() {
+        if !exts.contains(&config.extension) {
+            exts.push(config.extension.clone());
+        }
+    }
+    write_application(&tx, &base, &exts)?;
+    tx.commit().map_err(|e| Error::RegistryError(e))?;
+    for (config, old) in configs.iter().zip(old.iter()) {
+        crate::audit::record(&config.extension, old.as_ref(), Some(config));
+    }
+    notify_shell_change();
+    // icons that were replaced may still be shown for other files
+    notify_icons_changed(configs.iter().chain(old.iter().flatten()));
+    Ok(())
+}
+
+/// Write registry keys of an extension within a transaction.
+#[cfg(feature = "gui")]
+fn write_extension(tx: &Transaction, base: &RegKey, config: &ExtConfig) -> Result<(), Error> {
+    use std::ffi::OsString;
+    let ext = config.extension.as_str();
     let name = format!("{}.{}", HANDLER_PREFIX, ext);
+    let previous = snapshot_previous_handler(tx, base, ext);
     // delete previous handler key in a transaction
     // see https://docs.microsoft.com/en-us/windows/win32/api/winreg/nf-winreg-regdeletekeytransactedw#remarks
-    if let Ok(key) = base.open_subkey_transacted_with_flags(&name, &tx, KEY_ALL_ACCESS) {
+    if let Ok(key) = base.open_subkey_transacted_with_flags(&name, tx, KEY_ALL_ACCESS) {
         key.delete_subkey_all("")
             .map_err(|e| Error::RegistryError(e))?;
     }
@@ -215,97 +1115,377 @@ pub fn register_extension(config: &ExtConfig) -> Result<(), Error> {
         .icon
         .as_ref()
         .map(|icon| icon.shell_path().to_os_string());
-    let handler_desc = format!("WSL Shell Script (.{})", ext);
+    let handler_desc = config
+        .type_name
+        .clone()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| default_type_name(ext));
     let hold_mode = config.hold_mode.as_string();
     let interactive = config.interactive as u32;
+    let crlf_mode = config.crlf_mode.as_string();
+    let raw_args = config.raw_args as u32;
+    let console_at_drop = config.console_at_drop as u32;
+    let utf8_console = config.utf8_console as u32;
+    let reuse_console = config.reuse_console as u32;
+    let drop_policy = config.drop_policy.to_string();
+    let arg_order = config.arg_order.as_string();
+    let abort_on_missing = config.abort_on_missing as u32;
+    let use_helper = config.use_helper as u32;
+    let single_instance = config.single_instance as u32;
+    let show_progress = config.show_progress as u32;
+    let event_log = config.event_log.as_string();
+    let association = config.association.as_string();
     // Software\Classes\wslscript.ext
-    set_value(&tx, &base, &name, "", &handler_desc)?;
-    set_value(&tx, &base, &name, "EditFlags", &0x30u32)?;
-    set_value(&tx, &base, &name, "FriendlyTypeName", &handler_desc)?;
-    set_value(&tx, &base, &name, "HoldMode", &hold_mode)?;
-    set_value(&tx, &base, &name, "Interactive", &interactive)?;
+    set_value(tx, base, &name, "", &handler_desc)?;
+    set_value(tx, base, &name, "SchemaVersion", &SCHEMA_VERSION)?;
+    set_value(tx, base, &name, "EditFlags", &0x30u32)?;
+    set_value(tx, base, &name, "FriendlyTypeName", &handler_desc)?;
+    set_value(tx, base, &name, "HoldMode", &hold_mode)?;
+    if let HoldMode::Timeout(secs) = config.hold_mode {
+        set_value(tx, base, &name, "HoldTimeout", &secs)?;
+    }
+    set_value(tx, base, &name, "HoldKey", &config.hold_key.to_string())?;
+    if let Some(message) = config.hold_message.as_ref().filter(|s| !s.is_empty()) {
+        set_value(tx, base, &name, "HoldMessage", message)?;
+    }
+    set_value(tx, base, &name, "Interactive", &interactive)?;
+    set_value(tx, base, &name, "CrlfMode", &crlf_mode)?;
+    set_value(tx, base, &name, "RawArgs", &raw_args)?;
+    set_value(tx, base, &name, "ConsoleAtDrop", &console_at_drop)?;
+    set_value(tx, base, &name, "Utf8Console", &utf8_console)?;
+    set_value(tx, base, &name, "ReuseConsole", &reuse_console)?;
     if let Some(distro) = &config.distro {
-        set_value(&tx, &base, &name, "Distribution", &distro.to_string())?;
+        set_value(tx, base, &name, "Distribution", &distro.to_string())?;
+    }
+    if let Some(title) = config.console_title.as_ref().filter(|s| !s.is_empty()) {
+        set_value(tx, base, &name, "ConsoleTitle", title)?;
+    }
+    if let Some(size) = &config.console_size {
+        set_value(tx, base, &name, "ConsoleSize", &size.to_string())?;
+    }
+    if let Some(colors) = &config.console_colors {
+        set_value(tx, base, &name, "ConsoleColors", &colors.to_string())?;
+    }
+    if let Some(category) = config.category.as_ref().filter(|s| !s.is_empty()) {
+        set_value(tx, base, &name, "Category", category)?;
+    }
+    if let Some(type_name) = config.type_name.as_ref().filter(|s| !s.is_empty()) {
+        set_value(tx, base, &name, "TypeName", type_name)?;
+    }
+    set_value(tx, base, &name, "DropPolicy", &drop_policy)?;
+    set_value(tx, base, &name, "ArgOrder", &arg_order)?;
+    if let Some(mode) = config.arg_chmod.as_ref().filter(|s| !s.is_empty()) {
+        set_value(tx, base, &name, "ArgChmod", mode)?;
+    }
+    if let Some(owner) = config.arg_chown.as_ref().filter(|s| !s.is_empty()) {
+        set_value(tx, base, &name, "ArgChown", owner)?;
+    }
+    set_value(tx, base, &name, "AbortOnMissing", &abort_on_missing)?;
+    set_value(tx, base, &name, "MaxArgs", &config.max_args)?;
+    set_value(tx, base, &name, "UseHelper", &use_helper)?;
+    if !config.wslenv.is_empty() {
+        set_value(tx, base, &name, "WslEnv", &config.wslenv.to_string())?;
+    }
+    set_value(tx, base, &name, "MinInterval", &config.min_interval)?;
+    set_value(tx, base, &name, "MaxInstances", &config.max_instances)?;
+    set_value(tx, base, &name, "SingleInstance", &single_instance)?;
+    set_value(tx, base, &name, "ShowProgress", &show_progress)?;
+    set_value(tx, base, &name, "EventLog", &event_log)?;
+    set_value(tx, base, &name, "LogMaxAge", &config.log_max_age)?;
+    set_value(tx, base, &name, "LogMaxFiles", &config.log_max_files)?;
+    set_value(tx, base, &name, "Association", &association)?;
+    if let Some(prog_id) = &previous.prog_id {
+        set_value(tx, base, &name, "PreviousProgId", prog_id)?;
+    }
+    if let Some(prog_id) = &previous.user_choice {
+        set_value(tx, base, &name, "PreviousUserChoice", prog_id)?;
     }
     // Software\Classes\wslscript.ext\DefaultIcon
     if let Some(s) = &icon {
         let path = format!(r"{}\DefaultIcon", name);
-        set_value(&tx, &base, &path, "", &s.as_os_str())?;
+        set_value(tx, base, &path, "", &s.as_os_str())?;
     }
     // Software\Classes\wslscript.ext\shell
     let path = format!(r"{}\shell", name);
-    set_value(&tx, &base, &path, "", &"open")?;
+    set_value(tx, base, &path, "", &"open")?;
     // Software\Classes\wslscript.ext\shell\open - Open command
     let path = format!(r"{}\shell\open", name);
-    set_value(&tx, &base, &path, "", &"Run in WSL")?;
+    set_value(tx, base, &path, "", &"Run in WSL")?;
     if let Some(s) = &icon {
-        set_value(&tx, &base, &path, "Icon", &s.as_os_str())?;
+        set_value(tx, base, &path, "Icon", &s.as_os_str())?;
     }
     // Software\Classes\wslscript.ext\shell\open\command
     let path = format!(r"{}\shell\open\command", name);
-    set_value(&tx, &base, &path, "", &cmd.as_os_str())?;
+    set_value(tx, base, &path, "", &cmd.as_os_str())?;
     // Software\Classes\wslscript.ext\shell\runas - Run as administrator
     let path = format!(r"{}\shell\runas", name);
-    set_value(&tx, &base, &path, "Extended", &"")?;
+    set_value(tx, base, &path, "Extended", &"")?;
     if let Some(s) = &icon {
-        set_value(&tx, &base, &path, "Icon", &s.as_os_str())?;
+        set_value(tx, base, &path, "Icon", &s.as_os_str())?;
     }
     // Software\Classes\wslscript.ext\shell\runas\command
     let path = format!(r"{}\shell\runas\command", name);
-    set_value(&tx, &base, &path, "", &cmd.as_os_str())?;
-    // Software\Classes\wslscript.ext\shellex\DropHandler - Drop handler
-    let path = format!(r"{}\shellex\DropHandler", name);
+    set_value(tx, base, &path, "", &cmd.as_os_str())?;
+    // Software\Classes\wslscript.ext\shell\wslshell - Open WSL shell in folder
+    let path = format!(r"{}\shell\{}", name, SHELL_VERB);
+    set_value(tx, base, &path, "", &"Open WSL shell here")?;
+    // Software\Classes\wslscript.ext\shell\wslshell\command
+    let path = format!(r"{}\shell\{}\command", name, SHELL_VERB);
+    let shell_cmd = get_shell_command(config)?.to_os_string();
+    set_value(tx, base, &path, "", &shell_cmd.as_os_str())?;
+    // Software\Classes\wslscript.ext\shell\wslpath - Copy WSL path
+    let path = format!(r"{}\shell\{}", name, COPY_PATH_VERB);
+    set_value(tx, base, &path, "", &"Copy WSL path")?;
+    set_value(tx, base, &path, "MultiSelectModel", &COPY_PATH_MULTI_SELECT)?;
+    // Software\Classes\wslscript.ext\shell\wslpath\command
+    let path = format!(r"{}\shell\{}\command", name, COPY_PATH_VERB);
+    let copy_cmd = get_copy_path_command(Some(ext))?.to_os_string();
+    set_value(tx, base, &path, "", &copy_cmd.as_os_str())?;
     // {60254CA5-953B-11CF-8C96-00AA00B8708C} (WSH DropHandler)
     // {86C86720-42A0-1069-A2E8-08002B30309D} (EXE DropHandler)
-    let value = DROP_HANDLER_CLSID.to_string();
-    set_value(&tx, &base, &path, "", &value)?;
-    // Software\Classes\.ext - Register handler for extension
-    let path = format!(".{}", ext);
-    set_value(&tx, &base, &path, "", &name)?;
-    set_value(&tx, &base, &path, "PerceivedType", &"application")?;
+    let value = drop_handler_clsid().to_string();
+    delete_system_drop_handler(tx, base, ext)?;
+    match config.association {
+        Association::OpenWith => {
+            // Software\Classes\SystemFileAssociations\.ext\shellex\DropHandler
+            // applies whichever program is the default
+            set_value(tx, base, &system_drop_handler_subkey(ext), "", &value)?;
+            // give the default back if it was taken by an earlier registration
+            let path = format!(".{}", ext);
+            if let Ok(key) = base.open_subkey_transacted_with_flags(&path, tx, KEY_ALL_ACCESS) {
+                if key
+                    .get_value::<String, _>("")
+                    .is_ok_and(|val| val.to_lowercase() == name)
+                {
+                    match &previous.prog_id {
+                        Some(prog_id) => key.set_value("", prog_id),
+                        None => key.delete_value(""),
+                    }
+                    .map_err(Error::RegistryError)?;
+                }
+            }
+        }
+        Association::Default | Association::Full => {
+            if config.association == Association::Full {
+                // Software\Classes\wslscript.ext\shellex\DropHandler - Drop handler
+                let path = format!(r"{}\shellex\DropHandler", name);
+                set_value(tx, base, &path, "", &value)?;
+            }
+            // Software\Classes\.ext - Register handler for extension
+            let path = format!(".{}", ext);
+            set_value(tx, base, &path, "", &name)?;
+            set_value(tx, base, &path, "PerceivedType", &"application")?;
+        }
+    }
     // Software\Classes\.ext\OpenWithProgIds - Add extension to open with list
     let path = format!(r".{}\OpenWithProgIds", ext);
-    set_value(&tx, &base, &path, &name, &"")?;
-    tx.commit().map_err(|e| Error::RegistryError(e))?;
-    notify_shell_change();
+    set_value(tx, base, &path, &name, &"")?;
+    Ok(())
+}
+
+/// Get the description of an extension shown in Explorer when none is set.
+pub fn default_type_name(ext: &str) -> String {
+    format!("WSL Shell Script (.{})", ext)
+}
+
+/// Get path of the drop handler key that applies to an extension
+/// regardless of its default program.
+fn system_drop_handler_subkey(ext: &str) -> String {
+    format!(r"SystemFileAssociations\.{}\shellex\DropHandler", ext)
+}
+
+/// Delete the drop handler registered for an extension regardless of its
+/// default program, within a transaction.
+///
+/// Parent keys that are left empty are deleted too.
+fn delete_system_drop_handler(tx: &Transaction, base: &RegKey, ext: &str) -> Result<(), Error> {
+    let path = system_drop_handler_subkey(ext);
+    let is_ours = base
+        .open_subkey_transacted(&path, tx)
+        .and_then(|key| key.get_value::<String, _>(""))
+        .is_ok_and(|val| {
+            val.eq_ignore_ascii_case(&drop_handler_clsid().to_string())
+                || val.eq_ignore_ascii_case(&LEGACY_DROP_HANDLER_CLSID.to_string())
+        });
+    if !is_ours {
+        return Ok(());
+    }
+    base.delete_subkey_transacted(&path, tx)
+        .map_err(Error::RegistryError)?;
+    for parent in [
+        format!(r"SystemFileAssociations\.{}\shellex", ext),
+        format!(r"SystemFileAssociations\.{}", ext),
+    ] {
+        let is_empty = base
+            .open_subkey_transacted(&parent, tx)
+            .and_then(|key| key.query_info())
+            .is_ok_and(|info| info.sub_keys == 0 && info.values == 0);
+        if !is_empty {
+            break;
+        }
+        base.delete_subkey_transacted(&parent, tx)
+            .map_err(Error::RegistryError)?;
+    }
     Ok(())
 }
 
+/// Handler that was associated with an extension before WSL Script took it over.
+#[derive(Default)]
+pub struct PreviousHandler {
+    /// Default ProgID of the extension under HKCU.
+    pub prog_id: Option<String>,
+    /// ProgID the user had chosen in Explorer's "Open with" dialog.
+    pub user_choice: Option<String>,
+}
+
+impl PreviousHandler {
+    fn is_empty(&self) -> bool {
+        self.prog_id.is_none() && self.user_choice.is_none()
+    }
+}
+
+/// Get the handler that was associated with an extension before it was
+/// registered for WSL.
+///
+/// Returns None if there was no previous handler.
+pub fn get_previous_handler(ext: &str) -> Option<PreviousHandler> {
+    let name = handler_name(ext);
+    let key = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(CLASSES_SUBKEY)
+        .and_then(|k| k.open_subkey(name))
+        .ok()?;
+    let previous = PreviousHandler {
+        prog_id: key.get_value::<String, _>("PreviousProgId").ok(),
+        user_choice: key.get_value::<String, _>("PreviousUserChoice").ok(),
+    };
+    (!previous.is_empty()).then_some(previous)
+}
+
+/// Get the handler of an extension to preserve it before registration.
+///
+/// If the extension is already registered for WSL, the previously
+/// preserved handler is kept.
+#[cfg(feature = "gui")]
+fn snapshot_previous_handler(tx: &Transaction, base: &RegKey, ext: &str) -> PreviousHandler {
+    let name = handler_name(ext);
+    let mut previous = get_previous_handler(ext).unwrap_or_default();
+    let prog_id = base
+        .open_subkey_transacted(format!(".{}", ext), tx)
+        .and_then(|k| k.get_value::<String, _>(""))
+        .ok()
+        .filter(|s| !s.is_empty() && s.to_lowercase() != name);
+    if prog_id.is_some() {
+        previous.prog_id = prog_id;
+    }
+    let user_choice = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(format!(r"{}\.{}\UserChoice", FILE_EXTS_SUBKEY, ext))
+        .and_then(|k| k.get_value::<String, _>("ProgId"))
+        .ok()
+        .filter(|s| !s.is_empty() && s.to_lowercase() != name);
+    if user_choice.is_some() {
+        previous.user_choice = user_choice;
+    }
+    previous
+}
+
 /// Unregister extension.
 pub fn unregister_extension(ext: &str) -> Result<(), Error> {
+    unregister_extensions(&[ext], false)
+}
+
+/// Unregister multiple extensions in a single transaction.
+///
+/// If `restore` is set, extensions are associated back to the handlers
+/// that were replaced when the extensions were registered.
+/// See `get_previous_handler`.
+pub fn unregister_extensions<S: AsRef<str>>(exts: &[S], restore: bool) -> Result<(), Error> {
+    let exts: Vec<String> = exts
+        .iter()
+        .map(|ext| normalize_extension(ext.as_ref()))
+        .collect();
     let tx = Transaction::new().map_err(|e| Error::RegistryError(e))?;
     let base = RegKey::predef(HKEY_CURRENT_USER)
         .open_subkey_transacted_with_flags(CLASSES_SUBKEY, &tx, KEY_ALL_ACCESS)
         .map_err(|e| Error::RegistryError(e))?;
-    let name = format!("{}.{}", HANDLER_PREFIX, ext);
+    let old: Vec<Option<ExtConfig>> = exts
+        .iter()
+        .map(|ext| get_extension_config(ext).ok())
+        .collect();
+    for ext in exts.iter() {
+        delete_extension(&tx, &base, ext, restore)?;
+    }
+    let remaining = query_registered_extensions()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|ext| !exts.contains(ext))
+        .count();
+    delete_application_types(&tx, &base, &exts, remaining == 0)?;
+    tx.commit().map_err(|e| Error::RegistryError(e))?;
+    for (ext, old) in exts.iter().zip(old.iter()) {
+        crate::audit::record(ext, old.as_ref(), None);
+        if restore {
+            reset_user_choice(ext);
+        }
+    }
+    // if there's no registered extensions, unregister shell extension
+    if let Ok(exts) = query_registered_extensions() {
+        if exts.is_empty() {
+            remove_server_from_registry(ServerScope::User)?;
+        }
+    }
+    notify_shell_change();
+    #[cfg(feature = "gui")]
+    notify_icons_changed(old.iter().flatten());
+    Ok(())
+}
+
+/// Delete registry keys of an extension within a transaction.
+///
+/// If `restore` is set, the previous handler is made default again.
+fn delete_extension(
+    tx: &Transaction,
+    base: &RegKey,
+    ext: &str,
+    restore: bool,
+) -> Result<(), Error> {
+    let name = handler_name(ext);
+    let previous = restore
+        .then(|| get_previous_handler(ext))
+        .flatten()
+        .unwrap_or_default();
     // delete handler
-    if let Ok(key) = base.open_subkey_transacted_with_flags(&name, &tx, KEY_ALL_ACCESS) {
+    if let Ok(key) = base.open_subkey_transacted_with_flags(&name, tx, KEY_ALL_ACCESS) {
         key.delete_subkey_all("")
             .map_err(|e| Error::RegistryError(e))?;
-        base.delete_subkey_transacted(&name, &tx)
+        base.delete_subkey_transacted(&name, tx)
             .map_err(|e| Error::RegistryError(e))?;
     }
+    delete_system_drop_handler(tx, base, ext)?;
     let ext_name = format!(".{}", ext);
-    if let Ok(ext_key) = base.open_subkey_transacted_with_flags(&ext_name, &tx, KEY_ALL_ACCESS) {
+    if let Ok(ext_key) = base.open_subkey_transacted_with_flags(&ext_name, tx, KEY_ALL_ACCESS) {
         // if extension has handler as a default
         if let Ok(val) = ext_key.get_value::<String, _>("") {
-            if val == name {
-                // set default handler to unset
-                ext_key
-                    .delete_value("")
-                    .map_err(|e| Error::RegistryError(e))?;
+            if val.to_lowercase() == name {
+                // set default handler to unset, or restore the previous one
+                match &previous.prog_id {
+                    Some(prog_id) => ext_key
+                        .set_value("", prog_id)
+                        .map_err(Error::RegistryError)?,
+                    None => ext_key.delete_value("").map_err(Error::RegistryError)?,
+                }
             }
         }
         // cleanup OpenWithProgids
         let open_with_name = "OpenWithProgIds";
         if let Ok(open_with_key) =
-            ext_key.open_subkey_transacted_with_flags(open_with_name, &tx, KEY_ALL_ACCESS)
+            ext_key.open_subkey_transacted_with_flags(open_with_name, tx, KEY_ALL_ACCESS)
         {
             // remove handler
-            if let Some(progid) = open_with_key
-                .enum_values()
-                .find_map(|item| item.ok().filter(|(k, _)| *k == name).map(|(k, _)| k))
-            {
+            if let Some(progid) = open_with_key.enum_values().find_map(|item| {
+                item.ok()
+                    .filter(|(k, _)| k.to_lowercase() == name)
+                    .map(|(k, _)| k)
+            }) {
                 open_with_key
                     .delete_value(progid)
                     .map_err(|e| Error::RegistryError(e))?;
@@ -314,7 +1494,7 @@ pub fn unregister_extension(ext: &str) -> Result<(), Error> {
             if let Ok(info) = open_with_key.query_info() {
                 if info.sub_keys == 0 && info.values == 0 {
                     ext_key
-                        .delete_subkey_transacted(open_with_name, &tx)
+                        .delete_subkey_transacted(open_with_name, tx)
                         .map_err(|e| Error::RegistryError(e))?;
                 }
             }
@@ -325,30 +1505,103 @@ pub fn unregister_extension(ext: &str) -> Result<(), Error> {
             if let Ok(info) = ext_key.query_info() {
                 if info.sub_keys == 0 {
                     // ... remove extension key altogether
-                    base.delete_subkey_transacted(&ext_name, &tx)
+                    base.delete_subkey_transacted(&ext_name, tx)
                         .map_err(|e| Error::RegistryError(e))?;
                 }
             }
         }
     }
-    tx.commit().map_err(|e| Error::RegistryError(e))?;
-    // if there's no registered extensions, unregister shell extension
-    if let Ok(exts) = query_registered_extensions() {
-        if exts.is_empty() {
-            remove_server_from_registry()?;
+    Ok(())
+}
+
+/// Path of the `Applications` key of the running executable.
+fn application_subkey() -> Result<String, Error> {
+    let exe = std::env::current_exe()?;
+    let name = exe.file_name().ok_or(Error::InvalidPathError)?;
+    Ok(format!(r"Applications\{}", name.to_string_lossy()))
+}
+
+/// Register WSL Script as an application within a transaction, so that
+/// it's listed by name in the "Open with" dialog of the registered
+/// filetypes.
+///
+/// `exts` are all extensions registered once the transaction is committed.
+///
+/// See https://learn.microsoft.com/en-us/windows/win32/shell/app-registration#using-the-applications-subkey
+#[cfg(feature = "gui")]
+fn write_application(tx: &Transaction, base: &RegKey, exts: &[String]) -> Result<(), Error> {
+    let name = application_subkey()?;
+    // SupportedTypes is rewritten from scratch
+    if let Ok(key) = base.open_subkey_transacted_with_flags(&name, tx, KEY_ALL_ACCESS) {
+        key.delete_subkey_all("").map_err(Error::RegistryError)?;
+    }
+    let mut cmd = quoted_exe()?;
+    cmd.push_slice(wch!(r#" --exec -- "%1""#));
+    let cmd = cmd.to_os_string();
+    // Software\Classes\Applications\wslscript.exe
+    set_value(tx, base, &name, "FriendlyAppName", &"WSL Script")?;
+    // Software\Classes\Applications\wslscript.exe\SupportedTypes
+    let path = format!(r"{}\SupportedTypes", name);
+    for ext in exts {
+        set_value(tx, base, &path, &format!(".{}", ext), &"")?;
+    }
+    // Software\Classes\Applications\wslscript.exe\shell\open\command
+    let path = format!(r"{}\shell\open\command", name);
+    set_value(tx, base, &path, "", &cmd.as_os_str())?;
+    Ok(())
+}
+
+/// Remove extensions from the supported types of the application within
+/// a transaction.
+///
+/// If `all` is set, no extensions remain registered and the application
+/// is removed altogether.
+fn delete_application_types(
+    tx: &Transaction,
+    base: &RegKey,
+    exts: &[String],
+    all: bool,
+) -> Result<(), Error> {
+    let name = application_subkey()?;
+    let Ok(key) = base.open_subkey_transacted_with_flags(&name, tx, KEY_ALL_ACCESS) else {
+        return Ok(());
+    };
+    if all {
+        key.delete_subkey_all("").map_err(Error::RegistryError)?;
+        return base
+            .delete_subkey_transacted(&name, tx)
+            .map_err(Error::RegistryError);
+    }
+    if let Ok(types) = key.open_subkey_transacted_with_flags("SupportedTypes", tx, KEY_ALL_ACCESS) {
+        for ext in exts {
+            // value may be missing if registered by an earlier version
+            let _ = types.delete_value(format!(".{}", ext));
         }
     }
-    notify_shell_change();
     Ok(())
 }
 
-extern "system" {
-    fn SHChangeNotify(
-        weventid: winnt::LONG,
-        uflags: minwindef::UINT,
-        dwitem1: minwindef::LPCVOID,
-        dwitem2: minwindef::LPCVOID,
-    );
+/// Remove the user's "Open with" choice if it refers to the WSL handler.
+///
+/// UserChoice is protected by a hash, so the previous choice can't be
+/// written back. Removing it lets Explorer fall back to the default
+/// handler, which has been restored. Failure is ignored, since newer
+/// Windows versions may deny access to the key.
+fn reset_user_choice(ext: &str) {
+    let name = handler_name(ext);
+    let Ok(key) = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey_with_flags(format!(r"{}\.{}", FILE_EXTS_SUBKEY, ext), KEY_ALL_ACCESS)
+    else {
+        return;
+    };
+    let choice = key
+        .open_subkey("UserChoice")
+        .and_then(|k| k.get_value::<String, _>("ProgId"));
+    if choice.is_ok_and(|prog_id| prog_id.to_lowercase() == name) {
+        if let Err(e) = key.delete_subkey("UserChoice") {
+            log::warn!("Failed to reset UserChoice of .{}: {}", ext, e);
+        }
+    }
 }
 
 /// Notify the system that file associations have been changed.
@@ -356,29 +1609,91 @@ extern "system" {
 /// See: https://docs.microsoft.com/en-us/windows/win32/shell/fa-file-types
 /// See: https://docs.microsoft.com/en-us/windows/win32/api/shlobj_core/nf-shlobj_core-shchangenotify
 fn notify_shell_change() {
-    const SHCNE_ASSOCCHANGED: winnt::LONG = 0x08000000;
-    const SHCNF_IDLIST: minwindef::UINT = 0;
-    unsafe {
-        SHChangeNotify(
-            SHCNE_ASSOCCHANGED,
-            SHCNF_IDLIST,
-            std::ptr::null(),
-            std::ptr::null(),
-        )
-    };
+    use windows::Win32::UI::Shell::*;
+    unsafe { SHChangeNotify(SHCNE_ASSOCCHANGED, SHCNF_IDLIST, None, None) };
+}
+
+/// Notify the system that icon files of extensions have changed, so that
+/// Explorer doesn't keep showing a cached icon.
+///
+/// Should be called after `notify_shell_change`.
+#[cfg(feature = "gui")]
+fn notify_icons_changed<'a>(configs: impl IntoIterator<Item = &'a ExtConfig>) {
+    use windows::Win32::UI::Shell::*;
+    let mut notified: Vec<PathBuf> = Vec::new();
+    for icon in configs
+        .into_iter()
+        .filter_map(|config| config.icon.as_ref())
+    {
+        let path = icon.path().expand().unwrap_or_else(|_| icon.path());
+        if notified.contains(&path) {
+            continue;
+        }
+        let s = path.to_wide();
+        unsafe {
+            SHChangeNotify(
+                SHCNE_UPDATEITEM,
+                SHCNF_PATHW | SHCNF_FLUSHNOWAIT,
+                Some(s.as_ptr().cast()),
+                None,
+            )
+        };
+        notified.push(path.to_path_buf());
+    }
 }
 
 /// Get the wslscript command for filetype registry.
-fn get_command(config: &ExtConfig) -> Result<WideString, Error> {
+///
+/// This is the `shell\open\command` value written on registration.
+#[cfg(feature = "gui")]
+pub fn get_command(config: &ExtConfig) -> Result<WideString, Error> {
+    let mut cmd = command_prefix(config)?;
+    cmd.push_slice(wch!(r#" --exec -- "%0" %*"#));
+    Ok(cmd)
+}
+
+/// Get command to open a WSL shell in the folder of a file of the filetype.
+pub fn get_shell_command(config: &ExtConfig) -> Result<WideString, Error> {
+    let mut cmd = command_prefix(config)?;
+    cmd.push_slice(wch!(" "));
+    cmd.push_str(SHELL_FLAG);
+    cmd.push_slice(wch!(r#" --exec -- "%1""#));
+    Ok(cmd)
+}
+
+/// Get command to copy the WSL path of a file to the clipboard.
+///
+/// If `ext` is given, the path is converted in the filetype's distribution.
+/// Command takes a single file, and the instances started for a selection
+/// of files hand their paths over to the first one, which copies them all.
+pub fn get_copy_path_command(ext: Option<&str>) -> Result<WideString, Error> {
+    let mut cmd = quoted_exe()?;
+    cmd.push_slice(wch!(" "));
+    cmd.push_str(COPY_PATH_COMMAND);
+    if let Some(ext) = ext {
+        cmd.push_slice(wch!(r#" --ext ""#));
+        cmd.push_str(ext);
+        cmd.push_slice(wch!(r#"""#));
+    }
+    cmd.push_slice(wch!(r#" --quiet "%1""#));
+    Ok(cmd)
+}
+
+/// Get the quoted path of the running executable.
+fn quoted_exe() -> Result<WideString, Error> {
+    use crate::win32::WinPathBuf;
     let exe = WinPathBuf::new(std::env::current_exe()?)
         .canonicalize()?
         .without_extended();
-    let mut cmd = WideString::new();
-    cmd.push(exe.quoted());
+    Ok(exe.quoted())
+}
+
+/// Get the executable and extension arguments of the filetype's commands.
+fn command_prefix(config: &ExtConfig) -> Result<WideString, Error> {
+    let mut cmd = quoted_exe()?;
     cmd.push_slice(wch!(r#" --ext ""#));
     cmd.push_str(&config.extension);
     cmd.push_slice(wch!(r#"""#));
-    cmd.push_slice(wch!(r#" -E "%0" %*"#));
     Ok(cmd)
 }
 
@@ -416,6 +1731,53 @@ pub fn query_registered_extensions() -> Result<Vec<String>, Error> {
     Ok(extensions)
 }
 
+/// Rename registry keys of extensions that aren't in the normalized form.
+///
+/// Registry keys are case-insensitive, but retain the case they were created
+/// with. Versions prior to normalization may have registered eg. `.SH`.
+/// Renaming is done by rewriting the keys, all in a single transaction.
+///
+/// Returns the number of renamed extensions.
+#[cfg(feature = "gui")]
+pub fn migrate_extension_names() -> Result<usize, Error> {
+    let legacy: Vec<String> = query_registered_extensions()?
+        .into_iter()
+        .filter(|ext| *ext != normalize_extension(ext))
+        .collect();
+    if legacy.is_empty() {
+        return Ok(0);
+    }
+    let tx = Transaction::new().map_err(Error::RegistryError)?;
+    let base = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey_transacted_with_flags(CLASSES_SUBKEY, &tx, KEY_ALL_ACCESS)
+        .map_err(Error::RegistryError)?;
+    for ext in legacy.iter() {
+        // configuration is read with the extension normalized
+        let config = get_extension_config(ext)?;
+        delete_extension(&tx, &base, ext, false)?;
+        write_extension(&tx, &base, &config)?;
+    }
+    tx.commit().map_err(Error::RegistryError)?;
+    notify_shell_change();
+    Ok(legacy.len())
+}
+
+/// Get filename extension candidates of a path, longest first.
+///
+/// Extensions may be compound, eg. `archive.tar.gz` yields `tar.gz` and `gz`.
+/// Leading dot of the filename isn't considered a separator.
+pub fn path_extensions(path: &Path) -> Vec<String> {
+    let name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = name.trim_start_matches('.');
+    name.match_indices('.')
+        .map(|(i, _)| name[i + 1..].to_string())
+        .filter(|ext| !ext.is_empty())
+        .collect()
+}
+
 /// Query installed WSL distributions.
 pub fn query_distros() -> Result<Distros, Error> {
     let base = RegKey::predef(HKEY_CURRENT_USER)
@@ -440,6 +1802,22 @@ pub fn query_distros() -> Result<Distros, Error> {
     Ok(distros)
 }
 
+/// Whether the WSL service is installed.
+///
+/// Inbox WSL runs as `LxssManager` and WSL from the Microsoft Store
+/// as `WSLService`. Neither is present until the WSL feature is enabled.
+pub fn is_wsl_service_installed() -> bool {
+    let services = match RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey(r"SYSTEM\CurrentControlSet\Services")
+    {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    ["LxssManager", "WSLService"]
+        .iter()
+        .any(|name| services.open_subkey(name).is_ok())
+}
+
 /// Query distribution name by GUID.
 pub fn distro_guid_to_name(guid: DistroGUID) -> Option<String> {
     if let Ok(key) = RegKey::predef(HKEY_CURRENT_USER)
@@ -455,11 +1833,20 @@ pub fn distro_guid_to_name(guid: DistroGUID) -> Option<String> {
 ///
 /// `ext` is the registered filename extension without a leading dot.
 pub fn get_extension_config(ext: &str) -> Result<ExtConfig, Error> {
+    let ext = normalize_extension(ext);
+    let ext = ext.as_str();
     let handler_key = RegKey::predef(HKEY_CURRENT_USER)
         .open_subkey(CLASSES_SUBKEY)
-        .and_then(|key| key.open_subkey(format!("{}.{}", HANDLER_PREFIX, ext)))
+        .and_then(|key| key.open_subkey(handler_name(ext)))
         .map_err(|e| Error::RegistryError(e))?;
+    if schema_version(&handler_key) < SCHEMA_VERSION {
+        if let Err(e) = upgrade_handler(ext) {
+            log::error!("Failed to upgrade .{} handler: {}", ext, e);
+        }
+    }
+    #[cfg(feature = "gui")]
     let mut icon: Option<ShellIcon> = None;
+    #[cfg(feature = "gui")]
     if let Ok(key) = handler_key.open_subkey("DefaultIcon") {
         if let Ok(s) = key.get_value::<String, _>("") {
             icon = s.parse::<ShellIcon>().ok();
@@ -468,8 +1855,27 @@ pub fn get_extension_config(ext: &str) -> Result<ExtConfig, Error> {
     let hold_mode = handler_key
         .get_value::<String, _>("HoldMode")
         .ok()
-        .and_then(|s| HoldMode::from_str(&s))
+        .and_then(|s| s.parse::<HoldMode>().ok())
+        .map(|mode| match mode {
+            HoldMode::Timeout(secs) => HoldMode::Timeout(
+                handler_key
+                    .get_value::<u32, _>("HoldTimeout")
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .unwrap_or(secs),
+            ),
+            mode => mode,
+        })
+        .unwrap_or_default();
+    let hold_key = handler_key
+        .get_value::<String, _>("HoldKey")
+        .ok()
+        .and_then(|s| s.parse::<HoldKey>().ok())
         .unwrap_or_default();
+    let hold_message = handler_key
+        .get_value::<String, _>("HoldMessage")
+        .ok()
+        .filter(|s| !s.is_empty());
     let distro = handler_key
         .get_value::<String, _>("Distribution")
         .ok()
@@ -479,26 +1885,319 @@ pub fn get_extension_config(ext: &str) -> Result<ExtConfig, Error> {
         .ok()
         .map(|v| v != 0)
         .unwrap_or(false);
+    let crlf_mode = handler_key
+        .get_value::<String, _>("CrlfMode")
+        .ok()
+        .and_then(|s| s.parse::<CrlfMode>().ok())
+        .unwrap_or_default();
+    let raw_args = handler_key
+        .get_value::<u32, _>("RawArgs")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(false);
+    let console_at_drop = handler_key
+        .get_value::<u32, _>("ConsoleAtDrop")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(false);
+    let utf8_console = handler_key
+        .get_value::<u32, _>("Utf8Console")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(false);
+    let reuse_console = handler_key
+        .get_value::<u32, _>("ReuseConsole")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(false);
+    let console_title = handler_key
+        .get_value::<String, _>("ConsoleTitle")
+        .ok()
+        .filter(|s| !s.is_empty());
+    let console_size = handler_key
+        .get_value::<String, _>("ConsoleSize")
+        .ok()
+        .and_then(|s| s.parse::<ConsoleSize>().ok());
+    let console_colors = handler_key
+        .get_value::<String, _>("ConsoleColors")
+        .ok()
+        .and_then(|s| s.parse::<ConsoleColors>().ok());
+    let category = handler_key
+        .get_value::<String, _>("Category")
+        .ok()
+        .filter(|s| !s.is_empty());
+    let type_name = handler_key
+        .get_value::<String, _>("TypeName")
+        .ok()
+        .filter(|s| !s.is_empty());
+    let drop_policy = handler_key
+        .get_value::<String, _>("DropPolicy")
+        .ok()
+        .and_then(|s| s.parse::<DropPolicy>().ok())
+        .unwrap_or_default();
+    let arg_order = handler_key
+        .get_value::<String, _>("ArgOrder")
+        .ok()
+        .and_then(|s| s.parse::<ArgOrder>().ok())
+        .unwrap_or_default();
+    let arg_chmod = handler_key
+        .get_value::<String, _>("ArgChmod")
+        .ok()
+        .filter(|s| !s.is_empty());
+    let arg_chown = handler_key
+        .get_value::<String, _>("ArgChown")
+        .ok()
+        .filter(|s| !s.is_empty());
+    let abort_on_missing = handler_key
+        .get_value::<u32, _>("AbortOnMissing")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(false);
+    let max_args = handler_key
+        .get_value::<u32, _>("MaxArgs")
+        .unwrap_or(DEFAULT_MAX_ARGS);
+    let use_helper = handler_key
+        .get_value::<u32, _>("UseHelper")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(false);
+    let wslenv = handler_key
+        .get_value::<String, _>("WslEnv")
+        .ok()
+        .and_then(|s| s.parse::<WslEnv>().ok())
+        .unwrap_or_default();
+    let min_interval = handler_key.get_value::<u32, _>("MinInterval").unwrap_or(0);
+    let max_instances = handler_key.get_value::<u32, _>("MaxInstances").unwrap_or(0);
+    let single_instance = handler_key
+        .get_value::<u32, _>("SingleInstance")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(false);
+    let show_progress = handler_key
+        .get_value::<u32, _>("ShowProgress")
+        .ok()
+        .map(|v| v != 0)
+        .unwrap_or(true);
+    let event_log = handler_key
+        .get_value::<String, _>("EventLog")
+        .ok()
+        .and_then(|s| s.parse::<EventLog>().ok())
+        .unwrap_or_default();
+    let log_max_age = handler_key
+        .get_value::<u32, _>("LogMaxAge")
+        .unwrap_or(DEFAULT_LOG_MAX_AGE);
+    let log_max_files = handler_key
+        .get_value::<u32, _>("LogMaxFiles")
+        .unwrap_or(DEFAULT_LOG_MAX_FILES);
+    let association = handler_key
+        .get_value::<String, _>("Association")
+        .ok()
+        .and_then(|s| s.parse::<Association>().ok())
+        .unwrap_or_default();
     Ok(ExtConfig {
         extension: ext.to_owned(),
+        #[cfg(feature = "gui")]
         icon,
         hold_mode,
+        hold_key,
+        hold_message,
         interactive,
         distro,
+        crlf_mode,
+        raw_args,
+        console_at_drop,
+        console_title,
+        console_size,
+        console_colors,
+        utf8_console,
+        reuse_console,
+        category,
+        type_name,
+        drop_policy,
+        arg_order,
+        arg_chmod,
+        arg_chown,
+        abort_on_missing,
+        max_args,
+        use_helper,
+        wslenv,
+        min_interval,
+        max_instances,
+        single_instance,
+        show_progress,
+        event_log,
+        log_max_age,
+        log_max_files,
+        association,
     })
 }
 
+/// Version of the registry layout written by this build.
+///
+/// Stored as `SchemaVersion` in handler keys and in the settings key.
+/// Keys written before versioning are version 1.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Upgrade from a schema version to the next one.
+type Upgrade = fn(&RegKey) -> std::io::Result<()>;
+
+/// Upgrades of handler keys, the first one upgrading from version 1.
+const HANDLER_UPGRADES: [Upgrade; 1] = [upgrade_handler_v1];
+
+/// Upgrades of the settings key, the first one upgrading from version 1.
+const SETTINGS_UPGRADES: [Upgrade; 1] = [upgrade_settings_v1];
+
+/// Get schema version of a key.
+fn schema_version(key: &RegKey) -> u32 {
+    key.get_value::<u32, _>("SchemaVersion").unwrap_or(1).max(1)
+}
+
+/// Run upgrades from the schema version of a key to the current one.
+///
+/// Keys written by a newer version are left as is. Values that aren't
+/// understood fall back to their defaults when read.
+fn run_upgrades(key: &RegKey, upgrades: &[Upgrade]) -> std::io::Result<()> {
+    let version = schema_version(key);
+    if version > SCHEMA_VERSION {
+        log::warn!(
+            "Schema version {} is newer than {}",
+            version,
+            SCHEMA_VERSION
+        );
+        return Ok(());
+    }
+    for (from, upgrade) in upgrades.iter().enumerate().skip(version as usize - 1) {
+        log::debug!("Upgrading schema from version {}", from + 1);
+        upgrade(key)?;
+        key.set_value("SchemaVersion", &(from as u32 + 2))?;
+    }
+    Ok(())
+}
+
+/// Upgrade handler key of an extension to the current schema version.
+fn upgrade_handler(ext: &str) -> Result<(), Error> {
+    let key = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(CLASSES_SUBKEY)
+        .and_then(|key| key.open_subkey_with_flags(handler_name(ext), KEY_ALL_ACCESS))
+        .map_err(Error::RegistryError)?;
+    run_upgrades(&key, &HANDLER_UPGRADES).map_err(Error::RegistryError)
+}
+
+/// Upgrade the settings key to the current schema version.
+pub fn upgrade_settings() -> Result<(), Error> {
+    let key = match RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey_with_flags(SETTINGS_SUBKEY, KEY_ALL_ACCESS)
+    {
+        Ok(key) => key,
+        // nothing to upgrade
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(Error::RegistryError(e)),
+    };
+    run_upgrades(&key, &SETTINGS_UPGRADES).map_err(Error::RegistryError)
+}
+
+/// Store defaults of version 1 explicitly, so that later changes to the
+/// defaults don't alter existing registrations.
+fn upgrade_handler_v1(key: &RegKey) -> std::io::Result<()> {
+    let defaults: [(&str, String); 5] = [
+        ("HoldMode", HoldMode::Error.as_string()),
+        ("HoldKey", HoldKey::Any.to_string()),
+        ("CrlfMode", CrlfMode::Warn.as_string()),
+        ("DropPolicy", DropPolicy::Single.to_string()),
+        ("ArgOrder", ArgOrder::None.as_string()),
+    ];
+    for (name, value) in defaults {
+        if key.get_raw_value(name).is_err() {
+            key.set_value(name, &value)?;
+        }
+    }
+    if key.get_raw_value("MaxArgs").is_err() {
+        key.set_value("MaxArgs", &DEFAULT_MAX_ARGS)?;
+    }
+    Ok(())
+}
+
+/// Remove the per-distribution `Shell` value, which was replaced by
+/// probed distribution facts.
+fn upgrade_settings_v1(key: &RegKey) -> std::io::Result<()> {
+    let distros = match key.open_subkey_with_flags("Distributions", KEY_ALL_ACCESS) {
+        Ok(distros) => distros,
+        Err(_) => return Ok(()),
+    };
+    for name in distros.enum_keys().filter_map(Result::ok) {
+        if let Ok(distro) = distros.open_subkey_with_flags(&name, KEY_ALL_ACCESS) {
+            let _ = distro.delete_value("Shell");
+        }
+    }
+    Ok(())
+}
+
+/// Get registry key path for the settings of a WSL distribution.
+///
+/// `distro` is the distribution name, or `None` for the default distribution.
+fn distro_settings_key(distro: Option<&OsStr>) -> String {
+    let name = distro.map_or("@default".into(), |d| d.to_string_lossy());
+    format!(r"{}\Distributions\{}", SETTINGS_SUBKEY, name)
+}
+
+/// Open settings stored for a WSL distribution.
+///
+/// Returns `None` if nothing has been stored for the distribution.
+pub fn open_distro_settings(distro: Option<&OsStr>) -> Option<RegKey> {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(distro_settings_key(distro))
+        .ok()
+}
+
+/// Open settings of a WSL distribution for writing, creating the key
+/// if necessary.
+pub fn create_distro_settings(distro: Option<&OsStr>) -> Result<RegKey, Error> {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .create_subkey(distro_settings_key(distro))
+        .map(|(key, _)| key)
+        .map_err(Error::RegistryError)
+}
+
+/// Get the root of Windows drive mounts set for a distribution, if any.
+///
+/// See `wsl::mount_root`. Value is checked before it's used in commands,
+/// see `wsl::is_valid_mount_root`.
+pub fn distro_mount_root(distro: Option<&OsStr>) -> Option<String> {
+    open_distro_settings(distro)?
+        .get_value::<String, _>("MountRoot")
+        .ok()
+}
+
+/// Set the root of Windows drive mounts for a distribution, or `None` to
+/// use the probed root.
+pub fn set_distro_mount_root(distro: Option<&OsStr>, root: Option<&str>) -> Result<(), Error> {
+    let key = create_distro_settings(distro)?;
+    match root {
+        Some(root) => key.set_value("MountRoot", &root),
+        None => match key.delete_value("MountRoot") {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+    .map_err(Error::RegistryError)
+}
+
 /// Check whether extension is registered for WSL Script.
 pub fn is_extension_registered_for_wsl(ext: &str) -> Result<bool, Error> {
-    RegKey::predef(HKEY_CURRENT_USER)
+    let base = RegKey::predef(HKEY_CURRENT_USER)
         .open_subkey(CLASSES_SUBKEY)
-        .map_err(|e| Error::RegistryError(e))?
+        .map_err(|e| Error::RegistryError(e))?;
+    let is_default = base
         // try to open .ext key
         .open_subkey(format!(".{}", ext))
         .and_then(|key| key.get_value::<String, _>(""))
-        .map(|val| val == format!("{}.{}", HANDLER_PREFIX, ext))
-        // if .ext registry key didn't exist
-        .or(Ok(false))
+        .is_ok_and(|val| val.to_lowercase() == handler_name(ext));
+    // registered without taking over the default program
+    let is_open_with = base
+        .open_subkey(handler_name(ext))
+        .and_then(|key| key.get_value::<String, _>("Association"))
+        .is_ok_and(|s| s.parse() == Ok(Association::OpenWith));
+    Ok(is_default || is_open_with)
 }
 
 /// Check whether extension is associated with other than WSL Script.
@@ -509,26 +2208,30 @@ pub fn is_registered_for_other(ext: &str) -> Result<bool, Error> {
         // try to open .ext key
         .open_subkey(format!(".{}", ext))
         .and_then(|key| key.get_value::<String, _>(""))
-        .map(|val| val != format!("{}.{}", HANDLER_PREFIX, ext))
+        .map(|val| val.to_lowercase() != handler_name(ext))
         // if .ext registry key didn't exist
         .or(Ok(false))
 }
 
-/// Get executable path of the WSL Script handler.
-pub fn get_handler_executable_path(ext: &str) -> Result<PathBuf, Error> {
+/// Get the registered open command of an extension.
+pub fn get_open_command(ext: &str) -> Result<String, Error> {
     RegKey::predef(HKEY_CURRENT_USER)
         .open_subkey(CLASSES_SUBKEY)
         .and_then(|key| key.open_subkey(format!(r"{}.{}\shell\open\command", HANDLER_PREFIX, ext)))
         .and_then(|key| key.get_value::<String, _>(""))
-        .map_err(|e| Error::from(Error::RegistryError(e)))
-        .and_then(|cmd| {
-            // remove quotes
-            cmd.trim_start_matches('"')
-                .split_terminator('"')
-                .next()
-                .map(PathBuf::from)
-                .ok_or_else(|| Error::InvalidPathError)
-        })
+        .map_err(Error::RegistryError)
+}
+
+/// Get executable path of the WSL Script handler.
+pub fn get_handler_executable_path(ext: &str) -> Result<PathBuf, Error> {
+    get_open_command(ext).and_then(|cmd| {
+        // remove quotes
+        cmd.trim_start_matches('"')
+            .split_terminator('"')
+            .next()
+            .map(PathBuf::from)
+            .ok_or_else(|| Error::InvalidPathError)
+    })
 }
 
 /// Whether extension is registered for current wslscript executable.
@@ -546,16 +2249,216 @@ pub fn is_registered_for_current_executable(ext: &str) -> Result<bool, Error> {
     Ok(false)
 }
 
+/// Whether the binaries registered for an extension are signed by the same
+/// signer as `reference`.
+///
+/// Both the executable registered for the extension and the drop handler
+/// library are checked. Binaries that aren't registered are skipped.
+pub fn handler_signatures_match(ext: &str, reference: &Path) -> bool {
+    use crate::signature;
+    [get_handler_executable_path(ext), get_server_dll_path()]
+        .into_iter()
+        .filter_map(Result::ok)
+        .all(|path| {
+            let matches = signature::same_signer(&path, reference);
+            if !matches {
+                log::warn!("Signature mismatch: {}", path.to_string_lossy());
+            }
+            matches
+        })
+}
+
+/// Registry key under HKLM for the Application log's event sources.
+const EVENTLOG_APPLICATION_SUBKEY: &str = r"SYSTEM\CurrentControlSet\Services\EventLog\Application";
+
+/// Whether the Event Log source is registered.
+pub fn is_event_source_registered() -> bool {
+    RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey(event_source_subkey())
+        .is_ok()
+}
+
+/// Get registry key under HKLM of the Event Log source.
+fn event_source_subkey() -> String {
+    format!(
+        r"{}\{}",
+        EVENTLOG_APPLICATION_SUBKEY,
+        crate::eventlog::EVENT_SOURCE
+    )
+}
+
+/// Register the Event Log source with the message table of
+/// the current executable.
+///
+/// Requires administrator rights.
+#[cfg(feature = "gui")]
+pub fn register_event_source() -> Result<(), Error> {
+    use windows::Win32::System::EventLog::*;
+    let exe = std::env::current_exe()?;
+    let (key, _) = RegKey::predef(HKEY_LOCAL_MACHINE)
+        .create_subkey(event_source_subkey())
+        .map_err(Error::RegistryError)?;
+    let types =
+        (EVENTLOG_ERROR_TYPE.0 | EVENTLOG_WARNING_TYPE.0 | EVENTLOG_INFORMATION_TYPE.0) as u32;
+    key.set_value("EventMessageFile", &exe.as_os_str())
+        .and_then(|_| key.set_value("TypesSupported", &types))
+        .map_err(Error::RegistryError)
+}
+
+/// Whether to verify signatures of registered handler binaries.
+pub fn verify_signatures() -> bool {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(SETTINGS_SUBKEY)
+        .and_then(|key| key.get_value::<u32, _>("VerifySignatures"))
+        .is_ok_and(|v| v != 0)
+}
+
+/// Set whether to verify signatures of registered handler binaries.
+pub fn set_verify_signatures(enabled: bool) -> Result<(), Error> {
+    let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+        .create_subkey(SETTINGS_SUBKEY)
+        .map_err(Error::RegistryError)?;
+    key.set_value("VerifySignatures", &(enabled as u32))
+        .map_err(Error::RegistryError)
+}
+
+/// Whether chosen icons are copied to the managed icons folder on
+/// registration, see `ShellIcon::to_managed_copy`.
+pub fn managed_icons() -> bool {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(SETTINGS_SUBKEY)
+        .and_then(|key| key.get_value::<u32, _>("ManagedIcons"))
+        .is_ok_and(|v| v != 0)
+}
+
+/// Set whether chosen icons are copied to the managed icons folder.
+///
+/// Takes effect when a filetype is registered or saved.
+pub fn set_managed_icons(enabled: bool) -> Result<(), Error> {
+    let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+        .create_subkey(SETTINGS_SUBKEY)
+        .map_err(Error::RegistryError)?;
+    key.set_value("ManagedIcons", &(enabled as u32))
+        .map_err(Error::RegistryError)
+}
+
+/// Get the `wsl.exe` options that have been found to be supported,
+/// eg. `--cd`.
+///
+/// Only support is recorded, since it can't go away with WSL updates.
+pub fn wsl_supported_options() -> Vec<String> {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(SETTINGS_SUBKEY)
+        .and_then(|key| key.get_value::<Vec<String>, _>("WslSupportedOptions"))
+        .unwrap_or_default()
+}
+
+/// Record the `wsl.exe` options found to be supported.
+pub fn set_wsl_supported_options(options: &[&str]) -> Result<(), Error> {
+    let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+        .create_subkey(SETTINGS_SUBKEY)
+        .map_err(Error::RegistryError)?;
+    key.set_value("WslSupportedOptions", &options.to_vec())
+        .map_err(Error::RegistryError)
+}
+
+/// Whether the drop handler runs out of process, hosted by `wslscript.exe`.
+pub fn out_of_process() -> bool {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(SETTINGS_SUBKEY)
+        .and_then(|key| key.get_value::<u32, _>("OutOfProcess"))
+        .is_ok_and(|v| v != 0)
+}
+
+/// Set whether the drop handler runs out of process.
+///
+/// Takes effect when the drop handler is registered again.
+pub fn set_out_of_process(enabled: bool) -> Result<(), Error> {
+    let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+        .create_subkey(SETTINGS_SUBKEY)
+        .map_err(Error::RegistryError)?;
+    key.set_value("OutOfProcess", &(enabled as u32))
+        .map_err(Error::RegistryError)
+}
+
+/// Whether the "Copy WSL path" verb is registered for all files.
+pub fn copy_path_for_all_files() -> bool {
+    RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(CLASSES_SUBKEY)
+        .and_then(|key| key.open_subkey(ALL_FILES_COPY_PATH_SUBKEY))
+        .is_ok()
+}
+
+/// Register or remove the "Copy WSL path" verb for all files.
+///
+/// The verb is extended, ie. shown only when Shift is held, so that it
+/// doesn't clutter the context menu of every file. Like the verb of
+/// a filetype, it copies the paths of all selected files at once.
+#[cfg(feature = "gui")]
+pub fn set_copy_path_for_all_files(enabled: bool) -> Result<(), Error> {
+    let base = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey_with_flags(CLASSES_SUBKEY, KEY_ALL_ACCESS)
+        .map_err(Error::RegistryError)?;
+    if !enabled {
+        return match base.delete_subkey_all(ALL_FILES_COPY_PATH_SUBKEY) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::RegistryError(e)),
+            _ => Ok(()),
+        };
+    }
+    let cmd = get_copy_path_command(None)?.to_os_string();
+    let (key, _) = base
+        .create_subkey(ALL_FILES_COPY_PATH_SUBKEY)
+        .map_err(Error::RegistryError)?;
+    key.set_value("", &"Copy WSL path")
+        .and_then(|_| key.set_value("Extended", &""))
+        .and_then(|_| key.set_value("MultiSelectModel", &COPY_PATH_MULTI_SELECT))
+        .map_err(Error::RegistryError)?;
+    let (key, _) = key.create_subkey("command").map_err(Error::RegistryError)?;
+    key.set_value("", &cmd.as_os_str())
+        .map_err(Error::RegistryError)
+}
+
+/// Register the drop handler again, eg. after changing whether it runs
+/// out of process.
+///
+/// Does nothing if no extensions are registered.
+#[cfg(feature = "gui")]
+pub fn reregister_server() -> Result<(), Error> {
+    if query_registered_extensions()?.is_empty() {
+        return Ok(());
+    }
+    register_server()?;
+    notify_shell_change();
+    Ok(())
+}
+
+/// Get path to the registered drop handler library.
+pub fn get_server_dll_path() -> Result<PathBuf, Error> {
+    RegKey::predef(server_scope().root())
+        .open_subkey(CLASSES_SUBKEY)
+        .and_then(|key| key.open_subkey(format!(r"CLSID\{}\InProcServer32", drop_handler_clsid())))
+        .and_then(|key| key.get_value::<String, _>(""))
+        .map(PathBuf::from)
+        .map_err(Error::RegistryError)
+}
+
 /// Call DllRegisterServer from shell extension handler library.
+#[cfg(feature = "gui")]
 fn register_server() -> Result<(), Error> {
     use libloading::{Library, Symbol};
+    use windows::Win32::Foundation::S_OK;
+    // machine-wide server is registered by the installer
+    if server_scope() == ServerScope::Machine {
+        return Ok(());
+    }
+    ensure_drop_handler_clsid(ServerScope::User)?;
     let lib = unsafe { Library::new("wslscript_handler.dll") }
         .map_err(|e| Error::LibraryError(format!("{}", e)))?;
     let dll_register_server: Symbol<unsafe extern "C" fn() -> i32> =
         unsafe { lib.get(b"DllRegisterServer\0") }
             .map_err(|e| Error::LibraryError(format!("{}", e)))?;
     let rv = unsafe { dll_register_server() };
-    if rv != winerror::S_OK {
+    if rv != S_OK.0 {
         log::debug!("DllRegisterServer returned {}", rv);
         return Err(Error::GenericError(
             "Failed to register shell extension.".to_string(),
@@ -564,37 +2467,176 @@ fn register_server() -> Result<(), Error> {
     Ok(())
 }
 
-/// Register in-process server for drop handler shell extension.
+/// Register in-process server for drop handler shell extension in a scope,
+/// with the CLSID stored in the same scope.
+///
+/// If the drop handler is set to run out of process, a local server
+/// hosted by `wslscript.exe` next to the library is registered instead.
 ///
 /// See: https://docs.microsoft.com/en-us/windows/win32/com/inprocserver32
-pub fn add_server_to_registry(dll_path: &Path) -> Result<(), Error> {
+/// and https://docs.microsoft.com/en-us/windows/win32/com/localserver32
+pub fn add_server_to_registry(scope: ServerScope, dll_path: &Path) -> Result<(), Error> {
+    let clsid =
+        stored_drop_handler_clsid(scope).unwrap_or_else(|| LEGACY_DROP_HANDLER_CLSID.clone());
     let tx = Transaction::new().map_err(|e| Error::RegistryError(e))?;
-    let base = RegKey::predef(HKEY_CURRENT_USER)
+    let base = RegKey::predef(scope.root())
         .open_subkey_transacted_with_flags(CLASSES_SUBKEY, &tx, KEY_ALL_ACCESS)
         .map_err(|e| Error::RegistryError(e))?;
-    let clsid = format!(r"CLSID\{}", DROP_HANDLER_CLSID.to_string());
+    let clsid = format!(r"CLSID\{}", clsid);
     set_value(&tx, &base, &clsid, "", &"WSLScript Drop Handler")?;
-    let path = format!(r"{}\InProcServer32", clsid);
-    let val = dll_path.to_string_lossy().to_string();
-    set_value(&tx, &base, &path, "", &val)?;
-    set_value(&tx, &base, &path, "ThreadingModel", &"Apartment")?;
+    let inproc = format!(r"{}\InProcServer32", clsid);
+    let local = format!(r"{}\LocalServer32", clsid);
+    // only one server is registered, so that COM can't fall back to the other
+    let stale = if out_of_process() {
+        // COM appends -Embedding to the command line when starting the server
+        let exe = dll_path.with_file_name("wslscript.exe");
+        let val = format!("\"{}\"", exe.to_string_lossy());
+        set_value(&tx, &base, &local, "", &val)?;
+        inproc
+    } else {
+        let val = dll_path.to_string_lossy().to_string();
+        set_value(&tx, &base, &inproc, "", &val)?;
+        set_value(&tx, &base, &inproc, "ThreadingModel", &"Apartment")?;
+        local
+    };
+    match base.delete_subkey_transacted(&stale, &tx) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(Error::RegistryError(e));
+        }
+        _ => {}
+    }
     tx.commit().map_err(|e| Error::RegistryError(e))?;
     Ok(())
 }
 
-/// Remove registry keys related to drop handler shell extension.
-pub fn remove_server_from_registry() -> Result<(), Error> {
+/// Remove registry keys related to drop handler shell extension in a scope.
+pub fn remove_server_from_registry(scope: ServerScope) -> Result<(), Error> {
+    let clsid =
+        stored_drop_handler_clsid(scope).unwrap_or_else(|| LEGACY_DROP_HANDLER_CLSID.clone());
     let tx = Transaction::new().map_err(|e| Error::RegistryError(e))?;
-    let base = RegKey::predef(HKEY_CURRENT_USER)
+    let base = RegKey::predef(scope.root())
         .open_subkey_transacted_with_flags(CLASSES_SUBKEY, &tx, KEY_ALL_ACCESS)
         .map_err(|e| Error::RegistryError(e))?;
-    let clsid = format!(r"CLSID\{}", DROP_HANDLER_CLSID.to_string());
-    if let Ok(key) = base.open_subkey_transacted_with_flags(&clsid, &tx, KEY_ALL_ACCESS) {
+    delete_server_keys(&tx, &base, &clsid)?;
+    tx.commit().map_err(|e| Error::RegistryError(e))?;
+    Ok(())
+}
+
+/// Delete the server keys of a CLSID within a transaction.
+fn delete_server_keys(tx: &Transaction, base: &RegKey, clsid: &Guid) -> Result<(), Error> {
+    let clsid = format!(r"CLSID\{}", clsid);
+    if let Ok(key) = base.open_subkey_transacted_with_flags(&clsid, tx, KEY_ALL_ACCESS) {
         key.delete_subkey_all("")
             .map_err(|e| Error::RegistryError(e))?;
-        base.delete_subkey_transacted(&clsid, &tx)
+        base.delete_subkey_transacted(&clsid, tx)
             .map_err(|e| Error::RegistryError(e))?;
     }
-    tx.commit().map_err(|e| Error::RegistryError(e))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const GUID: &str = "{12345678-9abc-def0-1234-56789abcdef0}";
+    #[test]
+    fn test_distro_guid_formats() {
+        let expected = DistroGUID::from_str(GUID).unwrap();
+        for s in [
+            "{12345678-9ABC-DEF0-1234-56789ABCDEF0}",
+            "12345678-9abc-def0-1234-56789abcdef0",
+            "12345678-9ABC-def0-1234-56789abcDEF0",
+            "urn:uuid:12345678-9abc-def0-1234-56789abcdef0",
+            "URN:UUID:{12345678-9ABC-DEF0-1234-56789ABCDEF0}",
+            "  {12345678-9abc-def0-1234-56789abcdef0}\n",
+        ] {
+            let guid = DistroGUID::from_str(s).unwrap();
+            assert!(guid == expected, "{}", s);
+            assert_eq!(guid.to_string(), GUID);
+        }
+    }
+    #[test]
+    fn test_distro_guid_invalid() {
+        for s in [
+            "",
+            "{}",
+            "not-a-guid",
+            "{12345678-9abc-def0-1234-56789abcdef0",
+            "12345678-9abc-def0-1234-56789abcdef0}",
+            "12345678-9abc-def0-123456789abcdef0",
+            "12345678-9abc-def0-1234-56789abcdefg",
+            "{Shell.Application}",
+        ] {
+            assert!(DistroGUID::from_str(s).is_err(), "{}", s);
+        }
+    }
+    #[test]
+    fn test_distro_guid_serde_round_trip() {
+        let guid = DistroGUID::from_str(GUID).unwrap();
+        let json = serde_json::to_string(&guid).unwrap();
+        assert_eq!(json, format!("\"{}\"", GUID));
+        assert!(serde_json::from_str::<DistroGUID>(&json).unwrap() == guid);
+        assert!(serde_json::from_str::<DistroGUID>("\"not-a-guid\"").is_err());
+    }
+    #[test]
+    fn test_config_document_round_trip() {
+        let config = ExtConfig {
+            extension: "sh".to_string(),
+            hold_mode: HoldMode::Timeout(5),
+            hold_key: HoldKey::Enter,
+            distro: DistroGUID::from_str(GUID).ok(),
+            console_size: Some(ConsoleSize {
+                columns: 120,
+                rows: 30,
+            }),
+            console_colors: Some(ConsoleColors(0x1f)),
+            drop_policy: DropPolicy::Batch(10),
+            arg_order: ArgOrder::Modified,
+            wslenv: "USERPROFILE/p:!TOKEN".parse().unwrap(),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&ConfigDocument::new(vec![config])).unwrap();
+        let doc: ConfigDocument = serde_json::from_str(&json).unwrap();
+        assert_eq!(doc.version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(serde_json::to_string(&doc).unwrap(), json);
+        assert!(json.contains(r#""hold_mode":"timeout:5""#), "{}", json);
+        let cfg = &doc.extensions[0];
+        assert!(cfg.hold_mode == HoldMode::Timeout(5));
+        assert!(cfg.drop_policy == DropPolicy::Batch(10));
+        assert_eq!(cfg.wslenv.to_string(), "USERPROFILE/p:!TOKEN");
+    }
+    #[test]
+    fn test_hold_mode_strings() {
+        for (s, mode) in [
+            ("never", HoldMode::Never),
+            ("always", HoldMode::Always),
+            ("error", HoldMode::Error),
+            ("timeout:30", HoldMode::Timeout(30)),
+        ] {
+            assert!(s.parse::<HoldMode>() == Ok(mode), "{}", s);
+            assert_eq!(mode.to_string(), s);
+            assert_eq!(serde_json::to_string(&mode).unwrap(), format!("\"{}\"", s));
+        }
+        assert!("timeout".parse::<HoldMode>() == Ok(HoldMode::Timeout(HoldMode::DEFAULT_TIMEOUT)));
+        for s in ["", "timeout:", "timeout:0", "timeout:x", "sometimes"] {
+            assert!(s.parse::<HoldMode>().is_err(), "{}", s);
+        }
+        assert!(serde_json::from_str::<HoldMode>(r#"{"timeout":30}"#).is_err());
+    }
+    #[test]
+    fn test_config_missing_fields_default() {
+        let doc: ConfigDocument =
+            serde_json::from_str(r#"{"version":1,"extensions":[{"extension":"py"}]}"#).unwrap();
+        let cfg = &doc.extensions[0];
+        assert_eq!(cfg.extension, "py");
+        assert!(cfg.hold_mode == HoldMode::default());
+        assert_eq!(cfg.max_args, DEFAULT_MAX_ARGS);
+        assert!(serde_json::from_str::<ExtConfig>(r#"{"console_size":"0x0"}"#).is_err());
+    }
+    #[test]
+    fn test_distro_guid_display_round_trip() {
+        let guid = DistroGUID::from_str(GUID).unwrap();
+        let parsed = DistroGUID::from_str(&guid.to_string()).unwrap();
+        assert!(parsed == guid);
+        assert_eq!(parsed.as_wcstr().to_string_lossy(), GUID);
+    }
+}
@@ -0,0 +1,102 @@
+//! Authenticode signature verification of wslscript binaries.
+//!
+//! Used to detect registered handler binaries that have been replaced
+//! with ones signed by someone else, or not signed at all.
+
+use std::path::Path;
+use widestring::*;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HANDLE, HWND};
+use windows::Win32::Security::{Cryptography, WinTrust};
+
+/// Authenticode signature status of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Signature {
+    /// File has no signature.
+    Unsigned,
+    /// File is signed, but the signature is not valid or trusted.
+    Invalid,
+    /// File has a valid signature. Contains the display name of the signer.
+    Signed(String),
+}
+
+/// Verify the Authenticode signature of a file.
+///
+/// Revocation is not checked, so that verification works offline.
+pub fn verify_file(path: &Path) -> Signature {
+    let path = WideCString::from_os_str_truncate(path);
+    let mut file_info = WinTrust::WINTRUST_FILE_INFO {
+        cbStruct: std::mem::size_of::<WinTrust::WINTRUST_FILE_INFO>() as u32,
+        pcwszFilePath: PCWSTR(path.as_ptr()),
+        ..Default::default()
+    };
+    let mut data = WinTrust::WINTRUST_DATA {
+        cbStruct: std::mem::size_of::<WinTrust::WINTRUST_DATA>() as u32,
+        dwUIChoice: WinTrust::WTD_UI_NONE,
+        fdwRevocationChecks: WinTrust::WTD_REVOKE_NONE,
+        dwUnionChoice: WinTrust::WTD_CHOICE_FILE,
+        Anonymous: WinTrust::WINTRUST_DATA_0 {
+            pFile: &mut file_info,
+        },
+        dwStateAction: WinTrust::WTD_STATEACTION_VERIFY,
+        ..Default::default()
+    };
+    let mut action = WinTrust::WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    let rv =
+        unsafe { WinTrust::WinVerifyTrust(HWND::default(), &mut action, &mut data as *mut _ as _) };
+    let signature = match rv {
+        0 => Signature::Signed(signer_name(data.hWVTStateData).unwrap_or_default()),
+        rv if rv == windows::Win32::Foundation::TRUST_E_NOSIGNATURE.0 => Signature::Unsigned,
+        rv => {
+            log::debug!("Signature verification failed with {:#x}", rv);
+            Signature::Invalid
+        }
+    };
+    // release state data
+    data.dwStateAction = WinTrust::WTD_STATEACTION_CLOSE;
+    unsafe { WinTrust::WinVerifyTrust(HWND::default(), &mut action, &mut data as *mut _ as _) };
+    signature
+}
+
+/// Get the display name of the signer from verification state data.
+fn signer_name(state: HANDLE) -> Option<String> {
+    let prov = unsafe { WinTrust::WTHelperProvDataFromStateData(state) };
+    if prov.is_null() {
+        return None;
+    }
+    let signer = unsafe { WinTrust::WTHelperGetProvSignerFromChain(prov, 0, false, 0) };
+    if signer.is_null() {
+        return None;
+    }
+    let signer = unsafe { &*signer };
+    if signer.csCertChain == 0 || signer.pasCertChain.is_null() {
+        return None;
+    }
+    // first certificate in the chain is the signer's
+    let cert = unsafe { (*signer.pasCertChain).pCert };
+    let mut buf = [0u16; 256];
+    let len = unsafe {
+        Cryptography::CertGetNameStringW(
+            cert,
+            Cryptography::CERT_NAME_SIMPLE_DISPLAY_TYPE,
+            0,
+            None,
+            Some(&mut buf),
+        )
+    };
+    if len <= 1 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&buf[..len as usize - 1]))
+}
+
+/// Check whether `path` is signed by the same signer as `reference`.
+///
+/// If `reference` isn't validly signed there's nothing to compare against,
+/// and the check passes.
+pub fn same_signer(path: &Path, reference: &Path) -> bool {
+    match verify_file(reference) {
+        Signature::Signed(signer) => verify_file(path) == Signature::Signed(signer),
+        _ => true,
+    }
+}
@@ -0,0 +1,185 @@
+//! Throttling of rapid and concurrent runs of a filetype.
+//!
+//! Running scripts are counted with a named semaphore per extension, and
+//! the start time of the latest run is recorded in the registry. User is
+//! asked to confirm a run that exceeds either limit, so that accidental
+//! repeated drops don't flood WSL with scripts.
+//!
+//! Filetypes run as a single instance focus the console of the running
+//! script instead.
+
+use crate::error::*;
+use crate::registry::SETTINGS_SUBKEY;
+use crate::wsl::{self, WSLOptions};
+use crate::{wcstring, win32};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{self, HANDLE, HWND, WAIT_OBJECT_0};
+use windows::Win32::System::Threading;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use winreg::enums::*;
+use winreg::RegKey;
+
+/// Key of the last run times under the settings key.
+const LAST_RUN_SUBKEY: &str = "LastRun";
+
+/// Running instance of a filetype's script.
+///
+/// Instance is counted until the slot is dropped.
+pub struct InstanceSlot(HANDLE);
+
+impl Drop for InstanceSlot {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = Threading::ReleaseSemaphore(self.0, 1, None);
+            let _ = Foundation::CloseHandle(self.0);
+        }
+    }
+}
+
+/// Check the run limits of the filetype before running a script.
+///
+/// User is asked to confirm if a script of the same filetype was started
+/// less than the minimum interval ago, or if the maximum number of them
+/// are already running. Returns `Error::Cancel` if the user declined.
+///
+/// Returned slot, if any, must be held until the script exits. A run that
+/// the user allowed over the limit isn't counted.
+///
+/// If the filetype is run as a single instance and its script is already
+/// running, the script's console is brought to the foreground and
+/// `Error::Cancel` is returned.
+pub fn begin_run(opts: &WSLOptions) -> Result<Option<InstanceSlot>, Error> {
+    let Some(ext) = opts.extension() else {
+        return Ok(None);
+    };
+    if let Some(pid) = Some(ext)
+        .filter(|_| opts.single_instance())
+        .and_then(wsl::running_instance)
+    {
+        log::debug!("Script of .{} already running in process {}", ext, pid);
+        if !focus_console(pid) {
+            let text = wcstring(format!("A .{} script is already running.", ext));
+            unsafe {
+                MessageBoxW(
+                    HWND::default(),
+                    PCWSTR(text.as_ptr()),
+                    w!("WSL Script"),
+                    MB_OK | MB_ICONINFORMATION | MB_SETFOREGROUND,
+                );
+            }
+        }
+        return Err(Error::Cancel);
+    }
+    let min_interval = Duration::from_secs(opts.min_interval() as u64);
+    match since_last_run(ext) {
+        Some(elapsed) if elapsed < min_interval => {
+            log::debug!("Previous .{} script started {:?} ago", ext, elapsed);
+            let text = format!(
+                "A .{} script was started {} seconds ago.\n\nRun it again?",
+                ext,
+                win32::format_number(elapsed.as_secs())
+            );
+            if !confirm(&text) {
+                return Err(Error::Cancel);
+            }
+        }
+        _ => {}
+    }
+    let slot = match opts.max_instances() {
+        0 => None,
+        max => match acquire_slot(ext, max) {
+            Ok(Some(slot)) => Some(slot),
+            Ok(None) => {
+                log::debug!("{} .{} scripts already running", max, ext);
+                let text = format!(
+                    "{} .{} scripts are already running.\n\nRun another one anyway?",
+                    win32::format_number(max as u64),
+                    ext
+                );
+                if !confirm(&text) {
+                    return Err(Error::Cancel);
+                }
+                None
+            }
+            Err(e) => {
+                log::error!("Failed to count running scripts: {}", e);
+                None
+            }
+        },
+    };
+    if opts.min_interval() > 0 {
+        record_run(ext);
+    }
+    Ok(slot)
+}
+
+/// Take a slot from the filetype's instance semaphore.
+///
+/// Returns `None` if all slots are taken.
+fn acquire_slot(ext: &str, max: u32) -> Result<Option<InstanceSlot>, Error> {
+    let name = wcstring(format!(r"Local\wslscript-instances-{}", ext.to_lowercase()));
+    let max = max.min(i32::MAX as u32) as i32;
+    // maximum count is set by whichever process creates the semaphore
+    let handle = unsafe { Threading::CreateSemaphoreW(None, max, max, PCWSTR(name.as_ptr())) }
+        .map_err(|e| Error::WinAPIError(format!("CreateSemaphoreW: {}", e)))?;
+    if unsafe { Threading::WaitForSingleObject(handle, 0) } == WAIT_OBJECT_0 {
+        return Ok(Some(InstanceSlot(handle)));
+    }
+    let _ = unsafe { Foundation::CloseHandle(handle) };
+    Ok(None)
+}
+
+/// Time elapsed since a script of the filetype was last started.
+fn since_last_run(ext: &str) -> Option<Duration> {
+    let millis = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(format!(r"{}\{}", SETTINGS_SUBKEY, LAST_RUN_SUBKEY))
+        .and_then(|key| key.get_value::<u64, _>(ext.to_lowercase()))
+        .ok()?;
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH + Duration::from_millis(millis))
+        .ok()
+}
+
+/// Record the start time of a filetype's script.
+fn record_run(ext: &str) {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let result = RegKey::predef(HKEY_CURRENT_USER)
+        .create_subkey(format!(r"{}\{}", SETTINGS_SUBKEY, LAST_RUN_SUBKEY))
+        .and_then(|(key, _)| key.set_value(ext.to_lowercase(), &millis));
+    if let Err(e) = result {
+        log::error!("Failed to record run time: {}", e);
+    }
+}
+
+/// Bring the console window of a process to the foreground.
+///
+/// Returns false if the window wasn't found, eg. when the console is
+/// hosted by Windows Terminal.
+fn focus_console(pid: u32) -> bool {
+    let Some(hwnd) = win32::find_process_window(pid) else {
+        return false;
+    };
+    unsafe {
+        if IsIconic(hwnd).as_bool() {
+            ShowWindow(hwnd, SW_RESTORE);
+        }
+        SetForegroundWindow(hwnd).as_bool()
+    }
+}
+
+/// Ask the user whether to run the script.
+fn confirm(text: &str) -> bool {
+    let text = wcstring(text);
+    let result = unsafe {
+        MessageBoxW(
+            HWND::default(),
+            PCWSTR(text.as_ptr()),
+            w!("WSL Script"),
+            MB_YESNO | MB_ICONQUESTION | MB_DEFBUTTON2 | MB_SETFOREGROUND,
+        )
+    };
+    result == IDYES
+}
@@ -4,8 +4,8 @@ use std::path::Path;
 use std::ptr;
 use widestring::WideCStr;
 use widestring::WideChar;
-use winapi::shared::minwindef as win;
-use winapi::um::winver;
+use windows::core::PCWSTR;
+use windows::Win32::Storage::FileSystem as fs;
 
 /// Get version string from file.
 pub fn product_version(path: &Path) -> Option<String> {
@@ -28,8 +28,8 @@ pub fn product_version(path: &Path) -> Option<String> {
 
 #[repr(C)]
 struct LANGANDCODEPAGE {
-    lang: win::WORD,
-    cp: win::WORD,
+    lang: u16,
+    cp: u16,
 }
 
 struct FileVersion {
@@ -42,17 +42,15 @@ struct FileVersion {
 impl FileVersion {
     pub fn try_new(path: &Path) -> Result<Self, Error> {
         let path_c = WinPathBuf::new(path.to_owned()).to_wide();
-        let size = unsafe { winver::GetFileVersionInfoSizeW(path_c.as_ptr(), ptr::null_mut()) };
+        let size = unsafe { fs::GetFileVersionInfoSizeW(PCWSTR(path_c.as_ptr()), None) };
         if size == 0 {
             return Err(last_error());
         }
         let mut data = Vec::<u8>::with_capacity(size as _);
-        let rv = unsafe {
-            winver::GetFileVersionInfoW(path_c.as_ptr(), 0, size, data.as_mut_ptr() as _)
-        };
-        if rv == 0 {
-            return Err(last_error());
+        unsafe {
+            fs::GetFileVersionInfoW(PCWSTR(path_c.as_ptr()), 0, size, data.as_mut_ptr() as _)
         }
+        .map_err(|_| last_error())?;
         unsafe { data.set_len(size as _) };
         Ok(Self { data })
     }
@@ -61,17 +59,17 @@ impl FileVersion {
     ///
     /// See: https://docs.microsoft.com/en-us/windows/win32/api/winver/nf-winver-verqueryvaluew
     pub fn query<T>(&self, sub_block: &str) -> Result<&[T], Error> {
-        let mut buf: win::LPVOID = ptr::null_mut();
-        let mut len: win::UINT = 0;
+        let mut buf: *mut std::ffi::c_void = ptr::null_mut();
+        let mut len: u32 = 0;
         let rv = unsafe {
-            winver::VerQueryValueW(
+            fs::VerQueryValueW(
                 self.data.as_ptr() as _,
-                wcstring(sub_block).as_ptr(),
+                PCWSTR(wcstring(sub_block).as_ptr()),
                 &mut buf,
                 &mut len,
             )
         };
-        if rv == 0 {
+        if !rv.as_bool() {
             return Err(Error::GenericError("Version not found.".to_string()));
         }
         let s = unsafe { std::slice::from_raw_parts::<T>(buf as _, len as _) };
@@ -2,11 +2,10 @@ use crate::error::*;
 use std::convert::From;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
-use std::ptr::null_mut;
 use wchar::*;
 use widestring::*;
-use winapi::shared::minwindef as win;
-use winapi::um::winnt;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation;
 
 /// Convert &str to WideCString
 pub fn wcstring<T: AsRef<str>>(s: T) -> WideCString {
@@ -26,9 +25,277 @@ pub fn wcstr(s: &[wchar_t]) -> &WideCStr {
     WideCStr::from_slice_truncate(s).unwrap_or_default()
 }
 
+/// Convert string to Unicode normalization form C.
+///
+/// Returns the string unchanged if normalization fails.
+pub fn normalize_nfc(s: &str) -> String {
+    use windows::Win32::Globalization::{NormalizationC, NormalizeString};
+    let src: Vec<u16> = s.encode_utf16().collect();
+    if src.is_empty() {
+        return String::new();
+    }
+    // returned length is an estimate, actual length is known after conversion
+    let len = unsafe { NormalizeString(NormalizationC, &src, None) };
+    if len <= 0 {
+        return s.to_owned();
+    }
+    let mut buf = vec![0_u16; len as usize];
+    let len = unsafe { NormalizeString(NormalizationC, &src, Some(&mut buf)) };
+    if len <= 0 {
+        return s.to_owned();
+    }
+    buf.truncate(len as usize);
+    String::from_utf16_lossy(&buf)
+}
+
+/// Expand 8.3 short names in a path, eg. `PROGRA~1` to `Program Files`.
+///
+/// Returns the path as is if it has no short names, or doesn't exist.
+pub fn long_path(path: &std::path::Path) -> std::path::PathBuf {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows::Win32::Storage::FileSystem::GetLongPathNameW;
+    // short names always contain a tilde
+    if !path.as_os_str().encode_wide().any(|c| c == '~' as u16) {
+        return path.to_path_buf();
+    }
+    let src = WideCString::from_os_str_truncate(path);
+    let len = unsafe { GetLongPathNameW(PCWSTR(src.as_ptr()), None) };
+    if len == 0 {
+        return path.to_path_buf();
+    }
+    let mut buf = vec![0_u16; len as usize];
+    let len = unsafe { GetLongPathNameW(PCWSTR(src.as_ptr()), Some(&mut buf)) } as usize;
+    if len == 0 || len >= buf.len() {
+        return path.to_path_buf();
+    }
+    std::path::PathBuf::from(std::ffi::OsString::from_wide(&buf[..len]))
+}
+
+/// Availability of a drive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DriveStatus {
+    /// Drive is accessible.
+    Ready,
+    /// Drive is locked by BitLocker.
+    Locked,
+    /// Drive is offline, eg. a disconnected network drive or a card reader
+    /// without a card.
+    NotReady,
+    /// Drive doesn't exist.
+    Missing,
+    /// Drive is not accessible for some other reason.
+    Unknown,
+}
+
+/// Probe whether a drive is accessible.
+///
+/// `letter` is the drive letter, eg. `C`.
+pub fn drive_status(letter: char) -> DriveStatus {
+    use windows::Win32::Storage::FileSystem::GetVolumeInformationW;
+    let root = wcstring(format!(r"{}:\", letter));
+    let code =
+        match unsafe { GetVolumeInformationW(PCWSTR(root.as_ptr()), None, None, None, None, None) }
+        {
+            Ok(()) => return DriveStatus::Ready,
+            Err(e) => e.code(),
+        };
+    match code {
+        c if c == Foundation::FVE_E_LOCKED_VOLUME => DriveStatus::Locked,
+        c if c == Foundation::ERROR_NOT_READY.to_hresult() => DriveStatus::NotReady,
+        c if c == Foundation::ERROR_INVALID_DRIVE.to_hresult()
+            || c == Foundation::ERROR_PATH_NOT_FOUND.to_hresult() =>
+        {
+            DriveStatus::Missing
+        }
+        _ => DriveStatus::Unknown,
+    }
+}
+
+/// Parse file names from `CF_HDROP` data, ie. a `DROPFILES` structure
+/// followed by a double null terminated file name array.
+///
+/// `data` is the whole memory block, so offsets and names are checked to
+/// be within it. Names in the ANSI code page are converted. Returns `None`
+/// if the data is malformed.
+///
+/// See: https://learn.microsoft.com/en-us/windows/win32/shell/clipboard#cf_hdrop
+pub fn parse_drop_files(data: &[u8]) -> Option<Vec<PathBuf>> {
+    use std::os::windows::ffi::OsStringExt;
+    use windows::Win32::UI::Shell::DROPFILES;
+    if data.len() < std::mem::size_of::<DROPFILES>() {
+        return None;
+    }
+    // structure is packed, so it may be unaligned
+    let header = unsafe { std::ptr::read_unaligned(data.as_ptr().cast::<DROPFILES>()) };
+    let offset = header.pFiles as usize;
+    if offset < std::mem::size_of::<DROPFILES>() || offset > data.len() {
+        return None;
+    }
+    let names = &data[offset..];
+    if header.fWide.as_bool() {
+        let wide: Vec<u16> = names
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        split_names(&wide)?
+            .into_iter()
+            .map(|name| Some(PathBuf::from(std::ffi::OsString::from_wide(name))))
+            .collect()
+    } else {
+        split_names(names)?
+            .into_iter()
+            .map(|name| {
+                ansi_to_wide(name).map(|s| PathBuf::from(std::ffi::OsString::from_wide(&s)))
+            })
+            .collect()
+    }
+}
+
+/// Split a double null terminated array of strings.
+///
+/// End of the array counts as the final terminator. Returns `None` if the
+/// last string is not terminated.
+fn split_names<T: Default + PartialEq>(array: &[T]) -> Option<Vec<&[T]>> {
+    let nul = T::default();
+    let mut names = Vec::new();
+    let mut rest = array;
+    while !rest.is_empty() {
+        let len = rest.iter().position(|c| *c == nul)?;
+        if len == 0 {
+            break;
+        }
+        names.push(&rest[..len]);
+        rest = &rest[len + 1..];
+    }
+    Some(names)
+}
+
+/// Convert a string in the ANSI code page to UTF-16.
+fn ansi_to_wide(s: &[u8]) -> Option<Vec<u16>> {
+    use windows::Win32::Globalization::{MultiByteToWideChar, CP_ACP, MB_ERR_INVALID_CHARS};
+    let len = unsafe { MultiByteToWideChar(CP_ACP, MB_ERR_INVALID_CHARS, s, None) };
+    if len <= 0 {
+        return None;
+    }
+    let mut buf = vec![0_u16; len as usize];
+    let len = unsafe { MultiByteToWideChar(CP_ACP, MB_ERR_INVALID_CHARS, s, Some(&mut buf)) };
+    if len <= 0 {
+        return None;
+    }
+    buf.truncate(len as usize);
+    Some(buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// Quote an argument and return the result as a string.
+    fn quoted(arg: &str, force_quotes: bool) -> String {
+        let mut cmdline = Vec::new();
+        append_quoted_arg(&mut cmdline, std::ffi::OsStr::new(arg), force_quotes);
+        String::from_utf16(&cmdline).unwrap()
+    }
+
+    #[test]
+    fn test_append_quoted_arg() {
+        let cases = [
+            ("", r#""""#),
+            ("abc", "abc"),
+            ("a b", r#""a b""#),
+            ("a\tb", "\"a\tb\""),
+            (r#"a"b"#, r#"a\"b"#),
+            (r#"a\"b"#, r#"a\\\"b"#),
+            (r#"a\\"b"#, r#"a\\\\\"b"#),
+            (r"a\\b", r"a\\b"),
+            (r"C:\dir\", r"C:\dir\"),
+            (r"C:\my dir\", r#""C:\my dir\\""#),
+            (r"C:\my dir\\", r#""C:\my dir\\\\""#),
+            (r"\\server\share", r"\\server\share"),
+        ];
+        for (arg, expected) in cases {
+            assert_eq!(quoted(arg, false), expected, "{}", arg);
+        }
+        assert_eq!(quoted("abc", true), r#""abc""#);
+        assert_eq!(quoted(r"C:\dir\", true), r#""C:\dir\\""#);
+    }
+
+    #[test]
+    fn test_append_quoted_arg_round_trip() {
+        use windows::Win32::UI::Shell::CommandLineToArgvW;
+        let args = [
+            "",
+            "a b",
+            r#"a\"b"#,
+            r#"a\\"b"#,
+            r"C:\my dir\",
+            r"C:\my dir\\",
+            r#"" \ ""#,
+            r"\\server\share\",
+        ];
+        let mut cmdline: Vec<u16> = "prog.exe".encode_utf16().collect();
+        for arg in args {
+            cmdline.push(' ' as u16);
+            append_quoted_arg(&mut cmdline, std::ffi::OsStr::new(arg), false);
+        }
+        cmdline.push(0);
+        let mut argc = 0;
+        let argv = unsafe { CommandLineToArgvW(PCWSTR(cmdline.as_ptr()), &mut argc) };
+        assert!(!argv.is_null());
+        let parsed: Vec<String> = (1..argc as usize)
+            .map(|i| unsafe { (*argv.add(i)).to_string() }.unwrap())
+            .collect();
+        unsafe { Foundation::LocalFree(Foundation::HLOCAL(argv as _)) };
+        assert_eq!(parsed, args);
+    }
+
+    /// Get 8.3 short name of an existing path.
+    fn short_path(path: &Path) -> PathBuf {
+        use std::os::windows::ffi::OsStringExt;
+        use windows::Win32::Storage::FileSystem::GetShortPathNameW;
+        let src = WideCString::from_os_str_truncate(path);
+        let mut buf = vec![0_u16; 1024];
+        let len = unsafe { GetShortPathNameW(PCWSTR(src.as_ptr()), Some(&mut buf)) } as usize;
+        assert!(len > 0 && len < buf.len());
+        PathBuf::from(std::ffi::OsString::from_wide(&buf[..len]))
+    }
+
+    #[test]
+    fn test_parse_grouping() {
+        assert_eq!(parse_grouping("3;0"), Some(3));
+        assert_eq!(parse_grouping("3;2;0"), Some(32));
+        assert_eq!(parse_grouping("3"), Some(30));
+        assert_eq!(parse_grouping(""), None);
+    }
+
+    #[test]
+    fn test_long_path_mixed_segments() {
+        let dir = std::env::temp_dir().join("wslscript long path test");
+        let file = dir.join("long script name.sh");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&file, "").unwrap();
+        let expected = long_path(&short_path(&file));
+        // short directory with long file name
+        let mixed = short_path(&dir).join("long script name.sh");
+        assert_eq!(long_path(&mixed), expected);
+        // long directory with short file name
+        let short_file = short_path(&file);
+        let mixed = dir.join(short_file.file_name().unwrap());
+        assert_eq!(long_path(&mixed), expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+        // 8.3 names may be disabled on the volume
+        if short_file.as_os_str() != file.as_os_str() {
+            assert!(!expected.to_string_lossy().contains('~'));
+        }
+    }
+    #[test]
+    fn test_long_path_unchanged() {
+        let path = Path::new(r"C:\does not exist\PROGRA~1\script.sh");
+        assert_eq!(long_path(path), path);
+        let path = Path::new(r"C:\Windows\script.sh");
+        assert_eq!(long_path(path), path);
+    }
     #[test]
     fn test_wcstring_with_null() {
         assert_eq!(wcstring("with\0null"), wcstring("with"));
@@ -37,52 +304,426 @@ mod tests {
     fn test_wcstr() {
         assert_eq!(wcstr(wchz!("test")).as_slice(), &wchz!("test")[0..4]);
     }
+    #[test]
+    fn test_normalize_nfc() {
+        assert_eq!(normalize_nfc("e\u{301}"), "\u{e9}");
+        assert_eq!(normalize_nfc("\u{448}"), "\u{448}");
+        assert_eq!(normalize_nfc(""), "");
+    }
+
+    /// Build `CF_HDROP` data with given file name array.
+    fn drop_data(names: &[u8], wide: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(20_u32.to_le_bytes()); // pFiles
+        data.extend([0; 12]); // pt, fNC
+        data.extend((wide as u32).to_le_bytes()); // fWide
+        data.extend(names);
+        data
+    }
+
+    fn wide_names(names: &[&str]) -> Vec<u8> {
+        let mut array = String::new();
+        for name in names {
+            array.push_str(name);
+            array.push('\0');
+        }
+        array.push('\0');
+        array.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_parse_drop_files() {
+        let data = drop_data(
+            &wide_names(&[r"C:\a.txt", r"C:\p\u{e4}iv\u{e4}\b.txt"]),
+            true,
+        );
+        assert_eq!(
+            parse_drop_files(&data).unwrap(),
+            [
+                PathBuf::from(r"C:\a.txt"),
+                PathBuf::from(r"C:\p\u{e4}iv\u{e4}\b.txt")
+            ]
+        );
+        let data = drop_data(b"C:\\a.txt\0C:\\b.txt\0\0", false);
+        assert_eq!(
+            parse_drop_files(&data).unwrap(),
+            [PathBuf::from(r"C:\a.txt"), PathBuf::from(r"C:\b.txt")]
+        );
+        // missing double null at the end of the block
+        let data = drop_data(b"C:\\a.txt\0", false);
+        assert_eq!(
+            parse_drop_files(&data).unwrap(),
+            [PathBuf::from(r"C:\a.txt")]
+        );
+        assert_eq!(
+            parse_drop_files(&drop_data(&[], true)).unwrap(),
+            Vec::<PathBuf>::new()
+        );
+    }
+    #[test]
+    fn test_parse_drop_files_malformed() {
+        let valid = drop_data(&wide_names(&[r"C:\a.txt"]), true);
+        assert!(parse_drop_files(&valid[..19]).is_none());
+        // unterminated name
+        assert!(parse_drop_files(&valid[..valid.len() - 4]).is_none());
+        // offset past the end of the block
+        let mut data = valid.clone();
+        data[..4].copy_from_slice(&(valid.len() as u32 + 1).to_le_bytes());
+        assert!(parse_drop_files(&data).is_none());
+        // offset into the header
+        data[..4].copy_from_slice(&4_u32.to_le_bytes());
+        assert!(parse_drop_files(&data).is_none());
+        data[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_drop_files(&data).is_none());
+    }
+    #[test]
+    fn test_parse_drop_files_fuzz() {
+        let valid = drop_data(&wide_names(&[r"C:\a.txt", r"D:\b c\d.sh"]), true);
+        // xorshift, so that failures are reproducible
+        let mut state = 0x2545_f491_u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        for _ in 0..10_000 {
+            let mut data = valid.clone();
+            for _ in 0..next() % 4 + 1 {
+                let i = next() as usize % data.len();
+                data[i] = next() as u8;
+            }
+            data.truncate(next() as usize % (data.len() + 1));
+            // must not panic, and each name takes at least two bytes
+            if let Some(paths) = parse_drop_files(&data) {
+                assert!(paths.len() * 2 <= data.len());
+            }
+        }
+    }
 }
 
 /// Display error message as a message box.
+#[cfg(feature = "gui")]
 pub fn error_message(msg: &WideCStr) {
-    use winapi::um::winuser::{MessageBoxW, MB_ICONERROR, MB_OK};
+    use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK};
     unsafe {
         MessageBoxW(
-            null_mut(),
-            msg.as_ptr(),
-            wcstr(wchz!("Error")).as_ptr(),
+            Foundation::HWND::default(),
+            PCWSTR(msg.as_ptr()),
+            PCWSTR(wchz!("Error").as_ptr()),
             MB_OK | MB_ICONERROR,
         );
     }
 }
 
+/// Display an error in a task dialog, with `details` behind an expander.
+///
+/// Details are the error output of WSL, which is too verbose to show
+/// up front but often the only explanation of the error.
+#[cfg(feature = "gui")]
+pub fn error_dialog(title: &str, text: &str, details: &str) {
+    use windows::core::w;
+    use windows::Win32::UI::Controls::*;
+    let title = wcstring(title);
+    let text = wcstring(text);
+    let details = wcstring(details);
+    let config = TASKDIALOGCONFIG {
+        cbSize: std::mem::size_of::<TASKDIALOGCONFIG>() as u32,
+        dwCommonButtons: TDCBF_OK_BUTTON,
+        pszWindowTitle: PCWSTR(title.as_ptr()),
+        Anonymous1: TASKDIALOGCONFIG_0 {
+            pszMainIcon: TD_ERROR_ICON,
+        },
+        pszContent: PCWSTR(text.as_ptr()),
+        pszExpandedInformation: PCWSTR(details.as_ptr()),
+        pszCollapsedControlText: w!("Show details"),
+        pszExpandedControlText: w!("Hide details"),
+        ..Default::default()
+    };
+    if unsafe { TaskDialogIndirect(&config, None, None, None) }.is_err() {
+        let text = format!(
+            "{}\n\n{}",
+            text.to_string_lossy(),
+            details.to_string_lossy()
+        );
+        error_message(&wcstring(text));
+    }
+}
+
+/// Find a visible top-level window of a process.
+#[cfg(feature = "gui")]
+pub fn find_process_window(pid: u32) -> Option<Foundation::HWND> {
+    use windows::Win32::UI::WindowsAndMessaging::*;
+    struct Search {
+        pid: u32,
+        hwnd: Foundation::HWND,
+    }
+    unsafe extern "system" fn find_window(
+        hwnd: Foundation::HWND,
+        lparam: Foundation::LPARAM,
+    ) -> Foundation::BOOL {
+        let search = &mut *(lparam.0 as *mut Search);
+        let mut window_pid = 0_u32;
+        GetWindowThreadProcessId(hwnd, Some(&mut window_pid));
+        if window_pid == search.pid && IsWindowVisible(hwnd).as_bool() {
+            search.hwnd = hwnd;
+            return Foundation::FALSE;
+        }
+        Foundation::TRUE
+    }
+    let mut search = Search {
+        pid,
+        hwnd: Foundation::HWND::default(),
+    };
+    // enumeration reports an error when stopped by the callback
+    let _ = unsafe {
+        EnumWindows(
+            Some(find_window),
+            Foundation::LPARAM(&mut search as *mut _ as isize),
+        )
+    };
+    Some(search.hwnd).filter(|hwnd| hwnd.0 != 0)
+}
+
+/// Get path of the binary this code is linked into, ie. the executable
+/// or the handler library.
+pub fn current_module_path() -> Option<PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+    use windows::Win32::System::LibraryLoader::*;
+    let mut module = Foundation::HMODULE::default();
+    unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR(current_module_path as *const u16),
+            &mut module,
+        )
+        .ok()?;
+    }
+    let mut buf = vec![0_u16; 32768];
+    let len = unsafe { GetModuleFileNameW(module, &mut buf) } as usize;
+    if len == 0 || len == buf.len() {
+        return None;
+    }
+    Some(PathBuf::from(std::ffi::OsString::from_wide(&buf[..len])))
+}
+
 /// Get the last WinAPI error.
 pub fn last_error() -> Error {
-    use winapi::um::winbase::*;
-    let mut buf: winnt::LPWSTR = null_mut();
-    let errno = unsafe { winapi::um::errhandlingapi::GetLastError() };
+    use windows::Win32::System::Diagnostics::Debug::*;
+    use windows::Win32::System::SystemServices::{LANG_NEUTRAL, SUBLANG_DEFAULT};
+    let mut buf = PWSTR::null();
+    let errno = unsafe { Foundation::GetLastError() };
     let res = unsafe {
         FormatMessageW(
             FORMAT_MESSAGE_FROM_SYSTEM
                 | FORMAT_MESSAGE_IGNORE_INSERTS
                 | FORMAT_MESSAGE_ALLOCATE_BUFFER,
-            null_mut(),
-            errno,
-            win::DWORD::from(winnt::MAKELANGID(
-                winnt::LANG_NEUTRAL,
-                winnt::SUBLANG_DEFAULT,
-            )),
-            &mut buf as *mut winnt::LPWSTR as _,
+            None,
+            errno.0,
+            // MAKELANGID
+            (SUBLANG_DEFAULT << 10) | LANG_NEUTRAL,
+            PWSTR(&mut buf as *mut PWSTR as _),
             0,
-            null_mut(),
+            None,
         )
     };
     let s: String = if res == 0 {
-        format!("Error code {}", errno)
+        format!("Error code {}", errno.0)
     } else {
-        let s = unsafe { WideCString::from_ptr_str(buf).to_string_lossy() };
-        unsafe { LocalFree(buf as _) };
+        let s = unsafe { WideCString::from_ptr_str(buf.0).to_string_lossy() };
+        unsafe { Foundation::LocalFree(Foundation::HLOCAL(buf.0 as _)) };
         s
     };
     Error::WinAPIError(s)
 }
 
+/// Append argument to a command line quoted the same way as
+/// `std::process::Command` does.
+pub(crate) fn append_quoted_arg(cmdline: &mut Vec<u16>, arg: &std::ffi::OsStr, force_quotes: bool) {
+    use std::os::windows::ffi::OsStrExt;
+    let quote = force_quotes || arg.is_empty() || arg.encode_wide().any(|c| c == 0x20 || c == 0x09);
+    if quote {
+        cmdline.push('"' as u16);
+    }
+    let mut backslashes: usize = 0;
+    for c in arg.encode_wide() {
+        if c == '\\' as u16 {
+            backslashes += 1;
+        } else {
+            if c == '"' as u16 {
+                // escape preceding backslashes and the quote itself
+                cmdline.extend((0..=backslashes).map(|_| '\\' as u16));
+            }
+            backslashes = 0;
+        }
+        cmdline.push(c);
+    }
+    if quote {
+        // double trailing backslashes so they don't escape the closing quote
+        cmdline.extend((0..backslashes).map(|_| '\\' as u16));
+        cmdline.push('"' as u16);
+    }
+}
+
+/// Whether a file is a cloud placeholder whose content isn't available locally,
+/// eg. a OneDrive file that's online-only.
+pub fn is_cloud_placeholder(path: &std::path::Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    use windows::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, FILE_ATTRIBUTE_RECALL_ON_OPEN,
+    };
+    let recall = FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS.0 | FILE_ATTRIBUTE_RECALL_ON_OPEN.0;
+    std::fs::metadata(path).is_ok_and(|m| m.file_attributes() & recall != 0)
+}
+
+/// Download content of a cloud placeholder by reading the file through.
+pub fn hydrate_file(path: &std::path::Path) -> std::io::Result<()> {
+    let mut file = std::fs::File::open(path)?;
+    std::io::copy(&mut file, &mut std::io::sink())?;
+    Ok(())
+}
+
+/// Resolve the target of a shell shortcut (.lnk) file.
+///
+/// Returns `None` if the path isn't a shortcut or it can't be read.
+pub fn shortcut_target(path: &std::path::Path) -> Option<PathBuf> {
+    use windows::core::Interface;
+    use windows::Win32::System::Com;
+    use windows::Win32::UI::Shell::{IShellLinkW, ShellLink};
+    if !path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("lnk"))
+    {
+        return None;
+    }
+    // COM may already be initialized by the host, eg. Explorer
+    let initialized = unsafe { Com::CoInitializeEx(None, Com::COINIT_APARTMENTTHREADED) }.is_ok();
+    let resolve = || -> windows::core::Result<PathBuf> {
+        let link: IShellLinkW =
+            unsafe { Com::CoCreateInstance(&ShellLink, None, Com::CLSCTX_INPROC_SERVER) }?;
+        let name = wcstring(path.to_string_lossy());
+        unsafe {
+            link.cast::<Com::IPersistFile>()?
+                .Load(PCWSTR(name.as_ptr()), Com::STGM_READ)
+        }?;
+        let mut buf = [0_u16; Foundation::MAX_PATH as usize];
+        unsafe { link.GetPath(&mut buf, std::ptr::null_mut(), 0) }?;
+        Ok(PathBuf::from(
+            WideCStr::from_slice_truncate(&buf)
+                .map_err(|_| windows::core::Error::from(Foundation::E_UNEXPECTED))?
+                .to_os_string(),
+        ))
+    };
+    let target = resolve();
+    if initialized {
+        unsafe { Com::CoUninitialize() };
+    }
+    target.ok().filter(|p| !p.as_os_str().is_empty())
+}
+
+/// Format an integer with the digit grouping of the user's locale,
+/// eg. `12,345` or `12 345`.
+///
+/// Falls back to plain digits if formatting fails.
+pub fn format_number(n: u64) -> String {
+    use windows::Win32::Globalization::*;
+    // locale's default number format has decimals, so a format without
+    // them is built from the locale's separators and grouping
+    let mut decimal = locale_info(LOCALE_SDECIMAL).unwrap_or_else(|| wcstring("."));
+    let mut thousand = locale_info(LOCALE_STHOUSAND).unwrap_or_else(|| wcstring(","));
+    let grouping = locale_info(LOCALE_SGROUPING)
+        .and_then(|s| parse_grouping(&s.to_string_lossy()))
+        .unwrap_or(3);
+    let format = NUMBERFMTW {
+        NumDigits: 0,
+        LeadingZero: 0,
+        Grouping: grouping,
+        lpDecimalSep: PWSTR(decimal.as_mut_ptr()),
+        lpThousandSep: PWSTR(thousand.as_mut_ptr()),
+        NegativeOrder: 1,
+    };
+    let value = wcstring(n.to_string());
+    let mut buf = [0_u16; 64];
+    let len = unsafe {
+        GetNumberFormatEx(
+            PCWSTR::null(),
+            0,
+            PCWSTR(value.as_ptr()),
+            Some(&format),
+            Some(&mut buf),
+        )
+    };
+    if len <= 0 {
+        return n.to_string();
+    }
+    // length includes the terminating null
+    String::from_utf16_lossy(&buf[..len as usize - 1])
+}
+
+/// Format date and time in the short formats of the user's locale.
+///
+/// Falls back to `YYYY-MM-DD hh:mm:ss` if formatting fails.
+pub fn format_date_time(t: &Foundation::SYSTEMTIME) -> String {
+    use windows::Win32::Globalization::*;
+    let mut date = [0_u16; 64];
+    let mut time = [0_u16; 64];
+    let date_len = unsafe {
+        GetDateFormatEx(
+            PCWSTR::null(),
+            DATE_SHORTDATE,
+            Some(t),
+            PCWSTR::null(),
+            Some(&mut date),
+            PCWSTR::null(),
+        )
+    };
+    let time_len = unsafe {
+        GetTimeFormatEx(
+            PCWSTR::null(),
+            TIME_FORMAT_FLAGS(0),
+            Some(t),
+            PCWSTR::null(),
+            Some(&mut time),
+        )
+    };
+    if date_len <= 0 || time_len <= 0 {
+        return format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            t.wYear, t.wMonth, t.wDay, t.wHour, t.wMinute, t.wSecond
+        );
+    }
+    format!(
+        "{} {}",
+        String::from_utf16_lossy(&date[..date_len as usize - 1]),
+        String::from_utf16_lossy(&time[..time_len as usize - 1])
+    )
+}
+
+/// Get a string value of the user's locale.
+fn locale_info(lctype: u32) -> Option<WideCString> {
+    use windows::Win32::Globalization::GetLocaleInfoEx;
+    let mut buf = [0_u16; 16];
+    let len = unsafe { GetLocaleInfoEx(PCWSTR::null(), lctype, Some(&mut buf)) };
+    if len <= 0 {
+        return None;
+    }
+    WideCString::from_vec(&buf[..len as usize - 1]).ok()
+}
+
+/// Convert `LOCALE_SGROUPING` string to `NUMBERFMTW` grouping.
+///
+/// Eg. `3;0` repeats groups of three digits and converts to `3`, while `3`
+/// only groups the last three digits and converts to `30`.
+fn parse_grouping(s: &str) -> Option<u32> {
+    let mut parts = s.split(';').collect::<Vec<_>>();
+    if parts.last() == Some(&"0") {
+        parts.pop();
+    } else {
+        parts.push("0");
+    }
+    parts.concat().parse().ok()
+}
+
 /// Path buffer with Windows semantics.
 #[derive(Clone)]
 pub struct WinPathBuf {
@@ -127,17 +768,16 @@ impl WinPathBuf {
 
     /// Expand environment variables in a path.
     pub fn expand(&self) -> Result<Self, Error> {
-        use winapi::um::fileapi::*;
-        use winapi::um::processenv::*;
+        use windows::Win32::Storage::FileSystem::GetLongPathNameW;
+        use windows::Win32::System::Environment::ExpandEnvironmentStringsW;
         let mut buf = [0_u16; 2048];
-        let len = unsafe {
-            ExpandEnvironmentStringsW(self.to_wide().as_ptr(), buf.as_mut_ptr(), buf.len() as _)
-        };
+        let len =
+            unsafe { ExpandEnvironmentStringsW(PCWSTR(self.to_wide().as_ptr()), Some(&mut buf)) };
         if len == 0 {
             return Err(last_error());
         }
         let path = unsafe { WideCString::from_ptr_unchecked(buf.as_ptr(), len as _) };
-        let len = unsafe { GetLongPathNameW(path.as_ptr(), buf.as_mut_ptr(), buf.len() as _) };
+        let len = unsafe { GetLongPathNameW(PCWSTR(path.as_ptr()), Some(&mut buf)) };
         if len == 0 {
             return Err(last_error());
         }
@@ -181,3 +821,89 @@ impl DerefMut for WinPathBuf {
         &mut self.buf
     }
 }
+
+/// Define an owned wrapper for a Win32 handle that is released on drop.
+#[cfg(feature = "gui")]
+macro_rules! owned_handle {
+    ($(#[$meta:meta])* $name:ident($handle:ty), |$h:ident| $release:expr) => {
+        $(#[$meta])*
+        #[derive(Default)]
+        pub struct $name($handle);
+
+        impl $name {
+            /// Take ownership of a raw handle.
+            ///
+            /// # Safety
+            ///
+            /// Handle must be valid or null, and not owned by anything else.
+            pub unsafe fn from_raw(handle: $handle) -> Self {
+                Self(handle)
+            }
+
+            /// Get the raw handle while retaining ownership.
+            pub fn get(&self) -> $handle {
+                self.0
+            }
+
+            /// Give up ownership of the handle without releasing it.
+            pub fn release(&mut self) -> $handle {
+                std::mem::take(&mut self.0)
+            }
+
+            pub fn is_invalid(&self) -> bool {
+                self.0 .0 == 0
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                let $h = self.release();
+                if $h.0 != 0 {
+                    let _ = unsafe { $release };
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "gui")]
+owned_handle!(
+    /// Window that is destroyed on drop.
+    ///
+    /// Must be dropped on the thread that created the window.
+    OwnedHwnd(Foundation::HWND),
+    |h| windows::Win32::UI::WindowsAndMessaging::DestroyWindow(h)
+);
+
+#[cfg(feature = "gui")]
+owned_handle!(
+    /// Icon that is destroyed on drop.
+    OwnedHicon(windows::Win32::UI::WindowsAndMessaging::HICON),
+    |h| windows::Win32::UI::WindowsAndMessaging::DestroyIcon(h)
+);
+
+#[cfg(feature = "gui")]
+owned_handle!(
+    /// Font that is deleted on drop.
+    OwnedHfont(windows::Win32::Graphics::Gdi::HFONT),
+    |h| windows::Win32::Graphics::Gdi::DeleteObject(h)
+);
+
+#[cfg(feature = "gui")]
+owned_handle!(
+    /// Menu that is destroyed on drop.
+    OwnedHmenu(windows::Win32::UI::WindowsAndMessaging::HMENU),
+    |h| windows::Win32::UI::WindowsAndMessaging::DestroyMenu(h)
+);
+
+#[cfg(feature = "gui")]
+impl Clone for OwnedHicon {
+    /// Clone by copying the icon, so that both copies can be destroyed.
+    fn clone(&self) -> Self {
+        use windows::Win32::UI::WindowsAndMessaging::CopyIcon;
+        if self.is_invalid() {
+            return Self::default();
+        }
+        Self(unsafe { CopyIcon(self.0) }.unwrap_or_default())
+    }
+}
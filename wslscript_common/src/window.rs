@@ -0,0 +1,130 @@
+//! Window procedure plumbing and the message loop shared by all windows.
+
+use crate::error::*;
+use crate::win32;
+use std::cell::RefCell;
+use std::mem;
+use windows::Win32::Foundation::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+pub trait WindowProc {
+    /// Window procedure callback.
+    ///
+    /// If None is returned, underlying wrapper calls `DefWindowProcW`.
+    fn window_proc(
+        &mut self,
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> Option<LRESULT>;
+}
+
+/// Window procedure wrapper that stores struct pointer to window attributes.
+///
+/// Pointer to `T` must be passed as `lpCreateParams` to `CreateWindowExW`,
+/// and `T` must outlive the window.
+/// Messages are proxied to `window_proc()` with *self*.
+pub extern "system" fn window_proc_wrapper<T: WindowProc>(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    // get pointer to T from userdata
+    let mut ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut T;
+    // not yet set, initialize from CREATESTRUCT
+    if ptr.is_null() && msg == WM_NCCREATE {
+        let cs = unsafe { &*(lparam.0 as *const CREATESTRUCTW) };
+        ptr = cs.lpCreateParams as *mut T;
+        log::debug!("Initialize window pointer {:p}", ptr);
+        unsafe { SetLastError(WIN32_ERROR(0)) };
+        if 0 == unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, ptr as isize) }
+            && unsafe { GetLastError() } != WIN32_ERROR(0)
+        {
+            return LRESULT(FALSE.0 as _);
+        }
+    }
+    // call wrapped window proc
+    let mut result = None;
+    if !ptr.is_null() {
+        let this = unsafe { &mut *ptr };
+        result = this.window_proc(hwnd, msg, wparam, lparam);
+    }
+    // last message to the window, forget the pointer
+    if msg == WM_NCDESTROY {
+        unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0) };
+    }
+    result.unwrap_or_else(|| unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) })
+}
+
+thread_local! {
+    /// Error that terminated the message loop of the current thread.
+    static LOOP_ERROR: RefCell<Option<Error>> = const { RefCell::new(None) };
+}
+
+/// Terminate the message loop of the current thread with an error.
+///
+/// Error is returned from `MessageLoop::run`.
+pub fn quit_with_error(e: Error) {
+    log::error!("Quitting message loop: {}", e);
+    LOOP_ERROR.with(|cell| cell.replace(Some(e)));
+    unsafe { PostQuitMessage(1) };
+}
+
+/// Take the error set by `quit_with_error`, if any.
+pub fn take_error() -> Option<Error> {
+    LOOP_ERROR.with(|cell| cell.take())
+}
+
+/// Message loop for the windows of the current thread.
+#[derive(Default)]
+pub struct MessageLoop {
+    /// Accelerator tables and the windows they're translated for.
+    accelerators: Vec<(HWND, HACCEL)>,
+}
+
+impl MessageLoop {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Translate keyboard accelerators for a window.
+    ///
+    /// Accelerator table is not owned by the message loop.
+    pub fn with_accelerators(mut self, hwnd: HWND, accel: HACCEL) -> Self {
+        self.accelerators.push((hwnd, accel));
+        self
+    }
+
+    /// Run message loop until WM_QUIT is received.
+    pub fn run(&self) -> Result<(), Error> {
+        loop {
+            let mut msg: MSG = unsafe { mem::zeroed() };
+            match unsafe { GetMessageW(&mut msg, None, 0, 0) }.0 {
+                1..=i32::MAX => {
+                    if self.translate_accelerator(&msg) {
+                        continue;
+                    }
+                    unsafe {
+                        let _ = TranslateMessage(&msg);
+                        DispatchMessageW(&msg);
+                    }
+                }
+                i32::MIN..=-1 => return Err(win32::last_error()),
+                0 => {
+                    log::debug!("Received WM_QUIT");
+                    return take_error().map_or(Ok(()), Err);
+                }
+            }
+        }
+    }
+
+    /// Returns true if the message was translated to an accelerator command.
+    fn translate_accelerator(&self, msg: &MSG) -> bool {
+        self.accelerators.iter().any(|(hwnd, accel)| {
+            (msg.hwnd == *hwnd || unsafe { IsChild(*hwnd, msg.hwnd) }.as_bool())
+                && unsafe { TranslateAcceleratorW(*hwnd, *accel, msg) } != 0
+        })
+    }
+}
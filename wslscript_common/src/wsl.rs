@@ -1,5 +1,9 @@
 use crate::error::*;
-use crate::registry::{self, HoldMode};
+use crate::probe;
+use crate::registry::{
+    self, ArgOrder, ConsoleColors, ConsoleSize, CrlfMode, DropPolicy, EventLog, HoldKey, HoldMode,
+    WslEnv,
+};
 use crate::wcstring;
 use crate::win32::*;
 use anyhow::Context;
@@ -8,60 +12,427 @@ use std::ffi::{OsStr, OsString};
 use std::os::windows::ffi::{OsStrExt, OsStringExt};
 use std::os::windows::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::{self, Stdio};
+use std::process;
 use wchar::*;
 use widestring::*;
-use winapi::shared::minwindef::MAX_PATH;
-use winapi::um::winbase;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation;
+use windows::Win32::System::Threading;
+
+/// Maximum path length on Windows.
+const MAX_PATH: usize = Foundation::MAX_PATH as usize;
 
 /// Maximum command line length on Windows.
 const MAX_CMD_LEN: usize = 8191;
 
-/// Maximum number of paths to convert per single bash invocation.
+/// Message displayed when the console is held open after the script exits.
+///
+/// `{code}` is replaced with the exit code.
+pub const DEFAULT_HOLD_MESSAGE: &str = "[Process exited - exit code {code}]";
+
+/// Shell commands shared by the session scripts.
+///
+/// Session files are kept in a directory of the user, under
+/// `$XDG_RUNTIME_DIR` if set. `ok path mode` checks that a path is owned by
+/// the user, isn't a symbolic link and has exactly the given mode, so that
+/// files planted by other users are never reused.
+macro_rules! session_prelude {
+    () => {
+        "ok() { [ -O $1 ] && [ ! -L $1 ] && find $1 -maxdepth 0 -perm $2 | grep -q .; }; \
+        d=${XDG_RUNTIME_DIR:-/tmp}/wslscript-$(id -u); s=$d/$2; "
+    };
+}
+
+/// Bash script of a reusable console session.
+///
+/// Runs the command given in `$1` and then waits for further commands on
+/// a named pipe, until the console is closed. `$2` is the session name.
+/// Session is owned by holding a lock on a file next to the pipe.
+/// If the session files can't be trusted, the command is run without
+/// a session.
+///
+/// NOTE: script is passed through `cmd.exe`, so shell metacharacters must
+/// not be enclosed in double quotes.
+const SESSION_SCRIPT: &str = concat!(
+    session_prelude!(),
+    "mkdir -m 700 $d 2>/dev/null; \
+    if ok $d 700 && { [ -e $s.lock ] || (umask 077; : > $s.lock); } && ok $s.lock 600 \
+    && exec 9>>$s.lock && flock -n 9 \
+    && { [ -e $s.fifo ] || mkfifo -m 600 $s.fifo; } && [ -p $s.fifo ] && ok $s.fifo 600; then \
+    (eval \"$1\") 9>&-; printf '\\n[Waiting for the next run]\\n'; \
+    while :; do while IFS= read -r -d '' -u 8 c; do \
+    (eval \"$c\") 8<&- 9>&-; printf '\\n[Waiting for the next run]\\n'; \
+    done 8<$s.fifo; done; else eval \"$1\"; fi"
+);
+
+/// Bash script to send a command to a running session.
+///
+/// Command is given in `$1` and session name in `$2`.
+/// Exits with a non-zero status if the session isn't running,
+/// or its files can't be trusted.
+const SESSION_SEND_SCRIPT: &str = concat!(
+    session_prelude!(),
+    "ok $d 700 && ok $s.lock 600 && exec 9>>$s.lock && ! flock -n 9 \
+    && [ -p $s.fifo ] && ok $s.fifo 600 && printf '%s\\0' \"$1\" > $s.fifo"
+);
+
+/// Bash script to convert Windows paths to WSL.
+///
+/// Reads null terminated Windows paths from stdin and prints
+/// the converted paths, also null terminated.
+/// Path that fails to convert is printed as an empty string.
+const PATH_CONVERT_SCRIPT: &str =
+    r#"while IFS= read -r -d '' p; do printf '%s\0' "$(wslpath -u "$p")"; done"#;
+
+/// Version of the Linux side helper script.
+///
+/// Must be incremented along with the version printed by `HELPER_SCRIPT`
+/// whenever the script changes, so that installed helpers get updated.
+const HELPER_VERSION: &str = "4";
+
+/// Linux side helper script, installed to `HELPER_PATH`.
+///
+/// * `version` prints the helper version.
+/// * `convert` converts paths as `PATH_CONVERT_SCRIPT`.
+/// * `run <command> [args...]` sets up the environment and runs the command.
+///   Variables assigned in `~/.config/wslscript/env` are exported, so that
+///   scripts started from Windows get them without a login shell.
+/// * `status <code> <seconds> <file>` writes the run status into a file
+///   and exits with the given code.
+const HELPER_SCRIPT: &str = r#"#!/bin/bash
+# Helper for WSL Script, installed and updated automatically.
+case "$1" in
+version) echo 4 ;;
+convert) while IFS= read -r -d '' p; do printf '%s\0' "$(wslpath -u "$p")"; done ;;
+run) shift; f=~/.config/wslscript/env; if [ -r "$f" ]; then set -a; . "$f"; set +a; fi; exec "$@" ;;
+status) echo "$2 $3" > "$4"; exit "$2" ;;
+*) echo "Usage: ${0##*/} version|convert|run <command> [args...]|status <code> <seconds> <file>" >&2; exit 2 ;;
+esac
+"#;
+
+/// Path of the installed helper script in WSL.
+const HELPER_PATH: &str = "~/.local/share/wslscript/helper.sh";
+
+/// Path of the helper script relative to the home directory, for installation.
+const HELPER_INSTALL_PATH: &str = ".local/share/wslscript/helper.sh";
+
+/// Version of the `wslscript` command installed into WSL.
+///
+/// Installed command prints the version followed by the path of
+/// `wslscript.exe`, so that it's updated when the executable moves.
+const COMPANION_VERSION: &str = "2";
+
+/// Path of the `wslscript` command relative to the home directory.
+const COMPANION_INSTALL_PATH: &str = ".local/bin/wslscript";
+
+/// Linux side `wslscript` command for invoking Windows from WSL.
+///
+/// `{version}` and `{exe}` are replaced with `COMPANION_VERSION` and the
+/// single quoted WSL path of `wslscript.exe`. `{explorer}` and
+/// `{powershell}` are replaced with the commands to run Explorer and
+/// PowerShell, which are full paths if Windows `PATH` isn't available in WSL.
+const COMPANION_SCRIPT: &str = r#"#!/bin/bash
+# WSL Script command for running Windows actions from WSL.
+# Installed by wslscript.exe, changes are overwritten on update.
+exe={exe}
+explorer={explorer}
+powershell={powershell}
+case "$1" in
+version) echo "{version} $exe $explorer" ;;
+open) shift; for p in "${@:-.}"; do "$explorer" "$(wslpath -w "$p")"; done; exit 0 ;;
+select) "$explorer" /select,"$(wslpath -w "$2")"; exit 0 ;;
+ps) shift; exec "$powershell" -NoProfile -Command "$*" ;;
+run) shift; WSLENV="${WSLENV:+$WSLENV:}WSL_DISTRO_NAME" exec "$exe" run "$@" ;;
+*) echo "Usage: ${0##*/} open [path...]|select <path>|ps <command>|run <script> [args...]" >&2; exit 2 ;;
+esac
+"#;
+
+/// Bash script to install a script under the home directory, unless it's
+/// already up to date.
+///
+/// Expected version is given in `$1`, path relative to the home directory
+/// in `$2`, and the script is read from stdin. Script is replaced
+/// atomically, so that concurrent runs never see a partially written file.
+const INSTALL_SCRIPT: &str = r#"h=~/"$2"; [ "$("$h" version 2>/dev/null)" = "$1" ] && exit 0; mkdir -p "${h%/*}" && cat > "$h.$$" && chmod 755 "$h.$$" && mv -f "$h.$$" "$h""#;
+
+/// Script to convert Windows paths to WSL with a POSIX shell.
+///
+/// As `PATH_CONVERT_SCRIPT`, but input paths are read line by line,
+/// since Windows paths never contain newlines.
+const PATH_CONVERT_SCRIPT_SH: &str =
+    r#"tr '\0' '\n' | while IFS= read -r p; do printf '%s\0' "$(wslpath -u "$p")"; done"#;
+
+/// Shell used to run commands in WSL.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Shell {
+    /// Bash, used whenever available.
+    Bash,
+    /// POSIX shell, for distributions without bash, eg. Alpine.
+    Sh,
+}
+
+impl Shell {
+    /// Name of the shell executable.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bash => "bash",
+            Self::Sh => "sh",
+        }
+    }
+
+    /// Expression of the seconds elapsed since the timer was started.
+    fn elapsed(self) -> &'static str {
+        match self {
+            Self::Bash => "$SECONDS",
+            Self::Sh => "$(($(date +%s)-s0))",
+        }
+    }
+}
+
+/// Shell detected for a distribution, and when it was probed.
+///
+/// `None` records a failed probe, which is retried after `SHELL_PROBE_RETRY`.
+type ShellProbe = (Option<OsString>, Option<Shell>, std::time::Instant);
+
+/// Shells detected per distribution during this process.
+static DISTRO_SHELLS: std::sync::Mutex<Vec<ShellProbe>> = std::sync::Mutex::new(Vec::new());
+
+/// Time after which a distribution that couldn't be probed is probed again.
+const SHELL_PROBE_RETRY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Options of `wsl.exe` whose support is detected, and used only if
+/// supported.
+///
+/// * `--cd` sets the working directory.
+/// * `--exec` runs a command without the default shell, also as `-e`.
+const DETECTED_WSL_OPTIONS: [&str; 2] = ["--cd", "--exec"];
+
+/// Options of `DETECTED_WSL_OPTIONS` that `wsl.exe` supports.
+static WSL_OPTIONS: once_cell::sync::Lazy<Vec<&'static str>> =
+    once_cell::sync::Lazy::new(detect_wsl_options);
+
+/// Environment variable passing the shell command to old versions of
+/// `wsl.exe` that lack `--exec`.
+const LEGACY_COMMAND_VAR: &str = "WSLSCRIPT_COMMAND";
+
+/// Distributions for which the helper has been verified to be up to date.
+static HELPER_READY: std::sync::Mutex<Vec<Option<OsString>>> = std::sync::Mutex::new(Vec::new());
+
+/// Number of converted paths between progress updates.
 #[cfg(not(feature = "debug"))]
-const MAX_PATHS_CONVERT_PER_PROCESS: usize = 100;
+const PATHS_PER_PROGRESS_UPDATE: usize = 100;
 #[cfg(feature = "debug")]
-const MAX_PATHS_CONVERT_PER_PROCESS: usize = 1;
+const PATHS_PER_PROGRESS_UPDATE: usize = 1;
+
+/// Delays between checks whether WSL has started.
+///
+/// The first run after boot may fail while the WSL service is still starting.
+const WSL_START_BACKOFF: [std::time::Duration; 5] = [
+    std::time::Duration::from_millis(500),
+    std::time::Duration::from_secs(1),
+    std::time::Duration::from_secs(2),
+    std::time::Duration::from_secs(4),
+    std::time::Duration::from_secs(8),
+];
+
+/// Callback notified while waiting for WSL to start.
+///
+/// Called with `true` when waiting begins and `false` when it ends.
+pub type WaitNotifier = fn(bool);
+
+/// Callback set with `set_wait_notifier`.
+static WAIT_NOTIFIER: std::sync::Mutex<Option<WaitNotifier>> = std::sync::Mutex::new(None);
+
+/// File names of WSL Script binaries.
+const SELF_FILE_NAMES: &[&str] = &["wslscript.exe", "wslscript_handler.dll"];
+
+/// Refuse to run a script that would start WSL Script again.
+///
+/// A mis-registered filetype, eg. one whose script is a shortcut to
+/// `wslscript.exe`, would otherwise start new instances without end.
+pub fn check_self_invocation(script: &Path) -> Result<(), Error> {
+    let target = shortcut_target(script);
+    let target = target.as_deref().unwrap_or(script);
+    let refuse = || Error::SelfInvocationError(script.to_string_lossy().into_owned());
+    if is_self_binary(target) {
+        return Err(refuse());
+    }
+    // interpreter of the script could be WSL Script via interop
+    let mut line = Vec::with_capacity(256);
+    if let Ok(file) = std::fs::File::open(target) {
+        use std::io::Read;
+        let _ = file.take(256).read_to_end(&mut line);
+    }
+    if shebang_invokes_self(&line) {
+        return Err(refuse());
+    }
+    Ok(())
+}
+
+/// Whether the path is this executable, or any WSL Script binary.
+fn is_self_binary(path: &Path) -> bool {
+    if let Ok(exe) = env::current_exe() {
+        let exe = exe.canonicalize().unwrap_or(exe);
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if path == exe {
+            return true;
+        }
+    }
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| SELF_FILE_NAMES.iter().any(|s| name.eq_ignore_ascii_case(s)))
+}
+
+/// Whether the interpreter line at the start of a script runs WSL Script,
+/// eg. `#!/usr/bin/env wslscript run`.
+fn shebang_invokes_self(head: &[u8]) -> bool {
+    let Some(line) = head.strip_prefix(b"#!") else {
+        return false;
+    };
+    let line = line.split(|&c| c == b'\n').next().unwrap_or_default();
+    String::from_utf8_lossy(line)
+        .to_ascii_lowercase()
+        .split(|c: char| c.is_ascii_whitespace() || c == '/' || c == '\\')
+        .any(|word| word == "wslscript" || SELF_FILE_NAMES.contains(&word))
+}
 
 /// Run script with optional arguments in a WSL.
 ///
-/// Paths must be in WSL context.
-pub fn run_wsl(script_path: &Path, args: &[PathBuf], opts: &WSLOptions) -> Result<(), Error> {
+/// `script_path` and `args` must be in WSL context. `win_script_path` is the
+/// same script in Windows context and is used to inspect the script file.
+/// If `wait` is set, returns after the console has been closed, with the exit
+/// code and run time of the script. Scripts logged to the Event Log are run
+/// without a console and always waited for. Runs in a reused console aren't
+/// waited for, since the console outlives them.
+/// The console's icon is held on a background thread, see `wait_console_icons`.
+pub fn run_wsl(
+    win_script_path: &Path,
+    script_path: &Path,
+    args: &[PathBuf],
+    opts: &WSLOptions,
+    wait: bool,
+) -> Result<Option<RunStatus>, Error> {
     // maximum length of the bash command
     const MAX_BASH_LEN: usize = MAX_CMD_LEN - MAX_PATH - MAX_PATH - 20;
-    let mut bash_cmd = compose_bash_command(script_path, args, opts, false)?;
+    validate_options(opts)?;
+    let crlf = has_crlf_line_endings(win_script_path);
+    if crlf {
+        log::debug!("Script has CRLF line endings");
+    }
+    let shell = distro_shell(opts);
+    // logged runs are waited for by reading the exit code of the process
+    let status_file = if wait && !opts.reuse_console && opts.event_log == EventLog::Off {
+        StatusFile::create(opts)
+            .map_err(|e| log::debug!("Failed to create status file: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let inv = Invocation {
+        shell,
+        helper: helper_ready(opts),
+        status_file: status_file.as_ref().map(|file| file.wsl_path.as_path()),
+    };
+    // arguments file requires bash
+    let args_in_file = opts.args_in_file && shell == Shell::Bash;
+    // sessions run the command in another shell, which must change directory itself
+    let wsl_cd = !opts.reuse_console;
+    let mut bash_cmd =
+        compose_bash_command(script_path, args, opts, &inv, crlf, args_in_file, wsl_cd)?;
     // if arguments won't fit into command line
     if bash_cmd.cmd.len() > MAX_BASH_LEN {
         // retry and force to write arguments into temporary file
-        bash_cmd = compose_bash_command(script_path, args, opts, true)?;
+        bash_cmd = compose_bash_command(script_path, args, opts, &inv, crlf, true, wsl_cd)?;
         if bash_cmd.cmd.len() > MAX_BASH_LEN {
             return Err(Error::CommandTooLong);
         }
     }
     log::debug!("Bash command: {}", bash_cmd.cmd.to_string_lossy());
-    // build command to start WSL process in a terminal window
-    let mut cmd = process::Command::new(cmd_bin_path().as_os_str());
-    cmd.args(&[OsStr::new("/C"), wsl_bin_path()?.as_os_str()]);
-    if let Some(distro) = &opts.distribution {
-        cmd.args(&[OsStr::new("-d"), distro]);
+    // reuse console only if the arguments fit on the command line,
+    // since the temporary file is removed when the process exits
+    // sessions require bash
+    let session = session_name(win_script_path).filter(|_| {
+        opts.reuse_console
+            && opts.event_log == EventLog::Off
+            && shell == Shell::Bash
+            && wsl_supports("--exec")
+            && bash_cmd.tmpfile.is_none()
+            && bash_cmd.cmd.len() + SESSION_SCRIPT.len() + 40 <= MAX_BASH_LEN
+    });
+    if let Some(session) = &session {
+        if send_to_session(session, &bash_cmd.cmd.to_os_string(), opts) {
+            log::debug!("Sent command to session {}", session);
+            return Ok(None);
+        }
+    }
+    let mut cmd = wsl_command(
+        &bash_cmd.cmd,
+        bash_cmd.cd.as_deref(),
+        shell,
+        session.as_deref(),
+        opts,
+    )?;
+    // share selected variables with WSL, keeping those already in WSLENV
+    if !opts.wslenv.is_empty() {
+        let existing = command_env(&cmd, "WSLENV").unwrap_or_default();
+        cmd.env("WSLENV", opts.wslenv.merge(&existing.to_string_lossy()));
+        let vars = opts
+            .wslenv
+            .variables()
+            .map(|(name, secret)| {
+                if secret {
+                    format!("{}=<redacted>", name)
+                } else {
+                    format!("{}={}", name, env::var(name).unwrap_or_default())
+                }
+            })
+            .collect::<Vec<_>>();
+        log::debug!("Passing environment: {}", vars.join(", "));
+    }
+    if opts.event_log != EventLog::Off {
+        let output = (opts.event_log == EventLog::Output).then_some(crate::eventlog::OutputLog {
+            extension: opts.extension.as_deref(),
+            max_age: opts.log_max_age,
+            max_files: opts.log_max_files,
+        });
+        let result = crate::eventlog::run_logged(cmd, win_script_path, args.len(), output);
+        if let Some(tmpfile) = bash_cmd.tmpfile {
+            let _ = std::fs::remove_file(tmpfile);
+        }
+        return result.map(Some);
+    }
+    // console window title defaults to the script name
+    let title = opts
+        .console_title
+        .as_deref()
+        .or_else(|| win_script_path.file_name())
+        .unwrap_or_default();
+    let console = ConsoleStartup {
+        title,
+        position: opts.console_position,
+        size: opts.console_size,
+        colors: opts.console_colors,
+        utf8: opts.utf8_console,
+    };
+    let proc = spawn_in_console(&cmd, &console).map_err(|source| Error::WSLProcessError {
+        command: format!("{:?}", cmd),
+        source,
+    })?;
+    if opts.single_instance {
+        if let Some(ext) = &opts.extension {
+            record_instance(ext, &proc);
+        }
+    }
+    #[cfg(feature = "gui")]
+    if let Some(icon) = &opts.console_icon {
+        keep_console_icon(&proc, icon);
     }
-    cmd.args(&[OsStr::new("-e"), OsStr::new("bash")]);
-    if opts.interactive {
-        cmd.args(&[OsStr::new("-i")]);
-    }
-    cmd.args(&[OsStr::new("-c"), &bash_cmd.cmd.to_os_string()]);
-    // start as a detached process in a new process group so we can safely
-    // exit this program and have the script execute on it's own
-    cmd.creation_flags(winbase::DETACHED_PROCESS | winbase::CREATE_NEW_PROCESS_GROUP);
-    let mut proc: process::Child = cmd
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .context(Error::WSLProcessError)?;
     // always wait on debug to spot errors
     #[cfg(feature = "debug")]
     let _ = proc.wait();
+    if wait {
+        let _ = proc.wait();
+    }
     // if a temporary file was created for the arguments
     if let Some(tmpfile) = bash_cmd.tmpfile {
         // wait for the process to exit
@@ -71,56 +442,1001 @@ pub fn run_wsl(script_path: &Path, args: &[PathBuf], opts: &WSLOptions) -> Resul
             log::debug!("Failed to remove temporary file");
         }
     }
-    Ok(())
+    let status = status_file.and_then(StatusFile::read);
+    if let Some(status) = &status {
+        log::debug!(
+            "Script exited with code {} after {}s",
+            status.exit_code,
+            status.duration.as_secs()
+        );
+    }
+    Ok(status)
+}
+
+/// Build command to start WSL process in a terminal window.
+///
+/// If `cd` is given, `wsl.exe` starts the shell in that directory.
+/// If `session` is given, the bash command is run in that console session.
+fn wsl_command(
+    bash_cmd: &WideStr,
+    cd: Option<&OsStr>,
+    shell: Shell,
+    session: Option<&str>,
+    opts: &WSLOptions,
+) -> Result<process::Command, Error> {
+    let mut cmd = process::Command::new(cmd_bin_path().as_os_str());
+    cmd.args(&[OsStr::new("/C"), wsl_bin_path()?.as_os_str()]);
+    if let Some(distro) = &opts.distribution {
+        cmd.args(&[OsStr::new("-d"), distro]);
+    }
+    if let Some(dir) = cd {
+        cmd.args([OsStr::new("--cd"), dir]);
+    }
+    let flags: &[&str] = if opts.interactive { &["-i"] } else { &[] };
+    if let Some(session) = session {
+        cmd.args([OsStr::new("-e"), OsStr::new(shell.name())]);
+        cmd.args(flags);
+        cmd.args([
+            OsStr::new("-c"),
+            OsStr::new(SESSION_SCRIPT),
+            OsStr::new("wslscript"),
+            &bash_cmd.to_os_string(),
+            OsStr::new(session),
+        ]);
+    } else {
+        push_shell_command(&mut cmd, shell.name(), flags, &bash_cmd.to_os_string());
+    }
+    Ok(cmd)
+}
+
+/// Get command line that runs a script without arguments.
+///
+/// Used to preview the command for the options. `script_path` is in WSL
+/// context and the script is assumed to have Unix line endings.
+/// The distribution isn't probed, so the preview uses the shell detected
+/// on an earlier run, or bash.
+pub fn preview_command_line(script_path: &Path, opts: &WSLOptions) -> Result<WideCString, Error> {
+    let shell = cached_distro_shell(opts).unwrap_or(Shell::Bash);
+    // helper isn't installed for the preview
+    let inv = Invocation {
+        shell,
+        helper: opts.use_helper && shell == Shell::Bash,
+        status_file: None,
+    };
+    let bash_cmd = compose_bash_command(
+        script_path,
+        &[],
+        opts,
+        &inv,
+        false,
+        false,
+        !opts.reuse_console,
+    )?;
+    let cmd = wsl_command(&bash_cmd.cmd, bash_cmd.cd.as_deref(), shell, None, opts)?;
+    Ok(command_line(&cmd))
+}
+
+/// Get name of the reusable console session for a script.
+///
+/// Sessions are per filename extension.
+fn session_name(win_script_path: &Path) -> Option<String> {
+    extension_key(&win_script_path.extension()?.to_string_lossy())
+}
+
+/// Encode extension for names of console sessions and instance markers.
+fn extension_key(ext: &str) -> Option<String> {
+    let ext = registry::normalize_extension(ext);
+    // non-ASCII characters are encoded, so that distinct extensions
+    // don't map to the same session
+    let name: String = ext
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_string(),
+            c if c.is_ascii() => "_".to_string(),
+            c => format!("u{:x}", c as u32),
+        })
+        .collect();
+    Some(name).filter(|s| !s.is_empty())
+}
+
+/// Send bash command to a running console session.
+///
+/// Returns false if there's no session to receive the command.
+fn send_to_session(session: &str, bash_cmd: &OsStr, opts: &WSLOptions) -> bool {
+    let wsl_bin = match wsl_bin_path() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let mut cmd = process::Command::new(wsl_bin);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    if let Some(distro) = &opts.distribution {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    cmd.args([
+        OsStr::new("-e"),
+        OsStr::new("bash"),
+        OsStr::new("-c"),
+        OsStr::new(SESSION_SEND_SCRIPT),
+        OsStr::new("wslscript"),
+        bash_cmd,
+        OsStr::new(session),
+    ]);
+    matches!(cmd.status(), Ok(status) if status.success())
+}
+
+/// Get path of the file recording the running console of a filetype.
+fn instance_marker_path(ext: &str) -> Option<PathBuf> {
+    let key = extension_key(ext)?;
+    let dir = temp_dir().ok()?;
+    Some(dir.join(format!("{}.instance", key)))
+}
+
+/// Record console process of a filetype's script.
+fn record_instance(ext: &str, proc: &OwnedProcess) {
+    let pid = unsafe { Threading::GetProcessId(proc.0) };
+    let Some(path) = instance_marker_path(ext) else {
+        return;
+    };
+    if let Err(e) = std::fs::write(path, pid.to_string()) {
+        log::debug!("Failed to record running instance: {}", e);
+    }
+}
+
+/// Get ID of the console process of a running script of a filetype.
+///
+/// Returns `None` if no script of the filetype is running, or it wasn't
+/// run as a single instance.
+pub fn running_instance(ext: &str) -> Option<u32> {
+    let path = instance_marker_path(ext)?;
+    let pid = std::fs::read_to_string(&path)
+        .ok()?
+        .trim()
+        .parse::<u32>()
+        .ok()?;
+    if is_console_process(pid) {
+        return Some(pid);
+    }
+    let _ = std::fs::remove_file(path);
+    None
+}
+
+/// Check whether a process is a running console started by `run_wsl`.
+///
+/// Image name is checked in case the process ID was reused.
+fn is_console_process(pid: u32) -> bool {
+    let access = Threading::PROCESS_QUERY_LIMITED_INFORMATION | Threading::PROCESS_SYNCHRONIZE;
+    let proc = match unsafe { Threading::OpenProcess(access, false, pid) } {
+        Ok(handle) => OwnedProcess(handle),
+        Err(_) => return false,
+    };
+    if unsafe { Threading::WaitForSingleObject(proc.0, 0) } != Foundation::WAIT_TIMEOUT {
+        return false;
+    }
+    let mut buf = [0_u16; MAX_PATH];
+    let mut len = buf.len() as u32;
+    if unsafe {
+        Threading::QueryFullProcessImageNameW(
+            proc.0,
+            Threading::PROCESS_NAME_WIN32,
+            windows::core::PWSTR(buf.as_mut_ptr()),
+            &mut len,
+        )
+    }
+    .is_err()
+    {
+        return false;
+    }
+    let image = PathBuf::from(OsString::from_wide(&buf[..len as usize]));
+    let name = |p: &Path| p.file_name().map(|s| s.to_ascii_lowercase());
+    name(&image) == name(&cmd_bin_path())
+}
+
+/// Set icon of a new console window.
+///
+/// Console window is created asynchronously, so it's polled for a while.
+/// Returns false if no window was found, eg. when the console is hosted
+/// by Windows Terminal.
+#[cfg(feature = "gui")]
+fn set_console_icon(proc: &OwnedProcess, icon: &crate::icon::ShellIcon) -> bool {
+    use windows::Win32::UI::WindowsAndMessaging::{PostMessageW, ICON_BIG, ICON_SMALL, WM_SETICON};
+    // maximum number of polls, 100 ms apart
+    const MAX_POLLS: usize = 20;
+    let pid = unsafe { Threading::GetProcessId(proc.0) };
+    for _ in 0..MAX_POLLS {
+        if let Some(hwnd) = find_process_window(pid) {
+            for (size, hicon) in [(ICON_BIG, icon.handle()), (ICON_SMALL, icon.small_handle())] {
+                let wparam = Foundation::WPARAM(size as usize);
+                let lparam = Foundation::LPARAM(hicon.0);
+                let _ = unsafe { PostMessageW(hwnd, WM_SETICON, wparam, lparam) };
+            }
+            return true;
+        }
+        // stop if the process exited
+        if unsafe { Threading::WaitForSingleObject(proc.0, 100) } != Foundation::WAIT_TIMEOUT {
+            return false;
+        }
+    }
+    log::debug!("Console window not found for setting the icon");
+    false
+}
+
+/// Threads keeping console icons alive until their consoles close.
+#[cfg(feature = "gui")]
+static ICON_KEEPERS: std::sync::Mutex<Vec<std::thread::JoinHandle<()>>> =
+    std::sync::Mutex::new(Vec::new());
+
+/// Set the filetype's icon on the console of a process.
+///
+/// The icon is destroyed when dropped, so a thread holds a copy of it
+/// until the console closes.
+#[cfg(feature = "gui")]
+fn keep_console_icon(proc: &OwnedProcess, icon: &crate::icon::ShellIcon) {
+    let proc = match proc.try_clone() {
+        Ok(proc) => proc,
+        Err(e) => {
+            log::debug!("Failed to duplicate process handle: {}", e);
+            return;
+        }
+    };
+    let icon = icon.clone();
+    let keeper = std::thread::spawn(move || {
+        if set_console_icon(&proc, &icon) {
+            let _ = proc.wait();
+        }
+    });
+    if let Ok(mut keepers) = ICON_KEEPERS.lock() {
+        keepers.retain(|keeper| !keeper.is_finished());
+        keepers.push(keeper);
+    }
+}
+
+/// Whether an icon set on a console is still in use.
+///
+/// Icons are destroyed when the process that loaded them exits, so the
+/// process must outlive the consoles, and the handler library must stay loaded.
+pub fn console_icons_in_use() -> bool {
+    #[cfg(feature = "gui")]
+    return ICON_KEEPERS
+        .lock()
+        .is_ok_and(|keepers| keepers.iter().any(|keeper| !keeper.is_finished()));
+    #[cfg(not(feature = "gui"))]
+    false
+}
+
+/// Wait until the consoles showing a filetype's icon have closed.
+pub fn wait_console_icons() {
+    #[cfg(feature = "gui")]
+    {
+        let keepers = match ICON_KEEPERS.lock() {
+            Ok(mut keepers) => std::mem::take(&mut *keepers),
+            Err(_) => return,
+        };
+        for keeper in keepers {
+            let _ = keeper.join();
+        }
+    }
+}
+
+/// Appearance of the console window for the WSL process.
+struct ConsoleStartup<'a> {
+    /// Window title.
+    title: &'a OsStr,
+    /// Window position in screen coordinates.
+    position: Option<(i32, i32)>,
+    /// Screen buffer size.
+    size: Option<ConsoleSize>,
+    /// Text and background colors.
+    colors: Option<ConsoleColors>,
+    /// Whether to use UTF-8 code page and a TrueType font.
+    utf8: bool,
+}
+
+/// Process handle that is closed on drop.
+struct OwnedProcess(Foundation::HANDLE);
+
+impl OwnedProcess {
+    /// Duplicate the handle, eg. to wait for the process on another thread.
+    #[cfg(feature = "gui")]
+    fn try_clone(&self) -> windows::core::Result<Self> {
+        let mut handle = Foundation::HANDLE::default();
+        unsafe {
+            let current = Threading::GetCurrentProcess();
+            Foundation::DuplicateHandle(
+                current,
+                self.0,
+                current,
+                &mut handle,
+                0,
+                false,
+                Foundation::DUPLICATE_SAME_ACCESS,
+            )?;
+        }
+        Ok(Self(handle))
+    }
+
+    fn wait(&self) -> std::io::Result<()> {
+        if unsafe { Threading::WaitForSingleObject(self.0, Threading::INFINITE) }
+            == Foundation::WAIT_FAILED
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OwnedProcess {
+    fn drop(&mut self) {
+        let _ = unsafe { Foundation::CloseHandle(self.0) };
+    }
+}
+
+/// Start command in a new console window.
+///
+/// `std::process::Command` can't pass console settings to the new process,
+/// so the process is created directly with the same command line.
+/// Process is started in a new process group so we can safely exit this
+/// program and have the script execute on it's own.
+fn spawn_in_console(
+    cmd: &process::Command,
+    console: &ConsoleStartup,
+) -> std::io::Result<OwnedProcess> {
+    let mut cmdline = command_line(cmd).into_vec_with_nul();
+    let mut title = WideCString::from_os_str_truncate(console.title).into_vec_with_nul();
+    let mut si = Threading::STARTUPINFOW {
+        cb: std::mem::size_of::<Threading::STARTUPINFOW>() as u32,
+        lpTitle: windows::core::PWSTR(title.as_mut_ptr()),
+        ..Default::default()
+    };
+    if let Some((x, y)) = console.position {
+        si.dwFlags |= Threading::STARTF_USEPOSITION;
+        si.dwX = x as u32;
+        si.dwY = y as u32;
+    }
+    if let Some(size) = console.size {
+        si.dwFlags |= Threading::STARTF_USECOUNTCHARS;
+        si.dwXCountChars = size.columns as u32;
+        si.dwYCountChars = size.rows as u32;
+    }
+    if let Some(colors) = console.colors {
+        si.dwFlags |= Threading::STARTF_USEFILLATTRIBUTE;
+        si.dwFillAttribute = colors.0 as u32;
+    }
+    let mut flags = Threading::CREATE_NEW_CONSOLE | Threading::CREATE_NEW_PROCESS_GROUP;
+    // console is configured before the process starts writing to it
+    if console.utf8 {
+        flags |= Threading::CREATE_SUSPENDED;
+    }
+    let env = environment_block(cmd);
+    if env.is_some() {
+        flags |= Threading::CREATE_UNICODE_ENVIRONMENT;
+    }
+    let mut pi = Threading::PROCESS_INFORMATION::default();
+    unsafe {
+        Threading::CreateProcessW(
+            PCWSTR::null(),
+            windows::core::PWSTR(cmdline.as_mut_ptr()),
+            None,
+            None,
+            false,
+            flags,
+            env.as_ref().map(|block| block.as_ptr() as *const _),
+            PCWSTR::null(),
+            &si,
+            &mut pi,
+        )
+    }
+    .map_err(|_| std::io::Error::last_os_error())?;
+    if console.utf8 {
+        if let Err(e) = configure_utf8_console(pi.dwProcessId) {
+            log::debug!("Failed to configure UTF-8 console: {}", e);
+        }
+        unsafe { Threading::ResumeThread(pi.hThread) };
+    }
+    let _ = unsafe { Foundation::CloseHandle(pi.hThread) };
+    Ok(OwnedProcess(pi.hProcess))
+}
+
+/// Serializes attaching to consoles, since a process can be attached to
+/// only one console at a time.
+static CONSOLE_ATTACH: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Set code page of a process's console to UTF-8, and switch from a raster
+/// font to a TrueType font that can display Unicode characters.
+fn configure_utf8_console(pid: u32) -> windows::core::Result<()> {
+    use windows::Win32::Storage::FileSystem;
+    use windows::Win32::System::Console;
+    // code page of UTF-8
+    const CP_UTF8: u32 = 65001;
+    // font family flag of TrueType fonts
+    const TMPF_TRUETYPE: u32 = 0x04;
+    let _guard = CONSOLE_ATTACH.lock();
+    unsafe { Console::AttachConsole(pid) }?;
+    let result = (|| unsafe {
+        Console::SetConsoleCP(CP_UTF8)?;
+        Console::SetConsoleOutputCP(CP_UTF8)?;
+        let conout = FileSystem::CreateFileW(
+            windows::core::w!("CONOUT$"),
+            (Foundation::GENERIC_READ | Foundation::GENERIC_WRITE).0,
+            FileSystem::FILE_SHARE_READ | FileSystem::FILE_SHARE_WRITE,
+            None,
+            FileSystem::OPEN_EXISTING,
+            FileSystem::FILE_FLAGS_AND_ATTRIBUTES::default(),
+            None,
+        )?;
+        let mut font = Console::CONSOLE_FONT_INFOEX {
+            cbSize: std::mem::size_of::<Console::CONSOLE_FONT_INFOEX>() as u32,
+            ..Default::default()
+        };
+        let result = Console::GetCurrentConsoleFontEx(conout, false, &mut font);
+        // keep TrueType fonts chosen by the user
+        let result = match result {
+            Ok(()) if font.FontFamily & TMPF_TRUETYPE == 0 => {
+                let face = wch!("Consolas");
+                font.FaceName = [0; 32];
+                font.FaceName[..face.len()].copy_from_slice(face);
+                font.FontFamily = TMPF_TRUETYPE;
+                font.nFont = 0;
+                font.dwFontSize.X = 0;
+                Console::SetCurrentConsoleFontEx(conout, false, &font)
+            }
+            result => result,
+        };
+        let _ = Foundation::CloseHandle(conout);
+        result
+    })();
+    let _ = unsafe { Console::FreeConsole() };
+    result
+}
+
+/// Get environment block of a command for `CreateProcessW`.
+///
+/// Environment of this process with the variables set on the command applied.
+/// Returns `None` if the command doesn't modify the environment.
+fn environment_block(cmd: &process::Command) -> Option<Vec<u16>> {
+    if cmd.get_envs().len() == 0 {
+        return None;
+    }
+    // variable names are case insensitive on Windows
+    let key = |name: &OsStr| name.to_string_lossy().to_uppercase();
+    let mut vars = env::vars_os().collect::<Vec<_>>();
+    for (name, value) in cmd.get_envs() {
+        vars.retain(|(n, _)| key(n) != key(name));
+        if let Some(value) = value {
+            vars.push((name.to_owned(), value.to_owned()));
+        }
+    }
+    vars.sort_by_cached_key(|(name, _)| key(name));
+    let mut block: Vec<u16> = Vec::new();
+    for (name, value) in vars {
+        block.extend(name.encode_wide());
+        block.push('=' as u16);
+        block.extend(value.encode_wide());
+        block.push(0);
+    }
+    block.push(0);
+    Some(block)
+}
+
+/// Get command line of a command quoted the same way as
+/// `std::process::Command` does.
+fn command_line(cmd: &process::Command) -> WideCString {
+    let mut cmdline: Vec<u16> = Vec::new();
+    append_quoted_arg(&mut cmdline, cmd.get_program(), true);
+    for arg in cmd.get_args() {
+        cmdline.push(' ' as u16);
+        append_quoted_arg(&mut cmdline, arg, false);
+    }
+    WideCString::from_vec_truncate(cmdline)
+}
+
+/// Get command line to run a script without arguments and without a terminal window.
+///
+/// Used to run the script in a pseudo console, so it never waits for
+/// a keypress after the script exits.
+/// `win_script_path` and `script_path` are as in `run_wsl`.
+pub fn test_run_command_line(
+    win_script_path: &Path,
+    script_path: &Path,
+    opts: &WSLOptions,
+) -> Result<WideCString, Error> {
+    validate_options(opts)?;
+    let opts = WSLOptions {
+        hold_mode: HoldMode::Never,
+        ..opts.clone()
+    };
+    let crlf = has_crlf_line_endings(win_script_path);
+    let shell = distro_shell(&opts);
+    let inv = Invocation {
+        shell,
+        helper: helper_ready(&opts),
+        status_file: None,
+    };
+    let bash_cmd = compose_bash_command(script_path, &[], &opts, &inv, crlf, false, true)?;
+    let mut cmd = process::Command::new(wsl_bin_path()?);
+    if let Some(distro) = &opts.distribution {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    if let Some(dir) = &bash_cmd.cd {
+        cmd.args([OsStr::new("--cd"), dir]);
+    }
+    let flags: &[&str] = if opts.interactive { &["-i"] } else { &[] };
+    push_shell_command(&mut cmd, shell.name(), flags, &bash_cmd.cmd.to_os_string());
+    Ok(command_line(&cmd))
 }
 
 struct BashCmdResult {
     /// Command line for bash.
     cmd: WideString,
+    /// Directory to start the shell in with `wsl.exe --cd`, if the command
+    /// doesn't change to the script's directory itself.
+    cd: Option<OsString>,
     /// Path to temporary file containing the script arguments.
     tmpfile: Option<PathBuf>,
 }
 
-/// Build bash command to execute script with given arguments.
+/// How the script is invoked in the distribution.
+struct Invocation<'a> {
+    /// Shell of the distribution, see `distro_shell`.
+    shell: Shell,
+    /// Whether the helper script is installed, see `helper_ready`.
+    helper: bool,
+    /// WSL path of the file the exit code and run time are written to.
+    status_file: Option<&'a Path>,
+}
+
+/// Exit code and run time of a script that was waited for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunStatus {
+    /// Exit code of the script.
+    pub exit_code: i32,
+    /// How long the script ran.
+    pub duration: std::time::Duration,
+}
+
+/// Temporary file where the command writes the run status on exit.
+struct StatusFile {
+    /// Windows path of the file.
+    path: PathBuf,
+    /// Path of the file in WSL.
+    wsl_path: PathBuf,
+}
+
+impl StatusFile {
+    /// Create an empty status file.
+    fn create(opts: &WSLOptions) -> Result<Self, Error> {
+        let path = create_temp_file("st")?;
+        match path_to_wsl(&path, opts) {
+            Ok(wsl_path) => Ok(Self { path, wsl_path }),
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                Err(e)
+            }
+        }
+    }
+
+    /// Read the run status and remove the file.
+    ///
+    /// Returns `None` if the command didn't write the status, eg. when the
+    /// console was closed while the script was running.
+    fn read(self) -> Option<RunStatus> {
+        let status = read_run_status(&self.path);
+        if std::fs::remove_file(&self.path).is_err() {
+            log::debug!("Failed to remove status file");
+        }
+        status
+    }
+}
+
+/// Read run status written by the command epilogue.
 ///
-/// If arguments are too long to fit on a command line, write them to temporary
-/// file and fetch on WSL side using bash's `mapfile` builtin.
-fn compose_bash_command(
-    script_path: &Path,
-    args: &[PathBuf],
-    opts: &WSLOptions,
-    force_args_in_file: bool,
-) -> Result<BashCmdResult, Error> {
-    let script_dir = script_path
-        .parent()
-        .ok_or(Error::InvalidPathError)?
-        .as_os_str();
-    let script_file = script_path.file_name().ok_or(Error::InvalidPathError)?;
-    // command line to invoke in WSL
-    let mut cmd = WideString::new();
-    let tmpfile = if force_args_in_file ||
-        // heuristic test whether argument list is too long to be passed on command line
-        args.iter().fold(0, |acc, s| acc + s.as_os_str().len()) > (MAX_CMD_LEN / 2)
-    {
-        let argfile = write_args_to_temp_file(args)?;
-        let path = path_to_wsl(&argfile, opts)?;
-        // read arguments from temporary file into $args variable
-        cmd.push_slice(wch!("mapfile -d '' -t args < '"));
-        cmd.push_os_str(single_quote_escape(path.as_os_str()));
-        cmd.push_slice(wch!("' && "));
+/// File contains the exit code and the duration in seconds.
+fn read_run_status(path: &Path) -> Option<RunStatus> {
+    let s = std::fs::read_to_string(path).ok()?;
+    let mut parts = s.split_whitespace();
+    let exit_code = parts.next()?.parse().ok()?;
+    let secs = parts.next()?.parse().ok()?;
+    Some(RunStatus {
+        exit_code,
+        duration: std::time::Duration::from_secs(secs),
+    })
+}
+
+/// Check that values loaded from the registry are safe to insert into
+/// the command line.
+///
+/// The command is run via `cmd.exe`, which interprets double quotes,
+/// `%` variable references and line breaks even within a quoted argument,
+/// so a tampered value could otherwise run arbitrary commands.
+/// Mount root of the distribution is checked too, since it's inserted into
+/// the commands that mount removable drives.
+fn validate_options(opts: &WSLOptions) -> Result<(), Error> {
+    let mount_root = registry::distro_mount_root(opts.distribution.as_deref());
+    validate_values(opts, mount_root.as_deref())
+}
+
+/// Check values of the options and the mount root set for the distribution,
+/// see `validate_options`.
+fn validate_values(opts: &WSLOptions, mount_root: Option<&str>) -> Result<(), Error> {
+    if let Some(distro) = &opts.distribution {
+        if !is_valid_distro_name(distro) {
+            log::error!("Unsafe distribution name: {}", distro.to_string_lossy());
+            return Err(Error::UnsafeValue("distribution name"));
+        }
+    }
+    if let Some(message) = &opts.hold_message {
+        if !is_cmd_safe(OsStr::new(message)) {
+            log::error!("Unsafe hold message: {}", message);
+            return Err(Error::UnsafeValue("exit message"));
+        }
+    }
+    if let Some(mode) = opts.arg_chmod.as_ref().filter(|s| !is_valid_chmod_mode(s)) {
+        log::error!("Invalid chmod mode: {}", mode);
+        return Err(Error::UnsafeValue("chmod mode"));
+    }
+    if let Some(owner) = opts.arg_chown.as_ref().filter(|s| !is_valid_chown_owner(s)) {
+        log::error!("Invalid chown owner: {}", owner);
+        return Err(Error::UnsafeValue("chown owner"));
+    }
+    // title is passed to the console, where control characters could
+    // eg. inject terminal escape sequences
+    if let Some(title) = opts
+        .console_title
+        .as_ref()
+        .filter(|s| !is_valid_console_title(s))
+    {
+        log::error!("Unsafe console title: {}", title.to_string_lossy());
+        return Err(Error::UnsafeValue("console title"));
+    }
+    if let Some((name, _)) = opts
+        .wslenv
+        .variables()
+        .find(|(name, _)| !is_valid_env_name(name))
+    {
+        log::error!("Invalid environment variable name: {}", name);
+        return Err(Error::UnsafeValue("environment variable name"));
+    }
+    if let Some(root) = mount_root.filter(|root| !is_valid_mount_root(root)) {
+        log::error!("Unsafe mount root: {}", root);
+        return Err(Error::UnsafeValue("mount root"));
+    }
+    Ok(())
+}
+
+/// Check whether a console title has no control characters.
+fn is_valid_console_title(title: &OsStr) -> bool {
+    !title
+        .encode_wide()
+        .any(|c| char::from_u32(c as u32).is_none_or(char::is_control))
+}
+
+/// Check whether a name of an environment variable shared via `WSLENV`
+/// is valid in both Windows and Linux.
+///
+/// Accepts ASCII letters, digits and underscores, not starting with a digit.
+fn is_valid_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check whether a mode is valid for `chmod`.
+///
+/// Accepts octal modes, eg. `644`, and symbolic modes, eg. `u+x,go-w`.
+pub fn is_valid_chmod_mode(mode: &str) -> bool {
+    if (1..=4).contains(&mode.len()) && mode.chars().all(|c| ('0'..='7').contains(&c)) {
+        return true;
+    }
+    !mode.is_empty()
+        && mode.split(',').all(|clause| {
+            let mut ops = clause
+                .trim_start_matches(['u', 'g', 'o', 'a'])
+                .chars()
+                .peekable();
+            if ops.peek().is_none() {
+                return false;
+            }
+            while let Some(op) = ops.next() {
+                if !matches!(op, '+' | '-' | '=') {
+                    return false;
+                }
+                while ops.next_if(|c| "rwxXstugo".contains(*c)).is_some() {}
+            }
+            true
+        })
+}
+
+/// Check whether an owner is valid for `chown`.
+///
+/// Accepts `user`, `user:group`, `user:` and `:group`, where names consist
+/// of ASCII letters, digits, periods, dashes and underscores.
+pub fn is_valid_chown_owner(owner: &str) -> bool {
+    let is_name = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with('-')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    };
+    match owner.split_once(':') {
+        Some(("", group)) => is_name(group),
+        Some((user, "")) => is_name(user),
+        Some((user, group)) => is_name(user) && is_name(group),
+        None => is_name(owner),
+    }
+}
+
+/// Check whether a distribution name is valid.
+///
+/// WSL accepts only ASCII letters, digits, periods, dashes and underscores.
+fn is_valid_distro_name(name: &OsStr) -> bool {
+    !name.is_empty()
+        && name.encode_wide().all(|c| {
+            char::from_u32(c as u32)
+                .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        })
+}
+
+/// Root under which WSL mounts Windows drives, unless changed in `wsl.conf`.
+pub const DEFAULT_MOUNT_ROOT: &str = "/mnt";
+
+/// Get the root under which Windows drives are mounted in a distribution.
+///
+/// A root set in the settings takes precedence over the probed one.
+/// `distro` is the distribution name, or `None` for the default distribution.
+pub fn mount_root(distro: Option<&OsStr>) -> String {
+    registry::distro_mount_root(distro)
+        .filter(|root| is_valid_mount_root(root))
+        .or_else(|| probe::distro_facts(distro).and_then(|facts| facts.mount_root))
+        .unwrap_or_else(|| DEFAULT_MOUNT_ROOT.to_string())
+}
+
+/// Normalize a mount root to have no trailing slash, except for `/`.
+pub fn normalize_mount_root(root: &str) -> String {
+    match root.trim_end_matches('/') {
+        "" => "/".to_string(),
+        s => s.to_string(),
+    }
+}
+
+/// Check that a mount root is an absolute path that can be passed safely
+/// to a shell script within single quotes.
+pub fn is_valid_mount_root(root: &str) -> bool {
+    root.starts_with('/') && !root.chars().any(|c| c == '\'' || c.is_control())
+}
+
+/// Get a boolean setting from the contents of `/etc/wsl.conf`.
+///
+/// Section and key names are case insensitive, as in WSL. Returns `None`
+/// if the setting isn't present or isn't a boolean.
+pub fn wsl_conf_flag(conf: &str, section: &str, key: &str) -> Option<bool> {
+    let mut in_section = false;
+    let mut flag = None;
+    for line in conf.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            in_section = name.trim().eq_ignore_ascii_case(section);
+            continue;
+        }
+        let Some((k, v)) = line.split_once('=').filter(|_| in_section) else {
+            continue;
+        };
+        if !k.trim().eq_ignore_ascii_case(key) {
+            continue;
+        }
+        // values may be followed by a comment
+        let v = v.split(['#', ';']).next().unwrap_or_default().trim();
+        // last occurrence wins
+        flag = match v.to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => flag,
+        };
+    }
+    flag
+}
+
+/// Get the mount path of a drive under a mount root, eg. `/mnt/c`.
+pub fn drive_mount_path(root: &str, letter: char) -> String {
+    format!(
+        "{}/{}",
+        root.trim_end_matches('/'),
+        letter.to_ascii_lowercase()
+    )
+}
+
+/// Check whether a string can be passed within double quotes via `cmd.exe`.
+pub fn is_cmd_safe(s: &OsStr) -> bool {
+    !s.encode_wide().any(|c| {
+        matches!(
+            char::from_u32(c as u32),
+            Some('"' | '%' | '\r' | '\n' | '\0')
+        )
+    })
+}
+
+/// Build bash command to execute script with given arguments.
+///
+/// If arguments are too long to fit on a command line, write them to temporary
+/// file and fetch on WSL side using bash's `mapfile` builtin.
+/// If the distribution lacks bash, a POSIX compatible command is composed
+/// instead, and arguments must fit on the command line.
+///
+/// `inv` tells how the script is invoked, and where its exit code is recorded.
+/// `crlf` tells whether the script has Windows line endings, in which case
+/// the script is invoked according to `opts.crlf_mode`.
+///
+/// If `wsl_cd` is set and `wsl.exe` supports it, the script's directory is
+/// left to be set with `wsl.exe --cd` instead of a `cd` command, which saves
+/// a layer of quoting.
+fn compose_bash_command(
+    script_path: &Path,
+    args: &[PathBuf],
+    opts: &WSLOptions,
+    inv: &Invocation,
+    crlf: bool,
+    force_args_in_file: bool,
+    wsl_cd: bool,
+) -> Result<BashCmdResult, Error> {
+    let shell = inv.shell;
+    let script_dir = script_path
+        .parent()
+        .ok_or(Error::InvalidPathError)?
+        .as_os_str();
+    let script_file = script_path.file_name().ok_or(Error::InvalidPathError)?;
+    // command line to invoke in WSL
+    let mut cmd = WideString::new();
+    let args_in_file = match shell {
+        Shell::Bash => {
+            force_args_in_file ||
+            // heuristic test whether argument list is too long to be passed on command line
+            args.iter().fold(0, |acc, s| acc + s.as_os_str().len()) > (MAX_CMD_LEN / 2)
+        }
+        Shell::Sh if force_args_in_file => return Err(Error::CommandTooLong),
+        Shell::Sh => false,
+    };
+    // raw arguments on the command line pass through cmd.exe unquoted
+    if opts.raw_args && !args_in_file {
+        if let Some(arg) = args.iter().find(|arg| !is_cmd_safe(arg.as_os_str())) {
+            log::error!("Unsafe raw argument: {}", arg.to_string_lossy());
+            return Err(Error::UnsafeValue("dropped file name"));
+        }
+    }
+    // nobody could dismiss the exit message without a console
+    let hold_mode = match opts.event_log {
+        EventLog::Off => opts.hold_mode,
+        EventLog::Events | EventLog::Output => HoldMode::Never,
+    };
+    // measure how long the script runs, for the exit message and run status
+    if hold_mode != HoldMode::Never || inv.status_file.is_some() {
+        match shell {
+            Shell::Bash => cmd.push_slice(wch!("SECONDS=0; ")),
+            Shell::Sh => cmd.push_slice(wch!("s0=$(date +%s); ")),
+        }
+    }
+    let tmpfile = if args_in_file {
+        let argfile = write_args_to_temp_file(args)?;
+        let path = path_to_wsl(&argfile, opts)?;
+        // read arguments from temporary file into $args variable
+        cmd.push_slice(wch!("mapfile -d '' -t args < '"));
+        cmd.push_os_str(single_quote_escape(path.as_os_str()));
+        cmd.push_slice(wch!("' && "));
         Some(argfile)
     } else {
         None
     };
+    // set permissions of the dropped files, without preventing the run
+    // if it fails, eg. on drives mounted without metadata
+    // files are always quoted, since raw arguments are meant for the script
+    if !args.is_empty() && (opts.arg_chmod.is_some() || opts.arg_chown.is_some()) {
+        cmd.push_slice(wch!("{ "));
+        for (command, value) in [("chmod", &opts.arg_chmod), ("chown", &opts.arg_chown)] {
+            if let Some(value) = value {
+                cmd.push_str(command);
+                cmd.push_slice(wch!(" -- '"));
+                cmd.push_os_str(single_quote_escape(OsStr::new(value)));
+                cmd.push_slice(wch!("'"));
+                push_args(&mut cmd, args, args_in_file, false);
+                cmd.push_slice(wch!("; "));
+            }
+        }
+        cmd.push_slice(wch!("true; } && "));
+    }
+    // wsl.exe --cd takes Linux paths only if they're absolute
+    let cd = Some(script_dir)
+        .filter(|dir| wsl_cd && dir.encode_wide().next() == Some(b'/' as u16))
+        .filter(|dir| is_cmd_safe(dir) && wsl_supports("--cd"))
+        .map(OsStr::to_owned);
     // cd 'dir' && './progname'
-    cmd.push_slice(wch!("cd '"));
-    cmd.push_os_str(single_quote_escape(script_dir));
-    cmd.push_slice(wch!("' && './"));
+    if cd.is_none() {
+        cmd.push_slice(wch!("cd '"));
+        cmd.push_os_str(single_quote_escape(script_dir));
+        cmd.push_slice(wch!("' && "));
+    }
+    if crlf && opts.crlf_mode == CrlfMode::Warn {
+        cmd.push_slice(wch!(
+            r"printf >&2 'Warning: script has Windows (CRLF) line endings.\n' && "
+        ));
+    }
+    // ~/.local/share/wslscript/helper.sh run './progname'
+    if inv.helper {
+        cmd.push_str(HELPER_PATH);
+        cmd.push_slice(wch!(" run "));
+    }
+    match (crlf, opts.crlf_mode) {
+        (false, _) | (true, CrlfMode::Warn) => {}
+        // bash './progname'
+        (true, CrlfMode::Bash) => {
+            cmd.push_str(shell.name());
+            cmd.push_slice(wch!(" "));
+        }
+        // bash <(sed 's/\r$//' './progname')
+        (true, CrlfMode::Fix) if shell == Shell::Bash => {
+            cmd.push_slice(wch!(r"bash <(sed 's/\r$//' "))
+        }
+        // sed 's/\r$//' './progname' | sh -s
+        (true, CrlfMode::Fix) => cmd.push_slice(wch!(r"sed 's/\r$//' ")),
+    }
+    cmd.push_slice(wch!("'./"));
     cmd.push_os_str(single_quote_escape(script_file));
     cmd.push_slice(wch!("'"));
+    if crlf && opts.crlf_mode == CrlfMode::Fix {
+        match shell {
+            Shell::Bash => cmd.push_slice(wch!(")")),
+            Shell::Sh => cmd.push_slice(wch!(" | sh -s")),
+        }
+    }
+    push_args(&mut cmd, args, tmpfile.is_some(), opts.raw_args);
+    // write exit code and duration to the status file,
+    // preserving the exit code for the commands that follow
+    if let Some(path) = inv.status_file {
+        if inv.helper {
+            cmd.push_slice(wch!("; "));
+            cmd.push_str(HELPER_PATH);
+            cmd.push_slice(wch!(" status $? $SECONDS '"));
+            cmd.push_os_str(single_quote_escape(path.as_os_str()));
+            cmd.push_slice(wch!("'"));
+        } else {
+            cmd.push_slice(wch!("; rc=$?; echo $rc "));
+            cmd.push_str(shell.elapsed());
+            cmd.push_slice(wch!(" > '"));
+            cmd.push_os_str(single_quote_escape(path.as_os_str()));
+            cmd.push_slice(wch!("'; (exit $rc)"));
+        }
+    }
+    // commands after script exits
+    match hold_mode {
+        HoldMode::Never => {}
+        HoldMode::Always | HoldMode::Error | HoldMode::Timeout(_) => {
+            if hold_mode != HoldMode::Error {
+                cmd.push_slice(wch!(";"));
+            } else {
+                cmd.push_slice(wch!(" ||"))
+            }
+            cmd.push(hold_postlude(script_file, opts, shell));
+        }
+    }
+    Ok(BashCmdResult { cmd, cd, tmpfile })
+}
+
+/// Append arguments to a command, each preceded by a space.
+///
+/// If `in_file` is set, arguments are referenced from the `$args` variable
+/// read from the temporary file. With `raw` set, arguments are left to be
+/// word split by the shell.
+fn push_args(cmd: &mut WideString, args: &[PathBuf], in_file: bool, raw: bool) {
     // if arguments are being passed via temporary file
-    if tmpfile.is_some() {
-        cmd.push_slice(wch!(" \"${args[@]}\""));
+    if in_file {
+        if raw {
+            // let bash word split the arguments
+            cmd.push_slice(wch!(" ${args[@]}"));
+        } else {
+            cmd.push_slice(wch!(" \"${args[@]}\""));
+        }
+    }
+    // insert arguments to command line as is
+    else if raw {
+        for arg in args {
+            cmd.push_slice(wch!(" "));
+            cmd.push_os_str(arg.as_os_str());
+        }
     }
     // insert arguments to command line
     else {
@@ -130,161 +1446,1111 @@ fn compose_bash_command(
             cmd.push_slice(wch!("'"));
         }
     }
-    // commands after script exits
-    match opts.hold_mode {
-        HoldMode::Never => {}
-        HoldMode::Always | HoldMode::Error => {
-            if opts.hold_mode == HoldMode::Always {
-                cmd.push_slice(wch!(";"));
-            } else {
-                cmd.push_slice(wch!(" ||"))
-            }
-            cmd.push_os_str(OsString::from_wide(wch!(
-                r#" { printf >&2 '\n[Process exited - exit code %d] ' "$?"; read -n 1 -s; }"#
-            )));
+}
+
+/// Build commands that display the exit message and wait for a key press.
+///
+/// `{code}` in the message template is replaced with the exit code.
+/// If the script failed, a red banner with the script name and
+/// the elapsed time is displayed before the message.
+///
+/// POSIX shell can only wait for Enter, or sleep for the timeout.
+fn hold_postlude(script_file: &OsStr, opts: &WSLOptions, shell: Shell) -> WideString {
+    let template = opts.hold_message.as_deref().unwrap_or(DEFAULT_HOLD_MESSAGE);
+    let mut cmd = WideString::new();
+    cmd.push_slice(wch!(" { rc=$?; t="));
+    cmd.push_str(shell.elapsed());
+    cmd.push_slice(wch!("; "));
+    cmd.push_slice(wch!(
+        r"[ $rc -eq 0 ] || printf >&2 '\n\033[1;31m%s failed after %ds\033[0m' '"
+    ));
+    cmd.push_os_str(single_quote_escape(script_file));
+    cmd.push_slice(wch!("' $t; "));
+    // message is passed as an argument, so it's not interpreted by printf
+    cmd.push_slice(wch!(r"printf >&2 '\n%s ' '"));
+    for (i, part) in template.split("{code}").enumerate() {
+        if i > 0 {
+            // exit code is numeric, so it needs no quoting
+            cmd.push_slice(wch!("'$rc'"));
+        }
+        cmd.push_os_str(single_quote_escape(OsStr::new(part)));
+    }
+    cmd.push_slice(wch!("'; "));
+    match (opts.hold_mode, opts.hold_key) {
+        (HoldMode::Timeout(secs), _) | (_, HoldKey::Timeout(secs)) if shell == Shell::Sh => {
+            cmd.push_str(format!("sleep {}", secs))
+        }
+        _ if shell == Shell::Sh => cmd.push_slice(wch!("read -r _")),
+        (HoldMode::Timeout(secs), _) | (_, HoldKey::Timeout(secs)) => {
+            cmd.push_str(format!("read -n 1 -s -t {}", secs))
+        }
+        (_, HoldKey::Any) => cmd.push_slice(wch!("read -n 1 -s")),
+        (_, HoldKey::Enter) => cmd.push_slice(wch!("read -r -s")),
+    }
+    cmd.push_slice(wch!("; }"));
+    cmd
+}
+
+/// Sort Windows paths into given order.
+///
+/// Names and paths are compared case-insensitively. Files whose
+/// modification time can't be read are sorted last.
+pub fn sort_paths(paths: &mut [PathBuf], order: ArgOrder) {
+    match order {
+        ArgOrder::None => {}
+        ArgOrder::Name => paths.sort_by_cached_key(|p| {
+            (
+                p.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_lowercase(),
+                p.to_string_lossy().to_lowercase(),
+            )
+        }),
+        ArgOrder::Path => paths.sort_by_cached_key(|p| p.to_string_lossy().to_lowercase()),
+        ArgOrder::Modified => paths.sort_by_cached_key(|p| {
+            let modified = std::fs::metadata(p).and_then(|m| m.modified()).ok();
+            (modified.is_none(), modified)
+        }),
+    }
+}
+
+/// Maximum number of bytes of the first line inspected for line endings.
+const MAX_FIRST_LINE_LEN: u64 = 4096;
+
+/// Check whether the script file has Windows (CRLF) line endings.
+///
+/// Only the first line is inspected, since a carriage return in the shebang
+/// is what prevents the script from starting.
+pub fn has_crlf_line_endings(path: &Path) -> bool {
+    std::fs::File::open(path).is_ok_and(|f| first_line_has_crlf(std::io::BufReader::new(f)))
+}
+
+/// Check whether the first line read from `reader` ends with CRLF.
+fn first_line_has_crlf(reader: impl std::io::BufRead) -> bool {
+    use std::io::prelude::*;
+    let mut line = Vec::new();
+    reader
+        .take(MAX_FIRST_LINE_LEN)
+        .read_until(b'\n', &mut line)
+        .is_ok()
+        && line.ends_with(b"\r\n")
+}
+
+/// Write arguments to temporary file as a nul separated list.
+fn write_args_to_temp_file(args: &[PathBuf]) -> Result<PathBuf, Error> {
+    use std::io::prelude::*;
+    let temp = create_temp_file("arg")?;
+    let paths: Result<Vec<_>, _> = args
+        .iter()
+        .map(|p| p.to_str().ok_or_else(|| Error::StringToPathUTF8Error))
+        .collect();
+    let s = match paths {
+        Err(e) => return Err(e),
+        Ok(p) => p.join("\0"),
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(&temp)?;
+    file.write_all(s.as_bytes())?;
+    log::debug!("Args written to: {}", temp.to_string_lossy());
+    Ok(temp)
+}
+
+/// Create a temporary file whose name starts with `prefix`.
+///
+/// Returned path is an empty file in wslscript's temp file directory,
+/// accessible only by its owner. The file is registered to the manifest,
+/// so it's removed by `sweep_temp_files` if wslscript fails to remove it.
+fn create_temp_file(prefix: &str) -> Result<PathBuf, Error> {
+    use windows::Win32::Storage::FileSystem as fs;
+    let temp_dir = WideCString::from_os_str_truncate(temp_dir()?);
+    let mut name = [0u16; MAX_PATH];
+    let uniq = unsafe {
+        fs::GetTempFileNameW(
+            PCWSTR(temp_dir.as_ptr()),
+            PCWSTR(wcstring(prefix).as_ptr()),
+            0,
+            &mut name,
+        )
+    };
+    if uniq == 0 {
+        return Err(last_error());
+    }
+    let temp_path = unsafe { WideCString::from_ptr_truncate(name.as_ptr(), name.len()) };
+    log::debug!("Temp path {}", temp_path.to_string_lossy());
+    if let Err(e) = restrict_to_owner(&temp_path) {
+        log::error!("Failed to restrict access to temporary file: {}", e);
+    }
+    let path = PathBuf::from(temp_path.to_os_string());
+    if let Err(e) = register_temp_file(&path) {
+        log::debug!("Failed to register temporary file: {}", e);
+    }
+    Ok(path)
+}
+
+/// Name of the file listing temporary files created by wslscript.
+const TEMP_MANIFEST: &str = "manifest.txt";
+
+/// Age after which a leftover temporary file is removed.
+const TEMP_FILE_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Get wslscript's directory for temporary files, creating it if necessary.
+fn temp_dir() -> Result<PathBuf, Error> {
+    let dir = env::temp_dir().join("wslscript");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Allow access to a file only by its owner.
+fn restrict_to_owner(path: &WideCStr) -> windows::core::Result<()> {
+    use windows::Win32::Security::{self, Authorization};
+    let mut sd = Security::PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        // protected DACL granting full access to the owner only
+        Authorization::ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            windows::core::w!("D:P(A;;FA;;;OW)"),
+            Authorization::SDDL_REVISION_1,
+            &mut sd,
+            None,
+        )?;
+        let result = Security::SetFileSecurityW(
+            PCWSTR(path.as_ptr()),
+            Security::DACL_SECURITY_INFORMATION | Security::PROTECTED_DACL_SECURITY_INFORMATION,
+            sd,
+        )
+        .ok();
+        Foundation::LocalFree(Foundation::HLOCAL(sd.0));
+        result
+    }
+}
+
+/// Open the manifest for exclusive access, waiting while another instance
+/// of wslscript holds it.
+fn open_temp_manifest() -> Result<std::fs::File, Error> {
+    use std::os::windows::fs::OpenOptionsExt;
+    let path = temp_dir()?.join(TEMP_MANIFEST);
+    let mut tries = 0;
+    loop {
+        let result = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .share_mode(0)
+            .open(&path);
+        match result {
+            Err(e)
+                if e.raw_os_error() == Some(Foundation::ERROR_SHARING_VIOLATION.0 as i32)
+                    && tries < 50 =>
+            {
+                tries += 1;
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// Add a temporary file to the manifest.
+fn register_temp_file(path: &Path) -> Result<(), Error> {
+    use std::io::prelude::*;
+    let mut manifest = open_temp_manifest()?;
+    manifest.seek(std::io::SeekFrom::End(0))?;
+    writeln!(manifest, "{}", path.to_string_lossy())?;
+    Ok(())
+}
+
+/// Remove temporary files left behind, eg. if wslscript crashed while
+/// the script was running.
+///
+/// Files listed in the manifest are removed once they're older than
+/// `TEMP_FILE_MAX_AGE`.
+pub fn sweep_temp_files() {
+    use std::io::prelude::*;
+    // manifest stays locked until rewritten, so that files registered
+    // meanwhile by other instances aren't lost
+    let Ok(mut manifest) = open_temp_manifest() else {
+        return;
+    };
+    let mut list = String::new();
+    if manifest.read_to_string(&mut list).is_err() {
+        return;
+    }
+    let mut keep = Vec::new();
+    for line in list.lines().filter(|l| !l.is_empty()) {
+        let path = Path::new(line);
+        let age = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok());
+        match age {
+            // removed by the run that created it
+            None => {}
+            Some(age) if age > TEMP_FILE_MAX_AGE => {
+                log::debug!("Removing stale temporary file {}", line);
+                let _ = std::fs::remove_file(path);
+            }
+            Some(_) => keep.push(line),
+        }
+    }
+    let mut list = keep.join("\n");
+    if !list.is_empty() {
+        list.push('\n');
+    }
+    let result = manifest
+        .set_len(0)
+        .and_then(|_| manifest.rewind())
+        .and_then(|_| manifest.write_all(list.as_bytes()));
+    if let Err(e) = result {
+        log::debug!("Failed to update temporary file manifest: {}", e);
+    }
+}
+
+/// Escape single quotes in an OsString.
+fn single_quote_escape(s: &OsStr) -> OsString {
+    let mut w: Vec<u16> = vec![];
+    for c in s.encode_wide() {
+        // escape ' to '\''
+        if c == '\'' as u16 {
+            w.extend_from_slice(wch!(r"'\''"));
+        } else {
+            w.push(c);
+        }
+    }
+    OsString::from_wide(&w)
+}
+
+/// Convert single Windows path to WSL equivalent.
+fn path_to_wsl(path: &Path, opts: &WSLOptions) -> Result<PathBuf, Error> {
+    let mut paths = paths_to_wsl(&[path.to_owned()], opts, None)?;
+    let p = paths.pop().ok_or_else(|| Error::WinToUnixPathError)?;
+    Ok(p)
+}
+
+/// Path conversion progress callback.
+///
+/// Callback must return true to continue processing.
+/// Conversion may be cancelled by returning false.
+pub type PathProgressCallback = Box<dyn Fn(usize) -> bool + 'static>;
+
+/// Convert Windows paths to WSL equivalents.
+///
+/// All paths are converted on a single WSL invocation, where they're read
+/// from stdin and converted one by one. Converted paths are returned in the
+/// same order as given.
+///
+/// Optional progress callback function shall be called with a number of
+/// paths converted so far.
+pub fn paths_to_wsl(
+    paths: &[PathBuf],
+    opts: &WSLOptions,
+    progress_callback: Option<PathProgressCallback>,
+) -> Result<Vec<PathBuf>, Error> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    validate_options(opts)?;
+    check_drives(paths)?;
+    let removable = removable_drives(paths);
+    with_wsl_retry(opts, || {
+        for drive in &removable {
+            mount_removable_drive(*drive, opts)?;
+        }
+        convert_paths(paths, opts, progress_callback.as_ref())
+    })
+}
+
+/// Convert Windows paths to WSL equivalents on a single WSL invocation.
+fn convert_paths(
+    paths: &[PathBuf],
+    opts: &WSLOptions,
+    progress_callback: Option<&PathProgressCallback>,
+) -> Result<Vec<PathBuf>, Error> {
+    use std::io::{BufRead, BufReader, Write};
+    // null terminated paths for the conversion script
+    let mut input: Vec<u8> = Vec::new();
+    for path in paths {
+        input.extend_from_slice(
+            path.to_str()
+                .ok_or(Error::StringToPathUTF8Error)?
+                .as_bytes(),
+        );
+        input.push(0);
+    }
+    let mut cmd = process::Command::new(wsl_bin_path()?);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    if let Some(distro) = &opts.distribution {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    let shell = distro_shell(opts);
+    let script = match (shell, helper_ready(opts)) {
+        (Shell::Bash, true) => format!("{} convert", HELPER_PATH),
+        (Shell::Bash, false) => PATH_CONVERT_SCRIPT.to_string(),
+        (Shell::Sh, _) => PATH_CONVERT_SCRIPT_SH.to_string(),
+    };
+    push_shell_command(&mut cmd, shell.name(), &[], OsStr::new(&script));
+    cmd.stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped());
+    let mut child = cmd.spawn().context(Error::WinToUnixPathError)?;
+    let mut stdin = child.stdin.take().ok_or(Error::WinToUnixPathError)?;
+    let stdout = child.stdout.take().ok_or(Error::WinToUnixPathError)?;
+    // write paths in a separate thread, so that the output pipe doesn't fill up
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let errors = capture_stderr(&mut child);
+    let mut reader = BufReader::new(stdout);
+    let mut wsl_paths: Vec<PathBuf> = Vec::with_capacity(paths.len());
+    let mut buf = Vec::new();
+    while reader
+        .read_until(b'\0', &mut buf)
+        .context(Error::WinToUnixPathError)?
+        > 0
+    {
+        if buf.last() == Some(&0) {
+            buf.pop();
+        }
+        let path =
+            String::from_utf8(std::mem::take(&mut buf)).context(Error::StringToPathUTF8Error)?;
+        wsl_paths.push(PathBuf::from(path));
+        let count = wsl_paths.len();
+        if let Some(cb) = progress_callback {
+            if (count.is_multiple_of(PATHS_PER_PROGRESS_UPDATE) || count == paths.len())
+                && !cb(count)
+            {
+                log::debug!("Progress callback returned false, cancelling");
+                let _ = child.kill();
+                let _ = child.wait();
+                let _ = writer.join();
+                let _ = join_stderr(errors);
+                return Err(Error::Cancel);
+            }
+        }
+    }
+    let _ = writer.join();
+    let status = child.wait().context(Error::WinToUnixPathError)?;
+    if !status.success() || wsl_paths.len() != paths.len() {
+        // errors of wsl.exe itself tell whether WSL needs an update
+        let stderr = join_stderr(errors);
+        let e = wsl_setup_error(&stderr).unwrap_or(Error::WinToUnixPathError);
+        return Err(with_stderr(e, stderr));
+    }
+    // wslpath prints nothing for a path it can't convert
+    if let Some(i) = wsl_paths.iter().position(|p| p.as_os_str().is_empty()) {
+        log::error!("Failed to convert {} to WSL path", paths[i].display());
+        return Err(Error::WinToUnixPathInputError(
+            paths[i].to_string_lossy().into_owned(),
+        ));
+    }
+    log::debug!("Converted {} Windows paths to WSL", wsl_paths.len());
+    Ok(wsl_paths)
+}
+
+/// Path conversion strategies for benchmarks.
+///
+/// Only compiled with the `bench` feature, see `benches/path_conversion.rs`.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+
+    /// Convert paths with a `wslpath` invocation per path.
+    pub fn convert_with_wslpath(
+        paths: &[PathBuf],
+        opts: &WSLOptions,
+    ) -> Result<Vec<PathBuf>, Error> {
+        paths
+            .iter()
+            .map(|path| {
+                let mut cmd = process::Command::new(wsl_bin_path()?);
+                cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+                if let Some(distro) = &opts.distribution {
+                    cmd.args([OsStr::new("-d"), distro]);
+                }
+                let output = cmd
+                    .args([OsStr::new("-e"), OsStr::new("wslpath"), OsStr::new("-u")])
+                    .arg(path)
+                    .stdin(process::Stdio::null())
+                    .stderr(process::Stdio::null())
+                    .output()
+                    .context(Error::WinToUnixPathError)?;
+                if !output.status.success() {
+                    return Err(Error::WinToUnixPathError);
+                }
+                let s = String::from_utf8(output.stdout).context(Error::StringToPathUTF8Error)?;
+                Ok(PathBuf::from(s.trim_end_matches('\n')))
+            })
+            .collect()
+    }
+
+    /// Convert paths on a single WSL invocation, streaming them through
+    /// the helper script or the inline conversion script.
+    pub fn convert_streaming(
+        paths: &[PathBuf],
+        opts: &WSLOptions,
+        use_helper: bool,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut opts = opts.clone();
+        opts.use_helper = use_helper;
+        convert_paths(paths, &opts, None)
+    }
+
+    /// Convert drive paths natively under the distribution's mount root.
+    ///
+    /// Serves as a lower bound, other paths are not supported.
+    pub fn convert_native(paths: &[PathBuf], opts: &WSLOptions) -> Result<Vec<PathBuf>, Error> {
+        use std::path::{Component, Prefix};
+        let root = mount_root(opts.distribution.as_deref());
+        paths
+            .iter()
+            .map(|path| {
+                let mut components = path.components();
+                let letter = match components.next() {
+                    Some(Component::Prefix(prefix)) => match prefix.kind() {
+                        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => letter,
+                        _ => return Err(Error::WinToUnixPathError),
+                    },
+                    _ => return Err(Error::WinToUnixPathError),
+                };
+                let mut s = drive_mount_path(&root, char::from(letter));
+                for component in components {
+                    if let Component::Normal(part) = component {
+                        s.push('/');
+                        s.push_str(part.to_str().ok_or(Error::StringToPathUTF8Error)?);
+                    }
+                }
+                Ok(PathBuf::from(s))
+            })
+            .collect()
+    }
+}
+
+/// Get letters of the drives that paths are on.
+fn path_drives(paths: &[PathBuf]) -> Vec<char> {
+    use std::path::{Component, Prefix};
+    let mut drives: Vec<char> = Vec::new();
+    for path in paths {
+        let letter = match path.components().next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => letter,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let letter = char::from(letter).to_ascii_uppercase();
+        if !drives.contains(&letter) {
+            drives.push(letter);
+        }
+    }
+    drives
+}
+
+/// Set callback to notify while waiting for WSL to start, or None to clear it.
+pub fn set_wait_notifier(notifier: Option<WaitNotifier>) {
+    if let Ok(mut n) = WAIT_NOTIFIER.lock() {
+        *n = notifier;
+    }
+}
+
+/// Notify the callback set with `set_wait_notifier`, if any.
+fn notify_wait(waiting: bool) {
+    if let Some(notifier) = WAIT_NOTIFIER.lock().ok().and_then(|n| *n) {
+        notifier(waiting);
+    }
+}
+
+/// Run a WSL invocation, retrying once WSL has started if it failed
+/// because WSL wasn't responding.
+///
+/// Checks are made with increasing delays given by `WSL_START_BACKOFF`.
+/// Failures while WSL is responding are returned as is.
+fn with_wsl_retry<T>(
+    opts: &WSLOptions,
+    mut f: impl FnMut() -> Result<T, Error>,
+) -> Result<T, Error> {
+    let e = match f() {
+        Err(e)
+            if matches!(
+                e.inner(),
+                Error::WinToUnixPathError
+                    | Error::RemovableDriveError(_)
+                    | Error::WSLProcessError { .. }
+            ) =>
+        {
+            e
+        }
+        result => return result,
+    };
+    if wsl_responds(opts) {
+        return Err(e);
+    }
+    log::debug!("WSL is not responding, waiting for it to start");
+    notify_wait(true);
+    let started = WSL_START_BACKOFF.iter().any(|delay| {
+        std::thread::sleep(*delay);
+        wsl_responds(opts)
+    });
+    notify_wait(false);
+    if !started {
+        log::debug!("WSL didn't start");
+        return Err(e);
+    }
+    log::debug!("WSL started, retrying");
+    f()
+}
+
+/// Check whether WSL runs a trivial command in the distribution.
+fn wsl_responds(opts: &WSLOptions) -> bool {
+    let mut cmd = match wsl_bin_path() {
+        Ok(path) => process::Command::new(path),
+        Err(_) => return false,
+    };
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    if let Some(distro) = &opts.distribution {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    push_shell_command(&mut cmd, "sh", &[], OsStr::new("true"));
+    cmd.stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null());
+    matches!(cmd.status(), Ok(status) if status.success())
+}
+
+/// Check that the drives paths are on are accessible.
+///
+/// Returns a specific error for drives that are locked or offline,
+/// which would otherwise surface as a failed path conversion.
+pub fn check_drives(paths: &[PathBuf]) -> Result<(), Error> {
+    for letter in path_drives(paths) {
+        match drive_status(letter) {
+            DriveStatus::Locked => return Err(Error::DriveLockedError(letter)),
+            DriveStatus::NotReady => return Err(Error::DriveNotReadyError(letter)),
+            status => log::debug!("Drive {}: is {:?}", letter, status),
+        }
+    }
+    Ok(())
+}
+
+/// Get letters of the removable drives that paths are on, eg. USB sticks.
+fn removable_drives(paths: &[PathBuf]) -> Vec<char> {
+    use windows::Win32::Storage::FileSystem::GetDriveTypeW;
+    use windows::Win32::System::WindowsProgramming::DRIVE_REMOVABLE;
+    path_drives(paths)
+        .into_iter()
+        .filter(|letter| {
+            let root = wcstring(format!(r"{}:\", letter));
+            unsafe { GetDriveTypeW(PCWSTR(root.as_ptr())) == DRIVE_REMOVABLE }
+        })
+        .collect()
+}
+
+/// Mount a removable drive in WSL, unless it's already mounted.
+///
+/// WSL mounts fixed drives automatically, but drives attached later, and
+/// drives with some filesystems, eg. exFAT, may be left unmounted.
+/// The drive is mounted with drvfs under the automount root as root user.
+fn mount_removable_drive(letter: char, opts: &WSLOptions) -> Result<(), Error> {
+    log::debug!("Checking that removable drive {}: is mounted", letter);
+    // root set in the settings, otherwise detected from the C: drive
+    let root = registry::distro_mount_root(opts.distribution.as_deref())
+        .filter(|root| is_valid_mount_root(root));
+    let root = match root {
+        Some(root) => format!("r='{}'", normalize_mount_root(&root)),
+        None => format!(
+            "r=$(dirname \"$(wslpath -u 'C:\\' 2>/dev/null)\"); \
+             [ -n \"$r\" ] && [ \"$r\" != . ] || r={}",
+            DEFAULT_MOUNT_ROOT
+        ),
+    };
+    let script = format!(
+        "{root}; \
+         d=\"${{r%/}}/{lower}\"; \
+         grep -qs \" $d \" /proc/mounts || {{ mkdir -p \"$d\" && mount -t drvfs '{upper}:' \"$d\"; }}",
+        root = root,
+        lower = letter.to_ascii_lowercase(),
+        upper = letter
+    );
+    let mut cmd = process::Command::new(wsl_bin_path()?);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    if let Some(distro) = &opts.distribution {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    cmd.args(["-u", "root", "-e", "sh", "-c", &script])
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped());
+    let mut child = cmd
+        .spawn()
+        .map_err(|_| Error::RemovableDriveError(letter))?;
+    let errors = capture_stderr(&mut child);
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err(with_stderr(
+            Error::RemovableDriveError(letter),
+            join_stderr(errors),
+        )),
+    }
+}
+
+/// Check whether the helper script should be used, installing or updating
+/// it as necessary.
+///
+/// Returns false if the helper is disabled or couldn't be installed,
+/// in which case the commands are run inline.
+fn helper_ready(opts: &WSLOptions) -> bool {
+    // helper is a bash script
+    if !opts.use_helper || distro_shell(opts) != Shell::Bash {
+        return false;
+    }
+    let mut ready = match HELPER_READY.lock() {
+        Ok(ready) => ready,
+        Err(_) => return false,
+    };
+    if ready.contains(&opts.distribution) {
+        return true;
+    }
+    let installed = install_script(
+        opts.distribution.as_deref(),
+        HELPER_INSTALL_PATH,
+        HELPER_VERSION,
+        HELPER_SCRIPT,
+    );
+    if installed {
+        log::debug!("Helper script is up to date");
+        ready.push(opts.distribution.clone());
+    } else {
+        log::debug!("Failed to install helper script");
+    }
+    installed
+}
+
+/// Install a script under the home directory in WSL, unless the installed
+/// script already reports the given version.
+///
+/// Returns false if the script couldn't be installed.
+fn install_script(distro: Option<&OsStr>, path: &str, version: &str, script: &str) -> bool {
+    use std::io::Write;
+    // installer takes positional arguments
+    if !wsl_supports("--exec") {
+        return false;
+    }
+    let mut cmd = match wsl_bin_path() {
+        Ok(path) => process::Command::new(path),
+        Err(_) => return false,
+    };
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    if let Some(distro) = distro {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    cmd.args([
+        "-e",
+        "bash",
+        "-c",
+        INSTALL_SCRIPT,
+        "wslscript",
+        version,
+        path,
+    ])
+    .stdin(process::Stdio::piped())
+    .stdout(process::Stdio::null())
+    .stderr(process::Stdio::null());
+    cmd.spawn().is_ok_and(|mut child| {
+        // scripts are small enough to fit in the pipe buffer, and aren't read
+        // at all if already up to date
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(script.as_bytes());
+        }
+        child.wait().is_ok_and(|status| status.success())
+    })
+}
+
+/// Install the `wslscript` command into a distribution.
+///
+/// The command lets Linux side code open paths in Explorer, run PowerShell
+/// commands and run scripts with `wslscript.exe`. Returns the installed
+/// path relative to the home directory.
+///
+/// Fails if interop is disabled in the distribution's `/etc/wsl.conf`,
+/// since the command couldn't run any Windows programs.
+pub fn install_companion(distro: Option<OsString>) -> Result<&'static str, Error> {
+    let mut opts = WSLOptions::default();
+    if let Some(distro) = distro {
+        opts.set_distribution(distro);
+    }
+    if distro_shell(&opts) != Shell::Bash {
+        return Err(Error::GenericError(
+            "The wslscript command requires bash.".to_owned(),
+        ));
+    }
+    let facts = probe::distro_facts(opts.distribution.as_deref());
+    if facts.as_ref().is_some_and(|f| !f.interop) {
+        return Err(Error::GenericError(
+            "Interop is disabled in /etc/wsl.conf of the distribution, \
+             so the wslscript command couldn't run Windows programs."
+                .to_owned(),
+        ));
+    }
+    let mut paths = vec![env::current_exe()?];
+    // without Windows PATH in WSL, programs must be run by their full paths
+    if facts.is_some_and(|f| !f.append_windows_path) {
+        log::debug!("Windows PATH isn't appended in WSL, using full paths");
+        let root =
+            env::var_os("SYSTEMROOT").map_or_else(|| PathBuf::from(r"C:\Windows"), PathBuf::from);
+        paths.push(root.join("explorer.exe"));
+        paths.push(root.join(r"System32\WindowsPowerShell\v1.0\powershell.exe"));
+    }
+    let mut paths = paths_to_wsl(&paths, &opts, None)?.into_iter();
+    let exe = paths.next().ok_or(Error::InvalidPathError)?;
+    let explorer = paths
+        .next()
+        .unwrap_or_else(|| PathBuf::from("explorer.exe"));
+    let powershell = paths
+        .next()
+        .unwrap_or_else(|| PathBuf::from("powershell.exe"));
+    let quote = |path: &Path| {
+        format!(
+            "'{}'",
+            single_quote_escape(path.as_os_str()).to_string_lossy()
+        )
+    };
+    // installed command echoes the paths unquoted
+    let version = format!(
+        "{} {} {}",
+        COMPANION_VERSION,
+        exe.to_string_lossy(),
+        explorer.to_string_lossy()
+    );
+    let script = COMPANION_SCRIPT
+        .replace("{version}", COMPANION_VERSION)
+        .replace("{exe}", &quote(&exe))
+        .replace("{explorer}", &quote(&explorer))
+        .replace("{powershell}", &quote(&powershell));
+    if !install_script(
+        opts.distribution.as_deref(),
+        COMPANION_INSTALL_PATH,
+        &version,
+        &script,
+    ) {
+        return Err(Error::GenericError(
+            "Failed to install the wslscript command.".to_owned(),
+        ));
+    }
+    Ok(COMPANION_INSTALL_PATH)
+}
+
+/// Get the shell to run commands with in a distribution.
+///
+/// Bash is used if the distribution has it, or if the distribution
+/// couldn't be probed, in which case the actual command reports the error.
+fn distro_shell(opts: &WSLOptions) -> Shell {
+    let distro = opts.distribution.as_deref();
+    let mut shells = match DISTRO_SHELLS.lock() {
+        Ok(shells) => shells,
+        Err(_) => return Shell::Bash,
+    };
+    let idx = shells.iter().position(|(d, _, _)| d.as_deref() == distro);
+    if let Some((_, shell, probed)) = idx.map(|i| &shells[i]) {
+        if shell.is_some() || probed.elapsed() < SHELL_PROBE_RETRY {
+            return shell.unwrap_or(Shell::Bash);
+        }
+    }
+    let shell = probe::distro_facts(distro).map(|facts| {
+        if facts.has_bash {
+            Shell::Bash
+        } else {
+            Shell::Sh
         }
+    });
+    let entry = (
+        distro.map(OsStr::to_owned),
+        shell,
+        std::time::Instant::now(),
+    );
+    match idx {
+        Some(i) => shells[i] = entry,
+        None => shells.push(entry),
     }
-    Ok(BashCmdResult { cmd, tmpfile })
+    shell.unwrap_or(Shell::Bash)
 }
 
-/// Write arguments to temporary file as a nul separated list.
-fn write_args_to_temp_file(args: &[PathBuf]) -> Result<PathBuf, Error> {
-    use std::io::prelude::*;
-    let temp = create_temp_file()?;
-    let paths: Result<Vec<_>, _> = args
+/// Get the shell detected earlier for a distribution, without probing.
+fn cached_distro_shell(opts: &WSLOptions) -> Option<Shell> {
+    let distro = opts.distribution.as_deref();
+    let shells = DISTRO_SHELLS.lock().ok()?;
+    shells
         .iter()
-        .map(|p| p.to_str().ok_or_else(|| Error::StringToPathUTF8Error))
-        .collect();
-    let s = match paths {
-        Err(e) => return Err(e),
-        Ok(p) => p.join("\0"),
-    };
-    let mut file = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(&temp)?;
-    file.write_all(s.as_bytes())?;
-    log::debug!("Args written to: {}", temp.to_string_lossy());
-    Ok(temp)
+        .find(|(d, _, _)| d.as_deref() == distro)
+        .and_then(|(_, shell, _)| *shell)
 }
 
-/// Create a temporary file.
+/// Get output of `wsl.exe --version`.
 ///
-/// Returned path is an empty file in Windows's temp file directory.
-fn create_temp_file() -> Result<PathBuf, Error> {
-    use winapi::um::fileapi as fa;
-    let mut buf = [0u16; MAX_PATH + 1];
-    let len = unsafe { fa::GetTempPathW(buf.len() as _, buf.as_mut_ptr()) };
-    if len == 0 {
-        return Err(last_error());
+/// Returns `None` if WSL is not installed or is too old to report its version.
+pub fn wsl_version() -> Option<String> {
+    let mut cmd = process::Command::new(wsl_bin_path().ok()?);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    cmd.arg("--version");
+    let output = cmd.output().ok().filter(|output| output.status.success())?;
+    Some(decode_wsl_output(&output.stdout).trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// Check whether `wsl.exe` supports an option of `DETECTED_WSL_OPTIONS`.
+fn wsl_supports(option: &str) -> bool {
+    WSL_OPTIONS.contains(&option)
+}
+
+/// Detect which of `DETECTED_WSL_OPTIONS` `wsl.exe` supports, by looking
+/// for them in the usage text.
+///
+/// Support is recorded in the settings, so that the usage is checked only
+/// until WSL supports all of the options.
+fn detect_wsl_options() -> Vec<&'static str> {
+    let recorded = registry::wsl_supported_options();
+    if DETECTED_WSL_OPTIONS
+        .iter()
+        .all(|option| recorded.iter().any(|s| s == option))
+    {
+        return DETECTED_WSL_OPTIONS.to_vec();
     }
-    let temp_dir = unsafe { WideCString::from_ptr_truncate(buf.as_ptr(), len as usize + 1) };
-    let uniq = unsafe {
-        fa::GetTempFileNameW(
-            temp_dir.as_ptr(),
-            wcstring("wsl").as_ptr(),
-            0,
-            buf.as_mut_ptr(),
-        )
+    let Ok(wsl_bin) = wsl_bin_path() else {
+        return Vec::new();
     };
-    if uniq == 0 {
-        return Err(last_error());
+    let mut cmd = process::Command::new(wsl_bin);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    cmd.arg("--help");
+    // usage is printed with a failure exit code on some versions
+    let usage = match cmd.output() {
+        Ok(output) => format!(
+            "{}\n{}",
+            decode_wsl_output(&output.stdout),
+            decode_wsl_output(&output.stderr)
+        ),
+        Err(_) => return Vec::new(),
+    };
+    let supported = DETECTED_WSL_OPTIONS
+        .into_iter()
+        .filter(|option| usage.contains(option))
+        .collect::<Vec<_>>();
+    log::debug!("wsl.exe supports options: {:?}", supported);
+    if let Err(e) = registry::set_wsl_supported_options(&supported) {
+        log::error!("Failed to record supported wsl.exe options: {}", e);
     }
-    let temp_path = unsafe { WideCString::from_ptr_truncate(buf.as_ptr(), buf.len()) };
-    log::debug!("Temp path {}", temp_path.to_string_lossy());
-    Ok(PathBuf::from(temp_path.to_string_lossy()))
+    supported
 }
 
-/// Escape single quotes in an OsString.
-fn single_quote_escape(s: &OsStr) -> OsString {
-    let mut w: Vec<u16> = vec![];
-    for c in s.encode_wide() {
-        // escape ' to '\''
-        if c == '\'' as u16 {
-            w.extend_from_slice(wch!(r"'\''"));
-        } else {
-            w.push(c);
+/// Add arguments to run a shell command in WSL.
+///
+/// `flags` are passed to the shell before `-c`. Old versions of `wsl.exe`
+/// lack `--exec` and run their command line with the default shell, so the
+/// command is passed in an environment variable shared via `WSLENV`
+/// instead, to keep it from being interpreted twice. Such versions also
+/// can't pass positional arguments to the command.
+pub(crate) fn push_shell_command(
+    cmd: &mut process::Command,
+    shell: &str,
+    flags: &[&str],
+    command: &OsStr,
+) {
+    if wsl_supports("--exec") {
+        cmd.args(["-e", shell]).args(flags).arg("-c").arg(command);
+        return;
+    }
+    let mut wslenv = command_env(cmd, "WSLENV").unwrap_or_default();
+    if !wslenv.is_empty() {
+        wslenv.push(":");
+    }
+    wslenv.push(LEGACY_COMMAND_VAR);
+    cmd.env("WSLENV", wslenv).env(LEGACY_COMMAND_VAR, command);
+    let mut line = shell.to_string();
+    for flag in flags {
+        line.push(' ');
+        line.push_str(flag);
+    }
+    cmd.raw_arg(format!("{} -c \"${}\"", line, LEGACY_COMMAND_VAR));
+}
+
+/// Get an environment variable as it will be passed to a command.
+fn command_env(cmd: &process::Command, name: &str) -> Option<OsString> {
+    match cmd.get_envs().find(|(key, _)| *key == name) {
+        Some((_, value)) => value.map(OsStr::to_owned),
+        None => env::var_os(name),
+    }
+}
+
+/// Maximum number of bytes of `wsl.exe` error output attached to errors.
+const MAX_STDERR_LEN: usize = 4096;
+
+/// Read error output of a `wsl.exe` process in a separate thread, so that
+/// the pipe doesn't fill up.
+///
+/// Only the end of a long output is kept.
+fn capture_stderr(child: &mut process::Child) -> Option<std::thread::JoinHandle<String>> {
+    use std::io::Read;
+    let mut stderr = child.stderr.take()?;
+    Some(std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let mut chunk = [0_u8; 1024];
+        while let Ok(n) = stderr.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            if buf.len() > 2 * MAX_STDERR_LEN {
+                buf.drain(..buf.len() - MAX_STDERR_LEN);
+            }
+        }
+        if buf.len() > MAX_STDERR_LEN {
+            buf.drain(..buf.len() - MAX_STDERR_LEN);
         }
+        decode_wsl_output(&buf).trim().to_string()
+    }))
+}
+
+/// Wait for the error output read by `capture_stderr`.
+fn join_stderr(handle: Option<std::thread::JoinHandle<String>>) -> String {
+    handle.and_then(|h| h.join().ok()).unwrap_or_default()
+}
+
+/// Attach error output of `wsl.exe` to an error.
+fn with_stderr(error: Error, stderr: String) -> Error {
+    if stderr.is_empty() {
+        return error;
+    }
+    log::debug!("wsl.exe error output: {}", stderr);
+    Error::WithStderr {
+        error: Box::new(error),
+        stderr,
     }
-    OsString::from_wide(&w)
 }
 
-/// Convert single Windows path to WSL equivalent.
-fn path_to_wsl(path: &Path, opts: &WSLOptions) -> Result<PathBuf, Error> {
-    let mut paths = paths_to_wsl(&[path.to_owned()], opts, None)?;
-    let p = paths.pop().ok_or_else(|| Error::WinToUnixPathError)?;
-    Ok(p)
+/// Open an interactive shell in the folder of a file, in the distribution
+/// configured for its filetype.
+///
+/// Returns once the shell's console has been opened.
+pub fn open_shell(path: &Path, opts: &WSLOptions) -> Result<(), Error> {
+    validate_options(opts)?;
+    let dir = path.parent().ok_or(Error::InvalidPathError)?;
+    // wsl.exe doesn't accept extended length paths
+    let dir = WinPathBuf::new(dir.to_path_buf())
+        .canonicalize()?
+        .without_extended();
+    let mut cmd = process::Command::new(wsl_bin_path()?);
+    cmd.creation_flags(Threading::CREATE_NEW_CONSOLE.0);
+    if let Some(distro) = &opts.distribution {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    cmd.arg("--cd").arg(dir.as_os_str());
+    log::debug!("Opening shell with {:?}", cmd);
+    cmd.spawn().map_err(|source| Error::WSLProcessError {
+        command: format!("{:?}", cmd),
+        source,
+    })?;
+    Ok(())
 }
 
-/// Path conversion progress callback.
+/// Template of scripts created with _New script_.
+pub const SCRIPT_TEMPLATE: &str = r#"#!/usr/bin/env bash
+# Double-click to run, or drop files on this script to pass their paths
+# as arguments.
+set -euo pipefail
+
+echo "Running $(basename "$0") in $PWD with $# arguments"
+for arg in "$@"; do
+    echo "  $arg"
+done
+"#;
+
+/// Create a script from `SCRIPT_TEMPLATE` and make it executable in WSL.
 ///
-/// Callback must return true to continue processing.
-/// Conversion may be cancelled by returning false.
-pub type PathProgressCallback = Box<dyn Fn(usize) -> bool + 'static>;
+/// An existing file is overwritten. Failure to set the executable bit is only
+/// logged, since drives mounted without metadata don't keep it and scripts
+/// are run regardless.
+pub fn create_script(path: &Path, opts: &WSLOptions) -> Result<(), Error> {
+    std::fs::write(path, SCRIPT_TEMPLATE)?;
+    let wsl_path = path_to_wsl(path, opts)?;
+    let mut command = OsString::from("chmod +x -- '");
+    command.push(single_quote_escape(wsl_path.as_os_str()));
+    command.push("'");
+    let mut cmd = process::Command::new(wsl_bin_path()?);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    if let Some(distro) = &opts.distribution {
+        cmd.args([OsStr::new("-d"), distro]);
+    }
+    push_shell_command(&mut cmd, "sh", &[], &command);
+    cmd.stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null());
+    log::debug!("Running {:?}", cmd);
+    match cmd.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!("chmod exited with code {}", status.code().unwrap_or(-1)),
+        Err(e) => log::warn!("Failed to run chmod: {}", e),
+    }
+    Ok(())
+}
 
-/// Convert Windows paths to WSL equivalents.
+/// Decode output of wsl.exe.
 ///
-/// Multiple paths can be converted on a single WSL invocation.
-/// Converted paths are returned in the same order as given.
+/// Messages of wsl.exe itself are UTF-16, while output of the commands
+/// run in WSL is UTF-8.
+fn decode_wsl_output(bytes: &[u8]) -> String {
+    if bytes.len() >= 2 && bytes[1] == 0 {
+        let wide = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16_lossy(&wide)
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+/// Recognize errors of wsl.exe that require updating WSL or restarting
+/// Windows before any command can run.
+fn wsl_setup_error(stderr: &str) -> Option<Error> {
+    // messages are localized, so error codes are matched as well
+    const UPDATE: &[&str] = &[
+        "requires an update to its kernel",
+        "wsl --update",
+        "wsl.exe --update",
+        "0x800701bc",
+    ];
+    const RESTART: &[&str] = &[
+        "restart your computer",
+        "restart is required",
+        "reboot is required",
+        "0x80070bc2",
+        "0x80070bc9",
+    ];
+    let s = stderr.to_lowercase();
+    if UPDATE.iter().any(|m| s.contains(m)) {
+        Some(Error::WSLUpdateRequired)
+    } else if RESTART.iter().any(|m| s.contains(m)) {
+        Some(Error::WSLRestartRequired)
+    } else {
+        None
+    }
+}
+
+/// Update WSL with `wsl.exe --update` in a console window.
 ///
-/// Optional progress callback function shall be called with a number of
-/// paths converted so far.
-pub fn paths_to_wsl(
-    paths: &[PathBuf],
-    opts: &WSLOptions,
-    progress_callback: Option<PathProgressCallback>,
-) -> Result<Vec<PathBuf>, Error> {
-    let mut wsl_paths: Vec<PathBuf> = Vec::with_capacity(paths.len());
-    let mut path_idx = 0;
-    while path_idx < paths.len() {
-        // build a printf command that prints null separated results
-        let mut printf = WideString::new();
-        printf.push_slice(wch!(r"printf '%s\0'"));
-        let mut n = 0;
-        // convert multiple paths on single WSL invocation up to maximum command line length
-        while path_idx < paths.len()
-            && printf.len() < MAX_CMD_LEN - MAX_PATH - 100
-            && n < MAX_PATHS_CONVERT_PER_PROCESS
-        {
-            printf.push_slice(wch!(r#" "$(wslpath -u '"#));
-            printf.push_os_str(single_quote_escape(paths[path_idx].as_os_str()));
-            printf.push_slice(wch!(r#"')""#));
-            path_idx += 1;
-            n += 1;
-        }
-        log::debug!("printf command length {}", printf.len());
-        let mut cmd = process::Command::new(wsl_bin_path()?);
-        cmd.creation_flags(winbase::CREATE_NO_WINDOW);
-        if let Some(distro) = &opts.distribution {
-            cmd.args(&[OsStr::new("-d"), distro]);
-        }
-        cmd.args(&[
-            OsStr::new("-e"),
-            OsStr::new("bash"),
-            OsStr::new("-c"),
-            &printf.to_os_string(),
-        ]);
-        let output = cmd.output().context(Error::WinToUnixPathError)?;
-        if !output.status.success() {
-            return Err(Error::WinToUnixPathError);
-        }
-        wsl_paths.extend(
-            std::str::from_utf8(&output.stdout)
-                .context(Error::StringToPathUTF8Error)?
-                .trim()
-                .trim_matches('\0')
-                .split('\0')
-                .map(PathBuf::from),
-        );
-        if let Some(cb) = &progress_callback {
-            if !cb(path_idx) {
-                log::debug!("Progress callback returned false, cancelling");
-                return Err(Error::Cancel);
-            }
-        }
+/// `wsl.exe` asks for administrator rights itself if needed.
+pub fn update_wsl() -> Result<(), Error> {
+    let mut cmd = process::Command::new(wsl_bin_path()?);
+    cmd.creation_flags(Threading::CREATE_NEW_CONSOLE.0)
+        .arg("--update");
+    let status = cmd.status().map_err(|source| Error::WSLProcessError {
+        command: format!("{:?}", cmd),
+        source,
+    })?;
+    if !status.success() {
+        return Err(Error::GenericError(format!(
+            "wsl --update exited with code {}",
+            status.code().unwrap_or(-1)
+        )));
     }
-    log::debug!("Converted {} Windows paths to WSL", wsl_paths.len());
-    Ok(wsl_paths)
+    Ok(())
 }
 
 /// Returns the path to Windows command prompt executable.
@@ -308,7 +2574,7 @@ fn cmd_bin_path() -> PathBuf {
 }
 
 /// Returns the path to WSL executable.
-fn wsl_bin_path() -> Result<PathBuf, Error> {
+pub(crate) fn wsl_bin_path() -> Result<PathBuf, Error> {
     // try %SYSTEMROOT\System32\wsl.exe
     if let Some(mut p) = env::var_os("SYSTEMROOT").map(PathBuf::from) {
         p.push(r"System32\wsl.exe");
@@ -320,14 +2586,262 @@ fn wsl_bin_path() -> Result<PathBuf, Error> {
     Err(Error::WSLNotFound)
 }
 
+/// Shut down all running distributions and the WSL virtual machine.
+pub fn shutdown_wsl() -> Result<(), Error> {
+    run_wsl_control(&[OsStr::new("--shutdown")])
+}
+
+/// Terminate a running distribution.
+pub fn terminate_distro(distro: &OsStr) -> Result<(), Error> {
+    run_wsl_control(&[OsStr::new("--terminate"), distro])
+}
+
+/// Terminate a distribution and start it again.
+pub fn restart_distro(distro: &OsStr) -> Result<(), Error> {
+    terminate_distro(distro)?;
+    run_wsl_control(&[
+        OsStr::new("-d"),
+        distro,
+        OsStr::new("-e"),
+        OsStr::new("true"),
+    ])
+}
+
+/// Run `wsl.exe` with given arguments without a console window.
+fn run_wsl_control(args: &[&OsStr]) -> Result<(), Error> {
+    let mut cmd = process::Command::new(wsl_bin_path()?);
+    cmd.creation_flags(Threading::CREATE_NO_WINDOW.0);
+    cmd.args(args)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped());
+    log::debug!("Running {:?}", cmd);
+    let mut child = cmd.spawn().map_err(|source| Error::WSLProcessError {
+        command: format!("{:?}", cmd),
+        source,
+    })?;
+    let errors = capture_stderr(&mut child);
+    let status = child.wait()?;
+    if !status.success() {
+        let e = Error::GenericError(format!(
+            "wsl.exe exited with code {}",
+            status.code().unwrap_or(-1)
+        ));
+        return Err(with_stderr(e, join_stderr(errors)));
+    }
+    Ok(())
+}
+
+/// Check that WSL is installed and has a distribution to run scripts in.
+///
+/// Returns `Error::WSLNotFound` if `wsl.exe` is missing,
+/// `Error::WSLFeatureDisabled` if WSL itself isn't enabled
+/// and `Error::WSLNoDistribution` if there are no distributions.
+pub fn check_wsl_installed() -> Result<(), Error> {
+    wsl_bin_path()?;
+    // wsl.exe ships with Windows even when the feature is disabled
+    if !registry::is_wsl_service_installed() {
+        return Err(Error::WSLFeatureDisabled);
+    }
+    if registry::query_distros().map_or(true, |distros| distros.list.is_empty()) {
+        return Err(Error::WSLNoDistribution);
+    }
+    Ok(())
+}
+
+/// Get name of the distribution this program was invoked from via interop,
+/// eg. `./wslscript.exe run script.sh` in a WSL shell.
+///
+/// Requires `WSL_DISTRO_NAME` to be shared with Windows, eg. via `WSLENV`.
+pub fn interop_distro() -> Option<OsString> {
+    env::var_os("WSL_DISTRO_NAME").filter(|s| is_valid_distro_name(s))
+}
+
+/// Context of a path argument given from within WSL.
+#[derive(Debug, PartialEq)]
+pub enum ArgContext {
+    /// Absolute Windows path, eg. `C:\file` or `\\server\share`.
+    Windows,
+    /// Absolute Linux path that needs no conversion.
+    Wsl,
+    /// Relative path, resolved against the working directory.
+    Relative,
+}
+
+/// Determine the context of an argument given from within WSL.
+pub fn interop_arg_context(arg: &OsStr) -> ArgContext {
+    let s = arg.to_string_lossy();
+    let mut chars = s.chars();
+    let drive = matches!(
+        (chars.next(), chars.next(), chars.next()),
+        (Some(c), Some(':'), Some('\\' | '/')) if c.is_ascii_alphabetic()
+    );
+    if drive || s.starts_with(r"\\") {
+        ArgContext::Windows
+    } else if s.starts_with('/') {
+        ArgContext::Wsl
+    } else {
+        ArgContext::Relative
+    }
+}
+
+/// Get Windows path of a file in a WSL distribution.
+///
+/// `path` is an absolute Linux path.
+pub fn distro_unc_path(distro: &OsStr, path: &OsStr) -> PathBuf {
+    let mut p = OsString::from(r"\\wsl$\");
+    p.push(distro);
+    p.push(path.to_string_lossy().replace('/', "\\"));
+    PathBuf::from(p)
+}
+
 /// Options for WSL invocation.
+///
+/// Options that only apply to a single run are not serialized.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct WSLOptions {
     /// Mode after the command exits.
     hold_mode: HoldMode,
+    /// Key to wait for when the console is held open.
+    hold_key: HoldKey,
+    /// Message template displayed when the console is held open.
+    hold_message: Option<String>,
     /// Whether to run bash as an interactive shell.
     interactive: bool,
     /// Name of the WSL distribution to invoke.
+    #[serde(with = "opt_os_string")]
     distribution: Option<OsString>,
+    /// Handling of scripts with Windows line endings.
+    crlf_mode: CrlfMode,
+    /// Whether to pass arguments unquoted.
+    raw_args: bool,
+    /// Whether to open the console at the position where files were dropped.
+    console_at_drop: bool,
+    /// Screen position of the console window.
+    #[serde(skip)]
+    console_position: Option<(i32, i32)>,
+    /// Title of the console window.
+    #[serde(with = "opt_os_string")]
+    console_title: Option<OsString>,
+    /// Console screen buffer size.
+    console_size: Option<ConsoleSize>,
+    /// Console text and background colors.
+    console_colors: Option<ConsoleColors>,
+    /// Whether to set the console to UTF-8 with a TrueType font.
+    utf8_console: bool,
+    /// Whether to run subsequent scripts in an already open console.
+    reuse_console: bool,
+    /// Handling of multiple dropped files.
+    drop_policy: DropPolicy,
+    /// Order of dropped files passed to the script.
+    arg_order: ArgOrder,
+    /// Mode applied with `chmod` to dropped files before the script runs.
+    arg_chmod: Option<String>,
+    /// Owner applied with `chown` to dropped files before the script runs.
+    arg_chown: Option<String>,
+    /// Whether to cancel the run if some of the dropped files are missing.
+    abort_on_missing: bool,
+    /// Number of dropped files above which the user is asked to confirm.
+    max_args: u32,
+    /// Whether to use the Linux side helper script.
+    use_helper: bool,
+    /// Windows environment variables passed via `WSLENV`.
+    wslenv: WslEnv,
+    /// Registered extension the options were loaded for.
+    extension: Option<String>,
+    /// Minimum number of seconds between runs before confirmation.
+    min_interval: u32,
+    /// Maximum number of running scripts before confirmation.
+    max_instances: u32,
+    /// Whether to focus a running script's console instead of running again.
+    single_instance: bool,
+    /// Whether the drop handler shows progress windows.
+    show_progress: bool,
+    /// Whether to run without a console and log to the Event Log.
+    event_log: EventLog,
+    /// Number of days after which output logs are deleted.
+    log_max_age: u32,
+    /// Number of most recent output logs kept.
+    log_max_files: u32,
+    /// Icon of the console window.
+    #[cfg(feature = "gui")]
+    console_icon: Option<crate::icon::ShellIcon>,
+    /// Whether to pass arguments via a temporary file regardless of their length.
+    #[serde(skip)]
+    args_in_file: bool,
+}
+
+/// Serialize optional OS strings as strings, instead of the platform
+/// specific representation of `OsString`.
+mod opt_os_string {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::ffi::OsString;
+
+    pub fn serialize<S: Serializer>(value: &Option<OsString>, s: S) -> Result<S::Ok, S::Error> {
+        value.as_ref().map(|v| v.to_string_lossy()).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<OsString>, D::Error> {
+        Ok(Option::<String>::deserialize(d)?.map(OsString::from))
+    }
+}
+
+/// Command line of a script run, split into options, the script and its
+/// arguments.
+#[derive(Debug, PartialEq)]
+pub struct ExecArgs {
+    /// Options preceding the script.
+    pub options: Vec<OsString>,
+    /// Path of the script.
+    pub script: OsString,
+    /// Arguments passed to the script.
+    pub args: Vec<OsString>,
+}
+
+impl ExecArgs {
+    /// Parse a command line of the form `[options] --exec -- <script> [args]`.
+    ///
+    /// Form `[options] -E <script> [args]` registered by earlier versions
+    /// is accepted too. Values of options are skipped, so that a value is
+    /// never taken for the flag preceding the script. Returns `None` if
+    /// there's no script to run.
+    ///
+    /// `args` excludes the program name.
+    pub fn parse(args: &[OsString]) -> Option<Self> {
+        let mut iter = args.iter();
+        let mut options = Vec::new();
+        while let Some(arg) = iter.next() {
+            if arg == registry::EXEC_FLAG {
+                // terminator is optional, in case the command was typed
+                let mut rest = iter.as_slice();
+                if rest.first().is_some_and(|arg| arg == "--") {
+                    rest = &rest[1..];
+                }
+                return Self::split(options, rest);
+            }
+            if arg == registry::LEGACY_EXEC_FLAG {
+                log::debug!("Command uses the deprecated -E flag");
+                return Self::split(options, iter.as_slice());
+            }
+            options.push(arg.clone());
+            // options taking a value, see `WSLOptions::from_args`
+            if arg == "--ext" || arg == "-h" || arg == "-d" {
+                options.extend(iter.next().cloned());
+            }
+        }
+        None
+    }
+
+    /// Split arguments following the flag to the script and its arguments.
+    fn split(options: Vec<OsString>, rest: &[OsString]) -> Option<Self> {
+        let (script, args) = rest.split_first()?;
+        Some(Self {
+            options,
+            script: script.clone(),
+            args: args.to_vec(),
+        })
+    }
 }
 
 impl WSLOptions {
@@ -362,27 +2876,187 @@ impl WSLOptions {
         }
         Self {
             hold_mode,
+            hold_key: HoldKey::default(),
+            hold_message: None,
             interactive,
             distribution,
+            crlf_mode: CrlfMode::default(),
+            raw_args: false,
+            console_at_drop: false,
+            console_position: None,
+            console_title: None,
+            console_size: None,
+            console_colors: None,
+            utf8_console: false,
+            reuse_console: false,
+            drop_policy: DropPolicy::default(),
+            arg_order: ArgOrder::default(),
+            arg_chmod: None,
+            arg_chown: None,
+            abort_on_missing: false,
+            max_args: registry::DEFAULT_MAX_ARGS,
+            use_helper: false,
+            wslenv: WslEnv::default(),
+            extension: None,
+            min_interval: 0,
+            max_instances: 0,
+            single_instance: false,
+            show_progress: true,
+            event_log: EventLog::default(),
+            log_max_age: registry::DEFAULT_LOG_MAX_AGE,
+            log_max_files: registry::DEFAULT_LOG_MAX_FILES,
+            #[cfg(feature = "gui")]
+            console_icon: None,
+            args_in_file: false,
         }
     }
 
+    /// Load options for the longest registered extension of a path.
+    ///
+    /// See `registry::path_extensions`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        registry::path_extensions(path)
+            .iter()
+            .find_map(|ext| Self::from_ext(ext))
+    }
+
     /// Load options for registered extension.
     ///
     /// `ext` is the filename extension without a leading dot.
     pub fn from_ext(ext: &str) -> Option<Self> {
-        if let Ok(config) = registry::get_extension_config(ext) {
-            let distro = config
-                .distro
-                .and_then(registry::distro_guid_to_name)
-                .map(OsString::from);
-            Some(Self {
-                hold_mode: config.hold_mode,
-                interactive: config.interactive,
-                distribution: distro,
-            })
-        } else {
-            None
+        registry::get_extension_config(ext)
+            .ok()
+            .map(|config| Self::from(&config))
+    }
+
+    /// Whether the console should be opened where files were dropped.
+    pub fn console_at_drop(&self) -> bool {
+        self.console_at_drop
+    }
+
+    /// How the script is invoked when multiple files are dropped on it.
+    pub fn drop_policy(&self) -> DropPolicy {
+        self.drop_policy
+    }
+
+    /// Order of dropped files passed to the script.
+    pub fn arg_order(&self) -> ArgOrder {
+        self.arg_order
+    }
+
+    /// Whether to cancel the run if some of the dropped files are missing.
+    pub fn abort_on_missing(&self) -> bool {
+        self.abort_on_missing
+    }
+
+    /// Number of dropped files above which the user is asked to confirm.
+    ///
+    /// Zero if confirmation is disabled.
+    pub fn max_args(&self) -> u32 {
+        self.max_args
+    }
+
+    /// Registered extension the options were loaded for, if any.
+    pub fn extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+
+    /// Minimum number of seconds between runs before the user is asked
+    /// to confirm.
+    ///
+    /// Zero if the check is disabled.
+    pub fn min_interval(&self) -> u32 {
+        self.min_interval
+    }
+
+    /// Maximum number of scripts running at a time before the user is
+    /// asked to confirm.
+    ///
+    /// Zero if there's no limit.
+    pub fn max_instances(&self) -> u32 {
+        self.max_instances
+    }
+
+    /// Whether to focus the console of a running script instead of
+    /// running the script again.
+    pub fn single_instance(&self) -> bool {
+        self.single_instance
+    }
+
+    /// Whether the drop handler shows progress while converting paths and
+    /// waiting for WSL to start.
+    pub fn show_progress(&self) -> bool {
+        self.show_progress
+    }
+
+    /// Whether the script is run without a console and logged to
+    /// the Event Log.
+    pub fn event_log(&self) -> EventLog {
+        self.event_log
+    }
+
+    /// Set name of the WSL distribution to invoke.
+    pub fn set_distribution(&mut self, distro: OsString) {
+        self.distribution = Some(distro);
+    }
+
+    /// Pass arguments via a temporary file regardless of their length.
+    pub fn set_args_in_file(&mut self) {
+        self.args_in_file = true;
+    }
+
+    /// Set screen position of the console window.
+    pub fn set_console_position(&mut self, pos: (i32, i32)) {
+        self.console_position = Some(pos);
+    }
+}
+
+/// Create options from a filetype configuration, eg. one being edited
+/// and not yet saved.
+///
+/// Only the name of the distribution is looked up, from the distributions
+/// registered with WSL.
+impl From<&registry::ExtConfig> for WSLOptions {
+    fn from(config: &registry::ExtConfig) -> Self {
+        let distro = config
+            .distro
+            .clone()
+            .and_then(registry::distro_guid_to_name)
+            .map(OsString::from);
+        Self {
+            hold_mode: config.hold_mode,
+            hold_key: config.hold_key,
+            hold_message: config.hold_message.clone(),
+            interactive: config.interactive,
+            distribution: distro,
+            crlf_mode: config.crlf_mode,
+            raw_args: config.raw_args,
+            console_at_drop: config.console_at_drop,
+            console_position: None,
+            console_title: config.console_title.as_ref().map(OsString::from),
+            console_size: config.console_size,
+            console_colors: config.console_colors,
+            utf8_console: config.utf8_console,
+            reuse_console: config.reuse_console,
+            drop_policy: config.drop_policy,
+            arg_order: config.arg_order,
+            arg_chmod: config.arg_chmod.clone(),
+            arg_chown: config.arg_chown.clone(),
+            abort_on_missing: config.abort_on_missing,
+            max_args: config.max_args,
+            use_helper: config.use_helper,
+            wslenv: config.wslenv.clone(),
+            extension: Some(config.extension.clone()),
+            min_interval: config.min_interval,
+            max_instances: config.max_instances,
+            single_instance: config.single_instance,
+            show_progress: config.show_progress,
+            event_log: config.event_log,
+            log_max_age: config.log_max_age,
+            log_max_files: config.log_max_files,
+            #[cfg(feature = "gui")]
+            console_icon: config.icon.clone(),
+            args_in_file: false,
         }
     }
 }
@@ -391,8 +3065,484 @@ impl Default for WSLOptions {
     fn default() -> Self {
         Self {
             hold_mode: HoldMode::default(),
+            hold_key: HoldKey::default(),
+            hold_message: None,
             interactive: false,
             distribution: None,
+            crlf_mode: CrlfMode::default(),
+            raw_args: false,
+            console_at_drop: false,
+            console_position: None,
+            console_title: None,
+            console_size: None,
+            console_colors: None,
+            utf8_console: false,
+            reuse_console: false,
+            drop_policy: DropPolicy::default(),
+            arg_order: ArgOrder::default(),
+            arg_chmod: None,
+            arg_chown: None,
+            abort_on_missing: false,
+            max_args: registry::DEFAULT_MAX_ARGS,
+            use_helper: false,
+            wslenv: WslEnv::default(),
+            extension: None,
+            min_interval: 0,
+            max_instances: 0,
+            single_instance: false,
+            show_progress: true,
+            event_log: EventLog::default(),
+            log_max_age: registry::DEFAULT_LOG_MAX_AGE,
+            log_max_files: registry::DEFAULT_LOG_MAX_FILES,
+            #[cfg(feature = "gui")]
+            console_icon: None,
+            args_in_file: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_first_line_has_crlf() {
+        use std::io::{BufReader, Cursor};
+        let crlf = |s: &[u8]| first_line_has_crlf(Cursor::new(s.to_vec()));
+        assert!(!crlf(b""));
+        assert!(!crlf(b"#!/bin/sh\necho\n"));
+        assert!(crlf(b"#!/bin/sh\r\necho\r\n"));
+        // only the first line counts
+        assert!(crlf(b"#!/bin/sh\r\necho\n"));
+        assert!(!crlf(b"#!/bin/sh\necho\r\n"));
+        assert!(!crlf(b"#!/bin/sh\r"));
+        // CR at the end of one buffer fill and LF at the start of the next
+        let reader = BufReader::with_capacity(4, Cursor::new(b"#!/\r\necho\n".to_vec()));
+        assert!(first_line_has_crlf(reader));
+    }
+    #[test]
+    fn test_has_crlf_line_endings() {
+        let path = std::env::temp_dir().join("wslscript crlf test.sh");
+        std::fs::write(&path, b"#!/bin/sh\r\n").unwrap();
+        assert!(has_crlf_line_endings(&path));
+        std::fs::write(&path, b"#!/bin/sh\n").unwrap();
+        assert!(!has_crlf_line_endings(&path));
+        std::fs::remove_file(&path).unwrap();
+        assert!(!has_crlf_line_endings(&path));
+    }
+    #[test]
+    fn test_valid_distro_name() {
+        assert!(is_valid_distro_name(OsStr::new("Ubuntu-22.04")));
+        assert!(is_valid_distro_name(OsStr::new("my_distro")));
+        assert!(!is_valid_distro_name(OsStr::new("")));
+        assert!(!is_valid_distro_name(OsStr::new("Ubuntu & calc")));
+        assert!(!is_valid_distro_name(OsStr::new("Ubuntu\"&calc&\"")));
+        assert!(!is_valid_distro_name(OsStr::new("%COMSPEC%")));
+    }
+    #[test]
+    fn test_wsl_conf_flag() {
+        let conf = "[automount]\nenabled = true\n\n[Interop]\nenabled=false # no windows\nappendWindowsPath = True\n";
+        assert_eq!(wsl_conf_flag(conf, "interop", "enabled"), Some(false));
+        assert_eq!(
+            wsl_conf_flag(conf, "interop", "appendwindowspath"),
+            Some(true)
+        );
+        assert_eq!(wsl_conf_flag(conf, "automount", "enabled"), Some(true));
+        assert_eq!(wsl_conf_flag(conf, "boot", "systemd"), None);
+        assert_eq!(wsl_conf_flag("", "interop", "enabled"), None);
+    }
+    #[test]
+    fn test_cmd_safe() {
+        assert!(is_cmd_safe(OsStr::new("Press any key, exit code {code}")));
+        assert!(is_cmd_safe(OsStr::new("a & b | c > d")));
+        assert!(!is_cmd_safe(OsStr::new("\" & calc & \"")));
+        assert!(!is_cmd_safe(OsStr::new("%USERPROFILE%")));
+        assert!(!is_cmd_safe(OsStr::new("line\r\ncalc")));
+    }
+    #[test]
+    fn test_validate_options() {
+        let opts = WSLOptions {
+            distribution: Some(OsString::from("Ubuntu")),
+            hold_message: Some("Done.".to_string()),
+            console_title: Some(OsString::from("Build \"%1\" & test")),
+            arg_chmod: Some("u+x".to_string()),
+            arg_chown: Some("me:users".to_string()),
+            wslenv: "USERPROFILE/p:!API_TOKEN".parse().unwrap(),
+            ..Default::default()
+        };
+        assert!(validate_values(&opts, Some("/media/win")).is_ok());
+        let hostile = [
+            WSLOptions {
+                distribution: Some(OsString::from("Ubuntu\" & calc & \"")),
+                ..Default::default()
+            },
+            WSLOptions {
+                hold_message: Some("\" & calc & \"".to_string()),
+                ..Default::default()
+            },
+            WSLOptions {
+                hold_message: Some("%COMSPEC%".to_string()),
+                ..Default::default()
+            },
+            WSLOptions {
+                arg_chmod: Some("u+x; calc".to_string()),
+                ..Default::default()
+            },
+            WSLOptions {
+                arg_chown: Some("me $(calc)".to_string()),
+                ..Default::default()
+            },
+            WSLOptions {
+                console_title: Some(OsString::from("Build\u{1b}]0;spoof\u{7}")),
+                ..Default::default()
+            },
+            WSLOptions {
+                console_title: Some(OsString::from("Build\r\ncalc")),
+                ..Default::default()
+            },
+            WSLOptions {
+                wslenv: "PATH\r\ncalc".parse().unwrap(),
+                ..Default::default()
+            },
+            WSLOptions {
+                wslenv: "%COMSPEC%/p".parse().unwrap(),
+                ..Default::default()
+            },
+        ];
+        for opts in &hostile {
+            assert!(matches!(
+                validate_values(opts, None),
+                Err(Error::UnsafeValue(_))
+            ));
+        }
+        for root in ["/mnt'; calc; '", "mnt", "/mnt\ncalc"] {
+            assert!(matches!(
+                validate_values(&WSLOptions::default(), Some(root)),
+                Err(Error::UnsafeValue(_))
+            ));
+        }
+    }
+    #[test]
+    fn test_interpreter_values() {
+        // interpreter is never taken from the registry, but chosen from
+        // fixed values
+        for s in ["bash; calc", "fix & calc", "\"bash\""] {
+            assert!(s.parse::<CrlfMode>().is_err(), "{}", s);
+        }
+        let script = Path::new("/home/me/s.sh");
+        for shell in [Shell::Bash, Shell::Sh] {
+            for crlf_mode in [CrlfMode::Warn, CrlfMode::Bash, CrlfMode::Fix] {
+                let opts = WSLOptions {
+                    hold_mode: HoldMode::Never,
+                    crlf_mode,
+                    ..Default::default()
+                };
+                let inv = Invocation {
+                    shell,
+                    helper: false,
+                    status_file: None,
+                };
+                let cmd = compose_bash_command(script, &[], &opts, &inv, true, false, false)
+                    .unwrap()
+                    .cmd
+                    .to_string_lossy();
+                let run = cmd.trim_start_matches("cd '/home/me' && ");
+                assert!(
+                    [
+                        r"printf >&2 'Warning: script has Windows (CRLF) line endings.\n' && './s.sh'",
+                        "bash './s.sh'",
+                        "sh './s.sh'",
+                        r"bash <(sed 's/\r$//' './s.sh')",
+                        r"sed 's/\r$//' './s.sh' | sh -s",
+                    ]
+                    .contains(&run),
+                    "{}",
+                    run
+                );
+            }
+        }
+    }
+    #[test]
+    fn test_raw_args() {
+        let script = Path::new("/home/me/s.sh");
+        let opts = WSLOptions {
+            hold_mode: HoldMode::Never,
+            raw_args: true,
+            arg_chmod: Some("644".to_string()),
+            ..Default::default()
+        };
+        let args = [PathBuf::from("a b;$(calc)")];
+        let inv = Invocation {
+            shell: Shell::Bash,
+            helper: false,
+            status_file: None,
+        };
+        let cmd = compose_bash_command(script, &args, &opts, &inv, false, false, false)
+            .unwrap()
+            .cmd
+            .to_string_lossy();
+        assert!(cmd.contains("chmod -- '644' 'a b;$(calc)';"), "{}", cmd);
+        assert!(cmd.ends_with("'./s.sh' a b;$(calc)"), "{}", cmd);
+        for arg in ["%COMSPEC%", "a\" & calc & \"b"] {
+            let args = [PathBuf::from(arg)];
+            assert!(matches!(
+                compose_bash_command(script, &args, &opts, &inv, false, false, false),
+                Err(Error::UnsafeValue(_))
+            ));
+        }
+    }
+    #[test]
+    fn test_run_status() {
+        let script = Path::new("/home/me/s.sh");
+        let opts = WSLOptions {
+            hold_mode: HoldMode::Error,
+            ..Default::default()
+        };
+        let status_file = Path::new("/mnt/c/tmp/st1.tmp");
+        for (shell, helper, epilogue) in [
+            (
+                Shell::Bash,
+                false,
+                "; rc=$?; echo $rc $SECONDS > '/mnt/c/tmp/st1.tmp'; (exit $rc) ||",
+            ),
+            (
+                Shell::Bash,
+                true,
+                "helper.sh run './s.sh'; ~/.local/share/wslscript/helper.sh status $? $SECONDS '/mnt/c/tmp/st1.tmp' ||",
+            ),
+            (
+                Shell::Sh,
+                false,
+                "; rc=$?; echo $rc $(($(date +%s)-s0)) > '/mnt/c/tmp/st1.tmp'; (exit $rc) ||",
+            ),
+        ] {
+            let inv = Invocation {
+                shell,
+                helper,
+                status_file: Some(status_file),
+            };
+            let cmd = compose_bash_command(script, &[], &opts, &inv, false, false, false)
+                .unwrap()
+                .cmd
+                .to_string_lossy();
+            assert!(cmd.contains(epilogue), "{}", cmd);
+        }
+        let path = std::env::temp_dir().join("wslscript status test.tmp");
+        std::fs::write(&path, "3 12\n").unwrap();
+        assert_eq!(
+            read_run_status(&path),
+            Some(RunStatus {
+                exit_code: 3,
+                duration: std::time::Duration::from_secs(12)
+            })
+        );
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(read_run_status(&path), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn test_helper_run() {
+        let script = Path::new("/home/me/s.sh");
+        let inv = Invocation {
+            shell: Shell::Bash,
+            helper: true,
+            status_file: None,
+        };
+        for (crlf_mode, run) in [
+            (
+                CrlfMode::Warn,
+                "' && ~/.local/share/wslscript/helper.sh run './s.sh'",
+            ),
+            (
+                CrlfMode::Bash,
+                " && ~/.local/share/wslscript/helper.sh run bash './s.sh'",
+            ),
+            (
+                CrlfMode::Fix,
+                r" && ~/.local/share/wslscript/helper.sh run bash <(sed 's/\r$//' './s.sh')",
+            ),
+        ] {
+            let opts = WSLOptions {
+                hold_mode: HoldMode::Never,
+                crlf_mode,
+                ..Default::default()
+            };
+            let cmd = compose_bash_command(script, &[], &opts, &inv, true, false, false)
+                .unwrap()
+                .cmd
+                .to_string_lossy();
+            assert!(cmd.ends_with(run), "{}", cmd);
+        }
+    }
+    #[test]
+    fn test_chmod_chown_values() {
+        for mode in ["644", "0755", "u+x", "go-w", "a=rX,u+w", "+x", "u=g"] {
+            assert!(is_valid_chmod_mode(mode), "{}", mode);
+        }
+        for mode in ["", "888", "12345", "u", "u+y", "u+x,", "x+u", "u+x'"] {
+            assert!(!is_valid_chmod_mode(mode), "{}", mode);
         }
+        for owner in ["user", "user:group", "user:", ":group", "www-data"] {
+            assert!(is_valid_chown_owner(owner), "{}", owner);
+        }
+        for owner in ["", ":", "-user", "user:-g", "us er", "user:group:x"] {
+            assert!(!is_valid_chown_owner(owner), "{}", owner);
+        }
+    }
+    #[test]
+    fn test_interop_arg_context() {
+        assert_eq!(
+            interop_arg_context(OsStr::new(r"C:\file.txt")),
+            ArgContext::Windows
+        );
+        assert_eq!(
+            interop_arg_context(OsStr::new("d:/dir/file")),
+            ArgContext::Windows
+        );
+        assert_eq!(
+            interop_arg_context(OsStr::new(r"\\server\share")),
+            ArgContext::Windows
+        );
+        assert_eq!(
+            interop_arg_context(OsStr::new("/home/me/file")),
+            ArgContext::Wsl
+        );
+        assert_eq!(
+            interop_arg_context(OsStr::new("/mnt/c/file")),
+            ArgContext::Wsl
+        );
+        assert_eq!(
+            interop_arg_context(OsStr::new("file.txt")),
+            ArgContext::Relative
+        );
+        assert_eq!(
+            interop_arg_context(OsStr::new("../C:/x")),
+            ArgContext::Relative
+        );
+        assert_eq!(interop_arg_context(OsStr::new("c:")), ArgContext::Relative);
+    }
+    #[test]
+    fn test_distro_unc_path() {
+        assert_eq!(
+            distro_unc_path(OsStr::new("Ubuntu"), OsStr::new("/home/me/a b.sh")),
+            PathBuf::from(r"\\wsl$\Ubuntu\home\me\a b.sh")
+        );
+    }
+    #[test]
+    fn test_shebang_invokes_self() {
+        assert!(shebang_invokes_self(b"#!/usr/bin/env wslscript run\n"));
+        assert!(shebang_invokes_self(
+            b"#!/mnt/c/Tools/WSLScript.exe\r\necho"
+        ));
+        assert!(!shebang_invokes_self(b"#!/bin/bash\nwslscript open ."));
+        assert!(!shebang_invokes_self(b"#!/home/me/wslscript-tools/run"));
+        assert!(!shebang_invokes_self(b"echo wslscript"));
+    }
+    #[test]
+    fn test_options_from_config() {
+        let config = registry::ExtConfig {
+            extension: "sh".to_string(),
+            hold_mode: HoldMode::Always,
+            console_title: Some("Build".to_string()),
+            max_args: 42,
+            ..Default::default()
+        };
+        let opts = WSLOptions::from(&config);
+        assert!(opts.hold_mode == HoldMode::Always);
+        assert_eq!(opts.console_title.as_deref(), Some(OsStr::new("Build")));
+        assert_eq!(opts.max_args(), 42);
+        assert_eq!(opts.extension.as_deref(), Some("sh"));
+        assert!(opts.distribution.is_none());
+    }
+    #[test]
+    fn test_options_serde_round_trip() {
+        let opts = WSLOptions {
+            hold_mode: HoldMode::Timeout(30),
+            distribution: Some(OsString::from("Ubuntu")),
+            console_title: Some(OsString::from("Build \u{e4}")),
+            arg_chmod: Some("u+x".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&opts).unwrap();
+        assert_eq!(json["distribution"], "Ubuntu");
+        assert_eq!(json["console_title"], "Build \u{e4}");
+        assert_eq!(json["hold_mode"], "timeout:30");
+        let parsed: WSLOptions = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(parsed.distribution.as_deref(), Some(OsStr::new("Ubuntu")));
+        assert_eq!(
+            parsed.console_title.as_deref(),
+            Some(OsStr::new("Build \u{e4}"))
+        );
+        assert!(parsed.hold_mode == HoldMode::Timeout(30));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+        // missing fields take their defaults
+        let parsed: WSLOptions = serde_json::from_str(r#"{"distribution":null}"#).unwrap();
+        assert!(parsed.distribution.is_none());
+        assert_eq!(parsed.max_args(), registry::DEFAULT_MAX_ARGS);
+    }
+    #[test]
+    fn test_exec_args() {
+        let parse = |args: &[&str]| {
+            let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            ExecArgs::parse(&args)
+        };
+        let exec = parse(&["--ext", "sh", "--exec", "--", "-E", "--exec", "--"]).unwrap();
+        assert_eq!(exec.options, ["--ext", "sh"]);
+        assert_eq!(exec.script, "-E");
+        assert_eq!(exec.args, ["--exec", "--"]);
+        // option values are never taken for the flag
+        let exec = parse(&["--ext", "-E", "-d", "--exec", "--exec", "s"]).unwrap();
+        assert_eq!(exec.options, ["--ext", "-E", "-d", "--exec"]);
+        assert_eq!(exec.script, "s");
+        assert!(exec.args.is_empty());
+        let exec = parse(&["-d", "Ubuntu", "--shell", "--exec", "s.sh", "a b"]).unwrap();
+        assert_eq!(exec.options, ["-d", "Ubuntu", "--shell"]);
+        assert_eq!(exec.script, "s.sh");
+        assert_eq!(exec.args, ["a b"]);
+        // legacy form
+        let exec = parse(&["--ext", "sh", "-E", "s.sh", "-E", "--"]).unwrap();
+        assert_eq!(exec.options, ["--ext", "sh"]);
+        assert_eq!(exec.script, "s.sh");
+        assert_eq!(exec.args, ["-E", "--"]);
+        let exec = parse(&["-E", "--exec"]).unwrap();
+        assert!(exec.options.is_empty());
+        assert_eq!(exec.script, "--exec");
+        assert!(parse(&["--ext", "sh", "s.sh"]).is_none());
+        assert!(parse(&["--ext", "sh", "--exec", "--"]).is_none());
+        assert!(parse(&["-d", "-E"]).is_none());
+        assert!(parse(&[]).is_none());
+    }
+    #[test]
+    fn test_wsl_setup_error() {
+        assert!(matches!(
+            wsl_setup_error(
+                "WSL 2 requires an update to its kernel component. For information \
+                 please visit https://aka.ms/wsl2kernel"
+            ),
+            Some(Error::WSLUpdateRequired)
+        ));
+        assert!(matches!(
+            wsl_setup_error("Error code: Wsl/Service/0x800701bc"),
+            Some(Error::WSLUpdateRequired)
+        ));
+        assert!(matches!(
+            wsl_setup_error("Please restart your computer to finish the update."),
+            Some(Error::WSLRestartRequired)
+        ));
+        assert!(wsl_setup_error("bash: wslpath: command not found").is_none());
+    }
+    #[test]
+    fn test_decode_wsl_output() {
+        assert_eq!(decode_wsl_output(b"W\0S\0L\0"), "WSL");
+        assert_eq!(decode_wsl_output("p\u{e4}th".as_bytes()), "p\u{e4}th");
+    }
+    #[test]
+    fn test_mount_root() {
+        assert_eq!(normalize_mount_root("/mnt/"), "/mnt");
+        assert_eq!(normalize_mount_root("/"), "/");
+        assert_eq!(drive_mount_path("/mnt", 'C'), "/mnt/c");
+        assert_eq!(drive_mount_path("/", 'd'), "/d");
+        assert!(is_valid_mount_root("/media/win"));
+        assert!(!is_valid_mount_root("mnt"));
+        assert!(!is_valid_mount_root("/mnt'; rm -rf ~; '"));
     }
 }
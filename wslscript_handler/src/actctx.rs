@@ -0,0 +1,75 @@
+//! Activation context of the common controls v6 manifest.
+//!
+//! The loader applies the manifest embedded in an executable, but a DLL's
+//! manifest only takes effect within an explicitly created activation
+//! context. Without one, windows get the controls of the host process,
+//! which may not have activated common controls v6 at all.
+
+use std::sync::Mutex;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HANDLE, HMODULE};
+use windows::Win32::System::ApplicationInstallationAndServicing::*;
+use windows::Win32::System::WindowsProgramming::{
+    ACTCTX_FLAG_HMODULE_VALID, ACTCTX_FLAG_RESOURCE_NAME_VALID,
+};
+use windows::Win32::UI::WindowsAndMessaging::CREATEPROCESS_MANIFEST_RESOURCE_ID;
+
+/// Activation context created from the DLL's manifest.
+static ACT_CTX: Mutex<Option<HANDLE>> = Mutex::new(None);
+
+/// Activation context active on the current thread until dropped.
+pub(crate) struct Activation(usize);
+
+impl Drop for Activation {
+    fn drop(&mut self) {
+        let _ = unsafe { DeactivateActCtx(0, self.0) };
+    }
+}
+
+/// Activate the DLL's activation context on the current thread.
+///
+/// Returns None if the context couldn't be activated, in which case windows
+/// are created with the host's controls.
+pub(crate) fn activate() -> Option<Activation> {
+    let handle = {
+        let mut ctx = ACT_CTX.lock().ok()?;
+        match *ctx {
+            Some(handle) => handle,
+            None => match create() {
+                Ok(handle) => *ctx.insert(handle),
+                Err(e) => {
+                    log::warn!("Failed to create activation context: {}", e);
+                    return None;
+                }
+            },
+        }
+    };
+    let mut cookie = 0;
+    match unsafe { ActivateActCtx(handle, &mut cookie) } {
+        Ok(()) => Some(Activation(cookie)),
+        Err(e) => {
+            log::warn!("Failed to activate activation context: {}", e);
+            None
+        }
+    }
+}
+
+/// Release the activation context, eg. before the DLL is unloaded.
+pub(crate) fn release() {
+    if let Some(handle) = ACT_CTX.lock().ok().and_then(|mut ctx| ctx.take()) {
+        unsafe { ReleaseActCtx(handle) };
+    }
+}
+
+/// Create activation context from the manifest resource of the DLL.
+fn create() -> windows::core::Result<HANDLE> {
+    let ctx = ACTCTXW {
+        cbSize: std::mem::size_of::<ACTCTXW>() as u32,
+        dwFlags: ACTCTX_FLAG_HMODULE_VALID | ACTCTX_FLAG_RESOURCE_NAME_VALID,
+        // winres embeds the manifest with the resource ID of executables
+        lpResourceName: PCWSTR(CREATEPROCESS_MANIFEST_RESOURCE_ID as usize as _),
+        hModule: HMODULE(crate::interface::dll_handle() as isize),
+        ..Default::default()
+    };
+    unsafe { CreateActCtxW(&ctx) }
+}
@@ -0,0 +1,161 @@
+//! Cache of WSL options for registered extensions.
+//!
+//! Dropping files repeatedly onto the same kind of script would otherwise
+//! read the configuration from the registry on every drop.
+//! Cache is invalidated whenever the relevant registry keys change.
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, ERROR_SUCCESS, HANDLE, WAIT_OBJECT_0};
+use windows::Win32::System::Registry::*;
+use windows::Win32::System::Threading::{CreateEventW, ResetEvent, WaitForSingleObject};
+use wslscript_common::registry::{self, CLASSES_SUBKEY, LXSS_SUBKEY};
+use wslscript_common::{wcstring, wsl};
+
+/// Maximum age of a cached entry.
+///
+/// Guards against change notifications that were missed.
+const MAX_AGE: Duration = Duration::from_secs(300);
+
+static CACHE: Lazy<Mutex<OptionsCache>> = Lazy::new(|| Mutex::new(OptionsCache::new()));
+
+/// Get WSL options for an extension, reading the registry only when needed.
+///
+/// Returns None if the extension isn't registered.
+pub(crate) fn get_wsl_options(ext: &str) -> Option<wsl::WSLOptions> {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.invalidate_if_changed();
+    let key = registry::normalize_extension(ext);
+    if let Some((opts, time)) = cache.entries.get(&key) {
+        if time.elapsed() < MAX_AGE {
+            log::debug!("Using cached options for .{}", ext);
+            return Some(opts.clone());
+        }
+    }
+    let opts = wsl::WSLOptions::from_ext(ext)?;
+    cache.entries.insert(key, (opts.clone(), Instant::now()));
+    Some(opts)
+}
+
+/// Clear cache and release registry watchers.
+pub(crate) fn clear() {
+    if let Some(cache) = Lazy::get(&CACHE) {
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.entries.clear();
+        cache.watchers.clear();
+    }
+}
+
+struct OptionsCache {
+    /// Options and the time they were read, keyed by lowercase extension.
+    entries: HashMap<String, (wsl::WSLOptions, Instant)>,
+    /// Watchers for registry keys affecting the options.
+    watchers: Vec<KeyWatcher>,
+}
+
+impl OptionsCache {
+    fn new() -> Self {
+        let watchers = [CLASSES_SUBKEY, LXSS_SUBKEY]
+            .iter()
+            .filter_map(|subkey| {
+                KeyWatcher::new(subkey).or_else(|| {
+                    log::warn!("Failed to watch registry key {}", subkey);
+                    None
+                })
+            })
+            .collect();
+        Self {
+            entries: HashMap::new(),
+            watchers,
+        }
+    }
+
+    /// Clear cached entries if any of the watched keys have changed.
+    fn invalidate_if_changed(&mut self) {
+        // check every watcher to re-arm all signalled notifications
+        let mut changed = false;
+        for w in self.watchers.iter_mut() {
+            changed |= w.changed();
+        }
+        if changed && !self.entries.is_empty() {
+            log::debug!("Registry changed, invalidating cached options");
+            self.entries.clear();
+        }
+    }
+}
+
+/// Change notification for a registry key under HKCU, including subkeys.
+struct KeyWatcher {
+    key: HKEY,
+    /// Event signalled on change.
+    event: HANDLE,
+    /// Whether notification was successfully requested.
+    armed: bool,
+}
+
+// Registry key and event handles may be used from any thread.
+unsafe impl Send for KeyWatcher {}
+
+impl KeyWatcher {
+    fn new(subkey: &str) -> Option<Self> {
+        let s = wcstring(subkey);
+        let mut key = HKEY::default();
+        let rv = unsafe {
+            RegOpenKeyExW(
+                HKEY_CURRENT_USER,
+                PCWSTR(s.as_ptr()),
+                0,
+                KEY_NOTIFY,
+                &mut key,
+            )
+        };
+        if rv != ERROR_SUCCESS {
+            return None;
+        }
+        let event = match unsafe { CreateEventW(None, true, false, PCWSTR::null()) } {
+            Ok(event) => event,
+            Err(_) => {
+                let _ = unsafe { RegCloseKey(key) };
+                return None;
+            }
+        };
+        let mut watcher = Self {
+            key,
+            event,
+            armed: false,
+        };
+        watcher.arm();
+        watcher.armed.then_some(watcher)
+    }
+
+    /// Request notification of the next change.
+    fn arm(&mut self) {
+        let _ = unsafe { ResetEvent(self.event) };
+        // thread agnostic, because the calling thread may exit before the change
+        let filter =
+            REG_NOTIFY_CHANGE_NAME | REG_NOTIFY_CHANGE_LAST_SET | REG_NOTIFY_THREAD_AGNOSTIC;
+        let rv = unsafe { RegNotifyChangeKeyValue(self.key, true, filter, self.event, true) };
+        self.armed = rv == ERROR_SUCCESS;
+    }
+
+    /// Check whether the key has changed since the previous check.
+    ///
+    /// Always returns true if changes can't be tracked.
+    fn changed(&mut self) -> bool {
+        if self.armed && unsafe { WaitForSingleObject(self.event, 0) } != WAIT_OBJECT_0 {
+            return false;
+        }
+        self.arm();
+        true
+    }
+}
+
+impl Drop for KeyWatcher {
+    fn drop(&mut self) {
+        let _ = unsafe { RegCloseKey(self.key) };
+        let _ = unsafe { CloseHandle(self.event) };
+    }
+}
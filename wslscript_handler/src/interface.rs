@@ -9,7 +9,7 @@ use std::cell::RefCell;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use wchar::wchar_t;
+use std::sync::Once;
 use widestring::WideCStr;
 use winapi::shared::guiddef;
 use winapi::shared::minwindef as win;
@@ -19,9 +19,11 @@ use winapi::um::winnt;
 use winapi::um::winuser;
 use windows::core as wc;
 use windows::core::Interface;
-use windows::Win32::UI::Shell;
-use windows::Win32::{Foundation, System::Com, System::Ole, System::SystemServices};
+use windows::Win32::{
+    Foundation, System::Com, System::Memory, System::Ole, System::SystemServices,
+};
 use wslscript_common::error::*;
+use wslscript_common::win32;
 
 use crate::progress::ProgressWindow;
 
@@ -34,16 +36,81 @@ use crate::progress::ProgressWindow;
 static CLASS_FACTORY_CLSID: Lazy<Guid> =
     Lazy::new(|| Guid::from_str("00000001-0000-0000-c000-000000000046").unwrap());
 
-/// Semaphore to keep track of running WSL threads.
+/// Number of references keeping the DLL loaded.
 ///
-/// DLL shall not be released if there are threads running.
-pub(crate) static THREAD_COUNTER: AtomicUsize = AtomicUsize::new(0);
+/// DLL shall not be released while any `ModuleRef` is alive.
+static MODULE_REFS: AtomicUsize = AtomicUsize::new(0);
+
+/// Reference that keeps the DLL loaded until dropped.
+///
+/// Held by every COM object, and by every thread and window spawned
+/// by the handler for the duration of their work.
+pub(crate) struct ModuleRef(());
+
+impl ModuleRef {
+    /// Acquire a reference to the DLL module.
+    pub(crate) fn acquire() -> Self {
+        MODULE_REFS.fetch_add(1, Ordering::SeqCst);
+        Self(())
+    }
+}
+
+impl Default for ModuleRef {
+    fn default() -> Self {
+        Self::acquire()
+    }
+}
+
+impl Drop for ModuleRef {
+    fn drop(&mut self) {
+        MODULE_REFS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Number of live COM objects.
+static LIVE_OBJECTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of locks taken with `IClassFactory::LockServer`.
+///
+/// Each lock is also counted in `MODULE_REFS`.
+static SERVER_LOCKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counted reference of a live COM object.
+///
+/// Object counts are logged to help find objects leaked by the host.
+struct ObjectRef {
+    _module: ModuleRef,
+}
+
+impl Default for ObjectRef {
+    fn default() -> Self {
+        let n = LIVE_OBJECTS.fetch_add(1, Ordering::SeqCst) + 1;
+        log::debug!("Object created, {} live objects", n);
+        Self {
+            _module: ModuleRef::acquire(),
+        }
+    }
+}
+
+impl Drop for ObjectRef {
+    fn drop(&mut self) {
+        let n = LIVE_OBJECTS.fetch_sub(1, Ordering::SeqCst) - 1;
+        log::debug!("Object released, {} live objects", n);
+    }
+}
 
 /// Handle to loaded DLL module.
 static mut DLL_HANDLE: win::HINSTANCE = std::ptr::null_mut();
 
+/// Guard for the one-time initialization on first use of the DLL.
+static INIT: Once = Once::new();
+
 /// DLL module entry point.
 ///
+/// Runs under the loader lock, so no work is done beyond storing the module
+/// handle. Everything else is set up on first use by `ensure_initialized`
+/// and torn down in `DllCanUnloadNow`.
+///
 /// See: https://docs.microsoft.com/en-us/windows/win32/dlls/dllmain
 #[no_mangle]
 extern "system" fn DllMain(
@@ -51,60 +118,78 @@ extern "system" fn DllMain(
     reason: win::DWORD,
     _reserved: win::LPVOID,
 ) -> win::BOOL {
-    match reason {
-        winnt::DLL_PROCESS_ATTACH => {
-            // store module instance to global variable
-            unsafe { DLL_HANDLE = hinstance };
-            // set up logging
-            #[cfg(feature = "debug")]
-            if let Ok(mut path) = get_module_path(hinstance) {
-                let stem = path.file_stem().map_or_else(
-                    || "debug.log".to_string(),
-                    |s| s.to_string_lossy().into_owned(),
-                );
-                path.pop();
-                path.push(format!("{}.log", stem));
-                if simple_logging::log_to_file(&path, log::LevelFilter::Debug).is_err() {
-                    unsafe {
-                        use winapi::um::winuser::*;
-                        let text = wslscript_common::wcstring(format!(
-                            "Failed to set up logging to {}",
-                            path.to_string_lossy()
-                        ));
-                        MessageBoxW(
-                            std::ptr::null_mut(),
-                            text.as_ptr(),
-                            wchar::wchz!("Error").as_ptr(),
-                            MB_OK | MB_ICONERROR | MB_SERVICE_NOTIFICATION,
-                        );
-                    }
-                }
-            }
-            log::debug!("DLL_PROCESS_ATTACH");
-            return win::TRUE;
-        }
-        winnt::DLL_PROCESS_DETACH => {
-            log::debug!("DLL_PROCESS_DETACH");
-            ProgressWindow::unregister_window_class();
+    if reason == winnt::DLL_PROCESS_ATTACH {
+        // store module instance to global variable
+        unsafe { DLL_HANDLE = hinstance };
+    }
+    win::TRUE
+}
+
+/// Initialize the DLL on first call into it.
+fn ensure_initialized() {
+    INIT.call_once(|| {
+        #[cfg(feature = "debug")]
+        setup_logging();
+        log::debug!("DLL initialized");
+    });
+}
+
+/// Set up logging to a file next to the DLL.
+#[cfg(feature = "debug")]
+fn setup_logging() {
+    let Ok(mut path) = get_module_path(unsafe { DLL_HANDLE }) else {
+        return;
+    };
+    let stem = path.file_stem().map_or_else(
+        || "debug.log".to_string(),
+        |s| s.to_string_lossy().into_owned(),
+    );
+    path.pop();
+    path.push(format!("{}.log", stem));
+    if simple_logging::log_to_file(&path, log::LevelFilter::Debug).is_err() {
+        unsafe {
+            use winapi::um::winuser::*;
+            let text = wslscript_common::wcstring(format!(
+                "Failed to set up logging to {}",
+                path.to_string_lossy()
+            ));
+            MessageBoxW(
+                std::ptr::null_mut(),
+                text.as_ptr(),
+                wchar::wchz!("Error").as_ptr(),
+                MB_OK | MB_ICONERROR | MB_SERVICE_NOTIFICATION,
+            );
         }
-        winnt::DLL_THREAD_ATTACH => {}
-        winnt::DLL_THREAD_DETACH => {}
-        _ => {}
     }
-    win::FALSE
 }
 
 /// Called to check whether DLL can be unloaded from memory.
 ///
+/// Resources set up on use are released when unloading is permitted,
+/// since the window procedure of the progress window class lives in
+/// this DLL.
+///
 /// See: https://docs.microsoft.com/en-us/windows/win32/api/combaseapi/nf-combaseapi-dllcanunloadnow
 #[no_mangle]
 extern "system" fn DllCanUnloadNow() -> winnt::HRESULT {
-    let n = THREAD_COUNTER.load(Ordering::SeqCst);
+    ensure_initialized();
+    let n = MODULE_REFS.load(Ordering::SeqCst);
     if n > 0 {
-        log::info!("{} WSL threads running, denying DLL unload", n);
+        log::info!(
+            "{} module references held ({} objects, {} server locks), denying DLL unload",
+            n,
+            LIVE_OBJECTS.load(Ordering::SeqCst),
+            SERVER_LOCKS.load(Ordering::SeqCst)
+        );
+        winerror::S_FALSE
+    } else if wslscript_common::wsl::console_icons_in_use() {
+        log::info!("Console icons in use, denying DLL unload");
         winerror::S_FALSE
     } else {
         log::info!("Permitting DLL unload");
+        ProgressWindow::unregister_window_class();
+        crate::actctx::release();
+        crate::cache::clear();
         winerror::S_OK
     }
 }
@@ -118,10 +203,11 @@ extern "system" fn DllGetClassObject(
     iid: guiddef::REFIID,
     result: *mut win::LPVOID,
 ) -> winnt::HRESULT {
+    ensure_initialized();
     let class_guid = guid_from_ref(class_id);
     let interface_guid = guid_from_ref(iid);
     // expect our registered class ID
-    if wslscript_common::DROP_HANDLER_CLSID.eq(&class_guid) {
+    if wslscript_common::drop_handler_clsid().eq(&class_guid) {
         // expect IClassFactory interface to be requested
         if !CLASS_FACTORY_CLSID.eq(&interface_guid) {
             log::warn!("Expected IClassFactory, got {}", interface_guid);
@@ -146,13 +232,15 @@ extern "system" fn DllGetClassObject(
 /// See: https://docs.microsoft.com/en-us/windows/win32/api/olectl/nf-olectl-dllregisterserver
 #[no_mangle]
 extern "system" fn DllRegisterServer() -> winnt::HRESULT {
+    ensure_initialized();
     let hinstance = unsafe { DLL_HANDLE };
     let path = match get_module_path(hinstance) {
         Ok(p) => p,
         Err(_) => return winerror::E_UNEXPECTED,
     };
     log::debug!("DllRegisterServer for {}", path.to_string_lossy());
-    match wslscript_common::registry::add_server_to_registry(&path) {
+    let scope = wslscript_common::registry::server_scope();
+    match wslscript_common::registry::add_server_to_registry(scope, &path) {
         Ok(_) => (),
         Err(e) => {
             log::error!("Failed to register server: {}", e);
@@ -167,7 +255,9 @@ extern "system" fn DllRegisterServer() -> winnt::HRESULT {
 /// See: https://docs.microsoft.com/en-us/windows/win32/api/olectl/nf-olectl-dllunregisterserver
 #[no_mangle]
 extern "system" fn DllUnregisterServer() -> winnt::HRESULT {
-    match wslscript_common::registry::remove_server_from_registry() {
+    ensure_initialized();
+    let scope = wslscript_common::registry::server_scope();
+    match wslscript_common::registry::remove_server_from_registry(scope) {
         Ok(_) => (),
         Err(e) => {
             log::error!("Failed to unregister server: {}", e);
@@ -177,6 +267,38 @@ extern "system" fn DllUnregisterServer() -> winnt::HRESULT {
     winerror::S_OK
 }
 
+/// Register or unregister the server for all users with
+/// `regsvr32 /n /i:machine`, which requires administrator rights.
+///
+/// The CLSID is stored under HKLM, separate from per-user installations.
+///
+/// See: https://learn.microsoft.com/en-us/windows/win32/api/shlwapi/nf-shlwapi-dllinstall
+#[no_mangle]
+extern "system" fn DllInstall(install: win::BOOL, cmd_line: wc::PCWSTR) -> winnt::HRESULT {
+    use wslscript_common::registry::{self, ServerScope};
+    ensure_initialized();
+    let cmd_line = unsafe { cmd_line.to_string() }.unwrap_or_default();
+    if !cmd_line.eq_ignore_ascii_case("machine") {
+        return winerror::E_INVALIDARG;
+    }
+    let result = if install != 0 {
+        get_module_path(unsafe { DLL_HANDLE }).and_then(|path| {
+            log::debug!("DllInstall for {}", path.to_string_lossy());
+            registry::ensure_drop_handler_clsid(ServerScope::Machine)?;
+            registry::add_server_to_registry(ServerScope::Machine, &path)
+        })
+    } else {
+        registry::remove_server_from_registry(ServerScope::Machine)
+    };
+    match result {
+        Ok(_) => winerror::S_OK,
+        Err(e) => {
+            log::error!("Failed to update machine-wide server: {}", e);
+            winerror::E_UNEXPECTED
+        }
+    }
+}
+
 /// Convert Win32 GUID pointer to Guid struct.
 const fn guid_from_ref(clsid: *const guiddef::GUID) -> Guid {
     Guid {
@@ -184,6 +306,16 @@ const fn guid_from_ref(clsid: *const guiddef::GUID) -> Guid {
     }
 }
 
+/// Get handle to this DLL module.
+pub(crate) fn dll_handle() -> win::HINSTANCE {
+    unsafe { DLL_HANDLE }
+}
+
+/// Get path to this DLL file.
+pub(crate) fn dll_path() -> Result<PathBuf, Error> {
+    get_module_path(unsafe { DLL_HANDLE })
+}
+
 /// Get path to loaded DLL file.
 fn get_module_path(hinstance: win::HINSTANCE) -> Result<PathBuf, Error> {
     use std::ffi::OsString;
@@ -216,6 +348,8 @@ bitflags::bitflags! {
 #[derive(Default)]
 struct Handler {
     target: RefCell<PathBuf>,
+    /// Keeps the DLL loaded while the object is alive.
+    _object: ObjectRef,
 }
 
 /// IClassFactory interface.
@@ -241,9 +375,24 @@ impl Com::IClassFactory_Impl for Handler {
     }
 
     /// https://learn.microsoft.com/en-us/windows/win32/api/unknwn/nf-unknwn-iclassfactory-lockserver
-    fn LockServer(&self, _flock: Foundation::BOOL) -> wc::Result<()> {
-        log::debug!("IClassFactory::LockServer");
-        Err(wc::Error::from(Foundation::E_NOTIMPL))
+    fn LockServer(&self, flock: Foundation::BOOL) -> wc::Result<()> {
+        if flock.as_bool() {
+            SERVER_LOCKS.fetch_add(1, Ordering::SeqCst);
+            MODULE_REFS.fetch_add(1, Ordering::SeqCst);
+        } else if SERVER_LOCKS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+        {
+            MODULE_REFS.fetch_sub(1, Ordering::SeqCst);
+        } else {
+            log::warn!("IClassFactory::LockServer unlocked without a lock");
+        }
+        log::debug!(
+            "IClassFactory::LockServer({}), {} server locks",
+            flock.as_bool(),
+            SERVER_LOCKS.load(Ordering::SeqCst)
+        );
+        Ok(())
     }
 }
 
@@ -254,7 +403,7 @@ impl Com::IPersist_Impl for Handler {
     /// https://learn.microsoft.com/en-us/windows/win32/api/objidl/nf-objidl-ipersist-getclassid
     fn GetClassID(&self) -> wc::Result<wc::GUID> {
         log::debug!("IPersist::GetClassID");
-        let guid = wslscript_common::DROP_HANDLER_CLSID.0;
+        let guid = wslscript_common::drop_handler_clsid().0;
         wc::Result::Ok(wc::GUID::from_values(
             guid.Data1, guid.Data2, guid.Data3, guid.Data4,
         ))
@@ -347,7 +496,7 @@ impl Ole::IDropTarget_Impl for Handler {
         &self,
         pdataobj: Option<&Com::IDataObject>,
         grfkeystate: SystemServices::MODIFIERKEYS_FLAGS,
-        _pt: &Foundation::POINTL,
+        pt: &Foundation::POINTL,
         pdweffect: *mut Ole::DROPEFFECT,
     ) -> wc::Result<()> {
         log::debug!("IDropTarget::Drop");
@@ -358,7 +507,7 @@ impl Ole::IDropTarget_Impl for Handler {
         let obj = pdataobj.ok_or_else(|| wc::Error::from(Foundation::E_UNEXPECTED))?;
         let paths = get_paths_from_data_obj(obj)?;
         let keys = KeyState::from_bits_truncate(grfkeystate.0);
-        super::handle_dropped_files(target, paths, keys)
+        super::handle_dropped_files(target, paths, keys, (pt.x, pt.y))
             .and_then(|_| {
                 unsafe { *pdweffect = Ole::DROPEFFECT_COPY };
                 Ok(())
@@ -388,16 +537,18 @@ fn get_paths_from_data_obj(obj: &Com::IDataObject) -> wc::Result<Vec<PathBuf>> {
     if medium.tymed != Com::TYMED_HGLOBAL.0 as _ {
         return Err(wc::Error::from(Foundation::E_UNEXPECTED));
     }
-    let ptr = unsafe { medium.u.hGlobal.0 };
-    // https://learn.microsoft.com/en-us/windows/win32/api/shlobj_core/ns-shlobj_core-dropfiles
-    let dropfiles = unsafe { &*(ptr as *const Shell::DROPFILES) };
-    if !dropfiles.fWide.as_bool() {
-        log::warn!("ANSI not supported");
-        return Err(wc::Error::from(Foundation::E_UNEXPECTED));
-    }
-    // file name array follows the DROPFILES structure
-    let farray = unsafe { ptr.cast::<u8>().offset(dropfiles.pFiles as _) };
-    let paths = parse_filename_array_wide(farray as *const wchar_t);
+    let hglobal = unsafe { medium.u.hGlobal };
+    // size may be rounded up from the requested size, but never down
+    let size = unsafe { Memory::GlobalSize(hglobal) };
+    let ptr = unsafe { Memory::GlobalLock(hglobal) };
+    let paths = if ptr.is_null() || size == 0 {
+        None
+    } else {
+        let data = unsafe { std::slice::from_raw_parts(ptr.cast::<u8>(), size) };
+        let paths = win32::parse_drop_files(data);
+        let _ = unsafe { Memory::GlobalUnlock(hglobal) };
+        paths
+    };
     if medium.pUnkForRelease.is_some() {
         log::debug!("Calling IUnknown::Release()");
         unsafe { std::mem::ManuallyDrop::drop(&mut medium.pUnkForRelease) }
@@ -407,23 +558,8 @@ fn get_paths_from_data_obj(obj: &Com::IDataObject) -> wc::Result<Vec<PathBuf>> {
             log::debug!("GlobalFree(): {}", e);
         });
     }
-    Ok(paths)
-}
-
-/// Parse file name array to list of paths.
-///
-/// See: https://docs.microsoft.com/en-us/windows/win32/shell/clipboard#cf_hdrop
-fn parse_filename_array_wide(mut ptr: *const wchar_t) -> Vec<PathBuf> {
-    let mut paths = Vec::<PathBuf>::new();
-    loop {
-        let s = unsafe { WideCStr::from_ptr_str(ptr) };
-        // terminated by double null, so last slice is empty
-        if s.is_empty() {
-            break;
-        }
-        // advance pointer
-        ptr = unsafe { ptr.offset(s.len() as isize + 1) };
-        paths.push(PathBuf::from(s.to_os_string()));
-    }
-    paths
+    paths.ok_or_else(|| {
+        log::warn!("Malformed file name array");
+        wc::Error::from(Foundation::E_UNEXPECTED)
+    })
 }
@@ -1,14 +1,18 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
-use std::sync::mpsc;
+use std::sync::{mpsc, Mutex};
 use std::thread;
-use winapi::shared::windef;
-use winapi::um::winuser;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HWND, LPARAM, POINT, WPARAM};
+use windows::Win32::Graphics::Gdi;
+use windows::Win32::UI::WindowsAndMessaging as winuser;
 use wslscript_common::error::*;
-use wslscript_common::wsl;
+use wslscript_common::{registry, throttle, wcstring, win32, wsl};
 
 use crate::progress::ProgressWindow;
 
+mod actctx;
+mod cache;
 mod interface;
 mod progress;
 
@@ -18,6 +22,11 @@ const CONVERT_WITH_PROGRESS_THRESHOLD: usize = 10;
 #[cfg(feature = "debug")]
 const CONVERT_WITH_PROGRESS_THRESHOLD: usize = 1;
 
+/// Approximate size of a new console window in pixels.
+///
+/// Used to keep the window within the monitor when placed at the drop point.
+const CONSOLE_SIZE: (i32, i32) = (680, 420);
+
 /// Handle files dropped to registered filetype.
 ///
 /// See: https://docs.microsoft.com/en-us/windows/win32/api/oleidl/nf-oleidl-idroptarget-drop
@@ -25,44 +34,402 @@ fn handle_dropped_files(
     target: PathBuf,
     mut paths: Vec<PathBuf>,
     key_state: interface::KeyState,
+    drop_point: (i32, i32),
 ) -> Result<(), Error> {
     log::debug!(
-        "Dropped {} items to {} with keys {:?}",
+        "Dropped {} items to {} at {:?} with keys {:?}",
         paths.len(),
         target.to_string_lossy(),
+        drop_point,
         key_state
     );
-    let opts = get_wsl_options(&target)?;
+    let target = win32::long_path(&target);
+    let mut opts = get_wsl_options(&target)?;
+    // notifier is shared by all drops, so it's cleared when not wanted
+    let notifier: Option<wsl::WaitNotifier> = opts.show_progress().then_some(show_wsl_wait);
+    wsl::set_wait_notifier(notifier);
+    if opts.console_at_drop() {
+        opts.set_console_position(console_position(drop_point));
+    }
+    // expand short names, since they're not converted to WSL paths
+    for path in paths.iter_mut() {
+        *path = win32::long_path(path);
+    }
     paths.insert(0, target);
-    // increment thread counter
-    interface::THREAD_COUNTER.fetch_add(1, Ordering::SeqCst);
+    // keep DLL loaded until the thread finishes
+    let module = interface::ModuleRef::acquire();
     // move further processing to thread
     thread::spawn(move || {
         log::debug!("Spawned thread to invoke WSL");
         if let Err(e) = run_wsl(paths, opts) {
             log::error!("Failed to invoke WSL: {}", e);
+            // nothing to report if the user cancelled
+            if !matches!(e, Error::Cancel) {
+                show_wsl_error(&e);
+            }
         }
-        // Decrement counter when thread finishes. Here all moved variables
+        // Release the reference when thread finishes. Here all moved variables
         // (paths and opts) have already been dropped, so DLL may be safely unloaded.
-        interface::THREAD_COUNTER.fetch_sub(1, Ordering::SeqCst);
+        drop(module);
     });
     Ok(())
 }
 
+/// Get the console window position for a drop point in screen coordinates.
+///
+/// Window is placed at the drop point, but shifted to fit within the work
+/// area of the monitor containing the point.
+fn console_position(drop_point: (i32, i32)) -> (i32, i32) {
+    let pt = POINT {
+        x: drop_point.0,
+        y: drop_point.1,
+    };
+    let monitor = unsafe { Gdi::MonitorFromPoint(pt, Gdi::MONITOR_DEFAULTTONEAREST) };
+    let mut mi = Gdi::MONITORINFO {
+        cbSize: std::mem::size_of::<Gdi::MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    if !unsafe { Gdi::GetMonitorInfoW(monitor, &mut mi) }.as_bool() {
+        return drop_point;
+    }
+    let area = mi.rcWork;
+    let x = pt.x.min(area.right - CONSOLE_SIZE.0).max(area.left);
+    let y = pt.y.min(area.bottom - CONSOLE_SIZE.1).max(area.top);
+    (x, y)
+}
+
+/// Display an error that prevented WSL from starting.
+///
+/// Drop handling happens in the background, so without this the user
+/// would see nothing at all.
+fn show_wsl_error(e: &Error) {
+    if matches!(e.inner(), Error::WSLUpdateRequired) {
+        return offer_wsl_update();
+    }
+    let mut text = format!("Failed to invoke WSL.\n\n{}", e);
+    if let Error::WSLProcessError { command, .. } = e.inner() {
+        text.push_str(&format!("\n\nCommand:\n{}", command));
+    }
+    if let Some(stderr) = e.stderr() {
+        return win32::error_dialog("WSL Script", &text, stderr);
+    }
+    unsafe {
+        winuser::MessageBoxW(
+            HWND::default(),
+            PCWSTR(wcstring(text).as_ptr()),
+            w!("WSL Script"),
+            winuser::MB_OK | winuser::MB_ICONERROR | winuser::MB_SETFOREGROUND,
+        );
+    }
+}
+
+/// Offer to update WSL when a drop failed because WSL needs an update.
+fn offer_wsl_update() {
+    let result = unsafe {
+        winuser::MessageBoxW(
+            HWND::default(),
+            w!("WSL requires an update before it can run scripts.\n\nUpdate WSL now?"),
+            w!("WSL Script"),
+            winuser::MB_YESNO | winuser::MB_ICONWARNING | winuser::MB_SETFOREGROUND,
+        )
+    };
+    if result != winuser::IDYES {
+        return;
+    }
+    if let Err(e) = wsl::update_wsl() {
+        win32::error_message(&wcstring(format!("Failed to update WSL: {}", e)));
+    }
+}
+
+/// Window shown while waiting for WSL to start, and its thread.
+static WAIT_WINDOW: Mutex<Option<(ProgressWindowHandle, thread::JoinHandle<()>)>> =
+    Mutex::new(None);
+
+/// Show or close a progress window while waiting for WSL to start.
+fn show_wsl_wait(waiting: bool) {
+    let Ok(mut wait_window) = WAIT_WINDOW.lock() else {
+        return;
+    };
+    if !waiting {
+        if let Some((hwnd, joiner)) = wait_window.take() {
+            let _ =
+                unsafe { winuser::PostMessageW(hwnd.0, winuser::WM_CLOSE, WPARAM(0), LPARAM(0)) };
+            let _ = joiner.join();
+        }
+        return;
+    }
+    if wait_window.is_some() {
+        return;
+    }
+    let (tx_hwnd, rx_hwnd) = mpsc::channel::<ProgressWindowHandle>();
+    let module = interface::ModuleRef::acquire();
+    let joiner = thread::spawn(move || {
+        let _module = module;
+        // waiting can't be cancelled, but the window can be closed
+        let (tx_cancel, _rx_cancel) = mpsc::channel::<()>();
+        let wnd = match ProgressWindow::new(0, None, tx_cancel) {
+            Ok(wnd) => wnd,
+            Err(e) => {
+                log::error!("Failed to create progress window: {}", e);
+                return;
+            }
+        };
+        wnd.set_title(wslscript_common::wcstr(wchar::wchz!(
+            "Waiting for WSL to start..."
+        )));
+        let _ = tx_hwnd.send(ProgressWindowHandle(wnd.handle()));
+        if let Err(e) = wnd.run() {
+            log::error!("Window thread returned error: {}", e);
+        }
+    });
+    match rx_hwnd.recv() {
+        Ok(hwnd) => *wait_window = Some((hwnd, joiner)),
+        Err(_) => {
+            let _ = joiner.join();
+        }
+    }
+}
+
 /// Invoke WSL with given path arguments.
 ///
 /// Paths are in Win32 context.
-fn run_wsl(win_paths: Vec<PathBuf>, opts: wsl::WSLOptions) -> Result<(), Error> {
-    let wsl_paths = if win_paths.len() > CONVERT_WITH_PROGRESS_THRESHOLD {
+fn run_wsl(mut win_paths: Vec<PathBuf>, mut opts: wsl::WSLOptions) -> Result<(), Error> {
+    wsl::check_self_invocation(&win_paths[0])?;
+    if registry::verify_signatures() {
+        check_handler_signatures(&win_paths[0])?;
+    }
+    ensure_script_available(&win_paths[0])?;
+    // counted instance is kept until the script exits, or until all
+    // batches have been started
+    let slot = throttle::begin_run(&opts)?;
+    let mut args = win_paths.split_off(1);
+    // files on a locked drive would otherwise be reported missing
+    wsl::check_drives(&args)?;
+    validate_dropped_paths(&mut args, opts.abort_on_missing())?;
+    // ask before starting a potentially lengthy conversion
+    if opts.max_args() > 0
+        && args.len() > opts.max_args() as usize
+        && !confirm_many_args(args.len())?
+    {
+        opts.set_args_in_file();
+    }
+    // sort before conversion, since modification times are read from Windows paths
+    wsl::sort_paths(&mut args, opts.arg_order());
+    win_paths.append(&mut args);
+    let script = win_paths[0].clone();
+    let wsl_paths = if opts.show_progress() && win_paths.len() > CONVERT_WITH_PROGRESS_THRESHOLD {
         convert_paths_with_progress(win_paths, &opts)?
     } else {
         wsl::paths_to_wsl(&win_paths, &opts, None)?
     };
-    wsl::run_wsl(&wsl_paths[0], &wsl_paths[1..], &opts)
+    let (wsl_script, args) = (&wsl_paths[0], &wsl_paths[1..]);
+    match opts.drop_policy() {
+        registry::DropPolicy::PerFile(limit) if args.len() > 1 => {
+            run_wsl_batches(&script, wsl_script, args.chunks(1).collect(), limit, &opts)
+        }
+        registry::DropPolicy::Batch(size) if args.len() > size as usize => {
+            let batches = args.chunks(size as usize).collect();
+            run_wsl_batches(&script, wsl_script, batches, 0, &opts)
+        }
+        _ => wsl::run_wsl(&script, wsl_script, args, &opts, slot.is_some()).map(drop),
+    }
+}
+
+/// Warn if handler binaries registered for the script's filetype aren't
+/// signed by the same signer as this library.
+///
+/// Returns `Error::Cancel` if the user chose not to run the script.
+fn check_handler_signatures(script: &Path) -> Result<(), Error> {
+    let dll = interface::dll_path()?;
+    let ext = registry::path_extensions(script)
+        .into_iter()
+        .find(|ext| registry::get_handler_executable_path(ext).is_ok());
+    let ext = match ext {
+        Some(ext) if !registry::handler_signatures_match(&ext, &dll) => ext,
+        _ => return Ok(()),
+    };
+    let text = wcstring(format!(
+        "The WSL Script handler registered for .{} is not signed by the same \
+         publisher as the drop handler. It may have been replaced.\n\n\
+         Run the script anyway?",
+        ext
+    ));
+    let result = unsafe {
+        winuser::MessageBoxW(
+            HWND::default(),
+            PCWSTR(text.as_ptr()),
+            w!("WSL Script"),
+            winuser::MB_OKCANCEL
+                | winuser::MB_ICONWARNING
+                | winuser::MB_DEFBUTTON2
+                | winuser::MB_SETFOREGROUND,
+        )
+    };
+    match result {
+        winuser::IDOK => Ok(()),
+        _ => Err(Error::Cancel),
+    }
+}
+
+/// Download the script if it's an online-only cloud file, eg. in OneDrive.
+///
+/// WSL may see such a file as empty, so the user is told how to make it
+/// available if the download fails. Returns `Error::Cancel` in that case.
+fn ensure_script_available(script: &Path) -> Result<(), Error> {
+    if !win32::is_cloud_placeholder(script) {
+        return Ok(());
+    }
+    log::debug!("Script is a cloud placeholder, downloading");
+    let e = match win32::hydrate_file(script) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    log::debug!("Failed to download script: {}", e);
+    let text = wcstring(format!(
+        "{} is stored online only and couldn't be downloaded.\n\n{}\n\n\
+         Check your connection, or right-click the file and choose \
+         \"Always keep on this device\", then try again.",
+        script.to_string_lossy(),
+        e
+    ));
+    unsafe {
+        winuser::MessageBoxW(
+            HWND::default(),
+            PCWSTR(text.as_ptr()),
+            w!("WSL Script"),
+            winuser::MB_OK | winuser::MB_ICONWARNING | winuser::MB_SETFOREGROUND,
+        );
+    }
+    Err(Error::Cancel)
+}
+
+/// Remove duplicate and missing paths from dropped files.
+///
+/// User is notified of missing files. Unless `abort` is set, the script is
+/// run with the remaining files, if there are any.
+fn validate_dropped_paths(paths: &mut Vec<PathBuf>, abort: bool) -> Result<(), Error> {
+    // maximum number of missing paths to list in the dialog
+    const MAX_LISTED: usize = 10;
+    let count = paths.len();
+    let mut seen = HashSet::new();
+    paths.retain(|p| seen.insert(p.to_string_lossy().to_lowercase()));
+    if paths.len() < count {
+        log::debug!("Removed {} duplicate paths", count - paths.len());
+    }
+    let (found, missing): (Vec<_>, Vec<_>) = paths.drain(..).partition(|p| p.exists());
+    *paths = found;
+    if missing.is_empty() {
+        return Ok(());
+    }
+    log::debug!("{} dropped paths not found", missing.len());
+    let abort = abort || paths.is_empty();
+    let mut text = match missing.len() {
+        1 => "1 item skipped: not found\n".to_string(),
+        n => format!(
+            "{} items skipped: not found\n",
+            win32::format_number(n as u64)
+        ),
+    };
+    for path in missing.iter().take(MAX_LISTED) {
+        text.push_str(&format!("\n{}", path.to_string_lossy()));
+    }
+    if missing.len() > MAX_LISTED {
+        text.push_str(&format!(
+            "\n...and {} more",
+            win32::format_number((missing.len() - MAX_LISTED) as u64)
+        ));
+    }
+    if abort {
+        text.push_str("\n\nScript was not run.");
+    }
+    unsafe {
+        winuser::MessageBoxW(
+            HWND::default(),
+            PCWSTR(wcstring(text).as_ptr()),
+            w!("WSL Script"),
+            winuser::MB_OK | winuser::MB_ICONWARNING | winuser::MB_SETFOREGROUND,
+        );
+    }
+    if abort {
+        return Err(Error::Cancel);
+    }
+    Ok(())
+}
+
+/// Ask the user whether to continue with a large number of dropped files.
+///
+/// Returns false if arguments should be passed via a temporary file,
+/// or `Error::Cancel` if the user cancelled.
+fn confirm_many_args(count: usize) -> Result<bool, Error> {
+    let text = wcstring(format!(
+        "You dropped {} files. Converting and passing them to the script may take a while.\n\n\
+         Yes: continue\n\
+         No: continue and pass the files via an arguments file\n\
+         Cancel: don't run the script",
+        win32::format_number(count as u64)
+    ));
+    let result = unsafe {
+        winuser::MessageBoxW(
+            HWND::default(),
+            PCWSTR(text.as_ptr()),
+            w!("WSL Script"),
+            winuser::MB_YESNOCANCEL | winuser::MB_ICONQUESTION | winuser::MB_SETFOREGROUND,
+        )
+    };
+    match result {
+        winuser::IDYES => Ok(true),
+        winuser::IDNO => Ok(false),
+        _ => Err(Error::Cancel),
+    }
+}
+
+/// Invoke WSL once per batch of arguments.
+///
+/// At most `limit` consoles are kept open at a time, zero meaning no limit.
+/// Remaining batches are run even if some fail, and the first error is returned.
+fn run_wsl_batches(
+    script: &Path,
+    wsl_script: &Path,
+    batches: Vec<&[PathBuf]>,
+    limit: u32,
+    opts: &wsl::WSLOptions,
+) -> Result<(), Error> {
+    if limit == 0 {
+        return batches
+            .into_iter()
+            .map(|args| wsl::run_wsl(script, wsl_script, args, opts, false).map(drop))
+            .fold(Ok(()), Result::and);
+    }
+    let workers = batches.len().min(limit as usize);
+    let queue = Mutex::new(batches.into_iter());
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut result = Ok(());
+                    // each worker waits for its console to close before taking the next batch
+                    while let Some(args) = queue.lock().ok().and_then(|mut q| q.next()) {
+                        let run = wsl::run_wsl(script, wsl_script, args, opts, true);
+                        result = result.and(run.map(drop));
+                    }
+                    result
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| {
+                h.join().unwrap_or_else(|_| {
+                    log::error!("WSL invocation thread panicked");
+                    Ok(())
+                })
+            })
+            .fold(Ok(()), Result::and)
+    })
 }
 
 /// Wrapped progress window handle.
-struct ProgressWindowHandle(windef::HWND);
+struct ProgressWindowHandle(HWND);
 /// Window handles are safe to send across threads.
 unsafe impl Send for ProgressWindowHandle {}
 
@@ -72,17 +439,22 @@ fn convert_paths_with_progress(
     opts: &wsl::WSLOptions,
 ) -> Result<Vec<PathBuf>, Error> {
     let path_count = win_paths.len();
+    let script = win_paths[0].clone();
     // channel to transfer current progress as in number of paths converted
     let (tx_progress, rx_progress) = mpsc::channel::<usize>();
     // channel to signal cancellation
     let (tx_cancel, rx_cancel) = mpsc::channel::<()>();
     // wait for progress updates in a seperate thread
+    let module = interface::ModuleRef::acquire();
     let progress_joiner = thread::spawn(move || {
+        let _module = module;
         // channel to transfer progress window handle to this thread
         let (tx_hwnd, rx_hwnd) = mpsc::channel::<ProgressWindowHandle>();
         // run window in a seperate thread
+        let module = interface::ModuleRef::acquire();
         let window_joiner = thread::spawn(move || {
-            let wnd = match ProgressWindow::new(path_count, tx_cancel) {
+            let _module = module;
+            let wnd = match ProgressWindow::new(path_count, Some(&script), tx_cancel) {
                 Ok(wnd) => wnd,
                 Err(e) => {
                     log::error!("Failed to create progress window: {}", e);
@@ -115,7 +487,14 @@ fn convert_paths_with_progress(
         // post progress to window
         let update_progress = |n: usize| {
             // post WM_PROGRESS message to window's queue
-            unsafe { winuser::PostMessageW(hwnd, progress::WM_PROGRESS, n, path_count as _) };
+            let _ = unsafe {
+                winuser::PostMessageW(
+                    hwnd,
+                    progress::WM_PROGRESS,
+                    WPARAM(n),
+                    LPARAM(path_count as _),
+                )
+            };
         };
         // blocking receive progress updates
         while let Ok(count) = rx_progress.recv() {
@@ -126,7 +505,7 @@ fn convert_paths_with_progress(
             update_progress(count);
         }
         // close progress window
-        unsafe { winuser::PostMessageW(hwnd, winuser::WM_CLOSE, 0, 0) };
+        let _ = unsafe { winuser::PostMessageW(hwnd, winuser::WM_CLOSE, WPARAM(0), LPARAM(0)) };
         // wait for window to be destroyed
         window_joiner.join().unwrap_or_else(|_| {
             log::error!("Progress window thread panicked");
@@ -158,15 +537,19 @@ fn convert_paths_with_progress(
 }
 
 /// Get WSL options from registry based on given filename's extension.
+///
+/// Longest registered extension takes precedence, eg. `tar.gz` over `gz`.
 fn get_wsl_options(path: &Path) -> Result<wsl::WSLOptions, Error> {
-    path.extension()
-        .ok_or_else(|| Error::DropHandlerError("No filename extension".to_owned()))
-        .and_then(|s| {
-            wsl::WSLOptions::from_ext(&s.to_string_lossy()).ok_or_else(|| {
-                Error::DropHandlerError(format!(
-                    "Extension {} not registered.",
-                    s.to_string_lossy()
-                ))
-            })
+    let exts = registry::path_extensions(path);
+    if exts.is_empty() {
+        return Err(Error::DropHandlerError("No filename extension".to_owned()));
+    }
+    exts.iter()
+        .find_map(|ext| cache::get_wsl_options(ext))
+        .ok_or_else(|| {
+            Error::DropHandlerError(format!(
+                "Extension {} not registered.",
+                exts.last().map(String::as_str).unwrap_or_default()
+            ))
         })
 }
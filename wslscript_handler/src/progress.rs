@@ -1,49 +1,41 @@
 use num_enum::IntoPrimitive;
 use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
-use std::{mem, pin::Pin, ptr};
-use wchar::*;
+use std::{mem, pin::Pin};
 use widestring::*;
-use winapi::shared::basetsd;
-use winapi::shared::minwindef as win;
-use winapi::shared::windef::*;
-use winapi::um::commctrl;
-use winapi::um::errhandlingapi;
-use winapi::um::libloaderapi;
-use winapi::um::wingdi;
-use winapi::um::winuser;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi;
+use windows::Win32::System::LibraryLoader;
+use windows::Win32::System::SystemServices::{SS_CENTER, SS_PATHELLIPSIS};
+use windows::Win32::UI::Controls;
+use windows::Win32::UI::WindowsAndMessaging as winuser;
 use wslscript_common::error::*;
 use wslscript_common::font::Font;
 use wslscript_common::wcstring;
-use wslscript_common::win32;
+use wslscript_common::win32::{self, OwnedHwnd};
+use wslscript_common::window::{self, MessageLoop, WindowProc};
 
+#[derive(Default)]
 pub struct ProgressWindow {
+    /// Window handle, destroyed before other fields are dropped.
+    hwnd: OwnedHwnd,
     /// Maximum value for progress.
     high_limit: usize,
+    /// Script the paths are converted for.
+    target: Option<PathBuf>,
     /// Sender to signal for cancellation.
     cancel_sender: Option<Sender<()>>,
-    /// Window handle.
-    hwnd: HWND,
     /// Default font.
     font: Font,
 }
 
-impl Default for ProgressWindow {
-    fn default() -> Self {
-        Self {
-            high_limit: 0,
-            cancel_sender: None,
-            hwnd: ptr::null_mut(),
-            font: Font::default(),
-        }
-    }
-}
-
 /// Progress window class name.
 static WND_CLASS: Lazy<WideCString> = Lazy::new(|| wcstring("WSLScriptProgress"));
 
 /// Window message for progress update.
-pub const WM_PROGRESS: win::UINT = winuser::WM_USER + 1;
+pub const WM_PROGRESS: u32 = winuser::WM_USER + 1;
 
 /// Child window identifiers.
 #[derive(IntoPrimitive, PartialEq)]
@@ -58,7 +50,15 @@ enum Control {
 const MIN_WINDOW_SIZE: (i32, i32) = (300, 150);
 
 impl ProgressWindow {
-    pub fn new(high_limit: usize, cancel_sender: Sender<()>) -> Result<Pin<Box<Self>>, Error> {
+    /// Create a progress window.
+    ///
+    /// If `target` is given, the title names the script the paths are
+    /// converted for.
+    pub fn new(
+        high_limit: usize,
+        target: Option<&Path>,
+        cancel_sender: Sender<()>,
+    ) -> Result<Pin<Box<Self>>, Error> {
         use winuser::*;
         // register window class
         if !Self::is_window_class_registered() {
@@ -66,47 +66,43 @@ impl ProgressWindow {
         }
         let mut wnd = Pin::new(Box::new(Self::default()));
         wnd.high_limit = high_limit;
+        wnd.target = target.map(Path::to_path_buf);
         wnd.cancel_sender = Some(cancel_sender);
-        let instance = unsafe { libloaderapi::GetModuleHandleW(ptr::null_mut()) };
-        let title = wchz!("WSL Script");
+        let instance = unsafe { LibraryLoader::GetModuleHandleW(None) }?;
+        let title = w!("WSL Script");
+        // controls are created within the window creation
+        let _activation = crate::actctx::activate();
         // create window
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            WS_EX_TOOLWINDOW | WS_EX_TOPMOST, WND_CLASS.as_ptr(), title.as_ptr(),
+            WS_EX_TOOLWINDOW | WS_EX_TOPMOST, PCWSTR(WND_CLASS.as_ptr()), title,
             WS_OVERLAPPEDWINDOW & !WS_MAXIMIZEBOX | WS_VISIBLE,
             CW_USEDEFAULT, CW_USEDEFAULT, MIN_WINDOW_SIZE.0, MIN_WINDOW_SIZE.1,
-            ptr::null_mut(), ptr::null_mut(), instance,
+            None, None, instance,
             // self as a `CREATESTRUCT`'s `lpCreateParams`
-            &*wnd as *const Self as win::LPVOID)
+            Some(&*wnd as *const Self as _))
         };
-        if hwnd.is_null() {
-            return Err(win32::last_error());
+        if hwnd.0 == 0 {
+            return Err(window::take_error().unwrap_or_else(win32::last_error));
         }
         Ok(wnd)
     }
 
     /// Get handle to main window.
     pub fn handle(&self) -> HWND {
-        self.hwnd
+        self.hwnd.get()
     }
 
     /// Run message loop.
     pub fn run(&self) -> Result<(), Error> {
         log::debug!("Starting message loop");
-        loop {
-            let mut msg: winuser::MSG = unsafe { mem::zeroed() };
-            match unsafe { winuser::GetMessageW(&mut msg, ptr::null_mut(), 0, 0) } {
-                1..=std::i32::MAX => unsafe {
-                    winuser::TranslateMessage(&msg);
-                    winuser::DispatchMessageW(&msg);
-                },
-                std::i32::MIN..=-1 => return Err(win32::last_error()),
-                0 => {
-                    log::debug!("Received WM_QUIT");
-                    return Ok(());
-                }
-            }
-        }
+        MessageLoop::new().run()
+    }
+
+    /// Set title displayed above the progress bar.
+    pub fn set_title(&self, title: &WideCStr) {
+        let hwnd = self.get_control_handle(Control::Title);
+        let _ = unsafe { winuser::SetWindowTextW(hwnd, PCWSTR(title.as_ptr())) };
     }
 
     /// Signal that progress should be cancelled.
@@ -120,52 +116,74 @@ impl ProgressWindow {
 
     /// Close main window.
     pub fn close(&self) {
-        unsafe { winuser::PostMessageW(self.hwnd, winuser::WM_CLOSE, 0, 0) };
+        let _ = unsafe {
+            winuser::PostMessageW(self.hwnd.get(), winuser::WM_CLOSE, WPARAM(0), LPARAM(0))
+        };
     }
 
     /// Create child control windows.
     fn create_window_controls(&mut self) -> Result<(), Error> {
         use winuser::*;
-        let instance = unsafe { GetWindowLongPtrW(self.hwnd, GWLP_HINSTANCE) as win::HINSTANCE };
+        let instance = HINSTANCE(unsafe { GetWindowLongPtrW(self.hwnd.get(), GWLP_HINSTANCE) });
         self.font = Font::new_caption(20)?;
         // init common controls
-        let icex = commctrl::INITCOMMONCONTROLSEX {
-            dwSize: mem::size_of::<commctrl::INITCOMMONCONTROLSEX>() as u32,
-            dwICC: commctrl::ICC_PROGRESS_CLASS,
+        let icex = Controls::INITCOMMONCONTROLSEX {
+            dwSize: mem::size_of::<Controls::INITCOMMONCONTROLSEX>() as u32,
+            dwICC: Controls::ICC_PROGRESS_CLASS,
         };
-        unsafe { commctrl::InitCommonControlsEx(&icex) };
+        unsafe { Controls::InitCommonControlsEx(&icex) };
         // progress bar
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wcstring(commctrl::PROGRESS_CLASS).as_ptr(), ptr::null_mut(),
-            WS_CHILD | WS_VISIBLE | commctrl::PBS_MARQUEE,
-            0, 0, 0, 0, self.hwnd,
-            Control::ProgressBar as u16 as _, instance, ptr::null_mut(),
+            WINDOW_EX_STYLE::default(), Controls::PROGRESS_CLASS, PCWSTR::null(),
+            WS_CHILD | WS_VISIBLE | WINDOW_STYLE(Controls::PBS_MARQUEE),
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::ProgressBar as isize), instance, None,
         ) };
-        unsafe { SendMessageW(hwnd, commctrl::PBM_SETRANGE32, 0, self.high_limit as _) };
-        unsafe { SendMessageW(hwnd, commctrl::PBM_SETMARQUEE, 1, 0) };
+        unsafe {
+            SendMessageW(
+                hwnd,
+                Controls::PBM_SETRANGE32,
+                WPARAM(0),
+                LPARAM(self.high_limit as _),
+            )
+        };
+        unsafe { SendMessageW(hwnd, Controls::PBM_SETMARQUEE, WPARAM(1), LPARAM(0)) };
         // static message area
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), ptr::null_mut(),
-            SS_CENTER | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::Message as u16 as _, instance, ptr::null_mut(),
+            WINDOW_EX_STYLE::default(), w!("STATIC"), PCWSTR::null(),
+            WINDOW_STYLE(SS_CENTER.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::Message as isize), instance, None,
         ) };
         Self::set_window_font(hwnd, &self.font);
-        // static title
+        // static title, long script paths are truncated in the middle
         #[rustfmt::skip]
         let hwnd = unsafe { CreateWindowExW(
-            0, wchz!("STATIC").as_ptr(), ptr::null_mut(),
-            SS_CENTER | WS_CHILD | WS_VISIBLE,
-            0, 0, 0, 0, self.hwnd,
-            Control::Title as u16 as _, instance, ptr::null_mut(),
+            WINDOW_EX_STYLE::default(), w!("STATIC"), PCWSTR::null(),
+            WINDOW_STYLE(SS_CENTER.0 | SS_PATHELLIPSIS.0) | WS_CHILD | WS_VISIBLE,
+            0, 0, 0, 0, self.hwnd.get(),
+            HMENU(Control::Title as isize), instance, None,
         ) };
         Self::set_window_font(hwnd, &self.font);
-        unsafe { SetWindowTextW(hwnd, wchz!("Converting paths...").as_ptr()) };
+        let title = wcstring(self.initial_title());
+        let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(title.as_ptr())) };
         Ok(())
     }
 
+    /// Title displayed until it's changed with `set_title`.
+    fn initial_title(&self) -> String {
+        match &self.target {
+            Some(target) => format!(
+                "Converting {} paths for {}",
+                win32::format_number(self.high_limit as u64),
+                target.display()
+            ),
+            None => "Converting paths...".to_string(),
+        }
+    }
+
     /// Called when client was resized.
     fn on_resize(&self, width: i32, _height: i32) {
         self.move_control(Control::Title, 10, 10, width - 20, 20);
@@ -176,38 +194,47 @@ impl ProgressWindow {
     /// Move control relative to main window.
     fn move_control(&self, control: Control, x: i32, y: i32, width: i32, height: i32) {
         let hwnd = self.get_control_handle(control);
-        unsafe { winuser::MoveWindow(hwnd, x, y, width, height, win::TRUE) };
+        let _ = unsafe { winuser::MoveWindow(hwnd, x, y, width, height, TRUE) };
     }
 
     /// Get window handle of given control.
     fn get_control_handle(&self, control: Control) -> HWND {
-        unsafe { winuser::GetDlgItem(self.hwnd, control as i32) }
+        unsafe { winuser::GetDlgItem(self.hwnd.get(), control as i32) }
     }
 
     /// Set font to given window.
     fn set_window_font(hwnd: HWND, font: &Font) {
         unsafe {
-            winuser::SendMessageW(hwnd, winuser::WM_SETFONT, font.handle as _, win::TRUE as _)
+            winuser::SendMessageW(
+                hwnd,
+                winuser::WM_SETFONT,
+                WPARAM(font.handle().0 as _),
+                LPARAM(TRUE.0 as _),
+            )
         };
     }
 
     /// Update controls to display given progress.
     fn update_progress(&mut self, current: usize, max: usize) {
-        use commctrl::*;
         use winuser::*;
+        use Controls::*;
         log::debug!("Progress update: {}/{}", current, max);
-        let msg = format!("{} / {}", current, max);
-        unsafe {
+        let msg = format!(
+            "{} / {}",
+            win32::format_number(current as u64),
+            win32::format_number(max as u64)
+        );
+        let _ = unsafe {
             SetWindowTextW(
                 self.get_control_handle(Control::Message),
-                wcstring(msg).as_ptr(),
+                PCWSTR(wcstring(msg).as_ptr()),
             )
         };
         if self.is_marquee_progress() {
             self.set_progress_to_range_mode();
         }
         let hwnd = self.get_control_handle(Control::ProgressBar);
-        unsafe { SendMessageW(hwnd, PBM_SETPOS, current, 0) };
+        unsafe { SendMessageW(hwnd, PBM_SETPOS, WPARAM(current), LPARAM(0)) };
         // if done, close cancellation channel
         if current == max {
             self.cancel_sender.take();
@@ -222,19 +249,19 @@ impl ProgressWindow {
                 winuser::GWL_STYLE,
             )
         } as u32;
-        style & commctrl::PBS_MARQUEE != 0
+        style & Controls::PBS_MARQUEE != 0
     }
 
     /// Set progress bar to range mode.
     fn set_progress_to_range_mode(&self) {
-        use commctrl::*;
         use winuser::*;
+        use Controls::*;
         let hwnd = self.get_control_handle(Control::ProgressBar);
         let mut style = unsafe { GetWindowLongW(hwnd, GWL_STYLE) } as u32;
         style &= !PBS_MARQUEE;
         style |= PBS_SMOOTH;
         unsafe { SetWindowLongW(hwnd, GWL_STYLE, style as _) };
-        unsafe { SendMessageW(hwnd, PBM_SETMARQUEE, 0, 0) };
+        unsafe { SendMessageW(hwnd, PBM_SETMARQUEE, WPARAM(0), LPARAM(0)) };
     }
 }
 
@@ -242,9 +269,9 @@ impl ProgressWindow {
     /// Check whether window class is registered.
     pub fn is_window_class_registered() -> bool {
         unsafe {
-            let instance = libloaderapi::GetModuleHandleW(ptr::null_mut());
+            let instance = LibraryLoader::GetModuleHandleW(None).unwrap_or_default();
             let mut wc: winuser::WNDCLASSEXW = mem::zeroed();
-            winuser::GetClassInfoExW(instance, WND_CLASS.as_ptr(), &mut wc) != 0
+            winuser::GetClassInfoExW(instance, PCWSTR(WND_CLASS.as_ptr()), &mut wc).is_ok()
         }
     }
 
@@ -252,16 +279,16 @@ impl ProgressWindow {
     pub fn register_window_class() -> Result<(), Error> {
         use winuser::*;
         log::debug!("Registering {} window class", WND_CLASS.to_string_lossy());
-        let instance = unsafe { libloaderapi::GetModuleHandleW(ptr::null_mut()) };
+        let instance = unsafe { LibraryLoader::GetModuleHandleW(None) }?;
         let wc = WNDCLASSEXW {
             cbSize: mem::size_of::<WNDCLASSEXW>() as u32,
             style: CS_OWNDC | CS_HREDRAW | CS_VREDRAW,
-            hbrBackground: (COLOR_WINDOW + 1) as HBRUSH,
-            lpfnWndProc: Some(window_proc_wrapper::<ProgressWindow>),
-            hInstance: instance,
-            lpszClassName: WND_CLASS.as_ptr(),
-            hIcon: ptr::null_mut(),
-            hCursor: unsafe { LoadCursorW(ptr::null_mut(), IDC_ARROW) },
+            hbrBackground: Gdi::HBRUSH((Gdi::COLOR_WINDOW.0 + 1) as _),
+            lpfnWndProc: Some(window::window_proc_wrapper::<ProgressWindow>),
+            hInstance: instance.into(),
+            lpszClassName: PCWSTR(WND_CLASS.as_ptr()),
+            hIcon: HICON::default(),
+            hCursor: unsafe { LoadCursorW(None, IDC_ARROW) }.unwrap_or_default(),
             ..unsafe { mem::zeroed() }
         };
         if 0 == unsafe { RegisterClassExW(&wc) } {
@@ -275,74 +302,26 @@ impl ProgressWindow {
     pub fn unregister_window_class() {
         log::debug!("Unregistering {} window class", WND_CLASS.to_string_lossy());
         unsafe {
-            let instance = libloaderapi::GetModuleHandleW(ptr::null_mut());
-            winuser::UnregisterClassW(WND_CLASS.as_ptr(), instance);
+            let instance = LibraryLoader::GetModuleHandleW(None).unwrap_or_default();
+            let _ = winuser::UnregisterClassW(PCWSTR(WND_CLASS.as_ptr()), instance);
         }
     }
 }
 
-trait WindowProc {
-    /// Window procedure callback.
-    ///
-    /// If None is returned, underlying wrapper calls `DefWindowProcW`.
-    fn window_proc(
-        &mut self,
-        hwnd: HWND,
-        msg: win::UINT,
-        wparam: win::WPARAM,
-        lparam: win::LPARAM,
-    ) -> Option<win::LRESULT>;
-}
-
-/// Window proc wrapper that manages the `&self` pointer to `ProgressWindow` object.
-///
-/// Must be `extern "system"` because the function is called by Windows.
-extern "system" fn window_proc_wrapper<T: WindowProc>(
-    hwnd: HWND,
-    msg: win::UINT,
-    wparam: win::WPARAM,
-    lparam: win::LPARAM,
-) -> win::LRESULT {
-    use winuser::*;
-    // get pointer to T from userdata
-    let mut ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut T;
-    // not yet set, initialize from CREATESTRUCT
-    if ptr.is_null() && msg == WM_NCCREATE {
-        let cs = unsafe { &*(lparam as LPCREATESTRUCTW) };
-        ptr = cs.lpCreateParams as *mut T;
-        log::debug!("Initialize window pointer {:p}", ptr);
-        unsafe { errhandlingapi::SetLastError(0) };
-        if 0 == unsafe {
-            SetWindowLongPtrW(hwnd, GWLP_USERDATA, ptr as *const _ as basetsd::LONG_PTR)
-        } && unsafe { errhandlingapi::GetLastError() } != 0
-        {
-            return win::FALSE as win::LRESULT;
-        }
-    }
-    // call wrapped window proc
-    if !ptr.is_null() {
-        let this = unsafe { &mut *(ptr as *mut T) };
-        if let Some(result) = this.window_proc(hwnd, msg, wparam, lparam) {
-            return result;
-        }
-    }
-    unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
-}
-
 impl WindowProc for ProgressWindow {
     fn window_proc(
         &mut self,
         hwnd: HWND,
-        msg: win::UINT,
-        wparam: win::WPARAM,
-        lparam: win::LPARAM,
-    ) -> Option<win::LRESULT> {
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> Option<LRESULT> {
         use winuser::*;
         match msg {
             // https://docs.microsoft.com/en-us/windows/win32/winmsg/wm-nccreate
             WM_NCCREATE => {
                 // store main window handle
-                self.hwnd = hwnd;
+                self.hwnd = unsafe { OwnedHwnd::from_raw(hwnd) };
                 // WM_NCCREATE must be passed to DefWindowProc
                 None
             }
@@ -350,43 +329,53 @@ impl WindowProc for ProgressWindow {
             WM_CREATE => match self.create_window_controls() {
                 Err(e) => {
                     log::error!("Failed to create window controls: {}", e);
-                    Some(-1)
+                    window::quit_with_error(e);
+                    Some(LRESULT(-1))
                 }
-                Ok(()) => Some(0),
+                Ok(()) => Some(LRESULT(0)),
             },
             // https://docs.microsoft.com/en-us/windows/win32/winmsg/wm-size
             WM_SIZE => {
                 self.on_resize(
-                    i32::from(win::LOWORD(lparam as u32)),
-                    i32::from(win::HIWORD(lparam as u32)),
+                    (lparam.0 & 0xffff) as i32,
+                    ((lparam.0 >> 16) & 0xffff) as i32,
                 );
-                Some(0)
+                Some(LRESULT(0))
             }
             // https://docs.microsoft.com/en-us/windows/win32/winmsg/wm-getminmaxinfo
             WM_GETMINMAXINFO => {
-                let mmi = unsafe { &mut *(lparam as LPMINMAXINFO) };
+                let mmi = unsafe { &mut *(lparam.0 as *mut MINMAXINFO) };
                 mmi.ptMinTrackSize.x = MIN_WINDOW_SIZE.0;
                 mmi.ptMinTrackSize.y = MIN_WINDOW_SIZE.1;
-                Some(0)
+                Some(LRESULT(0))
             }
             // https://docs.microsoft.com/en-us/windows/win32/controls/wm-ctlcolorstatic
-            WM_CTLCOLORSTATIC => {
-                Some(unsafe { wingdi::GetStockObject(COLOR_WINDOW + 1) } as win::LPARAM)
-            }
+            WM_CTLCOLORSTATIC => Some(LRESULT(
+                unsafe {
+                    Gdi::GetStockObject(Gdi::GET_STOCK_OBJECT_FLAGS(Gdi::COLOR_WINDOW.0 + 1))
+                }
+                .0,
+            )),
             // https://docs.microsoft.com/en-us/windows/win32/winmsg/wm-close
             WM_CLOSE => {
                 self.cancel();
-                unsafe { DestroyWindow(hwnd) };
-                Some(0)
+                let _ = unsafe { DestroyWindow(hwnd) };
+                Some(LRESULT(0))
             }
             // https://docs.microsoft.com/en-us/windows/win32/winmsg/wm-destroy
             WM_DESTROY => {
                 unsafe { PostQuitMessage(0) };
-                Some(0)
+                Some(LRESULT(0))
+            }
+            // https://docs.microsoft.com/en-us/windows/win32/winmsg/wm-ncdestroy
+            WM_NCDESTROY => {
+                // window is gone, nothing left to destroy on drop
+                self.hwnd.release();
+                None
             }
             WM_PROGRESS => {
-                self.update_progress(wparam, lparam as _);
-                Some(0)
+                self.update_progress(wparam.0, lparam.0 as _);
+                Some(LRESULT(0))
             }
             _ => None,
         }
@@ -0,0 +1,84 @@
+//! Mock of `wsl.exe` for the integration tests.
+//!
+//! Installed as `System32\wsl.exe` under a fake `SYSTEMROOT`. Every
+//! invocation is recorded to a file in the directory given by
+//! `WSLSCRIPT_MOCK_LOG`, with arguments separated by null characters.
+//! Probes and path conversions are answered as a distribution with bash
+//! and drives mounted under `/mnt` would, and everything else succeeds
+//! without doing anything.
+
+use std::env;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable of the directory where invocations are recorded.
+const LOG_DIR_VAR: &str = "WSLSCRIPT_MOCK_LOG";
+
+/// Environment variable carrying the command when `-e` isn't used.
+const LEGACY_COMMAND_VAR: &str = "WSLSCRIPT_COMMAND";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help") {
+        println!("Usage: wsl.exe [Argument] [Options...] [CommandLine]");
+        println!("    --cd <Directory>");
+        println!("    --exec, -e <CommandLine>");
+        return;
+    }
+    // command is the argument following the shell's -c
+    let command = args
+        .iter()
+        .position(|arg| arg == "-c")
+        .and_then(|i| args.get(i + 1))
+        .map(|command| match command.strip_prefix('$') {
+            Some(_) => env::var(LEGACY_COMMAND_VAR).unwrap_or_default(),
+            None => command.to_owned(),
+        })
+        .unwrap_or_default();
+    record(&args, &command);
+    if command.contains("--- wsl.conf ---") {
+        print!("bash=1\nuser=test\nmountroot=/mnt/\n--- wsl.conf ---\n");
+    } else if command.contains("wslpath") || command.ends_with(" convert") {
+        convert_stdin();
+    }
+}
+
+/// Record an invocation, with the command resolved from the environment.
+fn record(args: &[String], command: &str) {
+    let Some(dir) = env::var_os(LOG_DIR_VAR).map(PathBuf::from) else {
+        return;
+    };
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let path = dir.join(format!("{}-{}.args", nanos, std::process::id()));
+    let mut record = args.join("\0");
+    record.push('\0');
+    record.push_str(command);
+    let _ = std::fs::write(path, record);
+}
+
+/// Convert null separated Windows paths from stdin to WSL paths.
+fn convert_stdin() {
+    let mut input = String::new();
+    let _ = std::io::stdin().read_to_string(&mut input);
+    let mut out = std::io::stdout().lock();
+    for path in input.split('\0').filter(|s| !s.is_empty()) {
+        let _ = write!(out, "{}\0", to_wsl(path));
+    }
+}
+
+/// Convert a Windows path to a WSL path under `/mnt`.
+fn to_wsl(path: &str) -> String {
+    let path = path.strip_prefix(r"\\?\").unwrap_or(path);
+    match path.split_once(":\\") {
+        Some((drive, rest)) => format!(
+            "/mnt/{}/{}",
+            drive.to_ascii_lowercase(),
+            rest.replace('\\', "/")
+        ),
+        None => path.replace('\\', "/"),
+    }
+}
@@ -0,0 +1,441 @@
+//! End-to-end tests of dropping files on a registered script.
+//!
+//! Drop handler library is loaded into the test process and driven with
+//! `CF_HDROP` data objects constructed in-process, like Explorer does.
+//! WSL is replaced by `mock_wsl`, installed as `wsl.exe` under a fake
+//! `SYSTEMROOT`, and the registry by a private key that `HKEY_CURRENT_USER`
+//! is redirected to, so that settings of the user are never touched.
+//! Both are removed when the tests exit.
+//!
+//! The library is built by `cargo build --workspace`, which must be run
+//! before the tests. Crate isn't a default workspace member, so the tests
+//! are run with `cargo test -p wslscript_tests`.
+
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use widestring::U16CString;
+use windows::core::{implement, Interface, GUID, HRESULT, PCWSTR};
+use windows::Win32::Foundation::{BOOL, DV_E_FORMATETC, E_NOTIMPL, HGLOBAL, POINT, POINTL, S_OK};
+use windows::Win32::System::Com::*;
+use windows::Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW};
+use windows::Win32::System::Memory::{GlobalAlloc, GMEM_FIXED};
+use windows::Win32::System::Ole::{IDropTarget, CF_HDROP, DROPEFFECT_NONE};
+use windows::Win32::System::Registry::{RegOverridePredefKey, HKEY, HKEY_CURRENT_USER};
+use windows::Win32::System::SystemServices::MODIFIERKEYS_FLAGS;
+use windows::Win32::UI::Shell::DROPFILES;
+use winreg::RegKey;
+
+/// Extension registered for the test scripts.
+const EXT: &str = "wslscripttest";
+
+/// Drop handler CLSID of the sandboxed installation.
+const CLSID: GUID = GUID::from_u128(0x3f2a6c1e_9b4d_4e8a_a1c7_5d0e2b9f4a63);
+
+/// Key under `HKEY_CURRENT_USER` holding the sandboxed registry.
+const REGISTRY_SANDBOX: &str = r"Software\wslscript-tests";
+
+/// How long to wait for a script run to reach WSL.
+const RUN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Signature of `DllGetClassObject`.
+type DllGetClassObjectFn =
+    unsafe extern "system" fn(*const GUID, *const GUID, *mut *mut c_void) -> HRESULT;
+
+/// Prefix of the sandbox directory name, followed by the process ID.
+const DIR_PREFIX: &str = "wslscript-tests-";
+
+/// Sandbox shared by all tests.
+struct Sandbox {
+    /// Root directory of the sandbox.
+    root: PathBuf,
+    /// Directory of the test scripts and dropped files.
+    files: PathBuf,
+    /// Directory where `mock_wsl` records its invocations.
+    log: PathBuf,
+    /// `DllGetClassObject` of the loaded drop handler library.
+    get_class_object: DllGetClassObjectFn,
+}
+
+/// Sandbox, set up on first use.
+static SANDBOX: OnceLock<Sandbox> = OnceLock::new();
+
+extern "C" {
+    /// Register a function that the C runtime calls when the process exits.
+    fn atexit(callback: extern "C" fn()) -> i32;
+}
+
+/// Set up the sandbox on first use.
+fn sandbox() -> &'static Sandbox {
+    SANDBOX.get_or_init(|| {
+        remove_stale_sandboxes();
+        let root = std::env::temp_dir().join(format!("{}{}", DIR_PREFIX, std::process::id()));
+        let files = root.join("files");
+        let log = root.join("log");
+        for dir in [&root.join("System32"), &files, &log] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::copy(
+            env!("CARGO_BIN_EXE_mock_wsl"),
+            root.join(r"System32\wsl.exe"),
+        )
+        .unwrap();
+        // handler expands short names, so expected paths must be long too
+        let files = long_path(&files);
+        std::env::set_var("SYSTEMROOT", &root);
+        std::env::set_var("WSLSCRIPT_MOCK_LOG", &log);
+        redirect_registry();
+        // statics are never dropped, so the sandbox is removed on exit instead
+        unsafe { atexit(remove_sandbox) };
+        let get_class_object = load_handler();
+        Sandbox {
+            root,
+            files,
+            log,
+            get_class_object,
+        }
+    })
+}
+
+/// Redirect `HKEY_CURRENT_USER` of this process to an empty key, and
+/// register the test extension there.
+fn redirect_registry() {
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER.0 as _);
+    let _ = hkcu.delete_subkey_all(REGISTRY_SANDBOX);
+    let (sandbox, _) = hkcu.create_subkey(REGISTRY_SANDBOX).unwrap();
+    unsafe { RegOverridePredefKey(HKEY_CURRENT_USER, HKEY(sandbox.raw_handle() as _)) }
+        .ok()
+        .unwrap();
+    // predefined key now refers to the sandbox
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER.0 as _);
+    let (settings, _) = hkcu.create_subkey(r"Software\wslscript").unwrap();
+    settings
+        .set_value("DropHandlerCLSID", &format!("{:?}", CLSID))
+        .unwrap();
+    let (handler, _) = hkcu
+        .create_subkey(format!(r"Software\Classes\wslscript.{}", EXT))
+        .unwrap();
+    handler.set_value("SchemaVersion", &2_u32).unwrap();
+    handler.set_value("HoldMode", &"never").unwrap();
+    handler.set_value("ShowProgress", &0_u32).unwrap();
+}
+
+/// Remove the sandbox directory and registry key.
+extern "C" fn remove_sandbox() {
+    // restore the actual HKEY_CURRENT_USER
+    let _ = unsafe { RegOverridePredefKey(HKEY_CURRENT_USER, HKEY::default()) };
+    let _ = RegKey::predef(HKEY_CURRENT_USER.0 as _).delete_subkey_all(REGISTRY_SANDBOX);
+    if let Some(sandbox) = SANDBOX.get() {
+        let _ = std::fs::remove_dir_all(&sandbox.root);
+    }
+}
+
+/// Remove sandbox directories of earlier runs that exited without cleaning
+/// up, eg. after a failed test.
+fn remove_stale_sandboxes() {
+    let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(DIR_PREFIX) {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Load the drop handler library built next to the test binaries.
+fn load_handler() -> DllGetClassObjectFn {
+    // test binaries are built to the deps directory
+    let path = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .join("wslscript_handler.dll");
+    assert!(
+        path.is_file(),
+        "{} not found, run cargo build --workspace first",
+        path.display()
+    );
+    let name = U16CString::from_os_str(path.as_os_str()).unwrap();
+    let lib = unsafe { LoadLibraryW(PCWSTR(name.as_ptr())) }.unwrap();
+    let proc = unsafe { GetProcAddress(lib, windows::core::s!("DllGetClassObject")) }.unwrap();
+    unsafe { std::mem::transmute::<_, DllGetClassObjectFn>(proc) }
+}
+
+/// Create a drop handler loaded with a script, as Explorer does when files
+/// are dragged over the script.
+fn drop_target(script: &Path) -> IDropTarget {
+    let _ = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+    let mut factory: *mut c_void = std::ptr::null_mut();
+    unsafe { (sandbox().get_class_object)(&CLSID, &IClassFactory::IID, &mut factory) }
+        .ok()
+        .unwrap();
+    let factory = unsafe { IClassFactory::from_raw(factory) };
+    let file: IPersistFile = unsafe { factory.CreateInstance(None) }.unwrap();
+    let name = U16CString::from_os_str(script.as_os_str()).unwrap();
+    unsafe { file.Load(PCWSTR(name.as_ptr()), STGM_READ) }.unwrap();
+    file.cast().unwrap()
+}
+
+/// Create a script and files to be dropped on it in the sandbox.
+fn create_files(script: &str, names: &[&str]) -> (PathBuf, Vec<PathBuf>) {
+    let dir = &sandbox().files;
+    let script = dir.join(format!("{}.{}", script, EXT));
+    std::fs::write(&script, "#!/bin/sh\necho \"$@\"\n").unwrap();
+    let files = names
+        .iter()
+        .map(|name| {
+            let path = dir.join(name);
+            std::fs::write(&path, name).unwrap();
+            path
+        })
+        .collect();
+    (script, files)
+}
+
+/// Drop files on a script.
+fn drop_files(script: &Path, data: DropData) -> windows::core::Result<()> {
+    let target = drop_target(script);
+    let obj: IDataObject = data.into();
+    let mut effect = DROPEFFECT_NONE;
+    unsafe {
+        target.Drop(
+            &obj,
+            MODIFIERKEYS_FLAGS(0),
+            POINTL { x: 100, y: 100 },
+            &mut effect,
+        )
+    }
+}
+
+/// Get the shell commands that `mock_wsl` was asked to run for a script.
+fn recorded_commands(script: &Path) -> Vec<String> {
+    let name = script.file_name().unwrap().to_string_lossy().into_owned();
+    std::fs::read_dir(&sandbox().log)
+        .unwrap()
+        .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path()).ok())
+        .filter_map(|record| record.rsplit('\0').next().map(str::to_owned))
+        .filter(|command| command.contains(&name))
+        .collect()
+}
+
+/// Wait for a script to be run in WSL, returning the shell command.
+fn wait_for_run(script: &Path) -> String {
+    let started = Instant::now();
+    loop {
+        if let Some(command) = recorded_commands(script).pop() {
+            return command;
+        }
+        assert!(
+            started.elapsed() < RUN_TIMEOUT,
+            "{} wasn't run",
+            script.display()
+        );
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Convert a path as `mock_wsl` does, quoted for the shell.
+fn quoted_wsl_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let (drive, rest) = path.split_once(":\\").unwrap();
+    format!(
+        "'/mnt/{}/{}'",
+        drive.to_ascii_lowercase(),
+        rest.replace('\\', "/").replace('\'', r"'\''")
+    )
+}
+
+/// Get the long form of an existing path, without the verbatim prefix.
+fn long_path(path: &Path) -> PathBuf {
+    let path = std::fs::canonicalize(path).unwrap();
+    let s = path.to_string_lossy();
+    PathBuf::from(s.strip_prefix(r"\\?\").unwrap_or(&s))
+}
+
+/// `CF_HDROP` data of a drop.
+#[implement(IDataObject)]
+struct DropData {
+    /// `DROPFILES` structure followed by the file name array.
+    bytes: Vec<u8>,
+}
+
+impl DropData {
+    /// Data with file names in UTF-16, as Explorer provides them.
+    fn wide(paths: &[PathBuf]) -> Self {
+        let mut names: Vec<u16> = Vec::new();
+        for path in paths {
+            names.extend(
+                U16CString::from_os_str(path.as_os_str())
+                    .unwrap()
+                    .as_slice_with_nul(),
+            );
+        }
+        names.push(0);
+        let names = names
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+        Self::new(&names, true)
+    }
+
+    /// Data with file names in the ANSI code page, as legacy programs
+    /// provide them.
+    fn ansi(paths: &[PathBuf]) -> Self {
+        let mut names: Vec<u8> = Vec::new();
+        for path in paths {
+            names.extend(path.to_str().unwrap().bytes());
+            names.push(0);
+        }
+        names.push(0);
+        Self::new(&names, false)
+    }
+
+    /// Data with the file name array claimed to start past the end.
+    fn truncated(paths: &[PathBuf]) -> Self {
+        let mut data = Self::wide(paths);
+        let offset = data.bytes.len() as u32 + 64;
+        data.bytes[..4].copy_from_slice(&offset.to_le_bytes());
+        data
+    }
+
+    fn new(names: &[u8], wide: bool) -> Self {
+        let header = DROPFILES {
+            pFiles: std::mem::size_of::<DROPFILES>() as u32,
+            pt: POINT::default(),
+            fNC: BOOL(0),
+            fWide: BOOL(wide as i32),
+        };
+        let mut bytes = unsafe {
+            std::slice::from_raw_parts(
+                (&header as *const DROPFILES).cast::<u8>(),
+                std::mem::size_of::<DROPFILES>(),
+            )
+        }
+        .to_vec();
+        bytes.extend_from_slice(names);
+        Self { bytes }
+    }
+
+    fn is_hdrop(format: *const FORMATETC) -> bool {
+        let format = unsafe { &*format };
+        format.cfFormat == CF_HDROP.0 && format.tymed & TYMED_HGLOBAL.0 as u32 != 0
+    }
+}
+
+impl IDataObject_Impl for DropData {
+    fn GetData(&self, pformatetcin: *const FORMATETC) -> windows::core::Result<STGMEDIUM> {
+        if !Self::is_hdrop(pformatetcin) {
+            return Err(DV_E_FORMATETC.into());
+        }
+        // handler frees the memory since there's no release interface
+        let mem = unsafe { GlobalAlloc(GMEM_FIXED, self.bytes.len()) }?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.bytes.as_ptr(), mem.0.cast(), self.bytes.len())
+        };
+        Ok(STGMEDIUM {
+            tymed: TYMED_HGLOBAL.0 as u32,
+            u: STGMEDIUM_0 {
+                hGlobal: HGLOBAL(mem.0),
+            },
+            pUnkForRelease: std::mem::ManuallyDrop::new(None),
+        })
+    }
+
+    fn GetDataHere(
+        &self,
+        _pformatetc: *const FORMATETC,
+        _pmedium: *mut STGMEDIUM,
+    ) -> windows::core::Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn QueryGetData(&self, pformatetc: *const FORMATETC) -> HRESULT {
+        if Self::is_hdrop(pformatetc) {
+            S_OK
+        } else {
+            DV_E_FORMATETC
+        }
+    }
+
+    fn GetCanonicalFormatEtc(
+        &self,
+        _pformatectin: *const FORMATETC,
+        _pformatetcout: *mut FORMATETC,
+    ) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    fn SetData(
+        &self,
+        _pformatetc: *const FORMATETC,
+        _pmedium: *const STGMEDIUM,
+        _frelease: BOOL,
+    ) -> windows::core::Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn EnumFormatEtc(&self, _dwdirection: u32) -> windows::core::Result<IEnumFORMATETC> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn DAdvise(
+        &self,
+        _pformatetc: *const FORMATETC,
+        _advf: u32,
+        _padvsink: Option<&IAdviseSink>,
+    ) -> windows::core::Result<u32> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn DUnadvise(&self, _dwconnection: u32) -> windows::core::Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn EnumDAdvise(&self) -> windows::core::Result<IEnumSTATDATA> {
+        Err(E_NOTIMPL.into())
+    }
+}
+
+#[test]
+fn test_drop_multiple_files() {
+    let (script, files) = create_files("multi", &["first.txt", "second file.txt", "it's.txt"]);
+    drop_files(&script, DropData::wide(&files)).unwrap();
+    let command = wait_for_run(&script);
+    let args = files
+        .iter()
+        .map(|path| quoted_wsl_path(path))
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert!(command.contains(&args), "{}", command);
+}
+
+#[test]
+fn test_drop_unicode_names() {
+    let (script, files) = create_files("unicode", &["päivää.txt", "日本語.txt", "emoji 🐧.txt"]);
+    drop_files(&script, DropData::wide(&files)).unwrap();
+    let command = wait_for_run(&script);
+    for path in &files {
+        assert!(command.contains(&quoted_wsl_path(path)), "{}", command);
+    }
+}
+
+#[test]
+fn test_drop_ansi_names() {
+    let (script, files) = create_files("ansi", &["plain.txt", "with space.txt"]);
+    drop_files(&script, DropData::ansi(&files)).unwrap();
+    let command = wait_for_run(&script);
+    for path in &files {
+        assert!(command.contains(&quoted_wsl_path(path)), "{}", command);
+    }
+}
+
+#[test]
+fn test_drop_malformed_rejected() {
+    let (script, files) = create_files("malformed", &["plain.txt"]);
+    assert!(drop_files(&script, DropData::truncated(&files)).is_err());
+    // rejected before the run is started in the background
+    assert!(recorded_commands(&script).is_empty());
+}